@@ -0,0 +1,29 @@
+//! Runs arbitrary programs on both backends, which must end in a value or
+//! an error, never a panic. The programs are built as ASTs (see the
+//! `arbitrary` feature) rather than parsed, so they reach the interpreter
+//! without having to get past the parser first.
+
+#![no_main]
+
+use std::time::Duration;
+use libfuzzer_sys::fuzz_target;
+use mid_valyrian::{ ExecutionBackend, Interpreter, OutputBuffer, Program, ResourceLimits };
+
+fuzz_target!(|program: Program| {
+    for backend in [ExecutionBackend::TreeWalking, ExecutionBackend::Bytecode] {
+        let limits = ResourceLimits {
+            max_variables: Some(1_000),
+            max_string_length: Some(1 << 16),
+            max_output: Some(1 << 20),
+        };
+        let mut interpreter = Interpreter::new(false)
+            .with_backend(backend)
+            .with_sandbox(true)
+            .with_limits(limits)
+            .with_max_steps(Some(10_000))
+            .with_timeout(Some(Duration::from_secs(1)))
+            .with_output(OutputBuffer::new());
+        interpreter.set_sleeper(|_| Ok(()));
+        let _ = interpreter.interpret(&program);
+    }
+});
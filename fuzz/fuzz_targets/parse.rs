@@ -0,0 +1,10 @@
+//! Parses arbitrary text, which must give a program or an error, never a
+//! panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = mid_valyrian::parse_program(source);
+});
@@ -0,0 +1,71 @@
+/* C interface to the Mid Valyrian interpreter.
+ *
+ * Build the library with
+ *     cargo build --release --features ffi
+ * and see src/ffi.rs for the ownership rules of each function. The
+ * declarations can be regenerated with `cbindgen --config cbindgen.toml`.
+ */
+
+#ifndef MID_VALYRIAN_H
+#define MID_VALYRIAN_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum MvValueKind {
+  MV_VALUE_KIND_SCROLL,
+  MV_VALUE_KIND_BLADE,
+  MV_VALUE_KIND_WINE,
+  MV_VALUE_KIND_VOW,
+  MV_VALUE_KIND_SIGIL,
+  MV_VALUE_KIND_VOID,
+  MV_VALUE_KIND_EXTERNAL,
+  MV_VALUE_KIND_RAVEN,
+  MV_VALUE_KIND_ROOKERY,
+  MV_VALUE_KIND_VAULT,
+  MV_VALUE_KIND_TIDINGS,
+  MV_VALUE_KIND_MISSIVE,
+  MV_VALUE_KIND_SOCKET,
+} MvValueKind;
+
+typedef struct MvInterpreter MvInterpreter;
+
+typedef struct MvValue MvValue;
+
+const char *mv_last_error(void);
+
+MvValue *mv_run_code(const char *code);
+
+MvInterpreter *mv_interpreter_new(void);
+
+void mv_interpreter_free(MvInterpreter *interpreter);
+
+MvValue *mv_interpreter_run(MvInterpreter *interpreter, const char *code);
+
+MvValue *mv_interpreter_eval(MvInterpreter *interpreter, const char *expression);
+
+void mv_value_free(MvValue *value);
+
+MvValueKind mv_value_kind(const MvValue *value);
+
+bool mv_value_as_blade(const MvValue *value, int64_t *out);
+
+bool mv_value_as_wine(const MvValue *value, double *out);
+
+bool mv_value_as_vow(const MvValue *value, bool *out);
+
+bool mv_value_as_sigil(const MvValue *value, uint32_t *out);
+
+char *mv_value_to_string(const MvValue *value);
+
+void mv_string_free(char *text);
+
+#ifdef __cplusplus
+}  /* extern "C" */
+#endif
+
+#endif  /* MID_VALYRIAN_H */
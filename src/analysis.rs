@@ -0,0 +1,527 @@
+//! Semantic analysis producing non-fatal warnings.
+//!
+//! The pass runs over a parsed `Program` before it is interpreted and
+//! reports code that is legal but almost certainly not what the author
+//! meant, such as variables that are declared and never read.
+
+use std::collections::{ HashMap, HashSet };
+use std::fmt;
+use crate::ast::*;
+use crate::builtins;
+use crate::diagnostic::{ Diagnostic, Severity };
+use crate::symbols::{ Definition, SymbolKind, SymbolTable };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    UnusedVariable,
+    UnusedParameter,
+    UnreadAssignment,
+    UnreachableCode,
+    ConstantCondition,
+    Shadowing,
+    /// A branch or loop body whose condition rules it out, as opposed to
+    /// code cut off by a return.
+    ///
+    /// ```
+    /// use mid_valyrian::{ analyze, parse_program, WarningKind };
+    ///
+    /// let program = parse_program("on the iron throne:\n    if nay:\n        speak \"never\"\n").unwrap();
+    /// let kinds: Vec<_> = analyze(&program).into_iter().map(|w| w.kind).collect();
+    /// assert!(kinds.contains(&WarningKind::UnreachableBranch));
+    /// assert!(!kinds.contains(&WarningKind::UnreachableCode));
+    /// ```
+    UnreachableBranch,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 7] = [
+        WarningKind::UnusedVariable,
+        WarningKind::UnusedParameter,
+        WarningKind::UnreadAssignment,
+        WarningKind::UnreachableCode,
+        WarningKind::ConstantCondition,
+        WarningKind::Shadowing,
+        WarningKind::UnreachableBranch,
+    ];
+
+    /// The rule name used to enable or disable this warning from the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedVariable => "unused-variable",
+            WarningKind::UnusedParameter => "unused-parameter",
+            WarningKind::UnreadAssignment => "unread-assignment",
+            WarningKind::UnreachableCode => "unreachable-code",
+            WarningKind::ConstantCondition => "constant-condition",
+            WarningKind::Shadowing => "shadowing",
+            WarningKind::UnreachableBranch => "unreachable-branch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "⚠️ The maesters have concerns: {} (line {}, column {})",
+            self.message,
+            self.span.line,
+            self.span.col
+        )
+    }
+}
+
+/// Analyzes a program and returns its warnings in source order.
+pub fn analyze(program: &Program) -> Vec<Warning> {
+    let mut reads = HashSet::new();
+    collect_reads(&program.statements, &mut reads);
+
+    let mut globals = HashSet::new();
+    collect_global_declarations(&program.statements, &mut globals);
+
+    let symbols = SymbolTable::new(program);
+    let mut analyzer = Analyzer { reads, globals, symbols, warnings: Vec::new() };
+    analyzer.check_block(&program.statements);
+    analyzer.warnings.sort_by_key(|w| w.span.start);
+    analyzer.warnings
+}
+
+struct Analyzer {
+    /// Every variable name read anywhere in the program. Variables are
+    /// dynamically scoped, so a read in any function may observe a write
+    /// made elsewhere.
+    reads: HashSet<Symbol>,
+    /// Variables declared outside any function.
+    globals: HashSet<Symbol>,
+    /// Where each name is declared and read, for finding declarations
+    /// nothing reads.
+    symbols: SymbolTable,
+    warnings: Vec<Warning>,
+}
+
+impl Analyzer {
+    fn warn(&mut self, kind: WarningKind, message: String, span: Span) {
+        self.warnings.push(Warning { kind, message, span });
+    }
+
+    fn check_block(&mut self, statements: &[Statement]) {
+        for (i, statement) in statements.iter().enumerate() {
+            self.check_statement(statement, &statements[i + 1..]);
+            if always_returns(statement) {
+                if let Some(next) = statements.get(i + 1) {
+                    let last = statements.last().map_or(next.span, |s| s.span);
+                    self.warn(
+                        WarningKind::UnreachableCode,
+                        "this code comes after a return and will never run".into(),
+                        next.span.to(last)
+                    );
+                }
+                break;
+            }
+        }
+    }
+
+    fn warn_constant_condition(&mut self, condition: &Expression, value: bool) {
+        self.warn(
+            WarningKind::ConstantCondition,
+            format!("this condition is always {}", if value { "aye" } else { "nay" }),
+            condition.span
+        );
+    }
+
+    fn warn_unreachable_branch(&mut self, branch: &[Statement], reason: &str) {
+        if let (Some(first), Some(last)) = (branch.first(), branch.last()) {
+            self.warn(
+                WarningKind::UnreachableBranch,
+                format!("this branch will never run because {}", reason),
+                first.span.to(last.span)
+            );
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement, rest: &[Statement]) {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, value, .. } => {
+                let definition = self.symbols.definition_at(statement.span.start);
+                if !definition.is_some_and(Definition::is_read) {
+                    self.warn(
+                        WarningKind::UnusedVariable,
+                        format!("variable '{}' is declared but never read", name),
+                        statement.span
+                    );
+                } else if value.is_some() && is_overwritten_unread(name, rest) {
+                    self.warn(
+                        WarningKind::UnreadAssignment,
+                        format!("the value given to '{}' is overwritten before it is read", name),
+                        statement.span
+                    );
+                }
+            }
+            StatementKind::Assignment { name, .. } if
+                !self.reads.contains(&**name) || is_overwritten_unread(name, rest)
+            => {
+                self.warn(
+                    WarningKind::UnreadAssignment,
+                    format!("the value assigned to '{}' is never read", name),
+                    statement.span
+                );
+            }
+            StatementKind::FunctionDeclaration { name, parameters, body } => {
+                if builtins::is_builtin(name) {
+                    self.warn(
+                        WarningKind::Shadowing,
+                        format!("'{}' hides the builtin function of the same name", name),
+                        statement.span
+                    );
+                }
+                for param in parameters {
+                    if self.globals.contains(param) {
+                        self.warn(
+                            WarningKind::Shadowing,
+                            format!(
+                                "parameter '{}' of '{}' hides the variable of the same name while it runs",
+                                param,
+                                name
+                            ),
+                            statement.span
+                        );
+                    }
+                }
+                let unused: Vec<Symbol> = self.symbols
+                    .declared_by(statement)
+                    .filter(|definition| definition.kind == SymbolKind::Parameter && !definition.is_read())
+                    .map(|definition| definition.name.clone())
+                    .collect();
+                for param in unused {
+                    self.warn(
+                        WarningKind::UnusedParameter,
+                        format!("parameter '{}' of '{}' is never used", param, name),
+                        statement.span
+                    );
+                }
+                self.check_block(body);
+            }
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
+                if let Some(value) = constant_bool(condition) {
+                    self.warn_constant_condition(condition, value);
+                }
+                match constant_bool(condition) {
+                    Some(false) => {
+                        self.warn_unreachable_branch(then_branch, "its condition is always nay");
+                    }
+                    Some(true) => {
+                        if let Some(else_branch) = else_branch {
+                            self.warn_unreachable_branch(
+                                else_branch,
+                                "its condition is always aye"
+                            );
+                        }
+                    }
+                    None => {}
+                }
+                self.check_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_block(else_branch);
+                }
+            }
+            StatementKind::WhileLoop { condition, body } => {
+                if let Some(value) = constant_bool(condition) {
+                    self.warn_constant_condition(condition, value);
+                }
+                if constant_bool(condition) == Some(false) {
+                    self.warn_unreachable_branch(body, "its condition is always nay");
+                }
+                self.check_block(body);
+            }
+            StatementKind::ForLoop { body, .. } |
+            StatementKind::MainBlock(body) |
+            StatementKind::Trial { body, .. } => self.check_block(body),
+            _ => {}
+        }
+    }
+}
+
+/// Returns true if the statements following a write to `name` in the same
+/// block overwrite it before anything could have read it. Function calls and
+/// compound statements end the search, since they may read the variable.
+fn is_overwritten_unread(name: &str, rest: &[Statement]) -> bool {
+    for statement in rest {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name: target, value: None, .. } => {
+                if **target == *name {
+                    return true;
+                }
+            }
+            StatementKind::VariableDeclaration { name: target, value: Some(value), .. } |
+            StatementKind::Assignment { name: target, value } => {
+                if expression_may_read(value, name) {
+                    return false;
+                }
+                if **target == *name {
+                    return true;
+                }
+            }
+            StatementKind::Speak(value) => {
+                if expression_may_read(value, name) {
+                    return false;
+                }
+            }
+            _ => {
+                return false;
+            }
+        }
+    }
+    false
+}
+
+/// Evaluates conditions built only from literals, `!`, and comparisons.
+pub(crate) fn constant_bool(expression: &Expression) -> Option<bool> {
+    match &expression.kind {
+        ExpressionKind::Literal(Literal::Boolean(b)) => Some(*b),
+        ExpressionKind::Unary { operator: UnaryOperator::Not, operand } =>
+            constant_bool(operand).map(|b| !b),
+        ExpressionKind::Binary { left, operator, right } => {
+            let (ExpressionKind::Literal(l), ExpressionKind::Literal(r)) = (&left.kind, &right.kind) else {
+                return None;
+            };
+            match (operator, l, r) {
+                (BinaryOperator::Equal, l, r) => Some(l == r),
+                (BinaryOperator::NotEqual, l, r) => Some(l != r),
+                (BinaryOperator::Greater, Literal::Integer(l), Literal::Integer(r)) => Some(l > r),
+                (BinaryOperator::Less, Literal::Integer(l), Literal::Integer(r)) => Some(l < r),
+                (BinaryOperator::Greater, Literal::Float(l), Literal::Float(r)) => Some(l > r),
+                (BinaryOperator::Less, Literal::Float(l), Literal::Float(r)) => Some(l < r),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Collects variables declared outside of any function body.
+fn collect_global_declarations(statements: &[Statement], globals: &mut HashSet<Symbol>) {
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, .. } => {
+                globals.insert(name.symbol.clone());
+            }
+            StatementKind::Conditional { then_branch, else_branch, .. } => {
+                collect_global_declarations(then_branch, globals);
+                if let Some(else_branch) = else_branch {
+                    collect_global_declarations(else_branch, globals);
+                }
+            }
+            StatementKind::ForLoop { body, .. } |
+            StatementKind::WhileLoop { body, .. } |
+            StatementKind::MainBlock(body) => collect_global_declarations(body, globals),
+            _ => {}
+        }
+    }
+}
+
+/// Returns true if control never continues past `statement` because every
+/// path through it returns.
+fn always_returns(statement: &Statement) -> bool {
+    let block_returns = |block: &[Statement]| block.iter().any(always_returns);
+    match &statement.kind {
+        StatementKind::Return(_) => true,
+        StatementKind::Conditional { condition, then_branch, else_branch } => {
+            match (constant_bool(condition), else_branch) {
+                (Some(true), _) => block_returns(then_branch),
+                (Some(false), Some(else_branch)) => block_returns(else_branch),
+                (None, Some(else_branch)) =>
+                    block_returns(then_branch) && block_returns(else_branch),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if evaluating `expression` may read `name`, either directly
+/// or through a function call.
+fn expression_may_read(expression: &Expression, name: &str) -> bool {
+    match &expression.kind {
+        ExpressionKind::Identifier(id) => **id == *name,
+        ExpressionKind::Binary { left, right, .. } =>
+            expression_may_read(left, name) || expression_may_read(right, name),
+        ExpressionKind::Unary { operand, .. } => expression_may_read(operand, name),
+        ExpressionKind::FunctionCall { .. } | ExpressionKind::SendRaven(_) => true,
+        ExpressionKind::AwaitRaven(raven) => expression_may_read(raven, name),
+        ExpressionKind::Ask(prompt) => expression_may_read(prompt, name),
+        ExpressionKind::Literal(_) | ExpressionKind::Input(_) => false,
+    }
+}
+
+/// Collects the names of all variables read by the given statements.
+pub(crate) fn collect_reads(statements: &[Statement], reads: &mut HashSet<Symbol>) {
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::Assignment { value, .. } |
+            StatementKind::Speak(value) => collect_expression_reads(value, reads),
+            StatementKind::VariableDeclaration { value, .. } | StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    collect_expression_reads(value, reads);
+                }
+            }
+            StatementKind::FunctionCall { arguments, .. } => {
+                for argument in arguments {
+                    collect_expression_reads(argument, reads);
+                }
+            }
+            StatementKind::FunctionDeclaration { body, .. } |
+            StatementKind::ForLoop { body, .. } |
+            StatementKind::MainBlock(body) |
+            StatementKind::Trial { body, .. } => collect_reads(body, reads),
+            StatementKind::WhileLoop { condition, body } => {
+                collect_expression_reads(condition, reads);
+                collect_reads(body, reads);
+            }
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
+                collect_expression_reads(condition, reads);
+                collect_reads(then_branch, reads);
+                if let Some(else_branch) = else_branch {
+                    collect_reads(else_branch, reads);
+                }
+            }
+        }
+    }
+}
+
+fn collect_expression_reads(expression: &Expression, reads: &mut HashSet<Symbol>) {
+    match &expression.kind {
+        ExpressionKind::Identifier(name) => {
+            reads.insert(name.symbol.clone());
+        }
+        ExpressionKind::Binary { left, right, .. } => {
+            collect_expression_reads(left, reads);
+            collect_expression_reads(right, reads);
+        }
+        ExpressionKind::Unary { operand, .. } |
+        ExpressionKind::SendRaven(operand) |
+        ExpressionKind::AwaitRaven(operand) |
+        ExpressionKind::Ask(operand) => collect_expression_reads(operand, reads),
+        ExpressionKind::FunctionCall { arguments, .. } => {
+            for argument in arguments {
+                collect_expression_reads(argument, reads);
+            }
+        }
+        ExpressionKind::Literal(_) | ExpressionKind::Input(_) => {}
+    }
+}
+
+/// Checks declared types against the types of values that can be worked
+/// out without running the program, returning an error diagnostic for each
+/// mismatch. Values whose type depends on input or function results are
+/// not checked.
+pub fn typecheck(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = TypeChecker::default();
+    checker.check_block(&program.statements);
+    checker.diagnostics
+}
+
+#[derive(Default)]
+struct TypeChecker {
+    declared: HashMap<Symbol, DataType>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl TypeChecker {
+    fn check_block(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.check_statement(statement);
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, data_type, value } => {
+                if let Some(value) = value {
+                    self.check_value(data_type, value);
+                }
+                self.declared.insert(name.symbol.clone(), data_type.clone());
+            }
+            StatementKind::Assignment { name, value } => {
+                if let Some(data_type) = self.declared.get(&**name).cloned() {
+                    self.check_value(&data_type, value);
+                }
+            }
+            StatementKind::Conditional { then_branch, else_branch, .. } => {
+                self.check_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_block(else_branch);
+                }
+            }
+            StatementKind::FunctionDeclaration { body, .. } |
+            StatementKind::ForLoop { body, .. } |
+            StatementKind::WhileLoop { body, .. } |
+            StatementKind::MainBlock(body) |
+            StatementKind::Trial { body, .. } => self.check_block(body),
+            _ => {}
+        }
+    }
+
+    fn check_value(&mut self, expected: &DataType, value: &Expression) {
+        if let Some(found) = self.infer(value) {
+            if &found != expected {
+                self.diagnostics.push(Diagnostic {
+                    code: "MV0103",
+                    severity: Severity::Error,
+                    message: format!("type mismatch: expected {}, found {}", expected, found),
+                    span: Some(value.span),
+                    notes: Vec::new(),
+                    hint: Some(
+                        crate::diagnostic::type_mismatch_hint(&expected.to_string(), &found.to_string())
+                    ),
+                });
+            }
+        }
+    }
+
+    /// Infers the type of an expression where it doesn't depend on runtime
+    /// values.
+    fn infer(&self, expression: &Expression) -> Option<DataType> {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) =>
+                Some(match literal {
+                    Literal::String(_) => DataType::Scroll,
+                    Literal::Integer(_) => DataType::Blade,
+                    Literal::Float(_) => DataType::Wine,
+                    Literal::Boolean(_) => DataType::Vow,
+                    Literal::Char(_) => DataType::Sigil,
+                }),
+            ExpressionKind::Identifier(name) => self.declared.get(&**name).cloned(),
+            ExpressionKind::Unary { operator: UnaryOperator::Not, .. } => Some(DataType::Vow),
+            ExpressionKind::Unary { operator: UnaryOperator::Minus, operand } => self.infer(operand),
+            ExpressionKind::Binary { left, operator, right } => {
+                use BinaryOperator::*;
+                match operator {
+                    Greater | Less | Equal | NotEqual => Some(DataType::Vow),
+                    Add | Subtract | Multiply | Divide => {
+                        match (self.infer(left)?, self.infer(right)?) {
+                            (DataType::Blade, DataType::Blade) => Some(DataType::Blade),
+                            (DataType::Wine, DataType::Wine | DataType::Blade) |
+                            (DataType::Blade, DataType::Wine) => Some(DataType::Wine),
+                            (DataType::Scroll, DataType::Scroll) if *operator == Add =>
+                                Some(DataType::Scroll),
+                            _ => None,
+                        }
+                    }
+                }
+            }
+            ExpressionKind::Input(_) | ExpressionKind::Ask(_) => Some(DataType::Scroll),
+            ExpressionKind::SendRaven(_) => Some(DataType::Raven),
+            ExpressionKind::FunctionCall { .. } | ExpressionKind::AwaitRaven(_) => None,
+        }
+    }
+}
@@ -1,29 +1,175 @@
+use std::any::Any;
 use std::fmt;
+use std::sync::Arc;
+use crate::edition::Edition;
+use crate::interpreter::{ Missive, Raven, Rookery, Socket, Tidings, Vault };
 
-#[derive(Debug, Clone, PartialEq)]
+/// A parsed program. With the `serde` feature, programs (and values) can be
+/// serialized, e.g. to cache a parse:
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// use mid_valyrian::{ parse_program, Program };
+///
+/// let program = parse_program("on the iron throne:\n    speak \"Winter is coming\"\n").unwrap();
+/// let json = serde_json::to_string(&program).unwrap();
+/// let restored: Program = serde_json::from_str(&json).unwrap();
+/// assert_eq!(restored, program);
+/// # }
+/// ```
+///
+/// The tree is owned, with a `Box` or `Vec` per node, rather than kept in
+/// an arena. Every pass, visitor, and embedder walks these types directly,
+/// and the `serde` form is the tree itself, so an arena would change all
+/// of them. Names and strings are already interned (see `Symbol`), the
+/// interpreter shares function bodies instead of copying them, and large
+/// programs that want a flat representation can be compiled to bytecode
+/// (see `compile`).
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Program {
     pub statements: Vec<Statement>,
+    /// The edition the source was written in, from its `canon` line.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub edition: Edition,
+}
+
+/// A region of source text, as byte offsets plus the 1-based line and column
+/// of its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Self {
+        Self { start, end, line, col }
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(self);
+        }
+        Span { end: self.end.max(other.end), ..self }
+    }
+}
+
+/// A shared piece of text, for names and strings. Cloning one bumps a
+/// count instead of copying the text; the parser interns identifiers and
+/// string literals (see `Interner`), so a program holds each only once.
+pub type Symbol = Arc<str>;
+
+/// A variable's name where the code reads, declares, or assigns it. Before
+/// the tree-walking interpreter runs a function body or a run's top-level
+/// statements, it gives each name there the slot of the frame that holds
+/// the variable. Names compare, print, and serialize as their text alone.
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// use mid_valyrian::Name;
+///
+/// assert_eq!(serde_json::to_string(&Name::from("gold")).unwrap(), "\"gold\"");
+/// # }
+/// ```
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Name {
+    pub symbol: Symbol,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) slot: Option<u32>,
+}
+
+impl From<Symbol> for Name {
+    fn from(symbol: Symbol) -> Self {
+        Self { symbol, slot: None }
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Symbol::from(name).into()
+    }
+}
+
+impl std::ops::Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.symbol
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.symbol == other.symbol
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.symbol, f)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.symbol)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Name {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        arbitrary_name(u).map(Name::from)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
 
-pub enum Statement {
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum StatementKind {
     VariableDeclaration {
-        name: String,
+        name: Name,
         data_type: DataType,
-        value: Expression,
+        /// `None` when the declaration has no `with`, so the variable
+        /// starts at `DataType::default_value`.
+        value: Option<Expression>,
     },
     FunctionDeclaration {
-        name: String,
-        parameters: Vec<String>,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_name))]
+        name: Symbol,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_names))]
+        parameters: Vec<Symbol>,
         body: Vec<Statement>,
     },
     FunctionCall {
-        name: String,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_name))]
+        name: Symbol,
         arguments: Vec<Expression>,
     },
     Assignment {
-        name: String,
+        name: Name,
         value: Expression,
     },
     Conditional {
@@ -42,23 +188,52 @@ pub enum Statement {
     Return(Option<Expression>),
     Speak(Expression),
     MainBlock(Vec<Statement>),
+    /// A named test, run only by the test runner.
+    Trial {
+        name: String,
+        body: Vec<Statement>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum DataType {
     Scroll,  // String
     Blade,   // i64
     Wine,    // f64
     Vow,     // bool
     Sigil,   // char
+    Raven,   // A call running on its own thread
+    Rookery, // A channel ravens pass values through
+    Vault,   // A value ravens take turns with
+    Tidings, // How a program run by `command` went
+    Missive, // A server's answer to `fetch` or `post`
+    Socket,  // A TCP connection or listener
     Void,    // No return
 }
 
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expression {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ExpressionKind {
     Literal(Literal),
-    Identifier(String),
+    Identifier(Name),
     Binary {
         left: Box<Expression>,
         operator: BinaryOperator,
@@ -69,15 +244,23 @@ pub enum Expression {
         operand: Box<Expression>,
     },
     Input(String),
+    /// `ask with prompt`: input read after showing `prompt`.
+    Ask(Box<Expression>),
     FunctionCall {
-        name: String,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_name))]
+        name: Symbol,
         arguments: Vec<Expression>,
     },
+    /// `send_raven` of a call, always a `FunctionCall`.
+    SendRaven(#[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_call))] Box<Expression>),
+    AwaitRaven(Box<Expression>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Literal {
-    String(String),
+    String(Symbol),
     Integer(i64),
     Float(f64),
     Boolean(bool),
@@ -85,6 +268,8 @@ pub enum Literal {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -97,25 +282,162 @@ pub enum BinaryOperator {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum UnaryOperator {
     Minus,
     Not,
 }
 
+/// The names generated programs use. There are few, so that what a
+/// program declares is likely to be used; the builtins among them are
+/// ones that don't touch the outside world.
+#[cfg(feature = "arbitrary")]
+const ARBITRARY_NAMES: &[&str] = &[
+    "dragon",
+    "gold",
+    "winter",
+    "raven",
+    "assert",
+    "format_wine",
+    "from_base64",
+];
+
+#[cfg(feature = "arbitrary")]
+fn arbitrary_name(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Symbol> {
+    u.choose(ARBITRARY_NAMES).map(|&name| name.into())
+}
+
+#[cfg(feature = "arbitrary")]
+fn arbitrary_names(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Vec<Symbol>> {
+    let count = u.int_in_range(0..=3)?;
+    (0..count).map(|_| arbitrary_name(u)).collect()
+}
+
+/// A call for `SendRaven`, which holds nothing else.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_call(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Box<Expression>> {
+    let kind = ExpressionKind::FunctionCall { name: arbitrary_name(u)?, arguments: u.arbitrary()? };
+    Ok(Box::new(Expression::new(kind, u.arbitrary()?)))
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ControlFlow {
     Return(Value),
+    /// A `return` of a call to a declared function, with its arguments
+    /// evaluated. The caller's frame is reused to run it.
+    TailCall {
+        function: Symbol,
+        arguments: Vec<Value>,
+        call_site: Span,
+    },
 }
 
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
-    String(String),
+    String(Symbol),
     Integer(i64),
     Float(f64),
     Boolean(bool),
     Char(char),
     Void,
+    /// An opaque host object handed out by a native function; see
+    /// `ExternalValue`. Externals cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    External(ExternalValue),
+    /// A call sent off with `send_raven`; see `Raven`. Ravens cannot be
+    /// serialized either.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Raven(Raven),
+    /// A channel made by the `rookery` builtin; see `Rookery`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Rookery(Rookery),
+    /// A shared value made by the `vault` builtin; see `Vault`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Vault(Vault),
+    /// What the `command` builtin reports; see `Tidings`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Tidings(Tidings),
+    /// What the `fetch` and `post` builtins bring back; see `Missive`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Missive(Missive),
+    /// A connection made by `connect`, `listen`, or `accept`; see
+    /// `Socket`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Socket(Socket),
+}
+
+impl Value {
+    /// Wraps a host object so native functions can pass it to scripts.
+    pub fn external<T: Any + Send + Sync>(value: T) -> Self {
+        Value::External(ExternalValue::new(value))
+    }
+
+    /// Returns the host object inside an `External` value, if it is a `T`.
+    pub fn as_external<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::External(external) => external.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// A Rust object (a file handle, a game entity) that scripts can hold and
+/// pass back to native functions, but cannot look inside.
+///
+/// Clones share the object, and two externals are equal only when they
+/// are the same object.
+///
+/// ```
+/// use mid_valyrian::{ Interpreter, Value, ValyrianError };
+///
+/// struct Dragon { name: &'static str }
+///
+/// let mut interpreter = Interpreter::new(false);
+/// interpreter.register_native("hatch", |_| Ok(Value::external(Dragon { name: "Drogon" })));
+/// interpreter.register_native("dragon_name", |args| {
+///     match args.first().and_then(Value::as_external::<Dragon>) {
+///         Some(dragon) => Ok(Value::String(dragon.name.into())),
+///         None => Err(ValyrianError::RuntimeError("expected a dragon".into())),
+///     }
+/// });
+///
+/// let name = interpreter.eval_expression("dragon_name with (hatch with)").unwrap();
+/// assert_eq!(name, Value::String("Drogon".into()));
+/// ```
+#[derive(Clone)]
+pub struct ExternalValue {
+    object: Arc<dyn Any + Send + Sync>,
+    type_name: &'static str,
+}
+
+impl ExternalValue {
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self { object: Arc::new(value), type_name: std::any::type_name::<T>() }
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.object.downcast_ref()
+    }
+
+    /// The Rust type of the object, for messages.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl fmt::Debug for ExternalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ExternalValue({})", self.type_name)
+    }
+}
+
+impl PartialEq for ExternalValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.object, &other.object)
+    }
 }
 
 impl fmt::Display for Value {
@@ -127,11 +449,53 @@ impl fmt::Display for Value {
             Value::Boolean(b) => write!(f, "{}", if *b { "aye" } else { "nay" }),
             Value::Char(c) => write!(f, "{}", c),
             Value::Void => write!(f, "void"),
+            Value::External(external) => write!(f, "<{}>", external.type_name()),
+            Value::Raven(_) => write!(f, "<raven>"),
+            Value::Rookery(_) => write!(f, "<rookery>"),
+            Value::Vault(vault) => write!(f, "{}", vault),
+            Value::Tidings(tidings) => write!(f, "{}", tidings),
+            Value::Missive(missive) => write!(f, "{}", missive),
+            Value::Socket(_) => write!(f, "<socket>"),
+        }
+    }
+}
+
+/// Shows a value held inside another the way a program would write it,
+/// so that in `vault("7")` the scroll can be told from the blade 7.
+pub(crate) struct Nested<'v>(pub(crate) &'v Value);
+
+impl fmt::Display for Nested<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Value::String(s) => write!(f, "\"{}\"", s.escape_debug()),
+            Value::Char(c) => write!(f, "'{}'", c.escape_debug()),
+            value => write!(f, "{}", value),
         }
     }
 }
 
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DataType::Scroll => "scroll",
+            DataType::Blade => "blade",
+            DataType::Wine => "wine",
+            DataType::Vow => "vow",
+            DataType::Sigil => "sigil",
+            DataType::Raven => "raven",
+            DataType::Rookery => "rookery",
+            DataType::Vault => "vault",
+            DataType::Tidings => "tidings",
+            DataType::Missive => "missive",
+            DataType::Socket => "socket",
+            DataType::Void => "void",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl DataType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "scroll" => Some(DataType::Scroll),
@@ -139,13 +503,59 @@ impl DataType {
             "wine" => Some(DataType::Wine),
             "vow" => Some(DataType::Vow),
             "sigil" => Some(DataType::Sigil),
+            "raven" => Some(DataType::Raven),
+            "rookery" => Some(DataType::Rookery),
+            "vault" => Some(DataType::Vault),
+            "tidings" => Some(DataType::Tidings),
+            "missive" => Some(DataType::Missive),
+            "socket" => Some(DataType::Socket),
             "void" => Some(DataType::Void),
             _ => None,
         }
     }
+
+    /// The value a variable declared without `with` starts at: 0, 0.0,
+    /// `""`, nay, the NUL sigil, or void.
+    ///
+    /// Ravens, rookeries, vaults, tidings, missives, and sockets only come
+    /// from the builtins that make them, so they have no empty value (see
+    /// `has_default_value`); they give void, which the parser never lets a
+    /// declaration start at.
+    ///
+    /// ```
+    /// use mid_valyrian::{ DataType, Value };
+    ///
+    /// assert_eq!(DataType::Blade.default_value(), Value::Integer(0));
+    /// assert_eq!(DataType::Vow.default_value(), Value::Boolean(false));
+    /// assert_eq!(DataType::Sigil.default_value(), Value::Char('\0'));
+    /// ```
+    pub fn default_value(&self) -> Value {
+        match self {
+            DataType::Scroll => Value::String("".into()),
+            DataType::Blade => Value::Integer(0),
+            DataType::Wine => Value::Float(0.0),
+            DataType::Vow => Value::Boolean(false),
+            DataType::Sigil => Value::Char('\0'),
+            _ => Value::Void,
+        }
+    }
+
+    /// Whether a variable of this type can be declared without `with`.
+    pub fn has_default_value(&self) -> bool {
+        matches!(
+            self,
+            DataType::Scroll |
+                DataType::Blade |
+                DataType::Wine |
+                DataType::Vow |
+                DataType::Sigil |
+                DataType::Void
+        )
+    }
 }
 
 impl BinaryOperator {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "+" => Some(BinaryOperator::Add),
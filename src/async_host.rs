@@ -0,0 +1,266 @@
+//! 🦅 Running programs from async code.
+//!
+//! `Interpreter::interpret_async` lets an async host (a Tokio server, a chat
+//! bot) run a program without blocking its executor: the program runs on a
+//! thread of its own, and every time it speaks, asks for input, or sleeps,
+//! the request is handed back to the awaiting task, which answers it with
+//! the host's `AsyncHost` callbacks.
+//!
+//! No particular runtime is required; the futures involved only rely on
+//! `std::task` wakers.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{ self, BufRead, Read, Write };
+use std::pin::Pin;
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::task::{ Context, Poll, Waker };
+use std::time::Duration;
+use crate::ast::{ Program, Value };
+use crate::error::ValyrianError;
+use crate::interpreter::{ HostIo, Interpreter, PROGRAM_STACK_SIZE };
+
+/// A boxed future returned by `AsyncHost` callbacks.
+pub type HostFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The async side of a program's input and output.
+pub trait AsyncHost: Send {
+    /// Shows text the program speaks (including input prompts).
+    fn write(&mut self, text: String) -> HostFuture<'_, io::Result<()>>;
+
+    /// Waits for one line of input, without its line ending.
+    fn read_line(&mut self) -> HostFuture<'_, io::Result<String>>;
+
+    /// Waits for the program's `sleep` builtin.
+    fn sleep(&mut self, duration: Duration) -> HostFuture<'_, ()>;
+}
+
+/// What the program thread needs from the host.
+enum Request {
+    Write(String),
+    ReadLine,
+    Sleep(Duration),
+    Done(Box<Interpreter>, Result<Value, ValyrianError>),
+}
+
+/// Requests waiting for the async side, plus the task to wake for them.
+#[derive(Default)]
+struct Queue {
+    requests: VecDeque<Request>,
+    waker: Option<Waker>,
+}
+
+#[derive(Clone)]
+struct Bridge {
+    queue: Arc<Mutex<Queue>>,
+    responses: Arc<Mutex<Receiver<io::Result<String>>>>,
+}
+
+impl Bridge {
+    fn push(&self, request: Request) {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.requests.push_back(request);
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Sends a request and blocks the program thread until it is answered.
+    fn ask(&self, request: Request) -> io::Result<String> {
+        self.push(request);
+        self.responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recv()
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "the host stopped listening"))
+            })
+    }
+}
+
+/// `speak` output, forwarded to the host a line at a time.
+struct BridgeWriter {
+    bridge: Bridge,
+    pending: Vec<u8>,
+}
+
+impl Write for BridgeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        self.bridge.ask(Request::Write(text)).map(|_| ())
+    }
+}
+
+/// Input, requested from the host a line at a time.
+struct BridgeReader {
+    bridge: Bridge,
+    line: Vec<u8>,
+    position: usize,
+}
+
+impl Read for BridgeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for BridgeReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position >= self.line.len() {
+            let mut line = self.bridge.ask(Request::ReadLine)?;
+            line.push('\n');
+            self.line = line.into_bytes();
+            self.position = 0;
+        }
+        Ok(&self.line[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
+
+/// Resolves to the next request from the program thread.
+struct NextRequest<'a> {
+    queue: &'a Mutex<Queue>,
+}
+
+impl Future for NextRequest<'_> {
+    type Output = Request;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Request> {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        match queue.requests.pop_front() {
+            Some(request) => Poll::Ready(request),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Interpreter {
+    /// Runs a program like `interpret_with_result`, driving its output,
+    /// input, and sleeps through `host` so the calling task never blocks.
+    ///
+    /// The interpreter's own writer, reader, and sleeper are replaced for
+    /// the run and put back afterwards. If the returned future is dropped
+    /// early, the program is stopped at its next output, input, or sleep,
+    /// and `self` is left as a fresh interpreter.
+    ///
+    /// ```
+    /// use std::io;
+    /// use std::time::Duration;
+    /// use mid_valyrian::{ parse_program, AsyncHost, HostFuture, Interpreter, Value };
+    ///
+    /// struct Chat { said: Vec<String> }
+    ///
+    /// impl AsyncHost for Chat {
+    ///     fn write(&mut self, text: String) -> HostFuture<'_, io::Result<()>> {
+    ///         self.said.push(text);
+    ///         Box::pin(async { Ok(()) })
+    ///     }
+    ///     fn read_line(&mut self) -> HostFuture<'_, io::Result<String>> {
+    ///         Box::pin(async { Ok("Hodor".to_string()) })
+    ///     }
+    ///     fn sleep(&mut self, _duration: Duration) -> HostFuture<'_, ()> {
+    ///         Box::pin(async {})
+    ///     }
+    /// }
+    ///
+    /// # fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #     use std::task::{ Context, Poll, Wake };
+    /// #     struct Thread(std::thread::Thread);
+    /// #     impl Wake for Thread { fn wake(self: std::sync::Arc<Self>) { self.0.unpark() } }
+    /// #     let waker = std::sync::Arc::new(Thread(std::thread::current())).into();
+    /// #     let mut cx = Context::from_waker(&waker);
+    /// #     let mut future = std::pin::pin!(future);
+    /// #     loop {
+    /// #         match future.as_mut().poll(&mut cx) {
+    /// #             Poll::Ready(output) => return output,
+    /// #             Poll::Pending => std::thread::park(),
+    /// #         }
+    /// #     }
+    /// # }
+    /// let source = "on the iron throne:\n    name is a scroll with guest speaks for input\n    speak \"Hello, \" + name\n";
+    /// let program = parse_program(source).unwrap();
+    /// let mut chat = Chat { said: Vec::new() };
+    /// let mut interpreter = Interpreter::new(false);
+    /// block_on(interpreter.interpret_async(&program, &mut chat)).unwrap();
+    /// assert_eq!(chat.said.last().unwrap(), "Hello, Hodor\n");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError` if the program fails, a host callback reports
+    /// an I/O error, or the program thread cannot be started.
+    pub async fn interpret_async<H: AsyncHost>(
+        &mut self,
+        program: &Program,
+        host: &mut H
+    ) -> Result<Value, ValyrianError> {
+        let (respond, responses): (Sender<io::Result<String>>, _) = mpsc::channel();
+        let bridge = Bridge {
+            queue: Arc::new(Mutex::new(Queue::default())),
+            responses: Arc::new(Mutex::new(responses)),
+        };
+
+        let mut interpreter = std::mem::replace(self, Interpreter::new(false));
+        let sleeper = bridge.clone();
+        let host_io = interpreter.swap_io(HostIo {
+            output: Box::new(BridgeWriter { bridge: bridge.clone(), pending: Vec::new() }),
+            input: Box::new(BridgeReader { bridge: bridge.clone(), line: Vec::new(), position: 0 }),
+            sleeper: Some(Box::new(move |duration| sleeper.ask(Request::Sleep(duration)).map(|_| ()))),
+        });
+
+        let program = program.clone();
+        let worker = bridge.clone();
+        std::thread::Builder
+            ::new()
+            .name("mid-valyrian-async".into())
+            .stack_size(PROGRAM_STACK_SIZE)
+            .spawn(move || {
+                // The run flushes what it spoke before returning
+                let result = interpreter.interpret_with_result(&program);
+                interpreter.swap_io(host_io);
+                worker.push(Request::Done(Box::new(interpreter), result));
+            })
+            .map_err(|e| ValyrianError::io("Failed to start the program thread", e))?;
+
+        loop {
+            let request = (NextRequest { queue: &bridge.queue }).await;
+            let response = match request {
+                Request::Write(text) => host.write(text).await.map(|()| String::new()),
+                Request::ReadLine => host.read_line().await,
+                Request::Sleep(duration) => {
+                    host.sleep(duration).await;
+                    Ok(String::new())
+                }
+                Request::Done(interpreter, result) => {
+                    *self = *interpreter;
+                    return result;
+                }
+            };
+            // The program thread only stops listening once it is done
+            let _ = respond.send(response);
+        }
+    }
+}
@@ -0,0 +1,933 @@
+//! Functions provided by the realm itself rather than declared by the council.
+//!
+//! Builtins are called with the same `name with args` syntax as user
+//! functions. A function declared in the program shadows a builtin of the
+//! same name.
+//!
+//! `BUILTINS` describes every builtin (its parameters, return type, and
+//! documentation) and is what calls are dispatched through, so tools that
+//! list builtins can't drift from what the interpreter provides:
+//!
+//! ```
+//! use mid_valyrian::builtins;
+//!
+//! let assert = builtins::lookup("assert").unwrap();
+//! assert_eq!(assert.signature(), "assert with condition[, message]");
+//! assert_eq!(assert.arity(), 1..=2);
+//! ```
+
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::Duration;
+use crate::ast::{ DataType, Value };
+use crate::error::ValyrianError;
+use crate::interpreter::{ Interpreter, Missive, Rookery, Socket, Tidings, Vault };
+
+type BuiltinFn = fn(&mut Interpreter, &[Value]) -> Result<Value, ValyrianError>;
+
+/// Everything known about one builtin, for completion, documentation, and
+/// editor tooling.
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub parameters: &'static [Parameter],
+    /// What the builtin hands back, or `None` if it could be any value.
+    pub returns: Option<DataType>,
+    pub doc: &'static str,
+    /// Whether it reaches outside the interpreter (files, processes, the
+    /// network); such builtins are refused in sandbox mode.
+    pub io: bool,
+    function: BuiltinFn,
+}
+
+/// A builtin's parameter.
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: &'static str,
+    /// The type the argument must have, or `None` for any value.
+    pub data_type: Option<DataType>,
+    /// Optional parameters may be left off the end of a call.
+    pub optional: bool,
+}
+
+impl Builtin {
+    /// How many arguments a call may pass.
+    pub fn arity(&self) -> RangeInclusive<usize> {
+        let required = self.parameters
+            .iter()
+            .filter(|parameter| !parameter.optional)
+            .count();
+        required..=self.parameters.len()
+    }
+
+    /// The builtin as it is called, e.g. `assert with condition[, message]`.
+    pub fn signature(&self) -> String {
+        let mut signature = format!("{} with", self.name);
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            if parameter.optional {
+                signature.push_str(&format!("[{}{}]", separator, parameter.name));
+            } else {
+                signature.push_str(separator);
+                signature.push_str(parameter.name);
+            }
+        }
+        signature
+    }
+}
+
+const fn required(name: &'static str, data_type: Option<DataType>) -> Parameter {
+    Parameter { name, data_type, optional: false }
+}
+
+const fn optional(name: &'static str, data_type: Option<DataType>) -> Parameter {
+    Parameter { name, data_type, optional: true }
+}
+
+/// Every builtin function, in alphabetical order.
+///
+/// ```
+/// use mid_valyrian::builtins::BUILTINS;
+///
+/// assert!(BUILTINS.windows(2).all(|pair| pair[0].name < pair[1].name));
+/// ```
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "accept",
+        parameters: &[required("listener", Some(DataType::Socket))],
+        returns: Some(DataType::Socket),
+        doc: "Waits for the next connection to a listening socket.",
+        io: true,
+        function: accept,
+    },
+    Builtin {
+        name: "after",
+        parameters: &[required("ms", Some(DataType::Blade)), required("spell", Some(DataType::Scroll))],
+        returns: Some(DataType::Void),
+        doc: "Casts the function named spell once, ms milliseconds from now, at a run_pending.",
+        io: false,
+        function: after,
+    },
+    Builtin {
+        name: "arg",
+        parameters: &[required("n", Some(DataType::Blade))],
+        returns: Some(DataType::Scroll),
+        doc: "The script argument at index n, counting from zero.",
+        io: false,
+        function: arg,
+    },
+    Builtin {
+        name: "arg_count",
+        parameters: &[],
+        returns: Some(DataType::Blade),
+        doc: "The number of arguments passed to the script.",
+        io: false,
+        function: arg_count,
+    },
+    Builtin {
+        name: "ask_until_blade",
+        parameters: &[
+            required("prompt", Some(DataType::Scroll)),
+            required("min", Some(DataType::Blade)),
+            required("max", Some(DataType::Blade)),
+        ],
+        returns: Some(DataType::Blade),
+        doc: "Asks with prompt until the answer is a blade from min to max, and gives it.",
+        io: true,
+        function: ask_until_blade,
+    },
+    Builtin {
+        name: "assert",
+        parameters: &[required("condition", Some(DataType::Vow)), optional("message", None)],
+        returns: Some(DataType::Void),
+        doc: "Fails unless condition is aye, with message if one is given.",
+        io: false,
+        function: assert,
+    },
+    Builtin {
+        name: "assert_equal",
+        parameters: &[required("found", None), required("expected", None)],
+        returns: Some(DataType::Void),
+        doc: "Fails unless the two values are equal.",
+        io: false,
+        function: assert_equal,
+    },
+    Builtin {
+        name: "body",
+        parameters: &[required("missive", Some(DataType::Missive))],
+        returns: Some(DataType::Scroll),
+        doc: "The text of a server's answer to fetch or post.",
+        io: false,
+        function: body,
+    },
+    Builtin {
+        name: "claim",
+        parameters: &[required("vault", Some(DataType::Vault))],
+        returns: None,
+        doc: "Takes the value out of the vault, waiting while another raven holds it.",
+        io: false,
+        function: claim,
+    },
+    Builtin {
+        name: "command",
+        parameters: &[required("program", Some(DataType::Scroll)), optional("arguments", Some(DataType::Scroll))],
+        returns: Some(DataType::Tidings),
+        doc: "Runs program with arguments split at whitespace, if commands are allowed, and waits for it.",
+        io: true,
+        function: command,
+    },
+    Builtin {
+        name: "connect",
+        parameters: &[required("host", Some(DataType::Scroll)), required("port", Some(DataType::Blade))],
+        returns: Some(DataType::Socket),
+        doc: "Opens a TCP connection to host on port.",
+        io: true,
+        function: connect,
+    },
+    Builtin {
+        name: "dispatch",
+        parameters: &[required("rookery", Some(DataType::Rookery)), required("value", None)],
+        returns: Some(DataType::Void),
+        doc: "Leaves value in the rookery for a receive.",
+        io: false,
+        function: dispatch,
+    },
+    Builtin {
+        name: "every",
+        parameters: &[required("ms", Some(DataType::Blade)), required("spell", Some(DataType::Scroll))],
+        returns: Some(DataType::Void),
+        doc: "Casts the function named spell every ms milliseconds, at each run_pending.",
+        io: false,
+        function: every,
+    },
+    Builtin {
+        name: "exit_code",
+        parameters: &[required("tidings", Some(DataType::Tidings))],
+        returns: Some(DataType::Blade),
+        doc: "The exit code of the program run by command, or -1 if a signal ended it.",
+        io: false,
+        function: exit_code,
+    },
+    Builtin {
+        name: "fetch",
+        parameters: &[required("url", Some(DataType::Scroll))],
+        returns: Some(DataType::Missive),
+        doc: "Sends a GET request to url and waits for the answer (needs the http feature).",
+        io: true,
+        function: fetch,
+    },
+    Builtin {
+        name: "format_wine",
+        parameters: &[
+            required("value", Some(DataType::Wine)),
+            required("decimals", Some(DataType::Blade)),
+        ],
+        returns: Some(DataType::Scroll),
+        doc: "Gives value as a scroll, rounded to decimals decimal places.",
+        io: false,
+        function: format_wine,
+    },
+    Builtin {
+        name: "from_base64",
+        parameters: &[required("text", Some(DataType::Scroll))],
+        returns: Some(DataType::Scroll),
+        doc: "Decodes base64 text back into the scroll it encodes.",
+        io: false,
+        function: from_base64,
+    },
+    Builtin {
+        name: "is_dir",
+        parameters: &[required("path", Some(DataType::Scroll))],
+        returns: Some(DataType::Vow),
+        doc: "Whether path is a folder.",
+        io: true,
+        function: is_dir,
+    },
+    Builtin {
+        name: "list_scrolls",
+        parameters: &[required("dir", Some(DataType::Scroll))],
+        returns: Some(DataType::Scroll),
+        doc: "The names of everything in the folder dir, sorted, one per line.",
+        io: true,
+        function: list_scrolls,
+    },
+    Builtin {
+        name: "listen",
+        parameters: &[required("port", Some(DataType::Blade)), optional("host", Some(DataType::Scroll))],
+        returns: Some(DataType::Socket),
+        doc: "Listens for TCP connections on port, at host if given (127.0.0.1 if not).",
+        io: true,
+        function: listen,
+    },
+    Builtin {
+        name: "path_exists",
+        parameters: &[required("path", Some(DataType::Scroll))],
+        returns: Some(DataType::Vow),
+        doc: "Whether a file or folder exists at path.",
+        io: true,
+        function: path_exists,
+    },
+    Builtin {
+        name: "path_filename",
+        parameters: &[required("path", Some(DataType::Scroll))],
+        returns: Some(DataType::Scroll),
+        doc: "The last part of path, or an empty scroll if it has none.",
+        io: false,
+        function: path_filename,
+    },
+    Builtin {
+        name: "path_join",
+        parameters: &[required("base", Some(DataType::Scroll)), required("part", Some(DataType::Scroll))],
+        returns: Some(DataType::Scroll),
+        doc: "base and part joined with this system's separator; an absolute part replaces base.",
+        io: false,
+        function: path_join,
+    },
+    Builtin {
+        name: "path_parent",
+        parameters: &[required("path", Some(DataType::Scroll))],
+        returns: Some(DataType::Scroll),
+        doc: "path without its last part, or an empty scroll if it has no parent.",
+        io: false,
+        function: path_parent,
+    },
+    Builtin {
+        name: "port",
+        parameters: &[required("socket", Some(DataType::Socket))],
+        returns: Some(DataType::Blade),
+        doc: "The port this end of the socket is on.",
+        io: false,
+        function: port,
+    },
+    Builtin {
+        name: "post",
+        parameters: &[required("url", Some(DataType::Scroll)), required("body", Some(DataType::Scroll))],
+        returns: Some(DataType::Missive),
+        doc: "Sends body to url in a POST request and waits for the answer (needs the http feature).",
+        io: true,
+        function: post,
+    },
+    Builtin {
+        name: "receive",
+        parameters: &[required("rookery", Some(DataType::Rookery))],
+        returns: None,
+        doc: "Takes the oldest value out of the rookery, waiting for one if it is empty.",
+        io: false,
+        function: receive,
+    },
+    Builtin {
+        name: "recv",
+        parameters: &[required("socket", Some(DataType::Socket))],
+        returns: None,
+        doc: "Reads the next line from a connection, or void once it has closed.",
+        io: true,
+        function: recv,
+    },
+    Builtin {
+        name: "release",
+        parameters: &[required("vault", Some(DataType::Vault)), optional("value", None)],
+        returns: Some(DataType::Void),
+        doc: "Puts value, if given, back in the claimed vault and lets the next raven claim it.",
+        io: false,
+        function: release,
+    },
+    Builtin {
+        name: "rookery",
+        parameters: &[],
+        returns: Some(DataType::Rookery),
+        doc: "A new, empty channel for passing values between ravens.",
+        io: false,
+        function: rookery,
+    },
+    Builtin {
+        name: "run_pending",
+        parameters: &[],
+        returns: Some(DataType::Blade),
+        doc: "Casts every spell set by after and every that has come due, giving how many.",
+        io: false,
+        function: run_pending,
+    },
+    Builtin {
+        name: "scroll_size",
+        parameters: &[required("path", Some(DataType::Scroll))],
+        returns: Some(DataType::Blade),
+        doc: "The size in bytes of the file at path.",
+        io: true,
+        function: scroll_size,
+    },
+    Builtin {
+        name: "send",
+        parameters: &[required("socket", Some(DataType::Socket)), required("text", Some(DataType::Scroll))],
+        returns: Some(DataType::Void),
+        doc: "Writes text and a line break to a connection.",
+        io: true,
+        function: send,
+    },
+    Builtin {
+        name: "sleep",
+        parameters: &[required("ms", Some(DataType::Blade))],
+        returns: Some(DataType::Void),
+        doc: "Pauses the program for ms milliseconds.",
+        io: false,
+        function: sleep,
+    },
+    Builtin {
+        name: "status",
+        parameters: &[required("missive", Some(DataType::Missive))],
+        returns: Some(DataType::Blade),
+        doc: "The HTTP status code of a server's answer to fetch or post.",
+        io: false,
+        function: status,
+    },
+    Builtin {
+        name: "stderr",
+        parameters: &[required("tidings", Some(DataType::Tidings))],
+        returns: Some(DataType::Scroll),
+        doc: "What the program run by command wrote to its standard error.",
+        io: false,
+        function: stderr,
+    },
+    Builtin {
+        name: "stdout",
+        parameters: &[required("tidings", Some(DataType::Tidings))],
+        returns: Some(DataType::Scroll),
+        doc: "What the program run by command wrote to its standard output.",
+        io: false,
+        function: stdout,
+    },
+    Builtin {
+        name: "to_base64",
+        parameters: &[required("text", Some(DataType::Scroll))],
+        returns: Some(DataType::Scroll),
+        doc: "Encodes text as base64.",
+        io: false,
+        function: to_base64,
+    },
+    Builtin {
+        name: "vault",
+        parameters: &[required("value", None)],
+        returns: Some(DataType::Vault),
+        doc: "A new vault holding value, for ravens to share.",
+        io: false,
+        function: vault,
+    },
+];
+
+/// Looks up the builtin called `name`.
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Returns true if `name` is a builtin function.
+pub fn is_builtin(name: &str) -> bool {
+    lookup(name).is_some()
+}
+
+/// Calls the builtin `name`, or returns `None` if there is no such builtin.
+pub(crate) fn call(
+    interpreter: &mut Interpreter,
+    name: &str,
+    args: &[Value]
+) -> Option<Result<Value, ValyrianError>> {
+    let builtin = lookup(name)?;
+    if builtin.io && interpreter.is_sandboxed() {
+        return Some(
+            Err(ValyrianError::SandboxViolation(format!("'{}' is not allowed in the sandbox", name)))
+        );
+    }
+    if !builtin.arity().contains(&args.len()) {
+        return Some(Err(ValyrianError::ArgumentMismatch));
+    }
+    Some((builtin.function)(interpreter, args))
+}
+
+/// `arg_count with` — the number of arguments passed to the script.
+fn arg_count(interpreter: &mut Interpreter, _args: &[Value]) -> Result<Value, ValyrianError> {
+    Ok(Value::Integer(interpreter.script_args().len() as i64))
+}
+
+/// `arg with n` — the script argument at index `n`, counting from zero.
+fn arg(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match &args[0] {
+        Value::Integer(index) => {
+            usize::try_from(*index)
+                .ok()
+                .and_then(|i| interpreter.script_args().get(i))
+                .map(|arg| Value::String(arg.as_str().into()))
+                .ok_or_else(|| {
+                    ValyrianError::RuntimeError(
+                        format!(
+                            "there is no script argument {} (only {} were given)",
+                            index,
+                            interpreter.script_args().len()
+                        )
+                    )
+                })
+        }
+        other => Err(ValyrianError::type_error("integer", &interpreter.type_name(other))),
+    }
+}
+
+/// `sleep with ms` — pauses the program for `ms` milliseconds.
+fn sleep(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let duration = sleep_duration(interpreter, args)?;
+    interpreter.sleep(duration)?;
+    Ok(Value::Void)
+}
+
+/// How long `sleep with args` asks to sleep for.
+pub(crate) fn sleep_duration(
+    interpreter: &Interpreter,
+    args: &[Value]
+) -> Result<Duration, ValyrianError> {
+    if args.len() != 1 {
+        return Err(ValyrianError::ArgumentMismatch);
+    }
+    match &args[0] {
+        Value::Integer(ms) => {
+            let ms = u64::try_from(*ms).map_err(|_| {
+                ValyrianError::RuntimeError(format!("cannot sleep for {} milliseconds", ms))
+            })?;
+            Ok(Duration::from_millis(ms))
+        }
+        other => Err(ValyrianError::type_error("blade", &interpreter.type_name(other))),
+    }
+}
+
+/// `after with ms, spell` — casts the function named `spell` once, at
+/// the first `run_pending` at least `ms` milliseconds from now.
+fn after(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    schedule(interpreter, args, false)
+}
+
+/// `every with ms, spell` — casts the function named `spell` at
+/// `run_pending` every `ms` milliseconds.
+fn every(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    schedule(interpreter, args, true)
+}
+
+fn schedule(interpreter: &mut Interpreter, args: &[Value], repeats: bool) -> Result<Value, ValyrianError> {
+    let delay = match &args[0] {
+        Value::Integer(ms) =>
+            u64::try_from(*ms).map(Duration::from_millis).map_err(|_| {
+                ValyrianError::RuntimeError(format!("cannot wait {} milliseconds", ms))
+            })?,
+        other => {
+            return Err(ValyrianError::type_error("blade", &interpreter.type_name(other)));
+        }
+    };
+    match &args[1] {
+        Value::String(spell) => {
+            interpreter.schedule(delay, repeats, spell)?;
+            Ok(Value::Void)
+        }
+        other => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+/// `run_pending with` — casts the spells set by `after` and `every` that
+/// have come due, giving how many were cast.
+fn run_pending(interpreter: &mut Interpreter, _args: &[Value]) -> Result<Value, ValyrianError> {
+    let cast = interpreter.run_pending()?;
+    Ok(Value::Integer(cast as i64))
+}
+
+/// `ask_until_blade with prompt, min, max` — a blade from `min` to `max`
+/// read from input, asking again after every answer that isn't one.
+fn ask_until_blade(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match (&args[0], &args[1], &args[2]) {
+        (prompt, Value::Integer(min), Value::Integer(max)) =>
+            interpreter.ask_until_blade(&prompt.to_string(), *min, *max).map(Value::Integer),
+        (_, Value::Integer(_), other) | (_, other, _) =>
+            Err(ValyrianError::type_error("blade", &interpreter.type_name(other))),
+    }
+}
+
+/// `assert with condition` or `assert with condition, message` — fails
+/// unless `condition` is aye.
+fn assert(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let message = args.get(1).map(Value::to_string);
+    match &args[0] {
+        Value::Boolean(true) => Ok(Value::Void),
+        Value::Boolean(false) =>
+            Err(
+                ValyrianError::AssertionFailed(
+                    message.unwrap_or_else(|| "the condition was nay".to_string())
+                )
+            ),
+        other => Err(ValyrianError::type_error("vow", &interpreter.type_name(other))),
+    }
+}
+
+/// `assert_equal with found, expected` — fails unless the two values are equal.
+fn assert_equal(_interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    if args[0] == args[1] {
+        Ok(Value::Void)
+    } else {
+        Err(ValyrianError::AssertionFailed(format!("expected {}, found {}", args[1], args[0])))
+    }
+}
+
+/// `rookery with` — a new, empty channel.
+fn rookery(_interpreter: &mut Interpreter, _args: &[Value]) -> Result<Value, ValyrianError> {
+    Ok(Value::Rookery(Rookery::new()))
+}
+
+/// `dispatch with rookery, value` — leaves `value` for a `receive`.
+fn dispatch(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match &args[0] {
+        Value::Rookery(rookery) => {
+            rookery.dispatch(args[1].clone());
+            Ok(Value::Void)
+        }
+        other => Err(ValyrianError::type_error("rookery", &interpreter.type_name(other))),
+    }
+}
+
+/// `receive with rookery` — the oldest value dispatched and not yet
+/// received, waiting for one if there is none.
+fn receive(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match &args[0] {
+        Value::Rookery(rookery) => interpreter.receive(rookery),
+        other => Err(ValyrianError::type_error("rookery", &interpreter.type_name(other))),
+    }
+}
+
+/// `vault with value` — a new vault holding `value`.
+fn vault(_interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    Ok(Value::Vault(Vault::new(args[0].clone())))
+}
+
+/// `claim with vault` — the value in `vault`, which no other raven can
+/// claim until it is released.
+fn claim(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match &args[0] {
+        Value::Vault(vault) => interpreter.claim(vault),
+        other => Err(ValyrianError::type_error("vault", &interpreter.type_name(other))),
+    }
+}
+
+/// `release with vault` or `release with vault, value` — gives up a
+/// claimed vault, with a new value if one is given.
+fn release(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match &args[0] {
+        Value::Vault(vault) => {
+            vault.release(args.get(1).cloned())?;
+            Ok(Value::Void)
+        }
+        other => Err(ValyrianError::type_error("vault", &interpreter.type_name(other))),
+    }
+}
+
+/// `command with program` or `command with program, arguments` — runs
+/// `program` and waits for it to finish.
+fn command(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let arguments = match args.get(1) {
+        Some(Value::String(arguments)) => arguments,
+        Some(other) => {
+            return Err(ValyrianError::type_error("scroll", &interpreter.type_name(other)));
+        }
+        None => "",
+    };
+    match &args[0] {
+        Value::String(program) => interpreter.command(program, arguments),
+        other => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+/// `exit_code with tidings` — the exit code of the program `command` ran.
+fn exit_code(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    tidings(interpreter, &args[0]).map(|tidings| Value::Integer(tidings.exit_code()))
+}
+
+/// `stdout with tidings` — what the program `command` ran wrote to its
+/// standard output.
+fn stdout(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    tidings(interpreter, &args[0]).map(|tidings| Value::String(tidings.stdout().into()))
+}
+
+/// `stderr with tidings` — what the program `command` ran wrote to its
+/// standard error.
+fn stderr(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    tidings(interpreter, &args[0]).map(|tidings| Value::String(tidings.stderr().into()))
+}
+
+fn tidings<'v>(interpreter: &Interpreter, value: &'v Value) -> Result<&'v Tidings, ValyrianError> {
+    match value {
+        Value::Tidings(tidings) => Ok(tidings),
+        other => Err(ValyrianError::type_error("tidings", &interpreter.type_name(other))),
+    }
+}
+
+/// `fetch with url` — the server's answer to a GET request for `url`.
+fn fetch(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match &args[0] {
+        Value::String(url) => interpreter.request(url, None),
+        other => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+/// `post with url, body` — the server's answer to `body` posted to `url`.
+fn post(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    match (&args[0], &args[1]) {
+        (Value::String(url), Value::String(body)) => interpreter.request(url, Some(body)),
+        (Value::String(_), other) | (other, _) =>
+            Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+/// `status with missive` — the HTTP status code of a server's answer.
+fn status(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    missive(interpreter, &args[0]).map(|missive| Value::Integer(missive.status()))
+}
+
+/// `body with missive` — the text of a server's answer.
+fn body(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    missive(interpreter, &args[0]).map(|missive| Value::String(missive.body().into()))
+}
+
+fn missive<'v>(interpreter: &Interpreter, value: &'v Value) -> Result<&'v Missive, ValyrianError> {
+    match value {
+        Value::Missive(missive) => Ok(missive),
+        other => Err(ValyrianError::type_error("missive", &interpreter.type_name(other))),
+    }
+}
+
+/// `connect with host, port` — a connection to `host` on `port`.
+fn connect(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let port = port_number(interpreter, &args[1])?;
+    match &args[0] {
+        Value::String(host) => interpreter.connect(host, port),
+        other => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+/// `listen with port` or `listen with port, host` — a socket that waits
+/// for connections to `port`.
+fn listen(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let port = port_number(interpreter, &args[0])?;
+    match args.get(1) {
+        Some(Value::String(host)) => interpreter.listen(host, port),
+        Some(other) => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+        None => interpreter.listen("127.0.0.1", port),
+    }
+}
+
+/// `accept with listener` — the next connection to a listening socket.
+fn accept(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let listener = socket(interpreter, &args[0])?;
+    interpreter.accept(listener)
+}
+
+/// `send with socket, text` — writes `text` and a line break.
+fn send(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let socket = socket(interpreter, &args[0])?;
+    match &args[1] {
+        Value::String(text) => {
+            interpreter.send(socket, text)?;
+            Ok(Value::Void)
+        }
+        other => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+/// `recv with socket` — the next line from a connection, or void once it
+/// has closed.
+fn recv(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let socket = socket(interpreter, &args[0])?;
+    interpreter.recv(socket)
+}
+
+/// `port with socket` — the port this end of `socket` is on.
+fn port(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let socket = socket(interpreter, &args[0])?;
+    socket
+        .port()
+        .map(|port| Value::Integer(i64::from(port)))
+        .map_err(|e| ValyrianError::io("Failed to find the socket's port", e))
+}
+
+fn socket<'v>(interpreter: &Interpreter, value: &'v Value) -> Result<&'v Socket, ValyrianError> {
+    match value {
+        Value::Socket(socket) => Ok(socket),
+        other => Err(ValyrianError::type_error("socket", &interpreter.type_name(other))),
+    }
+}
+
+fn port_number(interpreter: &Interpreter, value: &Value) -> Result<u16, ValyrianError> {
+    match value {
+        Value::Integer(port) =>
+            u16::try_from(*port).map_err(|_| {
+                ValyrianError::RuntimeError(format!("{} is not a port", port))
+            }),
+        other => Err(ValyrianError::type_error("blade", &interpreter.type_name(other))),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `to_base64 with text` — `text` encoded as standard, padded base64.
+fn to_base64(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let Value::String(text) = &args[0] else {
+        return Err(ValyrianError::type_error("scroll", &interpreter.type_name(&args[0])));
+    };
+    let mut encoded = String::with_capacity(text.len().div_ceil(3) * 4);
+    for group in text.as_bytes().chunks(3) {
+        let bits = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &byte)| bits | (u32::from(byte) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(BASE64[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    Ok(Value::String(encoded.into()))
+}
+
+/// `from_base64 with text` — the scroll that base64 `text` encodes.
+/// Whitespace is skipped, and the padding may be left off.
+fn from_base64(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let Value::String(text) = &args[0] else {
+        return Err(ValyrianError::type_error("scroll", &interpreter.type_name(&args[0])));
+    };
+    let invalid = |why: String| ValyrianError::RuntimeError(format!("invalid base64: {}", why));
+
+    let mut sextets = Vec::with_capacity(text.len());
+    let mut padding = 0;
+    for (position, c) in text.chars().enumerate().filter(|(_, c)| !c.is_whitespace()) {
+        match c {
+            '=' => padding += 1,
+            _ if padding > 0 => {
+                return Err(invalid(format!("'{}' after padding at position {}", c, position)));
+            }
+            _ => {
+                let value = BASE64
+                    .iter()
+                    .position(|&symbol| symbol as char == c)
+                    .ok_or_else(|| invalid(format!("'{}' at position {}", c, position)))?;
+                sextets.push(value as u32);
+            }
+        }
+    }
+    if sextets.len() % 4 == 1 || padding > 2 || (padding > 0 && (sextets.len() + padding) % 4 != 0) {
+        return Err(invalid("it is not a whole number of bytes long".into()));
+    }
+
+    let mut bytes = Vec::with_capacity(sextets.len() * 3 / 4);
+    for group in sextets.chunks(4) {
+        let bits = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &sextet)| bits | (sextet << (18 - 6 * i)));
+        for i in 0..group.len() - 1 {
+            bytes.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    String::from_utf8(bytes)
+        .map(|text| Value::String(text.into()))
+        .map_err(|_| invalid("it does not encode a scroll of text".into()))
+}
+
+/// `path_join with base, part` — `part` under `base`, with the right
+/// separator for this system.
+fn path_join(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let base = path(interpreter, &args[0])?;
+    let part = path(interpreter, &args[1])?;
+    Ok(path_value(&base.join(part)))
+}
+
+/// `path_parent with path` — `path` without its last part.
+fn path_parent(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let path = path(interpreter, &args[0])?;
+    Ok(path.parent().map_or_else(|| Value::String("".into()), path_value))
+}
+
+/// `path_filename with path` — the last part of `path`.
+fn path_filename(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let path = path(interpreter, &args[0])?;
+    let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    Ok(Value::String(name.into()))
+}
+
+/// `path_exists with path` — whether anything is at `path`.
+fn path_exists(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let path = path(interpreter, &args[0])?;
+    Ok(Value::Boolean(path.exists()))
+}
+
+/// `list_scrolls with dir` — the names of the entries in `dir`, sorted,
+/// one per line. Valyrian has no lists, so they come as one scroll.
+fn list_scrolls(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let dir = path(interpreter, &args[0])?;
+    let failed = |e: std::io::Error| {
+        ValyrianError::io(format!("Failed to list '{}': {}", dir.display(), e), e)
+    };
+    let mut names = std::fs::read_dir(dir)
+        .map_err(failed)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(failed)?;
+    names.sort();
+    Ok(Value::String(names.join("\n").into()))
+}
+
+/// `scroll_size with path` — the size in bytes of the file at `path`.
+fn scroll_size(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let path = path(interpreter, &args[0])?;
+    let metadata = std::fs::metadata(path).map_err(|e| {
+        ValyrianError::io(format!("Failed to read the size of '{}': {}", path.display(), e), e)
+    })?;
+    if metadata.is_dir() {
+        return Err(ValyrianError::RuntimeError(format!("'{}' is a folder, not a scroll", path.display())));
+    }
+    i64::try_from(metadata.len())
+        .map(Value::Integer)
+        .map_err(|_| ValyrianError::RuntimeError(format!("'{}' is too big to measure", path.display())))
+}
+
+/// `is_dir with path` — whether `path` is a folder.
+fn is_dir(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let path = path(interpreter, &args[0])?;
+    Ok(Value::Boolean(path.is_dir()))
+}
+
+fn path<'v>(interpreter: &Interpreter, value: &'v Value) -> Result<&'v Path, ValyrianError> {
+    match value {
+        Value::String(path) => Ok(Path::new(&**path)),
+        other => Err(ValyrianError::type_error("scroll", &interpreter.type_name(other))),
+    }
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().as_ref().into())
+}
+
+/// The most decimal places `format_wine` will show.
+const MAX_DECIMALS: i64 = 100;
+
+/// `format_wine with value, decimals` — `value` rounded to `decimals`
+/// decimal places, e.g. `"0.30"` for `0.1 + 0.2` and 2.
+fn format_wine(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, ValyrianError> {
+    let wine = match &args[0] {
+        Value::Float(wine) => *wine,
+        Value::Integer(blade) => *blade as f64,
+        other => {
+            return Err(ValyrianError::type_error("wine", &interpreter.type_name(other)));
+        }
+    };
+    match &args[1] {
+        Value::Integer(decimals @ 0..=MAX_DECIMALS) =>
+            Ok(Value::String(format!("{:.*}", *decimals as usize, wine).into())),
+        Value::Integer(decimals) =>
+            Err(
+                ValyrianError::RuntimeError(
+                    format!("format_wine shows from 0 to {} decimals, not {}", MAX_DECIMALS, decimals)
+                )
+            ),
+        other => Err(ValyrianError::type_error("blade", &interpreter.type_name(other))),
+    }
+}
@@ -0,0 +1,136 @@
+//! 📦 Saving compiled programs, so runs can skip parsing.
+//!
+//! `build` compiles a source file into a `.mvc` file, which `run_file` (and
+//! the `run` command) recognize by its header and run on the bytecode
+//! backend without parsing anything:
+//!
+//! ```
+//! use mid_valyrian::bytecode::BytecodeFile;
+//! use mid_valyrian::{ Interpreter, Value };
+//!
+//! let file = BytecodeFile::compile("on the iron throne:\n    return 6 * 7\n").unwrap();
+//! let loaded = BytecodeFile::from_bytes(&file.to_bytes()).unwrap();
+//! assert_eq!(loaded, file);
+//!
+//! let mut interpreter = Interpreter::new(false);
+//! assert_eq!(interpreter.run_compiled(loaded.chunk).unwrap(), Value::Integer(42));
+//! ```
+//!
+//! A file starts with the bytes `MVC\0` and a little-endian `u16` format
+//! version, followed by the program in the postcard encoding. The version
+//! changes whenever the instruction set does; files of any other version are
+//! refused rather than misread, and have to be rebuilt.
+
+use std::fs::File;
+use std::io::{ self, Read };
+use std::path::Path;
+use crate::compiler::{ compile, Chunk };
+use crate::error::ValyrianError;
+use crate::parser::parse_program;
+
+/// The extension `build` gives compiled files.
+pub const BYTECODE_EXTENSION: &str = "mvc";
+
+/// The format version this build reads and writes.
+pub const BYTECODE_VERSION: u16 = 4;
+
+/// The first bytes of every compiled file.
+const MAGIC: &[u8; 4] = b"MVC\0";
+
+/// A compiled program, as saved in a `.mvc` file.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BytecodeFile {
+    pub chunk: Chunk,
+    /// The source the program was compiled from, for error messages.
+    pub source: String,
+}
+
+impl BytecodeFile {
+    /// Parses and compiles `source`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::ParseError` if the source doesn't parse.
+    pub fn compile(source: &str) -> Result<Self, ValyrianError> {
+        let program = parse_program(source)?;
+        Ok(Self { chunk: compile(&program), source: source.to_string() })
+    }
+
+    /// The file's contents: the header, then the program.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&BYTECODE_VERSION.to_le_bytes());
+        postcard::to_extend(self, bytes).expect("compiled programs always serialize")
+    }
+
+    /// Reads a file's contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::IoError` if the bytes aren't a compiled
+    /// program of this `BYTECODE_VERSION`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ValyrianError> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err(invalid("This is not a compiled Mid Valyrian program".to_string()));
+        };
+        let (version, program) = match rest {
+            [low, high, program @ ..] => (u16::from_le_bytes([*low, *high]), program),
+            _ => {
+                return Err(invalid("The compiled program is cut short".to_string()));
+            }
+        };
+        if version != BYTECODE_VERSION {
+            return Err(
+                invalid(
+                    format!(
+                        "The program was compiled for bytecode version {}, but this interpreter runs version {}; build it again",
+                        version,
+                        BYTECODE_VERSION
+                    )
+                )
+            );
+        }
+        postcard
+            ::from_bytes(program)
+            .map_err(|e| invalid(format!("The compiled program is damaged: {}", e)))
+    }
+
+    /// Writes the program to a compiled file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::IoError` if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ValyrianError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()).map_err(|e| {
+            ValyrianError::io(format!("Could not write '{}': {}", path.display(), e), e)
+        })
+    }
+
+    /// Loads a compiled file saved by `save`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::IoError` if the file cannot be read or isn't
+    /// a compiled program this interpreter can run.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ValyrianError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| {
+            ValyrianError::io(format!("Failed to read file '{}': {}", path.display(), e), e)
+        })?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Returns true if the file at `path` starts like a compiled program,
+/// whatever its extension or version.
+pub fn is_bytecode_file<P: AsRef<Path>>(path: P) -> bool {
+    let mut header = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| &header == MAGIC)
+}
+
+fn invalid(message: String) -> ValyrianError {
+    ValyrianError::io(message.clone(), io::Error::new(io::ErrorKind::InvalidData, message))
+}
@@ -0,0 +1,913 @@
+//! 🌊 Carrying programs across the Narrow Sea.
+//!
+//! `transpile` renders a program in a mainstream language, so students can
+//! set it beside the Valyrian original and run it where the interpreter
+//! isn't installed. Python is the only target so far:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, transpile, Target };
+//!
+//! let program = parse_program("on the iron throne:\n    speak \"Winter is coming\"\n").unwrap();
+//! let python = transpile(&program, Target::Python).unwrap();
+//! assert!(python.contains("print(\"Winter is coming\")"));
+//! ```
+//!
+//! A program that runs without errors prints the same under Python, with
+//! one caveat: sigils and scrolls are both Python strings, so `==` finds a
+//! sigil equal to the one-letter scroll Valyrian calls different. Other
+//! values of different types are kept apart by an `equal` helper. Errors
+//! may differ: where Valyrian stops with a type error, Python may raise
+//! another or carry on.
+//!
+//! ```
+//! # use mid_valyrian::{ parse_program, transpile, Target };
+//! let program = parse_program("on the iron throne:\n    speak 1 == 1.0\n").unwrap();
+//! let python = transpile(&program, Target::Python).unwrap();
+//! assert!(python.contains("print(show(equal(1, 1.0)))"));
+//! ```
+//!
+//! Valyrian scoping is dynamic, so a function sees the parameters of
+//! whoever called it, while Python's is lexical. Variables become Python
+//! globals and parameters stay parameters, which agree until a function
+//! uses a parameter of one of its callers; such programs are refused with
+//! a `TranspileError`, as are programs that send ravens or nest blocks
+//! deeper than Python allows.
+//!
+//! Python has no tail calls either. A function that returns a call to
+//! itself is written as a `while True` loop, so it can recurse as deeply
+//! as Valyrian lets it. Other tail calls, such as those between two
+//! functions or from inside a loop, stay calls and stop with a
+//! `RecursionError` once they go a thousand deep.
+//!
+//! ```
+//! # use mid_valyrian::{ parse_program, transpile, Target };
+//! let source = "we declare countdown with n ->\ncouncil says:\n    if n == 0:\n        return 0\n    return countdown with n - 1\n";
+//! let python = transpile(&parse_program(source).unwrap(), Target::Python).unwrap();
+//! assert!(python.contains("    while True:\n"));
+//! assert!(python.contains("n = n - 1\n        continue\n"));
+//! ```
+
+use std::collections::{ BTreeSet, HashMap, HashSet };
+use crate::ast::*;
+use crate::error::ValyrianError;
+use crate::printer;
+use crate::visit::{ walk_expression, walk_statement, Visitor };
+
+/// A language `transpile` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Python,
+}
+
+impl Target {
+    pub const ALL: [Target; 1] = [Target::Python];
+
+    /// The name used to pick this target from the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Python => "python",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|target| target.name() == name)
+    }
+}
+
+/// Renders `program` as source in the `target` language.
+///
+/// # Errors
+///
+/// Returns `ValyrianError::TranspileError` if the program relies on a
+/// function seeing its caller's parameters or sends ravens; see the module
+/// docs.
+pub fn transpile(program: &Program, target: Target) -> Result<String, ValyrianError> {
+    match target {
+        Target::Python => python(program),
+    }
+}
+
+/// What the scoping check needs to know about one declared function.
+struct Function<'a> {
+    name: &'a str,
+    parameters: &'a [Symbol],
+    /// Functions it calls or declares; a function declared inside another
+    /// would see the outer one's parameters in Python too.
+    calls: BTreeSet<&'a str>,
+    /// Names it reads or binds, with where.
+    uses: Vec<(&'a Symbol, Span)>,
+}
+
+impl<'a> Function<'a> {
+    fn new(name: &'a str, parameters: &'a [Symbol]) -> Self {
+        Self { name, parameters, calls: BTreeSet::new(), uses: Vec::new() }
+    }
+}
+
+/// Gathers what `check_scoping` needs about every declared function, at
+/// any depth.
+struct Scanner<'a> {
+    /// The function being scanned, or the top level outside them all.
+    function: Function<'a>,
+    /// The functions scanned so far, in no set order.
+    functions: Vec<Function<'a>>,
+    /// The first `send_raven` or `await_raven`, which have no translation.
+    raven: Option<Span>,
+}
+
+impl<'a> Scanner<'a> {
+    fn scan(program: &'a Program) -> Self {
+        let mut scanner = Scanner { function: Function::new("", &[]), functions: Vec::new(), raven: None };
+        scanner.visit_program(program);
+        scanner
+    }
+}
+
+impl<'a> Visitor<'a> for Scanner<'a> {
+    fn visit_statement(&mut self, statement: &'a Statement) {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, .. } |
+            StatementKind::Assignment { name, .. } => {
+                self.function.uses.push((&name.symbol, statement.span));
+            }
+            StatementKind::FunctionDeclaration { name, parameters, body } => {
+                self.function.calls.insert(name);
+                let outer = std::mem::replace(&mut self.function, Function::new(name, parameters));
+                self.visit_block(body);
+                let declared = std::mem::replace(&mut self.function, outer);
+                self.functions.push(declared);
+                return;
+            }
+            StatementKind::FunctionCall { name, .. } => {
+                self.function.calls.insert(name);
+            }
+            // Trials aren't translated
+            StatementKind::Trial { .. } => {
+                return;
+            }
+            _ => {}
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &'a Expression) {
+        match &expression.kind {
+            ExpressionKind::Identifier(name) => self.function.uses.push((&name.symbol, expression.span)),
+            ExpressionKind::FunctionCall { name, .. } => {
+                self.function.calls.insert(name);
+            }
+            ExpressionKind::SendRaven(_) | ExpressionKind::AwaitRaven(_) => {
+                self.raven.get_or_insert(expression.span);
+            }
+            _ => {}
+        }
+        walk_expression(self, expression);
+    }
+}
+
+/// Fails if a function uses a parameter of a function that calls it,
+/// directly or through others.
+fn check_scoping(functions: &[Function]) -> Result<(), ValyrianError> {
+    for caller in functions.iter().filter(|function| !function.parameters.is_empty()) {
+        let mut seen = HashSet::new();
+        let mut pending: Vec<&str> = caller.calls.iter().copied().collect();
+        while let Some(name) = pending.pop() {
+            if name == caller.name || !seen.insert(name) {
+                continue;
+            }
+            for callee in functions.iter().filter(|function| function.name == name) {
+                let borrowed = callee.uses
+                    .iter()
+                    .find(|(used, _)| {
+                        caller.parameters.contains(used) && !callee.parameters.contains(used)
+                    });
+                if let Some((used, span)) = borrowed {
+                    return Err(ValyrianError::TranspileError {
+                        message: format!(
+                            "'{}' uses '{}', a parameter of its caller '{}', which Python functions cannot see",
+                            callee.name,
+                            used,
+                            caller.name
+                        ),
+                        span: *span,
+                    });
+                }
+                pending.extend(callee.calls.iter().copied());
+            }
+        }
+    }
+    Ok(())
+}
+
+const INDENT: &str = "    ";
+
+/// The deepest Python lets blocks nest.
+const MAX_DEPTH: usize = 99;
+
+/// Names Python code can't give a variable or function of its own:
+/// keywords, and the builtins and helpers the translation relies on.
+const RESERVED: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+    "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+    "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return",
+    "try", "while", "with", "yield",
+    "Decimal", "abs", "float", "format", "int", "isinstance", "len", "print", "range", "repr",
+    "str", "sys", "time",
+    "arg", "arg_count", "assert_equal", "divide", "equal", "listen", "main", "show",
+];
+
+/// Python functions the translation calls, written out ahead of the code
+/// that needs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    Show,
+    Divide,
+    Equal,
+    Listen,
+    Arg,
+    ArgCount,
+    Assert,
+    AssertEqual,
+}
+
+impl Helper {
+    fn source(self) -> &'static str {
+        match self {
+            Helper::Show =>
+                r#"def show(value):
+    """Renders a value the way `speak` does."""
+    if value is None:
+        return "void"
+    if isinstance(value, bool):
+        return "aye" if value else "nay"
+    if isinstance(value, float):
+        if value != value:
+            return "NaN"
+        if value in (float("inf"), float("-inf")):
+            return str(value)
+        text = format(Decimal(repr(value)), "f")
+        return text[:-2] if text.endswith(".0") else text
+    return str(value)
+"#,
+            Helper::Divide =>
+                r#"def divide(left, right):
+    """Divides the way `/` does, rounding whole numbers toward zero."""
+    if isinstance(left, int) and isinstance(right, int):
+        quotient = abs(left) // abs(right)
+        return quotient if (left < 0) == (right < 0) else -quotient
+    return left / right
+"#,
+            Helper::Equal =>
+                r#"def equal(left, right):
+    """Compares the way `==` does: values of different types are unequal."""
+    return type(left) is type(right) and left == right
+"#,
+            Helper::Listen =>
+                r#"def listen(prompt="🗣️ Speak your words: "):
+    """Reads a line of input, as `speaks for input` and `ask` do."""
+    print(prompt, end="", flush=True)
+    return sys.stdin.readline().strip()
+"#,
+            Helper::Arg =>
+                r#"def arg(n):
+    return sys.argv[n + 1]
+"#,
+            Helper::ArgCount =>
+                r#"def arg_count():
+    return len(sys.argv) - 1
+"#,
+            Helper::Assert =>
+                r#"def assert_(condition, message="the condition was nay"):
+    assert condition, message
+"#,
+            Helper::AssertEqual =>
+                r#"def assert_equal(found, expected):
+    assert equal(found, expected), f"expected {show(expected)}, found {show(found)}"
+"#,
+        }
+    }
+
+    fn import(self) -> Option<&'static str> {
+        match self {
+            Helper::Show => Some("from decimal import Decimal"),
+            Helper::Listen | Helper::Arg | Helper::ArgCount => Some("import sys"),
+            _ => None,
+        }
+    }
+}
+
+/// How tightly a Python expression binds, loosest first. An operand that
+/// binds more loosely than its operator is wrapped in parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Not,
+    Comparison,
+    Sum,
+    Product,
+    Negation,
+    Atom,
+}
+
+fn python(program: &Program) -> Result<String, ValyrianError> {
+    let scanner = Scanner::scan(program);
+    if let Some(span) = scanner.raven {
+        return Err(ValyrianError::TranspileError {
+            message: "ravens can't be carried to Python, which has no threads of the same kind".into(),
+            span,
+        });
+    }
+    check_scoping(&scanner.functions)?;
+
+    let mut writer = PythonWriter::new(&scanner);
+    let (declarations, main): (Vec<&Statement>, Vec<&Statement>) = program.statements
+        .iter()
+        .filter(|statement| !matches!(statement.kind, StatementKind::Trial { .. }))
+        .partition(|statement| matches!(statement.kind, StatementKind::FunctionDeclaration { .. }));
+    // Declared functions can be called from anywhere, as the interpreter
+    // declares them all before running the rest
+    for declaration in declarations {
+        writer.statement(declaration);
+        writer.end_section();
+    }
+    if !main.is_empty() {
+        writer.main(&main);
+    }
+    if let Some(span) = writer.too_deep {
+        return Err(ValyrianError::TranspileError {
+            message: format!("blocks nest more than {} deep, which Python cannot parse", MAX_DEPTH),
+            span,
+        });
+    }
+    Ok(writer.finish())
+}
+
+struct PythonWriter<'a> {
+    out: String,
+    depth: usize,
+    /// Top-level definitions written so far, each set apart by blank lines.
+    sections: Vec<String>,
+    variables: HashMap<&'a str, String>,
+    functions: HashMap<&'a str, String>,
+    helpers: BTreeSet<Helper>,
+    imports: BTreeSet<&'static str>,
+    /// The first statement nested deeper than `MAX_DEPTH`, if any.
+    too_deep: Option<Span>,
+    /// The function being written as a `while True` loop, with its Python
+    /// parameters, so its tail calls to itself can go round again.
+    looping: Option<(Symbol, Vec<String>)>,
+    /// How many Python loops the statement being written is inside, within
+    /// its function. A `continue` in one of them wouldn't reach ours.
+    loops: usize,
+}
+
+impl<'a> PythonWriter<'a> {
+    fn new(scanner: &Scanner<'a>) -> Self {
+        let functions = &scanner.functions;
+        let mut names = Vec::new();
+        for function in functions.iter().chain([&scanner.function]) {
+            names.extend(function.parameters.iter().map(|parameter| &**parameter));
+            names.extend(function.uses.iter().map(|(used, _)| &***used));
+        }
+
+        let mut taken: HashSet<&str> = names.iter().copied().collect();
+        taken.extend(functions.iter().map(|function| function.name));
+        let variables: HashMap<&str, String> = names
+            .iter()
+            .map(|&name| (name, rename(name, |renamed| taken.contains(renamed))))
+            .collect();
+        let python_variables: HashSet<&str> = variables.values().map(String::as_str).collect();
+        let functions = functions
+            .iter()
+            .map(|function| {
+                let clashes = |renamed: &str| {
+                    taken.contains(renamed) || python_variables.contains(renamed)
+                };
+                let name = if variables.contains_key(function.name) {
+                    rename(&format!("{}_", function.name), clashes)
+                } else {
+                    rename(function.name, clashes)
+                };
+                (function.name, name)
+            })
+            .collect();
+
+        Self {
+            out: String::new(),
+            depth: 0,
+            sections: Vec::new(),
+            variables,
+            functions,
+            helpers: BTreeSet::new(),
+            imports: BTreeSet::new(),
+            too_deep: None,
+            looping: None,
+            loops: 0,
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut header = String::from("# Translated from Mid Valyrian.\n");
+        if !self.imports.is_empty() {
+            header.push('\n');
+            for import in &self.imports {
+                header.push_str(import);
+                header.push('\n');
+            }
+        }
+        std::iter
+            ::once(header)
+            .chain(self.helpers.iter().map(|helper| helper.source().to_string()))
+            .chain(self.sections)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn end_section(&mut self) {
+        self.sections.push(std::mem::take(&mut self.out));
+    }
+
+    fn use_helper(&mut self, helper: Helper) {
+        if helper == Helper::AssertEqual {
+            self.use_helper(Helper::Show);
+            self.use_helper(Helper::Equal);
+        }
+        self.imports.extend(helper.import());
+        self.helpers.insert(helper);
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Writes the statements one level in, with a `pass` if none of them
+    /// has a Python counterpart.
+    fn block<'s>(&mut self, statements: impl IntoIterator<Item = &'s Statement>) {
+        self.depth += 1;
+        let start = self.out.len();
+        for statement in statements {
+            self.statement(statement);
+        }
+        if self.out.len() == start {
+            self.line("pass");
+        }
+        self.depth -= 1;
+    }
+
+    /// Writes the top-level statements other than function declarations
+    /// as a `main` function, run when the file is.
+    fn main(&mut self, statements: &[&Statement]) {
+        self.line("def main():");
+        self.globals(statements.iter().copied(), &[]);
+        self.block(statements.iter().copied());
+        self.end_section();
+        self.line("if __name__ == \"__main__\":");
+        self.line(&format!("{}main()", INDENT));
+        self.end_section();
+    }
+
+    /// Declares the names a function binds as globals, since every
+    /// Valyrian variable outlives the call that made it. Parameters stay
+    /// local.
+    fn globals<'s>(&mut self, statements: impl IntoIterator<Item = &'s Statement>, parameters: &[Symbol]) {
+        let mut names = Vec::new();
+        self.bound_names(statements, parameters, &mut names);
+        if !names.is_empty() {
+            self.depth += 1;
+            self.line(&format!("global {}", names.join(", ")));
+            self.depth -= 1;
+        }
+    }
+
+    fn bound_names<'s>(
+        &self,
+        statements: impl IntoIterator<Item = &'s Statement>,
+        parameters: &[Symbol],
+        names: &mut Vec<String>
+    ) {
+        for statement in statements {
+            let bound = match &statement.kind {
+                StatementKind::VariableDeclaration { name, .. } |
+                StatementKind::Assignment { name, .. } => {
+                    if parameters.contains(&name.symbol) {
+                        continue;
+                    }
+                    self.variable(name)
+                }
+                StatementKind::FunctionDeclaration { name, .. } => self.function(name),
+                StatementKind::Conditional { then_branch, else_branch, .. } => {
+                    self.bound_names(then_branch, parameters, names);
+                    self.bound_names(else_branch.iter().flatten(), parameters, names);
+                    continue;
+                }
+                StatementKind::ForLoop { body, .. } |
+                StatementKind::WhileLoop { body, .. } |
+                StatementKind::MainBlock(body) => {
+                    self.bound_names(body, parameters, names);
+                    continue;
+                }
+                _ => {
+                    continue;
+                }
+            };
+            if !names.contains(&bound) {
+                names.push(bound);
+            }
+        }
+    }
+
+    fn variable(&self, name: &str) -> String {
+        self.variables.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    fn function(&self, name: &str) -> String {
+        self.functions.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        if self.depth > MAX_DEPTH && self.too_deep.is_none() {
+            self.too_deep = Some(statement.span);
+        }
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, value: Some(value), .. } |
+            StatementKind::Assignment { name, value } => {
+                let value = self.expression(value);
+                self.line(&format!("{} = {}", self.variable(name), value));
+            }
+            StatementKind::VariableDeclaration { name, data_type, value: None } => {
+                let value = python_default(data_type);
+                self.line(&format!("{} = {}", self.variable(name), value));
+            }
+            StatementKind::FunctionDeclaration { name, parameters, body } => {
+                let renamed: Vec<String> = parameters
+                    .iter()
+                    .map(|parameter| self.variable(parameter))
+                    .collect();
+                self.line(&format!("def {}({}):", self.function(name), renamed.join(", ")));
+                self.globals(body, parameters);
+                let looping = has_self_tail_call(name, body).then(|| (name.clone(), renamed));
+                let outer = (std::mem::replace(&mut self.looping, looping), std::mem::take(&mut self.loops));
+                if self.looping.is_none() {
+                    self.block(body);
+                } else {
+                    self.depth += 1;
+                    self.line("while True:");
+                    self.block(body);
+                    // Falling off the end returns, rather than going round again
+                    if !matches!(body.last().map(|statement| &statement.kind), Some(StatementKind::Return(_))) {
+                        self.depth += 1;
+                        self.line("return");
+                        self.depth -= 1;
+                    }
+                    self.depth -= 1;
+                }
+                (self.looping, self.loops) = outer;
+            }
+            StatementKind::FunctionCall { name, arguments } => {
+                let line = match (self.is_builtin(name, "assert"), arguments.as_slice()) {
+                    (true, [condition]) => format!("assert {}", self.expression(condition)),
+                    (true, [condition, message]) => {
+                        let condition = self.expression(condition);
+                        format!("assert {}, {}", condition, self.displayed(message))
+                    }
+                    _ => self.call(name, arguments),
+                };
+                self.line(&line);
+            }
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
+                self.conditional("if", condition, then_branch, else_branch.as_deref());
+            }
+            StatementKind::ForLoop { count, body } => {
+                self.line(&format!("for _ in range({}):", count));
+                self.loops += 1;
+                self.block(body);
+                self.loops -= 1;
+            }
+            StatementKind::WhileLoop { condition, body } => {
+                let condition = self.expression(condition);
+                self.line(&format!("while {}:", condition));
+                self.loops += 1;
+                self.block(body);
+                self.loops -= 1;
+            }
+            StatementKind::Return(Some(Expression { kind: ExpressionKind::FunctionCall { name, arguments }, .. }))
+                if self.loops == 0 &&
+                    // A call with the wrong arguments is left to fail as calls do
+                    self.looping
+                        .as_ref()
+                        .is_some_and(|(looping, parameters)| {
+                            looping == name && parameters.len() == arguments.len()
+                        }) => {
+                let (_, parameters) = self.looping.clone().expect("checked above");
+                if !parameters.is_empty() {
+                    let values: Vec<String> = arguments
+                        .iter()
+                        .map(|argument| self.expression(argument))
+                        .collect();
+                    self.line(&format!("{} = {}", parameters.join(", "), values.join(", ")));
+                }
+                self.line("continue");
+            }
+            StatementKind::Return(Some(value)) => {
+                let value = self.expression(value);
+                self.line(&format!("return {}", value));
+            }
+            StatementKind::Return(None) => self.line("return"),
+            StatementKind::Speak(value) => {
+                let value = self.displayed(value);
+                self.line(&format!("print({})", value));
+            }
+            StatementKind::MainBlock(body) => {
+                for statement in body {
+                    self.statement(statement);
+                }
+            }
+            // Trials only run under the test runner
+            StatementKind::Trial { .. } => {}
+        }
+    }
+
+    /// Writes an `if`, turning an `else` that holds nothing but another
+    /// `if` into an `elif`.
+    fn conditional(
+        &mut self,
+        keyword: &str,
+        condition: &Expression,
+        then_branch: &[Statement],
+        else_branch: Option<&[Statement]>
+    ) {
+        let condition = self.expression(condition);
+        self.line(&format!("{} {}:", keyword, condition));
+        self.block(then_branch);
+        match else_branch {
+            Some(
+                [
+                    Statement {
+                        kind: StatementKind::Conditional { condition, then_branch, else_branch },
+                        ..
+                    },
+                ],
+            ) => self.conditional("elif", condition, then_branch, else_branch.as_deref()),
+            Some(else_branch) => {
+                self.line("else:");
+                self.block(else_branch);
+            }
+            None => {}
+        }
+    }
+
+    /// Whether `name` calls the builtin `builtin` rather than a function
+    /// the program declares.
+    fn is_builtin(&self, name: &str, builtin: &str) -> bool {
+        name == builtin && !self.functions.contains_key(name)
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> String {
+        let mut rendered: Vec<String> = arguments
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect();
+        if self.functions.contains_key(name) {
+            return format!("{}({})", self.function(name), rendered.join(", "));
+        }
+        let function = match name {
+            "arg" => {
+                self.use_helper(Helper::Arg);
+                "arg"
+            }
+            "arg_count" => {
+                self.use_helper(Helper::ArgCount);
+                "arg_count"
+            }
+            "assert" => {
+                self.use_helper(Helper::Assert);
+                "assert_"
+            }
+            "assert_equal" => {
+                self.use_helper(Helper::AssertEqual);
+                "assert_equal"
+            }
+            "sleep" if arguments.len() == 1 => {
+                self.imports.insert("import time");
+                let (ms, precedence) = self.operand(&arguments[0]);
+                rendered = vec![format!("{} / 1000", wrap(ms, precedence < Precedence::Product))];
+                "time.sleep"
+            }
+            // A native function the host provides; Python has no such thing
+            other => {
+                return format!("{}({})", rename(other, |_| false), rendered.join(", "));
+            }
+        };
+        format!("{}({})", function, rendered.join(", "))
+    }
+
+    /// Renders a value to be printed the way `speak` shows it.
+    fn displayed(&mut self, expression: &Expression) -> String {
+        let rendered = self.expression(expression);
+        if prints_alike(expression) {
+            rendered
+        } else {
+            self.use_helper(Helper::Show);
+            format!("show({})", rendered)
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> String {
+        self.operand(expression).0
+    }
+
+    fn operand(&mut self, expression: &Expression) -> (String, Precedence) {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) => {
+                let text = python_literal(literal);
+                let precedence = if text.starts_with('-') {
+                    Precedence::Negation
+                } else {
+                    Precedence::Atom
+                };
+                (text, precedence)
+            }
+            ExpressionKind::Identifier(name) => (self.variable(name), Precedence::Atom),
+            ExpressionKind::Input(_) => {
+                self.use_helper(Helper::Listen);
+                ("listen()".to_string(), Precedence::Atom)
+            }
+            ExpressionKind::Ask(prompt) => {
+                self.use_helper(Helper::Listen);
+                (format!("listen({})", self.displayed(prompt)), Precedence::Atom)
+            }
+            ExpressionKind::FunctionCall { name, arguments } => {
+                (self.call(name, arguments), Precedence::Atom)
+            }
+            ExpressionKind::SendRaven(_) | ExpressionKind::AwaitRaven(_) => {
+                unreachable!("programs with ravens are refused before they are written")
+            }
+            ExpressionKind::Unary { operator: UnaryOperator::Minus, operand } => {
+                let (operand, precedence) = self.operand(operand);
+                let operand = wrap(operand, precedence <= Precedence::Negation);
+                (format!("-{}", operand), Precedence::Negation)
+            }
+            ExpressionKind::Unary { operator: UnaryOperator::Not, operand } => {
+                (format!("not {}", self.expression(operand)), Precedence::Not)
+            }
+            ExpressionKind::Binary { left, operator, right } => {
+                if *operator == BinaryOperator::Divide && !is_float(left) && !is_float(right) {
+                    self.use_helper(Helper::Divide);
+                    let (left, right) = (self.expression(left), self.expression(right));
+                    return (format!("divide({}, {})", left, right), Precedence::Atom);
+                }
+                if matches!(operator, BinaryOperator::Equal | BinaryOperator::NotEqual) &&
+                    !is_same_literal_type(left, right)
+                {
+                    self.use_helper(Helper::Equal);
+                    let (left, right) = (self.expression(left), self.expression(right));
+                    let equal = format!("equal({}, {})", left, right);
+                    return if *operator == BinaryOperator::Equal {
+                        (equal, Precedence::Atom)
+                    } else {
+                        (format!("not {}", equal), Precedence::Not)
+                    };
+                }
+                let precedence = match operator {
+                    BinaryOperator::Add | BinaryOperator::Subtract => Precedence::Sum,
+                    BinaryOperator::Multiply | BinaryOperator::Divide => Precedence::Product,
+                    _ => Precedence::Comparison,
+                };
+                // The tree is grouped as the program's edition reads it,
+                // which needn't be as Python would, and Python would chain
+                // `a < b == c`
+                let (left, left_precedence) = self.operand(left);
+                let left = wrap(
+                    left,
+                    left_precedence < precedence ||
+                        (precedence == Precedence::Comparison && left_precedence == precedence)
+                );
+                let (right, right_precedence) = self.operand(right);
+                let right = wrap(right, right_precedence <= precedence);
+                let operator = printer::binary_operator(operator);
+                (format!("{} {} {}", left, operator, right), precedence)
+            }
+        }
+    }
+}
+
+/// Whether a function named `name` returns a call to itself from `body`
+/// outside any loop.
+fn has_self_tail_call(name: &Symbol, body: &[Statement]) -> bool {
+    body.iter().any(|statement| {
+        match &statement.kind {
+            StatementKind::Return(
+                Some(Expression { kind: ExpressionKind::FunctionCall { name: called, .. }, .. }),
+            ) => called == name,
+            StatementKind::Conditional { then_branch, else_branch, .. } =>
+                has_self_tail_call(name, then_branch) ||
+                    else_branch.as_deref().is_some_and(|branch| has_self_tail_call(name, branch)),
+            StatementKind::MainBlock(body) => has_self_tail_call(name, body),
+            _ => false,
+        }
+    })
+}
+
+fn wrap(text: String, parenthesize: bool) -> String {
+    if parenthesize { format!("({})", text) } else { text }
+}
+
+/// `name`, with underscores added until Python can use it.
+fn rename(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let mut renamed = name.to_string();
+    while RESERVED.contains(&renamed.as_str()) || (renamed != name && taken(&renamed)) {
+        renamed.push('_');
+    }
+    renamed
+}
+
+/// Whether an expression is certainly a wine, and so divides with `/`.
+fn is_float(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::Literal(Literal::Float(_)) => true,
+        ExpressionKind::Unary { operator: UnaryOperator::Minus, operand } => is_float(operand),
+        ExpressionKind::Binary { left, operator, right } =>
+            matches!(
+                operator,
+                BinaryOperator::Add |
+                    BinaryOperator::Subtract |
+                    BinaryOperator::Multiply |
+                    BinaryOperator::Divide
+            ) && (is_float(left) || is_float(right)),
+        _ => false,
+    }
+}
+
+/// Whether both expressions are literals of the same type, and so compare
+/// with `==` alike in Python.
+fn is_same_literal_type(left: &Expression, right: &Expression) -> bool {
+    match (&left.kind, &right.kind) {
+        (ExpressionKind::Literal(left), ExpressionKind::Literal(right)) =>
+            std::mem::discriminant(left) == std::mem::discriminant(right),
+        _ => false,
+    }
+}
+
+/// Whether Python's `print` shows the value of an expression as `speak`
+/// does, which holds for scrolls, blades, and sigils.
+fn prints_alike(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::Literal(literal) =>
+            matches!(literal, Literal::String(_) | Literal::Integer(_) | Literal::Char(_)),
+        ExpressionKind::Input(_) | ExpressionKind::Ask(_) => true,
+        ExpressionKind::Unary { operator: UnaryOperator::Minus, operand } => prints_alike(operand),
+        ExpressionKind::Binary { left, operator, right } =>
+            matches!(
+                operator,
+                BinaryOperator::Add |
+                    BinaryOperator::Subtract |
+                    BinaryOperator::Multiply |
+                    BinaryOperator::Divide
+            ) && prints_alike(left) && prints_alike(right),
+        _ => false,
+    }
+}
+
+fn python_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::String(text) => python_string(text),
+        Literal::Char(c) => python_string(&c.to_string()),
+        Literal::Integer(i) => i.to_string(),
+        Literal::Float(f) if f.is_nan() => "float(\"nan\")".to_string(),
+        Literal::Float(f) if f.is_infinite() => format!("float(\"{}\")", f),
+        Literal::Float(_) => printer::literal(literal),
+        Literal::Boolean(b) => (if *b { "True" } else { "False" }).to_string(),
+    }
+}
+
+/// The Python for `DataType::default_value`.
+fn python_default(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Scroll => "\"\"",
+        DataType::Blade => "0",
+        DataType::Wine => "0.0",
+        DataType::Vow => "False",
+        DataType::Sigil => "\"\\x00\"",
+        _ => "None",
+    }
+}
+
+fn python_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,456 @@
+//! ⚙️ Lowering programs to bytecode.
+//!
+//! `compile` turns a program into a `Chunk`: a flat list of `Instruction`s
+//! for a stack machine, with the constants, names, and statements they
+//! refer to. Each function the program declares gets a chunk of its own.
+//! Interpreters run chunks when configured with
+//! `ExecutionBackend::Bytecode`; the tree-walking interpreter remains the
+//! reference for what every instruction must do.
+//!
+//! ```
+//! use mid_valyrian::{ compile, parse_program, Instruction };
+//!
+//! let chunk = compile(&parse_program("gold is a blade with 2 * 3\n").unwrap());
+//! assert!(matches!(chunk.instructions()[0], Instruction::Statement(0)));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::ast::*;
+use crate::interpreter::with_stack;
+
+/// One step of the stack machine. Operands index the tables of the chunk
+/// holding the instruction, or (for jumps) its instructions.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Instruction {
+    /// Starts a statement: counts it, reports it to observers, and checks
+    /// the run's limits.
+    Statement(u32),
+    /// Returns to a statement already started, without counting it again,
+    /// so errors in a `while` condition point at the loop.
+    Resume(u32),
+    /// Pushes a constant.
+    Constant(u32),
+    /// Pushes the value of a variable.
+    Load(u32),
+    /// Pops a value into a new variable of the given type.
+    Declare(u32, DataType),
+    /// Fails unless a variable exists. Assignments check this before
+    /// computing the new value.
+    CheckDefined(u32),
+    /// Pops a value into an existing variable.
+    Assign(u32),
+    /// Pops the right operand, then the left, and pushes the result.
+    Binary(BinaryOperator),
+    /// Pops an operand and pushes the result.
+    Unary(UnaryOperator),
+    /// Reads a line of input and pushes it.
+    Input,
+    /// Pops a prompt, shows it, and pushes the line of input read after
+    /// it.
+    Ask,
+    /// Pops `arguments` values, calls the named function with them, and
+    /// pushes its result. `site` indexes the chunk's call sites.
+    Call {
+        function: u32,
+        arguments: u32,
+        site: u32,
+    },
+    /// Like `Call`, but throws the result away, as a call statement does.
+    CallStatement {
+        function: u32,
+        arguments: u32,
+        site: u32,
+    },
+    /// Like `Call`, for a call being returned. A compiled function called
+    /// this way from another takes over the caller's frame, and never
+    /// returns to it; otherwise the `Return` that follows runs as usual.
+    TailCall {
+        function: u32,
+        arguments: u32,
+        site: u32,
+    },
+    /// Pops `arguments` values and pushes a raven calling the named
+    /// function with them on its own thread.
+    SendRaven {
+        function: u32,
+        arguments: u32,
+        site: u32,
+    },
+    /// Pops a raven, waits for it, and pushes its result.
+    AwaitRaven,
+    /// Pops a value and speaks it.
+    Speak,
+    Jump(u32),
+    /// Pops a vow and jumps if it is false.
+    JumpUnless(u32),
+    /// Pushes the counter of a `the realm marches` loop.
+    Counter(i64),
+    /// Jumps past the loop, dropping its counter, once the counter on top
+    /// of the stack is spent; otherwise counts one iteration.
+    CountDown(u32),
+    /// Defines one of the chunk's functions.
+    DeclareFunction(u32),
+    /// Pops a value and returns it from the chunk.
+    Return,
+    ReturnVoid,
+}
+
+/// Compiled code for a program or one function; see the module docs.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chunk {
+    /// The function's name, or `main` for a program.
+    pub(crate) name: Symbol,
+    pub(crate) parameters: Vec<Symbol>,
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) constants: Vec<Value>,
+    /// Variable and function names.
+    pub(crate) names: Vec<Symbol>,
+    /// The statements `Instruction::Statement` starts, without the blocks
+    /// they open, for observers, traces, and error locations.
+    pub(crate) statements: Vec<Statement>,
+    pub(crate) call_sites: Vec<Span>,
+    /// Functions declared in this chunk.
+    pub(crate) functions: Vec<Arc<Chunk>>,
+    /// The functions defined before the chunk runs, as top-level
+    /// declarations are.
+    pub(crate) hoisted: Vec<u32>,
+}
+
+impl Chunk {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn parameters(&self) -> &[Symbol] {
+        &self.parameters
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    pub fn names(&self) -> &[Symbol] {
+        &self.names
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    pub fn call_sites(&self) -> &[Span] {
+        &self.call_sites
+    }
+
+    /// The chunks of the functions declared in this one.
+    pub fn functions(&self) -> &[Arc<Chunk>] {
+        &self.functions
+    }
+}
+
+/// Compiles a whole program. Its trials are left out, as ordinary runs
+/// skip them.
+pub fn compile(program: &Program) -> Chunk {
+    compile_statements(&program.statements)
+}
+
+/// Compiles top-level statements, as `Interpreter::run_statements` would
+/// run them.
+pub(crate) fn compile_statements(statements: &[Statement]) -> Chunk {
+    let mut compiler = Compiler::new("main".into(), &[]);
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::Trial { .. } => {}
+            StatementKind::FunctionDeclaration { .. } => {
+                let index = compiler.statement(statement);
+                compiler.chunk.hoisted.extend(index);
+            }
+            _ => {
+                compiler.statement(statement);
+            }
+        }
+    }
+    compiler.finish()
+}
+
+struct Compiler {
+    chunk: Chunk,
+    /// Where each name already is in `chunk.names`.
+    name_indices: HashMap<Symbol, u32>,
+}
+
+impl Compiler {
+    fn new(name: Symbol, parameters: &[Symbol]) -> Self {
+        Self {
+            chunk: Chunk {
+                name,
+                parameters: parameters.to_vec(),
+                ..Chunk::default()
+            },
+            name_indices: HashMap::new(),
+        }
+    }
+
+    fn finish(mut self) -> Chunk {
+        self.emit(Instruction::ReturnVoid);
+        self.chunk
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.chunk.instructions.push(instruction);
+        self.chunk.instructions.len() - 1
+    }
+
+    /// Where the next instruction will go, as a jump target.
+    fn here(&self) -> u32 {
+        self.chunk.instructions.len() as u32
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.chunk.instructions[at] {
+            Instruction::Jump(to) | Instruction::JumpUnless(to) | Instruction::CountDown(to) => {
+                *to = target;
+            }
+            other => unreachable!("patching {:?}, which doesn't jump", other),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        self.chunk.constants.push(value);
+        (self.chunk.constants.len() - 1) as u32
+    }
+
+    fn name(&mut self, name: &Symbol) -> u32 {
+        if let Some(&index) = self.name_indices.get(name) {
+            return index;
+        }
+        let index = self.chunk.names.len() as u32;
+        self.chunk.names.push(name.clone());
+        self.name_indices.insert(name.clone(), index);
+        index
+    }
+
+    fn call_site(&mut self, span: Span) -> u32 {
+        self.chunk.call_sites.push(span);
+        (self.chunk.call_sites.len() - 1) as u32
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Compiles a statement, returning the index of the function it
+    /// declares, if it is a declaration.
+    fn statement(&mut self, statement: &Statement) -> Option<u32> {
+        self.chunk.statements.push(head(statement));
+        let index = (self.chunk.statements.len() - 1) as u32;
+        self.emit(Instruction::Statement(index));
+
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, data_type, value } => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => {
+                        let constant = self.constant(data_type.default_value());
+                        self.emit(Instruction::Constant(constant));
+                    }
+                }
+                let name = self.name(&name.symbol);
+                self.emit(Instruction::Declare(name, data_type.clone()));
+            }
+            StatementKind::Assignment { name, value } => {
+                let name = self.name(&name.symbol);
+                self.emit(Instruction::CheckDefined(name));
+                self.expression(value);
+                self.emit(Instruction::Assign(name));
+            }
+            StatementKind::FunctionCall { name, arguments } => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                let function = self.name(name);
+                let site = self.call_site(statement.span);
+                self.emit(Instruction::CallStatement {
+                    function,
+                    arguments: arguments.len() as u32,
+                    site,
+                });
+            }
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                let skip_then = self.emit(Instruction::JumpUnless(0));
+                self.block(then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let skip_else = self.emit(Instruction::Jump(0));
+                        self.patch(skip_then);
+                        self.block(else_branch);
+                        self.patch(skip_else);
+                    }
+                    None => self.patch(skip_then),
+                }
+            }
+            // Nothing to repeat, and nothing to stop a long wait on it
+            StatementKind::ForLoop { body, .. } if body.is_empty() => {}
+            StatementKind::ForLoop { count, body } => {
+                self.emit(Instruction::Counter(*count));
+                let top = self.here();
+                let exit = self.emit(Instruction::CountDown(0));
+                self.block(body);
+                self.emit(Instruction::Jump(top));
+                self.patch(exit);
+            }
+            StatementKind::WhileLoop { condition, body } => {
+                let top = self.here();
+                self.expression(condition);
+                let exit = self.emit(Instruction::JumpUnless(0));
+                self.block(body);
+                self.emit(Instruction::Resume(index));
+                self.emit(Instruction::Jump(top));
+                self.patch(exit);
+            }
+            StatementKind::Return(value) => {
+                match value {
+                    Some(Expression { kind: ExpressionKind::FunctionCall { name, arguments }, span }) => {
+                        self.call(name, arguments, *span, true);
+                        self.emit(Instruction::Return);
+                    }
+                    Some(value) => {
+                        self.expression(value);
+                        self.emit(Instruction::Return);
+                    }
+                    None => {
+                        self.emit(Instruction::ReturnVoid);
+                    }
+                }
+            }
+            StatementKind::Speak(value) => {
+                self.expression(value);
+                self.emit(Instruction::Speak);
+            }
+            StatementKind::MainBlock(body) => self.block(body),
+            StatementKind::FunctionDeclaration { name, parameters, body } => {
+                let mut function = Compiler::new(name.clone(), parameters);
+                function.block(body);
+                self.chunk.functions.push(Arc::new(function.finish()));
+                let index = (self.chunk.functions.len() - 1) as u32;
+                self.emit(Instruction::DeclareFunction(index));
+                return Some(index);
+            }
+            // Trials only run under the test runner
+            StatementKind::Trial { .. } => {}
+        }
+        None
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        with_stack(|| self.expression_kind(expression));
+    }
+
+    fn expression_kind(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) => {
+                let value = match literal {
+                    Literal::String(s) => Value::String(s.clone()),
+                    Literal::Integer(i) => Value::Integer(*i),
+                    Literal::Float(f) => Value::Float(*f),
+                    Literal::Boolean(b) => Value::Boolean(*b),
+                    Literal::Char(c) => Value::Char(*c),
+                };
+                let constant = self.constant(value);
+                self.emit(Instruction::Constant(constant));
+            }
+            ExpressionKind::Identifier(name) => {
+                let name = self.name(&name.symbol);
+                self.emit(Instruction::Load(name));
+            }
+            ExpressionKind::Binary { left, operator, right } => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Instruction::Binary(operator.clone()));
+            }
+            ExpressionKind::Unary { operator, operand } => {
+                self.expression(operand);
+                self.emit(Instruction::Unary(operator.clone()));
+            }
+            ExpressionKind::Input(_) => {
+                self.emit(Instruction::Input);
+            }
+            ExpressionKind::Ask(prompt) => {
+                self.expression(prompt);
+                self.emit(Instruction::Ask);
+            }
+            ExpressionKind::FunctionCall { name, arguments } => {
+                self.call(name, arguments, expression.span, false);
+            }
+            ExpressionKind::SendRaven(call) => {
+                let ExpressionKind::FunctionCall { name, arguments } = &call.kind else {
+                    unreachable!("the parser only sends calls by raven")
+                };
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                let function = self.name(name);
+                let arguments = arguments.len() as u32;
+                let site = self.call_site(call.span);
+                self.emit(Instruction::SendRaven { function, arguments, site });
+            }
+            ExpressionKind::AwaitRaven(raven) => {
+                self.expression(raven);
+                self.emit(Instruction::AwaitRaven);
+            }
+        }
+    }
+
+    /// Pushes the arguments and calls the function, as a `TailCall` if it
+    /// is being returned.
+    fn call(&mut self, name: &Symbol, arguments: &[Expression], span: Span, tail: bool) {
+        for argument in arguments {
+            self.expression(argument);
+        }
+        let function = self.name(name);
+        let arguments = arguments.len() as u32;
+        let site = self.call_site(span);
+        if tail {
+            self.emit(Instruction::TailCall { function, arguments, site });
+        } else {
+            self.emit(Instruction::Call { function, arguments, site });
+        }
+    }
+}
+
+/// A statement without the blocks it opens, which the chunk holds as code.
+fn head(statement: &Statement) -> Statement {
+    let kind = match &statement.kind {
+        StatementKind::FunctionDeclaration { name, parameters, .. } =>
+            StatementKind::FunctionDeclaration {
+                name: name.clone(),
+                parameters: parameters.clone(),
+                body: Vec::new(),
+            },
+        StatementKind::Conditional { condition, else_branch, .. } =>
+            StatementKind::Conditional {
+                condition: condition.clone(),
+                then_branch: Vec::new(),
+                else_branch: else_branch.as_ref().map(|_| Vec::new()),
+            },
+        StatementKind::ForLoop { count, .. } =>
+            StatementKind::ForLoop { count: *count, body: Vec::new() },
+        StatementKind::WhileLoop { condition, .. } =>
+            StatementKind::WhileLoop { condition: condition.clone(), body: Vec::new() },
+        StatementKind::MainBlock(_) => StatementKind::MainBlock(Vec::new()),
+        StatementKind::Trial { name, .. } => StatementKind::Trial { name: name.clone(), body: Vec::new() },
+        kind => kind.clone(),
+    };
+    Statement { kind, span: statement.span }
+}
@@ -0,0 +1,117 @@
+//! 🗺️ Finding the parts of a program that never ran.
+//!
+//! `Coverage` is an `ExecutionObserver` that counts how often each
+//! statement runs. Clones share their counts, so keep one and hand a clone
+//! to the interpreter (or to every interpreter, for a test run), then ask
+//! for a report on the program:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Coverage, Interpreter };
+//!
+//! let source = "gold is a blade with 1\nif gold > 5:\n    speak \"rich\"\n";
+//! let program = parse_program(source).unwrap();
+//! let coverage = Coverage::new();
+//! let mut interpreter = Interpreter::new(false);
+//! interpreter.add_observer(coverage.clone());
+//! interpreter.interpret(&program).unwrap();
+//!
+//! let report = coverage.report(&program);
+//! assert_eq!(report.lines[&1], 1);
+//! assert_eq!(report.uncovered(), vec![3]);
+//! ```
+
+use std::collections::{ BTreeMap, HashMap };
+use std::fmt::Write;
+use std::sync::{ Arc, Mutex, PoisonError };
+use crate::ast::{ Program, Span, Statement, StatementKind };
+use crate::observer::ExecutionObserver;
+use crate::visit::{ walk_statement, Visitor };
+
+/// Counts executed statements; see the module docs.
+#[derive(Clone, Default)]
+pub struct Coverage(Arc<Mutex<HashMap<Span, u64>>>);
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often each line of `program` that starts a statement ran.
+    /// Trials are test code, so their own lines are left out.
+    pub fn report(&self, program: &Program) -> CoverageReport {
+        let hits = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut lines = Lines { hits: &hits, lines: BTreeMap::new() };
+        lines.visit_program(program);
+        CoverageReport { lines: lines.lines }
+    }
+}
+
+impl ExecutionObserver for Coverage {
+    fn on_statement(&mut self, _statement: &Statement, span: &Span) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner).entry(*span).or_insert(0) += 1;
+    }
+}
+
+/// Gathers the counts of the statements outside trials by line.
+struct Lines<'h> {
+    hits: &'h HashMap<Span, u64>,
+    lines: BTreeMap<usize, u64>,
+}
+
+impl Visitor<'_> for Lines<'_> {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let StatementKind::Trial { .. } = statement.kind {
+            return;
+        }
+        let count = self.hits.get(&statement.span).copied().unwrap_or(0);
+        let line = self.lines.entry(statement.span.line).or_insert(0);
+        *line = count.max(*line);
+        walk_statement(self, statement);
+    }
+}
+
+/// Per-line execution counts for one program.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CoverageReport {
+    /// Each 1-based line that starts a statement, with how often the most
+    /// frequently run statement on it ran.
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl CoverageReport {
+    /// The lines with a statement that never ran.
+    pub fn uncovered(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(line, _)| *line)
+            .collect()
+    }
+
+    /// How many lines with a statement ran at least once.
+    pub fn covered_count(&self) -> usize {
+        self.lines.values().filter(|count| **count > 0).count()
+    }
+
+    /// The share of lines with a statement that ran, as a percentage.
+    /// A program without statements counts as fully covered.
+    pub fn percent(&self) -> f64 {
+        if self.lines.is_empty() {
+            return 100.0;
+        }
+        (self.covered_count() as f64 * 100.0) / (self.lines.len() as f64)
+    }
+
+    /// The report as an lcov tracefile record for `source_file`, for tools
+    /// such as `genhtml` and CI coverage services.
+    pub fn to_lcov(&self, source_file: &str) -> String {
+        let mut lcov = format!("SF:{}\n", source_file);
+        for (line, count) in &self.lines {
+            let _ = writeln!(lcov, "DA:{},{}", line, count);
+        }
+        let _ = writeln!(lcov, "LF:{}", self.lines.len());
+        let _ = writeln!(lcov, "LH:{}", self.covered_count());
+        lcov.push_str("end_of_record\n");
+        lcov
+    }
+}
@@ -0,0 +1,301 @@
+//! 🔍 Pausing a program to look inside it.
+//!
+//! A `Debugger` runs a program on a thread of its own and stops it before
+//! statements: at breakpoints (a line, or the start of a function), or one
+//! statement at a time while stepping. While the program is paused, the
+//! host can read its variables and call stack, evaluate expressions, and
+//! change breakpoints, then let it carry on.
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Breakpoint, DebugEvent, Debugger, Interpreter, Value };
+//!
+//! let source = "gold is a blade with 1\ngold = gold * 10\ngold = gold + 2\n";
+//! let program = parse_program(source).unwrap();
+//! let mut debugger = Debugger::new(Interpreter::new(false), program);
+//! debugger.add_breakpoint(Breakpoint::Line(3));
+//!
+//! let DebugEvent::Paused(pause) = debugger.resume() else { panic!("expected a pause") };
+//! assert_eq!(pause.span.line, 3);
+//! assert_eq!(pause.variables["gold"], Value::Integer(10));
+//! assert_eq!(debugger.evaluate("gold + 1").unwrap(), Value::Integer(11));
+//!
+//! assert!(matches!(debugger.resume(), DebugEvent::Finished(Ok(_))));
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::sync::{ Arc, Mutex, PoisonError };
+use crate::ast::{ Program, Span, Statement, StatementKind, Value };
+use crate::error::{ StackFrame, ValyrianError };
+use crate::interpreter::{ Interpreter, PROGRAM_STACK_SIZE };
+
+/// Where a program should stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before any statement starting on this 1-based line.
+    Line(usize),
+    /// Before the first statement of each call to this function.
+    Function(String),
+}
+
+/// Why a program stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PauseReason {
+    Breakpoint(Breakpoint),
+    Step,
+}
+
+/// A paused program, stopped just before `statement` runs.
+#[derive(Debug, Clone)]
+pub struct Pause {
+    pub reason: PauseReason,
+    pub statement: Statement,
+    pub span: Span,
+    /// Calls in progress, innermost last.
+    pub call_stack: Vec<StackFrame>,
+    /// Every variable currently bound.
+    pub variables: BTreeMap<String, Value>,
+}
+
+/// What the program did after being resumed.
+#[derive(Debug)]
+pub enum DebugEvent {
+    Paused(Pause),
+    /// The program ended, with the value its main block returned.
+    Finished(Result<Value, ValyrianError>),
+}
+
+/// How far the program may run before stopping on its own.
+#[derive(Debug, Clone, Copy)]
+enum StepMode {
+    /// Until a breakpoint.
+    Run,
+    /// Until the next statement.
+    Step,
+    /// Until the next statement no deeper than this many calls.
+    StepOver(usize),
+}
+
+enum Command {
+    Resume(StepMode),
+    Evaluate(String, Sender<Result<Value, ValyrianError>>),
+}
+
+type Breakpoints = Arc<Mutex<Vec<Breakpoint>>>;
+
+/// The program thread's side of a debugger, installed in its interpreter.
+pub(crate) struct DebugHook {
+    breakpoints: Breakpoints,
+    mode: StepMode,
+    /// A function with a breakpoint that was just entered.
+    entered: Option<String>,
+    events: Sender<DebugEvent>,
+    commands: Receiver<Command>,
+}
+
+impl DebugHook {
+    /// Notes a call, so a function breakpoint stops at its first statement.
+    pub(crate) fn enter_function(&mut self, name: &str) {
+        let breakpoint = Breakpoint::Function(name.to_string());
+        if self.breakpoints().contains(&breakpoint) {
+            self.entered = Some(name.to_string());
+        }
+    }
+
+    /// Decides whether to stop before `statement`, at call depth `depth`.
+    pub(crate) fn pause_reason(&mut self, statement: &Statement, depth: usize) -> Option<PauseReason> {
+        if let Some(name) = self.entered.take() {
+            return Some(PauseReason::Breakpoint(Breakpoint::Function(name)));
+        }
+        let line = Breakpoint::Line(statement.span.line);
+        if self.breakpoints().contains(&line) {
+            return Some(PauseReason::Breakpoint(line));
+        }
+        // Declarations were hoisted before the program started
+        if matches!(statement.kind, StatementKind::FunctionDeclaration { .. }) {
+            return None;
+        }
+        match self.mode {
+            StepMode::Step => Some(PauseReason::Step),
+            StepMode::StepOver(max_depth) if depth <= max_depth => Some(PauseReason::Step),
+            _ => None,
+        }
+    }
+
+    /// Reports the pause and blocks until told to resume, evaluating
+    /// expressions with `evaluate` in the meantime.
+    pub(crate) fn pause(
+        &mut self,
+        pause: Pause,
+        mut evaluate: impl FnMut(&str) -> Result<Value, ValyrianError>
+    ) -> Result<(), ValyrianError> {
+        let depth = pause.call_stack.len();
+        self.events.send(DebugEvent::Paused(pause)).map_err(|_| ValyrianError::Interrupted)?;
+        loop {
+            match self.commands.recv() {
+                Ok(Command::Evaluate(expression, reply)) => {
+                    let _ = reply.send(evaluate(&expression));
+                }
+                Ok(Command::Resume(mode)) => {
+                    self.mode = match mode {
+                        StepMode::StepOver(_) => StepMode::StepOver(depth),
+                        other => other,
+                    };
+                    return Ok(());
+                }
+                // The debugger was dropped
+                Err(_) => {
+                    return Err(ValyrianError::Interrupted);
+                }
+            }
+        }
+    }
+
+    fn breakpoints(&self) -> std::sync::MutexGuard<'_, Vec<Breakpoint>> {
+        self.breakpoints.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Runs a program under the host's control; see the module docs.
+///
+/// Dropping the debugger while the program is paused stops the program.
+pub struct Debugger {
+    breakpoints: Breakpoints,
+    commands: Sender<Command>,
+    events: Receiver<DebugEvent>,
+    paused: Option<Pause>,
+    finished: bool,
+}
+
+impl Debugger {
+    /// Prepares to run `program` on `interpreter`. Nothing runs until the
+    /// first `resume` or `step`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program thread cannot be started.
+    pub fn new(mut interpreter: Interpreter, program: Program) -> Self {
+        let breakpoints = Breakpoints::default();
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+
+        std::thread::Builder
+            ::new()
+            .name("mid-valyrian-debugger".into())
+            .stack_size(PROGRAM_STACK_SIZE)
+            .spawn({
+                let breakpoints = breakpoints.clone();
+                move || {
+                    // Wait for the host to start the program
+                    let mode = match command_receiver.recv() {
+                        Ok(Command::Resume(mode)) => mode,
+                        _ => {
+                            return;
+                        }
+                    };
+                    interpreter.set_debug_hook(
+                        Some(DebugHook {
+                            breakpoints,
+                            mode,
+                            entered: None,
+                            events: event_sender.clone(),
+                            commands: command_receiver,
+                        })
+                    );
+                    let result = interpreter.interpret_with_result(&program);
+                    let _ = event_sender.send(DebugEvent::Finished(result));
+                }
+            })
+            .expect("failed to start the debugger thread");
+
+        Self { breakpoints, commands, events, paused: None, finished: false }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        let mut breakpoints = self.lock_breakpoints();
+        if !breakpoints.contains(&breakpoint) {
+            breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes a breakpoint, returning whether it was set.
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let mut breakpoints = self.lock_breakpoints();
+        let before = breakpoints.len();
+        breakpoints.retain(|existing| existing != breakpoint);
+        breakpoints.len() != before
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.lock_breakpoints().clone()
+    }
+
+    /// Where the program is stopped, if it is.
+    pub fn paused(&self) -> Option<&Pause> {
+        self.paused.as_ref()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Runs until the next breakpoint or the end of the program.
+    pub fn resume(&mut self) -> DebugEvent {
+        self.run(StepMode::Run)
+    }
+
+    /// Runs one statement, stopping before the next one, even inside a
+    /// function it calls.
+    pub fn step(&mut self) -> DebugEvent {
+        self.run(StepMode::Step)
+    }
+
+    /// Runs one statement, including any calls it makes, stopping before
+    /// the next statement of the current function (or its caller).
+    pub fn step_over(&mut self) -> DebugEvent {
+        self.run(StepMode::StepOver(0))
+    }
+
+    /// Evaluates an expression in the paused program's environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError` if the program isn't paused or the
+    /// expression fails.
+    pub fn evaluate(&mut self, expression: &str) -> Result<Value, ValyrianError> {
+        if self.paused.is_none() {
+            return Err(ValyrianError::RuntimeError("the program is not paused".to_string()));
+        }
+        let (reply, answer) = mpsc::channel();
+        self.commands
+            .send(Command::Evaluate(expression.to_string(), reply))
+            .map_err(|_| ValyrianError::Interrupted)?;
+        answer.recv().unwrap_or(Err(ValyrianError::Interrupted))
+    }
+
+    fn run(&mut self, mode: StepMode) -> DebugEvent {
+        if self.finished {
+            let error = ValyrianError::RuntimeError("the program has already finished".to_string());
+            return DebugEvent::Finished(Err(error));
+        }
+        self.paused = None;
+        if self.commands.send(Command::Resume(mode)).is_err() {
+            self.finished = true;
+            return DebugEvent::Finished(Err(ValyrianError::Interrupted));
+        }
+        let event = self.events.recv().unwrap_or(DebugEvent::Finished(Err(ValyrianError::Interrupted)));
+        match &event {
+            DebugEvent::Paused(pause) => {
+                self.paused = Some(pause.clone());
+            }
+            DebugEvent::Finished(_) => {
+                self.finished = true;
+            }
+        }
+        event
+    }
+
+    fn lock_breakpoints(&self) -> std::sync::MutexGuard<'_, Vec<Breakpoint>> {
+        self.breakpoints.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
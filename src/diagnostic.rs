@@ -0,0 +1,289 @@
+//! Structured diagnostics with stable error codes.
+//!
+//! Errors and warnings carry themed prose for humans; a `Diagnostic` is the
+//! same information in a form tools can match on. Codes never change
+//! meaning once published:
+//!
+//! * `MV00xx` — parse errors
+//! * `MV01xx` — runtime errors
+//! * `MV02xx` — I/O errors
+//! * `MV03xx` — CLI and policy errors
+//! * `MV1xxx` — warnings
+
+use std::fmt;
+use std::io::{ self, Write };
+use crate::analysis::{ Warning, WarningKind };
+use crate::ast::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    /// A themed suggestion shown below the snippet when rendering.
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " (line {}, column {})", span.line, span.col)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic with the offending source line and a caret
+    /// underline beneath its span, in the style of compiler error output:
+    ///
+    /// ```text
+    /// error[MV0104]: division by zero
+    ///   --> dragons.mv:3:12
+    ///    |
+    ///  3 |     return n / 0
+    ///    |     ^^^^^^^^^^^^
+    ///    = hint: ...
+    /// ```
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+
+        if let Some(span) = self.span {
+            let line_text = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+            let gutter = " ".repeat(span.line.to_string().len());
+            out.push_str(&format!("{}--> {}:{}:{}\n", gutter, file_name, span.line, span.col));
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} | {}\n", span.line, line_text));
+
+            // Underline the span, stopping at the end of its first line
+            let before = span.col.saturating_sub(1);
+            let remaining = line_text.chars().count().saturating_sub(before);
+            let span_chars = source
+                .get(span.start..span.end)
+                .map_or(0, |text| text.lines().next().unwrap_or("").chars().count());
+            let width = span_chars.min(remaining).max(1);
+            out.push_str(
+                &format!("{} | {}{}\n", gutter, " ".repeat(before), "^".repeat(width))
+            );
+
+            for note in &self.notes {
+                out.push_str(&format!("{} = note: {}\n", gutter, note));
+            }
+            if let Some(hint) = &self.hint {
+                out.push_str(&format!("{} = hint: {}\n", gutter, hint));
+            }
+        } else {
+            for note in &self.notes {
+                out.push_str(&format!("  = note: {}\n", note));
+            }
+            if let Some(hint) = &self.hint {
+                out.push_str(&format!("  = hint: {}\n", hint));
+            }
+        }
+
+        out
+    }
+}
+
+/// Returns the themed hint shown for a diagnostic code, if it has one.
+pub fn themed_hint(code: &str) -> Option<&'static str> {
+    let hint = match code {
+        "MV0001" | "MV0002" =>
+            "🐉 The Maesters could not read this line — check the spelling and indentation",
+        "MV0101" => "🏰 Declare it first: `name is a blade with 0`",
+        "MV0102" => "🗡️ The council must declare it: `we declare name with ... ->`",
+        "MV0103" => "🍷 Convert the value or change the type",
+        "MV0104" => "❄️ Even dragons cannot divide by nothing — check the divisor first",
+        "MV0105" => "🧙 Count the offerings: pass one argument per parameter",
+        "MV0106" => "🏹 These values cannot be combined with that operator",
+        "MV0107" => "📜 Run without --strict to allow this, or fix the code",
+        "MV0108" => "⚖️ The realm expected otherwise — check the value being asserted",
+        "MV0109" => "⏳ Check that every loop has a way to end, or raise the step limit",
+        "MV0110" => "⌛ Check that every loop has a way to end, or allow more time",
+        "MV0111" => "🏦 The program holds more than the host allows — build less, or raise the limit",
+        "MV0112" => "👑 Make sure the recursion has a base case that is reached",
+        "MV0113" => "🧱 This program runs in a sandbox; pass values in through globals instead",
+        "MV0114" => "🛑 The run was stopped from outside; start it again when ready",
+        "MV0201" => "🗺️ [project] takes name, entry, and sources; [run] takes strict, deny-warnings, debug, and args",
+        "MV0202" => "🧪 Rebuild the plugin against this version of mid-valyrian, with the same compiler",
+        "MV0203" => "📜 Rename the file so it ends in .mv",
+        "MV0301" => "🌊 Rework this part of the program, or run it with the interpreter instead",
+        "MV0302" => "✒️ Pick a name nothing else in the program uses",
+        "MV1001" | "MV1002" => "⚠️ Remove it, or put it to work",
+        "MV1003" => "⚠️ The value is lost before anyone reads it",
+        "MV1004" => "⚠️ Move this code before the return, or remove it",
+        "MV1005" => "⚠️ A condition that never changes decides nothing",
+        "MV1006" => "⚠️ Pick a different name so both stay visible",
+        "MV1007" => "⚠️ Fix the condition so the branch can be taken, or remove the branch",
+        _ => {
+            return None;
+        }
+    };
+    Some(hint)
+}
+
+/// The themed hint for a type mismatch, built from the types involved.
+/// Runtime type names such as `float` are given as programs write them.
+///
+/// ```
+/// use mid_valyrian::diagnostic::type_mismatch_hint;
+///
+/// assert_eq!(
+///     type_mismatch_hint("blade", "float"),
+///     "🍷 A blade cannot hold wine — convert the value or change the type"
+/// );
+/// assert!(type_mismatch_hint("sigil", "void").starts_with("🍷 A sigil cannot hold void"));
+/// ```
+pub fn type_mismatch_hint(expected: &str, found: &str) -> String {
+    let valyrian = |name: &'_ str| -> String {
+        match name {
+            "string" => "scroll",
+            "integer" => "blade",
+            "float" => "wine",
+            "boolean" => "vow",
+            "char" => "sigil",
+            other => other,
+        }.to_string()
+    };
+    format!(
+        "🍷 A {} cannot hold {} — convert the value or change the type",
+        valyrian(expected),
+        valyrian(found)
+    )
+}
+
+/// Removes emoji (and the space that follows one at the start of a phrase)
+/// so that messages read naturally without them. This is what `--plain`
+/// does to everything written for humans.
+///
+/// ```
+/// use mid_valyrian::diagnostic::{ strip_emoji, themed_hint };
+///
+/// assert_eq!(strip_emoji("🐉 AST: Program"), "AST: Program");
+/// assert!(strip_emoji(themed_hint("MV0110").unwrap()).is_ascii());
+/// ```
+pub fn strip_emoji(text: &str) -> String {
+    let is_emoji = |c: char| {
+        matches!(
+            c as u32,
+            0x1f000..=0x1faff |
+                0x2300..=0x23ff |
+                0x2600..=0x27bf |
+                0x2b00..=0x2bff |
+                0xfe0f |
+                0x200d
+        )
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) {
+            out.push(c);
+            continue;
+        }
+        while chars.peek().is_some_and(|&next| is_emoji(next)) {
+            chars.next();
+        }
+        if chars.peek() == Some(&' ') && out.chars().last().is_none_or(char::is_whitespace) {
+            chars.next();
+        }
+    }
+    out
+}
+
+/// A writer that passes whole lines through `strip_emoji`, for output that
+/// is formatted somewhere else, such as the interpreter's debug dumps.
+pub struct PlainWriter<W: Write> {
+    inner: W,
+    line: Vec<u8>,
+}
+
+impl<W: Write> PlainWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, line: Vec::new() }
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        let plain = strip_emoji(&String::from_utf8_lossy(&self.line));
+        self.line.clear();
+        self.inner.write_all(plain.as_bytes())
+    }
+}
+
+impl<W: Write> Write for PlainWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.line.push(byte);
+            if byte == b'\n' {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for PlainWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl WarningKind {
+    /// Returns the stable diagnostic code for this kind of warning.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedVariable => "MV1001",
+            WarningKind::UnusedParameter => "MV1002",
+            WarningKind::UnreadAssignment => "MV1003",
+            WarningKind::UnreachableCode => "MV1004",
+            WarningKind::ConstantCondition => "MV1005",
+            WarningKind::Shadowing => "MV1006",
+            WarningKind::UnreachableBranch => "MV1007",
+        }
+    }
+}
+
+impl Warning {
+    /// Converts the warning into a structured diagnostic.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            code: self.kind.code(),
+            severity: Severity::Warning,
+            message: self.message.clone(),
+            span: Some(self.span),
+            notes: Vec::new(),
+            hint: themed_hint(self.kind.code()).map(String::from),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! 🔍 Reading compiled code.
+//!
+//! `disassemble` lists a chunk's instructions, one per line, followed by
+//! the chunks of the functions it declares. Each line shows the
+//! instruction's offset, the source line of the statement it belongs to
+//! (or `|` while that stays the same), the opcode, and its operands
+//! resolved against the chunk's tables:
+//!
+//! ```
+//! use mid_valyrian::{ compile, disassemble, parse_program };
+//!
+//! let chunk = compile(&parse_program("gold is a blade with 2 * 3\n").unwrap());
+//! assert_eq!(
+//!     disassemble(&chunk),
+//!     "\
+//! == main ==
+//! 0000    1 statement      gold is a blade with 2 * 3
+//! 0001    | constant       2
+//! 0002    | constant       3
+//! 0003    | binary         *
+//! 0004    | declare        gold blade
+//! 0005    | return_void
+//! "
+//! );
+//! ```
+
+use std::fmt::Write;
+use crate::ast::{ Literal, Value };
+use crate::compiler::{ Chunk, Instruction };
+use crate::printer;
+
+/// Renders `chunk` and the functions declared in it as text; see the
+/// module docs.
+pub fn disassemble(chunk: &Chunk) -> String {
+    let mut out = String::new();
+    disassemble_into(&mut out, chunk);
+    out
+}
+
+fn disassemble_into(out: &mut String, chunk: &Chunk) {
+    if chunk.parameters.is_empty() {
+        let _ = writeln!(out, "== {} ==", chunk.name);
+    } else {
+        let _ = writeln!(out, "== {} with {} ==", chunk.name, chunk.parameters.join(", "));
+    }
+
+    let mut line = None;
+    for (offset, instruction) in chunk.instructions.iter().enumerate() {
+        // Instructions up to the first statement belong to no line
+        if let Instruction::Statement(index) = instruction {
+            let statement_line = chunk.statements[*index as usize].span.line;
+            line = Some(statement_line);
+            let _ = write!(out, "{:04} {:>4} ", offset, statement_line);
+        } else if line.is_some() {
+            let _ = write!(out, "{:04}    | ", offset);
+        } else {
+            let _ = write!(out, "{:04}      ", offset);
+        }
+        let (opcode, operands) = describe(chunk, instruction);
+        if operands.is_empty() {
+            let _ = writeln!(out, "{}", opcode);
+        } else {
+            let _ = writeln!(out, "{:<14} {}", opcode, operands);
+        }
+    }
+
+    for function in &chunk.functions {
+        out.push('\n');
+        disassemble_into(out, function);
+    }
+}
+
+/// The opcode of `instruction` and its operands as text.
+fn describe(chunk: &Chunk, instruction: &Instruction) -> (&'static str, String) {
+    let name = |index: &u32| chunk.names[*index as usize].to_string();
+    let statement = |index: &u32| printer::statement_head(&chunk.statements[*index as usize]);
+    let call = |function: &u32, arguments: &u32| {
+        let plural = if *arguments == 1 { "" } else { "s" };
+        format!("{} ({} argument{})", name(function), arguments, plural)
+    };
+    match instruction {
+        Instruction::Statement(index) => ("statement", statement(index)),
+        Instruction::Resume(index) => ("resume", statement(index)),
+        Instruction::Constant(index) => ("constant", constant(&chunk.constants[*index as usize])),
+        Instruction::Load(index) => ("load", name(index)),
+        Instruction::Declare(index, data_type) =>
+            ("declare", format!("{} {}", name(index), data_type)),
+        Instruction::CheckDefined(index) => ("check_defined", name(index)),
+        Instruction::Assign(index) => ("assign", name(index)),
+        Instruction::Binary(operator) => ("binary", printer::binary_operator(operator).to_string()),
+        Instruction::Unary(operator) => ("unary", printer::unary_operator(operator).to_string()),
+        Instruction::Input => ("input", String::new()),
+        Instruction::Ask => ("ask", String::new()),
+        Instruction::Call { function, arguments, .. } => ("call", call(function, arguments)),
+        Instruction::CallStatement { function, arguments, .. } =>
+            ("call_statement", call(function, arguments)),
+        Instruction::TailCall { function, arguments, .. } => ("tail_call", call(function, arguments)),
+        Instruction::SendRaven { function, arguments, .. } => ("send_raven", call(function, arguments)),
+        Instruction::AwaitRaven => ("await_raven", String::new()),
+        Instruction::Speak => ("speak", String::new()),
+        Instruction::Jump(target) => ("jump", format!("-> {:04}", target)),
+        Instruction::JumpUnless(target) => ("jump_unless", format!("-> {:04}", target)),
+        Instruction::Counter(count) => ("counter", count.to_string()),
+        Instruction::CountDown(target) => ("count_down", format!("-> {:04}", target)),
+        Instruction::DeclareFunction(index) =>
+            ("function", chunk.functions[*index as usize].name.to_string()),
+        Instruction::Return => ("return", String::new()),
+        Instruction::ReturnVoid => ("return_void", String::new()),
+    }
+}
+
+/// A constant as it would be written in source.
+fn constant(value: &Value) -> String {
+    let literal = match value {
+        Value::String(s) => Literal::String(s.clone()),
+        Value::Integer(i) => Literal::Integer(*i),
+        Value::Float(f) => Literal::Float(*f),
+        Value::Boolean(b) => Literal::Boolean(*b),
+        Value::Char(c) => Literal::Char(*c),
+        Value::Void | Value::External(_) | Value::Raven(_) | Value::Rookery(_) | Value::Vault(_) | Value::Tidings(_) | Value::Missive(_) | Value::Socket(_) => {
+            return value.to_string();
+        }
+    };
+    printer::literal(&literal)
+}
@@ -0,0 +1,84 @@
+//! 📅 Editions of the language.
+//!
+//! Some changes to the language would break scrolls written before them.
+//! Each such change comes with a new edition, which a file opts into with
+//! a `canon` line at its top; a file without one is read as canon 2024,
+//! the language as it first was. The editions so far:
+//!
+//! * **canon 2024**: operators apply strictly from left to right, so
+//!   `1 + 2 * 3` is 9.
+//! * **canon 2025**: `*` and `/` bind tighter than `+` and `-`, which bind
+//!   tighter than `>`, `<`, `==` and `!=`, so `1 + 2 * 3` is 7.
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Edition, Interpreter };
+//!
+//! let source = "canon 2025\n\non the iron throne:\n    return 1 + 2 * 3\n";
+//! let program = parse_program(source).unwrap();
+//! assert_eq!(program.edition, Edition::Canon2025);
+//! let seven = Interpreter::new(false).interpret_with_result(&program).unwrap();
+//! assert_eq!(seven.to_string(), "7");
+//!
+//! let program = parse_program("on the iron throne:\n    return 1 + 2 * 3\n").unwrap();
+//! assert_eq!(program.edition, Edition::Canon2024);
+//! let nine = Interpreter::new(false).interpret_with_result(&program).unwrap();
+//! assert_eq!(nine.to_string(), "9");
+//! ```
+//!
+//! The edition is settled when a file is parsed, so the tree records what
+//! the source meant in it: tools working on trees, such as the
+//! interpreter, need not ask. The printer writes the `canon` line back.
+
+use std::fmt;
+use crate::ast::BinaryOperator;
+
+/// The edition a program is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Edition {
+    /// The language as it first was, for files without a `canon` line.
+    #[default]
+    Canon2024,
+    /// Operators have precedence.
+    Canon2025,
+}
+
+impl Edition {
+    /// The newest edition.
+    pub const LATEST: Edition = Edition::Canon2025;
+
+    /// Every edition, oldest first.
+    pub const ALL: &'static [Edition] = &[Edition::Canon2024, Edition::Canon2025];
+
+    /// The edition of a `canon` line giving `year`, if there is one.
+    pub fn from_year(year: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|edition| edition.year() == year)
+    }
+
+    pub fn year(self) -> u32 {
+        match self {
+            Edition::Canon2024 => 2024,
+            Edition::Canon2025 => 2025,
+        }
+    }
+
+    /// How tightly `operator` binds in this edition; of two operators in a
+    /// row, the one that binds tighter applies first, and the left one if
+    /// they bind alike.
+    pub(crate) fn precedence(self, operator: &BinaryOperator) -> u8 {
+        match (self, operator) {
+            (Edition::Canon2024, _) => 0,
+            (_, BinaryOperator::Multiply | BinaryOperator::Divide) => 2,
+            (_, BinaryOperator::Add | BinaryOperator::Subtract) => 1,
+            (_, _) => 0,
+        }
+    }
+}
+
+/// The edition's `canon` line, e.g. `canon 2025`.
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "canon {}", self.year())
+    }
+}
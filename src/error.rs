@@ -1,4 +1,17 @@
 use thiserror::Error;
+use crate::ast::{ Span, Symbol };
+use crate::diagnostic::{ Diagnostic, Severity };
+use crate::parser::Rule;
+
+/// One active Valyrian function call, recorded for stack traces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: Symbol,
+    pub caller: Symbol,
+    pub call_site: Span,
+    /// How many tail calls ran in this frame before `function`.
+    pub elided: usize,
+}
 
 #[derive(Error, Debug)]
 pub enum ValyrianError {
@@ -18,15 +31,73 @@ pub enum ValyrianError {
     #[error("❄️ The Night King has entered your call stack (division by zero)")]
     DivisionByZero,
 
-    #[error("🔥 Dracarys! Your program has been consumed by flames: {0}")] IoError(String),
+    #[error("🔥 Dracarys! Your program has been consumed by flames: {message}")] IoError {
+        message: String,
+        #[source]
+        source: std::io::Error,
+    },
 
-    #[error("👑 The Iron Throne demands better syntax: {0}")] SyntaxError(String),
+    #[error("👑 The Iron Throne demands better syntax at line {line}, column {column}: {message}")]
+    SyntaxError {
+        message: String,
+        line: usize,
+        column: usize,
+        /// Byte offset of the error in the source.
+        offset: usize,
+        /// The full text of the offending source line.
+        source_line: String,
+        /// The error reported by the pest grammar.
+        #[source]
+        source: Box<pest::error::Error<Rule>>,
+    },
 
     #[error(
         "🧙‍♂️ The Red Priest miscounted the offerings — expected a different number of arguments"
     )]
     ArgumentMismatch,
 
+    #[error("🛡️ The realm refuses to march: {0} warning(s) raised with warnings denied")]
+    WarningsDenied(usize),
+
+    #[error("📜 Strict mode forbids this: {0}")] StrictViolation(String),
+
+    #[error("⏳ The long night never ended: the program ran more than {0} steps")]
+    ExecutionLimitExceeded(u64),
+
+    #[error("⌛ Winter came before the program finished (time limit of {0:?})")]
+    Timeout(std::time::Duration),
+
+    #[error("👑 The call stack has bent the knee: calls nested more than {0} deep")]
+    StackOverflow(usize),
+
+    #[error("🧱 The Wall stands in the way: {0}")] SandboxViolation(String),
+
+    #[error("🛑 The host called the watch back: the program was interrupted")]
+    Interrupted,
+
+    #[error("🏦 The Iron Bank refuses further credit: {0}")] ResourceLimit(String),
+
+    #[error("⚖️ The trial by combat was lost: {0}")] AssertionFailed(String),
+
+    #[error("🗺️ The project manifest is unreadable: {0}")] ManifestError(String),
+
+    #[error("🧪 The alchemists' plugin would not take: {0}")] PluginError(String),
+
+    #[error("📜 The Maesters read only scrolls ending in {}: '{0}' is not one", extension_list())]
+    WrongExtension(String),
+
+    #[error("🌊 The ship cannot cross the Narrow Sea at line {}, column {}: {message}", .span.line, .span.col)]
+    TranspileError {
+        message: String,
+        span: Span,
+    },
+
+    #[error("✒️ The maesters cannot rewrite the scroll at line {}, column {}: {message}", .span.line, .span.col)]
+    RenameError {
+        message: String,
+        span: Span,
+    },
+
     #[error(
         "🏹 Arrows must fly true: Invalid operation {op} on {left_type} and {right_type}"
     )] InvalidOperation {
@@ -34,15 +105,265 @@ pub enum ValyrianError {
         left_type: String,
         right_type: String,
     },
+
+    #[error("{}{}", .error, render_trace(.trace))] Traced {
+        #[source]
+        error: Box<ValyrianError>,
+        /// The statement that was executing when the error occurred.
+        span: Span,
+        /// Active calls when the error occurred, innermost first.
+        trace: Vec<StackFrame>,
+    },
+}
+
+/// The source extensions, as `.mv or .valyrian`.
+fn extension_list() -> String {
+    crate::SOURCE_EXTENSIONS
+        .iter()
+        .map(|extension| format!(".{}", extension))
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+fn render_trace(trace: &[StackFrame]) -> String {
+    trace_lines(trace)
+        .iter()
+        .map(|line| format!("\n    {}", line))
+        .collect()
+}
+
+/// Describes each frame of a trace, folding runs of identical frames (as
+/// deep recursion produces) into a single line with a count.
+fn trace_lines(trace: &[StackFrame]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut i = 0;
+    while i < trace.len() {
+        let frame = &trace[i];
+        let repeats = trace[i..].iter().take_while(|other| *other == frame).count();
+        let mut line = format!(
+            "in `{}` called from `{}` at line {}, column {}",
+            frame.function,
+            frame.caller,
+            frame.call_site.line,
+            frame.call_site.col
+        );
+        if frame.elided > 0 {
+            line.push_str(&format!(" ({} tail call(s) elided)", frame.elided));
+        }
+        lines.push(line);
+        if repeats > 1 {
+            lines.push(format!("... the same call {} more time(s)", repeats - 1));
+        }
+        i += repeats;
+    }
+    lines
 }
 
 impl From<std::io::Error> for ValyrianError {
     fn from(error: std::io::Error) -> Self {
-        ValyrianError::IoError(error.to_string())
+        ValyrianError::IoError {
+            message: error.to_string(),
+            source: error,
+        }
+    }
+}
+
+impl From<pest::error::Error<Rule>> for ValyrianError {
+    fn from(error: pest::error::Error<Rule>) -> Self {
+        let (line, column) = match error.line_col {
+            pest::error::LineColLocation::Pos(pos) => pos,
+            pest::error::LineColLocation::Span(start, _) => start,
+        };
+        let offset = match error.location {
+            pest::error::InputLocation::Pos(pos) => pos,
+            pest::error::InputLocation::Span((start, _)) => start,
+        };
+        ValyrianError::SyntaxError {
+            message: error.variant.message().into_owned(),
+            line,
+            column,
+            offset,
+            source_line: error.line().to_string(),
+            source: Box::new(error),
+        }
     }
 }
 
 impl ValyrianError {
+    /// Returns the 1-based `(line, column)` the error points at, if known.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            ValyrianError::SyntaxError { line, column, .. } => Some((*line, *column)),
+            ValyrianError::Traced { span, .. } |
+            ValyrianError::TranspileError { span, .. } |
+            ValyrianError::RenameError { span, .. } => Some((span.line, span.col)),
+            _ => None,
+        }
+    }
+
+    /// Returns the region of source the error points at, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ValyrianError::SyntaxError { line, column, offset, .. } =>
+                Some(Span::new(*offset, *offset, *line, *column)),
+            ValyrianError::Traced { span, .. } |
+            ValyrianError::TranspileError { span, .. } |
+            ValyrianError::RenameError { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Returns the process exit code the CLI uses for this error, so scripts
+    /// can tell failures apart:
+    ///
+    /// * `2` — the program was rejected before running (parse errors, denied
+    ///   warnings, bad manifest or plugin, a program that can't be
+    ///   transpiled, a rename that can't be made)
+    /// * `3` — runtime error
+    /// * `4` — I/O error, or a file without a source extension
+    /// * `5` — an `assert` or `assert_equal` failed
+    pub fn exit_code(&self) -> i32 {
+        match self.root() {
+            ValyrianError::ParseError(_) |
+            ValyrianError::SyntaxError { .. } |
+            ValyrianError::WarningsDenied(_) |
+            ValyrianError::ManifestError(_) |
+            ValyrianError::PluginError(_) |
+            ValyrianError::TranspileError { .. } |
+            ValyrianError::RenameError { .. } => 2,
+            ValyrianError::IoError { .. } | ValyrianError::WrongExtension(_) => 4,
+            ValyrianError::AssertionFailed(_) => 5,
+            _ => 3,
+        }
+    }
+
+    /// Returns the stable diagnostic code identifying this kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValyrianError::ParseError(_) => "MV0001",
+            ValyrianError::SyntaxError { .. } => "MV0002",
+            ValyrianError::RuntimeError(_) => "MV0100",
+            ValyrianError::UndefinedVariable(_) => "MV0101",
+            ValyrianError::UndefinedFunction(_) => "MV0102",
+            ValyrianError::TypeError { .. } => "MV0103",
+            ValyrianError::DivisionByZero => "MV0104",
+            ValyrianError::ArgumentMismatch => "MV0105",
+            ValyrianError::InvalidOperation { .. } => "MV0106",
+            ValyrianError::StrictViolation(_) => "MV0107",
+            ValyrianError::AssertionFailed(_) => "MV0108",
+            ValyrianError::ExecutionLimitExceeded(_) => "MV0109",
+            ValyrianError::Timeout(_) => "MV0110",
+            ValyrianError::ResourceLimit(_) => "MV0111",
+            ValyrianError::StackOverflow(_) => "MV0112",
+            ValyrianError::SandboxViolation(_) => "MV0113",
+            ValyrianError::Interrupted => "MV0114",
+            ValyrianError::IoError { .. } => "MV0200",
+            ValyrianError::ManifestError(_) => "MV0201",
+            ValyrianError::PluginError(_) => "MV0202",
+            ValyrianError::WrongExtension(_) => "MV0203",
+            ValyrianError::WarningsDenied(_) => "MV0300",
+            ValyrianError::TranspileError { .. } => "MV0301",
+            ValyrianError::RenameError { .. } => "MV0302",
+            ValyrianError::Traced { error, .. } => error.code(),
+        }
+    }
+
+    /// Returns a plain description of the error, without theming or location.
+    pub fn plain_message(&self) -> String {
+        match self {
+            ValyrianError::ParseError(message) => message.clone(),
+            ValyrianError::SyntaxError { message, .. } => message.clone(),
+            ValyrianError::RuntimeError(message) => message.clone(),
+            ValyrianError::UndefinedVariable(name) => format!("undefined variable '{}'", name),
+            ValyrianError::UndefinedFunction(name) => format!("undefined function '{}'", name),
+            ValyrianError::TypeError { expected, found } =>
+                format!("type mismatch: expected {}, found {}", expected, found),
+            ValyrianError::DivisionByZero => "division by zero".to_string(),
+            ValyrianError::ArgumentMismatch => "wrong number of arguments".to_string(),
+            ValyrianError::InvalidOperation { op, left_type, right_type } =>
+                format!("invalid operation {} on {} and {}", op, left_type, right_type),
+            ValyrianError::StrictViolation(message) => message.clone(),
+            ValyrianError::ExecutionLimitExceeded(max) =>
+                format!("execution limit of {} steps exceeded", max),
+            ValyrianError::Timeout(limit) => format!("time limit of {:?} exceeded", limit),
+            ValyrianError::ResourceLimit(message) => message.clone(),
+            ValyrianError::SandboxViolation(message) => message.clone(),
+            ValyrianError::Interrupted => "interrupted by the host".to_string(),
+            ValyrianError::StackOverflow(depth) =>
+                format!("call depth limit of {} exceeded", depth),
+            ValyrianError::AssertionFailed(message) => format!("assertion failed: {}", message),
+            ValyrianError::IoError { message, .. } => message.clone(),
+            ValyrianError::ManifestError(message) => format!("invalid manifest: {}", message),
+            ValyrianError::PluginError(message) => format!("plugin failed to load: {}", message),
+            ValyrianError::WrongExtension(path) =>
+                format!("'{}' is not a source file: its name must end with {}", path, extension_list()),
+            ValyrianError::WarningsDenied(count) =>
+                format!("{} warning(s) raised with warnings denied", count),
+            ValyrianError::TranspileError { message, .. } |
+            ValyrianError::RenameError { message, .. } => message.clone(),
+            ValyrianError::Traced { error, .. } => error.plain_message(),
+        }
+    }
+
+    /// Converts the error into a structured diagnostic.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let notes = trace_lines(self.stack_trace());
+        let hint = match self.root() {
+            ValyrianError::TypeError { expected, found } =>
+                Some(crate::diagnostic::type_mismatch_hint(expected, found)),
+            _ => crate::diagnostic::themed_hint(self.code()).map(String::from),
+        };
+        Diagnostic {
+            code: self.code(),
+            severity: Severity::Error,
+            message: self.plain_message(),
+            span: self.span(),
+            notes,
+            hint,
+        }
+    }
+
+    /// Creates an I/O error with a description of what was being attempted.
+    pub fn io(message: impl Into<String>, source: std::io::Error) -> Self {
+        ValyrianError::IoError {
+            message: message.into(),
+            source,
+        }
+    }
+
+    /// Returns the kind of the underlying I/O error, if this is one.
+    pub fn io_error_kind(&self) -> Option<std::io::ErrorKind> {
+        match self.root() {
+            ValyrianError::IoError { source, .. } => Some(source.kind()),
+            _ => None,
+        }
+    }
+
+    /// Returns the original pest error behind a syntax error.
+    pub fn pest_error(&self) -> Option<&pest::error::Error<Rule>> {
+        match self.root() {
+            ValyrianError::SyntaxError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying error, looking through any attached stack trace.
+    pub fn root(&self) -> &ValyrianError {
+        match self {
+            ValyrianError::Traced { error, .. } => error.root(),
+            other => other,
+        }
+    }
+
+    /// Returns the Valyrian call stack active when the error occurred,
+    /// innermost call first. Empty for errors raised outside any function.
+    pub fn stack_trace(&self) -> &[StackFrame] {
+        match self {
+            ValyrianError::Traced { trace, .. } => trace,
+            _ => &[],
+        }
+    }
+
     pub fn type_error(expected: &str, found: &str) -> Self {
         ValyrianError::TypeError {
             expected: expected.to_string(),
@@ -0,0 +1,316 @@
+//! 🔗 A C interface, for embedding the interpreter in C, C++, or any
+//! runtime that can call C functions. Enabled by the `ffi` feature; the
+//! declarations are in `include/mid_valyrian.h`.
+//!
+//! `cargo build --release --features ffi` builds a shared library to link
+//! against (`cargo rustc --lib --release --features ffi --crate-type
+//! staticlib` builds a static one). Functions that fail return null (or
+//! `false`) and leave a message for `mv_last_error`. Every `MvValue` and
+//! string handed out must be released with `mv_value_free` or
+//! `mv_string_free`.
+
+use std::cell::RefCell;
+use std::ffi::{ c_char, CStr, CString };
+use std::ptr;
+use crate::ast::Value;
+use crate::error::ValyrianError;
+use crate::interpreter::Interpreter;
+use crate::parser::parse_program;
+
+/// An interpreter owned by C code.
+pub struct MvInterpreter(Interpreter);
+
+/// A value owned by C code.
+pub struct MvValue(Value);
+
+/// The kind of an `MvValue`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MvValueKind {
+    Scroll,
+    Blade,
+    Wine,
+    Vow,
+    Sigil,
+    Void,
+    External,
+    Raven,
+    Rookery,
+    Vault,
+    Tidings,
+    Missive,
+    Socket,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior NULs can't cross into C; cut the message there
+    let message = message.split('\0').next().unwrap_or_default().to_string();
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = CString::new(message).ok();
+    });
+}
+
+/// Hands a result to C: the value on success, or null after recording the
+/// error.
+fn into_c(result: Result<Value, ValyrianError>) -> *mut MvValue {
+    match result {
+        Ok(value) => Box::into_raw(Box::new(MvValue(value))),
+        Err(error) => {
+            set_last_error(format!("{}: {}", error.code(), error.plain_message()));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Reads a C string argument, recording an error if it is null or not UTF-8.
+unsafe fn read_str<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        set_last_error("a null string was passed".to_string());
+        return None;
+    }
+    match CStr::from_ptr(text).to_str() {
+        Ok(text) => Some(text),
+        Err(_) => {
+            set_last_error("the string is not valid UTF-8".to_string());
+            None
+        }
+    }
+}
+
+/// The message of the last error on this thread, or null if nothing has
+/// failed yet. The string stays valid until the next failing call on the
+/// same thread; don't free it.
+#[no_mangle]
+pub extern "C" fn mv_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Runs a whole program in a fresh interpreter, returning the value its
+/// main block hands back (`Void` if none), or null on failure.
+///
+/// # Safety
+///
+/// `code` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mv_run_code(code: *const c_char) -> *mut MvValue {
+    match read_str(code) {
+        Some(code) => into_c(crate::run_code_with_result(code, false)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Creates an interpreter whose variables and functions persist between
+/// calls to `mv_interpreter_run` and `mv_interpreter_eval`.
+#[no_mangle]
+pub extern "C" fn mv_interpreter_new() -> *mut MvInterpreter {
+    Box::into_raw(Box::new(MvInterpreter(Interpreter::new(false))))
+}
+
+/// Frees an interpreter. Null is ignored.
+///
+/// # Safety
+///
+/// `interpreter` must be null or come from `mv_interpreter_new`, and must
+/// not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mv_interpreter_free(interpreter: *mut MvInterpreter) {
+    if !interpreter.is_null() {
+        drop(Box::from_raw(interpreter));
+    }
+}
+
+/// Runs program text against the interpreter's state, returning the value
+/// of a top-level `return` (`Void` if none), or null on failure.
+///
+/// # Safety
+///
+/// `interpreter` must come from `mv_interpreter_new`, and `code` must be
+/// null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mv_interpreter_run(
+    interpreter: *mut MvInterpreter,
+    code: *const c_char
+) -> *mut MvValue {
+    let Some(interpreter) = interpreter.as_mut() else {
+        set_last_error("a null interpreter was passed".to_string());
+        return ptr::null_mut();
+    };
+    match read_str(code) {
+        Some(code) =>
+            into_c(
+                parse_program(code).and_then(|program| {
+                    interpreter.0.run_statements_with_result(&program.statements)
+                })
+            ),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Evaluates an expression against the interpreter's state, returning its
+/// value, or null on failure.
+///
+/// # Safety
+///
+/// `interpreter` must come from `mv_interpreter_new`, and `expression`
+/// must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mv_interpreter_eval(
+    interpreter: *mut MvInterpreter,
+    expression: *const c_char
+) -> *mut MvValue {
+    let Some(interpreter) = interpreter.as_mut() else {
+        set_last_error("a null interpreter was passed".to_string());
+        return ptr::null_mut();
+    };
+    match read_str(expression) {
+        Some(expression) => into_c(interpreter.0.eval_expression(expression)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Frees a value. Null is ignored.
+///
+/// # Safety
+///
+/// `value` must be null or a value returned by this library, and must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_free(value: *mut MvValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// The kind of a value, telling which accessor applies.
+///
+/// # Safety
+///
+/// `value` must be a live value returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_kind(value: *const MvValue) -> MvValueKind {
+    match &(*value).0 {
+        Value::String(_) => MvValueKind::Scroll,
+        Value::Integer(_) => MvValueKind::Blade,
+        Value::Float(_) => MvValueKind::Wine,
+        Value::Boolean(_) => MvValueKind::Vow,
+        Value::Char(_) => MvValueKind::Sigil,
+        Value::Void => MvValueKind::Void,
+        Value::External(_) => MvValueKind::External,
+        Value::Raven(_) => MvValueKind::Raven,
+        Value::Rookery(_) => MvValueKind::Rookery,
+        Value::Vault(_) => MvValueKind::Vault,
+        Value::Tidings(_) => MvValueKind::Tidings,
+        Value::Missive(_) => MvValueKind::Missive,
+        Value::Socket(_) => MvValueKind::Socket,
+    }
+}
+
+/// Reads a blade, writing it to `out`. Returns false if the value is
+/// something else.
+///
+/// # Safety
+///
+/// `value` must be a live value returned by this library, and `out` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_as_blade(value: *const MvValue, out: *mut i64) -> bool {
+    match (*value).0 {
+        Value::Integer(i) => {
+            *out = i;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Reads a wine, writing it to `out`. Returns false if the value is
+/// something else.
+///
+/// # Safety
+///
+/// `value` must be a live value returned by this library, and `out` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_as_wine(value: *const MvValue, out: *mut f64) -> bool {
+    match (*value).0 {
+        Value::Float(f) => {
+            *out = f;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Reads a vow, writing it to `out`. Returns false if the value is
+/// something else.
+///
+/// # Safety
+///
+/// `value` must be a live value returned by this library, and `out` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_as_vow(value: *const MvValue, out: *mut bool) -> bool {
+    match (*value).0 {
+        Value::Boolean(b) => {
+            *out = b;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Reads a sigil as a Unicode code point, writing it to `out`. Returns
+/// false if the value is something else.
+///
+/// # Safety
+///
+/// `value` must be a live value returned by this library, and `out` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_as_sigil(value: *const MvValue, out: *mut u32) -> bool {
+    match (*value).0 {
+        Value::Char(c) => {
+            *out = c as u32;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Formats any value as `speak` would, as a new string to be released
+/// with `mv_string_free`. Returns null if the text contains a NUL.
+///
+/// # Safety
+///
+/// `value` must be a live value returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn mv_value_to_string(value: *const MvValue) -> *mut c_char {
+    match CString::new((*value).0.to_string()) {
+        Ok(text) => text.into_raw(),
+        Err(_) => {
+            set_last_error("the value contains a NUL character".to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a string returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `text` must be null or come from `mv_value_to_string`, and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mv_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
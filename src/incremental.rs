@@ -0,0 +1,145 @@
+//! ✂️ Reparsing after an edit.
+//!
+//! Editors reparse on every keystroke, and most keystrokes change a single
+//! statement. `reparse` takes the program parsed from the old text and the
+//! edit made to it, parses again only the top-level statements the edit
+//! touches, and moves the spans of those after it:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, reparse, TextEdit };
+//!
+//! let source = "we declare greet with name ->\ncouncil says:\n    speak name\non the iron throne:\n    greet with \"Arya\"\n";
+//! let mut program = parse_program(source).unwrap();
+//!
+//! let at = source.find("Arya").unwrap();
+//! let edit = TextEdit::new(at..at + 4, "Sansa");
+//! reparse(&mut program, source, &edit).unwrap();
+//! assert_eq!(program, parse_program(&edit.apply(source)).unwrap());
+//! ```
+//!
+//! The result is always what `parse_program` would give for the new text.
+//! When the statements around an edit can't be parsed on their own (the
+//! edit opens a scroll literal, say, or indents a line into the block
+//! above), the whole text is parsed instead.
+
+use std::ops::Range;
+use crate::ast::*;
+use crate::error::ValyrianError;
+use crate::parser::{ parse_program, parse_program_in };
+use crate::visit::{ walk_expression_mut, walk_statement_mut, VisitorMut };
+
+/// The bytes in `range` of a text replaced with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self { range, text: text.into() }
+    }
+
+    /// The text after the edit. Panics, like slicing, if the range isn't
+    /// within `source` on character boundaries.
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = source.to_string();
+        edited.replace_range(self.range.clone(), &self.text);
+        edited
+    }
+
+    /// How many bytes longer the edit makes the text.
+    fn growth(&self) -> isize {
+        (self.text.len() as isize) - (self.range.len() as isize)
+    }
+}
+
+/// Updates `program`, parsed from `old_source`, to match the text after
+/// `edit`. On a syntax error `program` is left as it was.
+pub fn reparse(program: &mut Program, old_source: &str, edit: &TextEdit) -> Result<(), ValyrianError> {
+    let new_source = edit.apply(old_source);
+    match reparse_region(program, old_source, &new_source, edit) {
+        Some(patched) => *program = patched,
+        None => *program = parse_program(&new_source)?,
+    }
+    Ok(())
+}
+
+/// The program with the statements `edit` touches parsed again, or `None`
+/// if they can't be parsed apart from the rest.
+fn reparse_region(program: &Program, old_source: &str, new_source: &str, edit: &TextEdit) -> Option<Program> {
+    let statements = &program.statements;
+    // Top-level statements start at the start of a line, so each one runs
+    // up to the line the next one starts on
+    let starts: Vec<usize> = statements.iter().map(|statement| statement.span.start).collect();
+
+    // The first statement touched, stepping back one when the edit starts
+    // right at a statement, in case it indents that line into the block
+    // above. Text before the first statement counts as part of it.
+    let first = starts.partition_point(|&start| start < edit.range.start).saturating_sub(1);
+    let last = starts.partition_point(|&start| start <= edit.range.end).max(first + 1).min(starts.len());
+    let (start, line) = match statements.get(first) {
+        Some(statement) if first > 0 => (statement.span.start, statement.span.line),
+        _ => (0, 1),
+    };
+    let old_end = starts.get(last).copied().unwrap_or(old_source.len());
+    let new_end = old_end.checked_add_signed(edit.growth())?;
+
+    // The region holds the canon line, if there is one, only when it
+    // starts the text. Changing the edition changes how the rest reads.
+    let region = &new_source[start..new_end];
+    let region = if start == 0 { parse_program(region) } else { parse_program_in(region, program.edition) };
+    let mut region = region.ok().filter(|region| region.edition == program.edition)?.statements;
+    // A main block without indentation runs to the end of the file
+    let to_end = new_end == new_source.len();
+    if !to_end && region.iter().any(|statement| matches!(statement.kind, StatementKind::MainBlock(_))) {
+        return None;
+    }
+    let mut shift = Shift { offset: start as isize, lines: line as isize - 1 };
+    for statement in &mut region {
+        shift.visit_statement_mut(statement);
+    }
+
+    let mut shift = Shift { offset: edit.growth(), lines: line_growth(old_source, edit) };
+    let mut patched = statements[..first].to_vec();
+    patched.extend(region);
+    for statement in &statements[last..] {
+        let mut statement = statement.clone();
+        shift.visit_statement_mut(&mut statement);
+        patched.push(statement);
+    }
+    Some(Program { statements: patched, edition: program.edition })
+}
+
+/// How many lines longer `edit` makes `source`.
+fn line_growth(source: &str, edit: &TextEdit) -> isize {
+    let lines = |text: &str| text.matches('\n').count() as isize;
+    lines(&edit.text) - lines(&source[edit.range.clone()])
+}
+
+/// Moves every span of the nodes it visits down by `lines` lines and
+/// `offset` bytes. Columns stay put, since whole lines move together.
+pub(crate) struct Shift {
+    pub(crate) offset: isize,
+    pub(crate) lines: isize,
+}
+
+impl Shift {
+    fn span(&self, span: &mut Span) {
+        span.start = span.start.saturating_add_signed(self.offset);
+        span.end = span.end.saturating_add_signed(self.offset);
+        span.line = span.line.saturating_add_signed(self.lines);
+    }
+}
+
+impl VisitorMut for Shift {
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        self.span(&mut statement.span);
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        self.span(&mut expression.span);
+        walk_expression_mut(self, expression);
+    }
+}
@@ -0,0 +1,49 @@
+//! 🔗 Sharing repeated text.
+//!
+//! An `Interner` hands out one `Symbol` per distinct string, so the names
+//! and literals of a program are stored once and copied by reference:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use mid_valyrian::Interner;
+//!
+//! let mut interner = Interner::new();
+//! let first = interner.intern("dragons");
+//! let second = interner.intern("dragons");
+//! assert!(Arc::ptr_eq(&first, &second));
+//! assert_eq!(interner.len(), 1);
+//! ```
+
+use std::collections::HashSet;
+use crate::ast::Symbol;
+
+/// A set of symbols; see the module docs.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    symbols: HashSet<Symbol>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol for `text`, made the first time it is asked for.
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(text) {
+            return symbol.clone();
+        }
+        let symbol = Symbol::from(text);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    /// How many distinct symbols have been handed out.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
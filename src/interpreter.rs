@@ -1,91 +1,1206 @@
-use std::collections::HashMap;
-use std::io::{ self, Write };
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::io::{ self, BufRead, BufWriter, Write };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ Arc, Mutex, OnceLock, PoisonError };
+use std::time::{ Duration, Instant };
 use crate::ast::*;
-use crate::error::ValyrianError;
+use crate::builtins;
+use crate::compiler::{ self, Chunk };
+use crate::error::{ StackFrame, ValyrianError };
+use crate::debugger::{ DebugHook, Pause };
+use crate::observer::ExecutionObserver;
+use crate::plugin::Plugin;
+use tracing::{ debug_span, info, info_span, trace };
 
+mod command;
+mod frame;
+mod http;
+mod raven;
+mod socket;
+mod task;
+mod timers;
+mod variables;
+mod vm;
+
+pub use command::Tidings;
+pub use http::Missive;
+pub use raven::{ Raven, Rookery, Vault };
+pub use socket::Socket;
+pub use task::{ Progress, Suspension, Task };
+use timers::Timers;
+use variables::Variables;
+use vm::ResolvedChunk;
+
+/// A function supplied by the host application, callable from Valyrian code
+/// like any builtin.
+pub type NativeFunction = Arc<dyn Fn(&[Value]) -> Result<Value, ValyrianError> + Send + Sync>;
+
+/// An in-memory writer whose clones share one buffer, for capturing what a
+/// program speaks.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+///
+/// let output = OutputBuffer::new();
+/// let mut interpreter = Interpreter::new(false).with_output(output.clone());
+/// let program = parse_program("on the iron throne:\n    speak \"Dracarys\"\n").unwrap();
+/// interpreter.interpret(&program).unwrap();
+/// assert_eq!(output.contents(), "Dracarys\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns everything written so far, decoded as UTF-8.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.bytes()).into_owned()
+    }
+
+    /// Discards everything written so far.
+    pub fn clear(&self) {
+        self.bytes().clear();
+    }
+
+    fn bytes(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        // A panic mid-write leaves the bytes intact, so poisoning is harmless
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stops a running program from another thread, e.g. behind a "Stop"
+/// button. The interpreter checks the handle before every statement and
+/// while it sleeps, and fails with an `Interrupted` error once it has been
+/// cancelled.
+///
+/// A cancelled handle stays cancelled, so a request made just before a
+/// run starts is not lost; call `reset` before running again.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter, ValyrianError };
+///
+/// let mut interpreter = Interpreter::new(false);
+/// let stop = interpreter.cancellation_handle();
+/// std::thread::spawn(move || stop.cancel());
+///
+/// let program = parse_program("while aye:\n    speak \"Hold the door\"\n").unwrap();
+/// let mut interpreter = interpreter.with_output(std::io::sink());
+/// let error = interpreter.interpret(&program).unwrap_err();
+/// assert!(matches!(error.root(), ValyrianError::Interrupted));
+/// ```
+///
+/// A program asleep wakes up early to stop:
+///
+/// ```
+/// use std::time::{ Duration, Instant };
+/// use mid_valyrian::{ parse_program, Interpreter, ValyrianError };
+///
+/// let mut interpreter = Interpreter::new(false);
+/// let stop = interpreter.cancellation_handle();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_millis(50));
+///     stop.cancel();
+/// });
+///
+/// let started = Instant::now();
+/// let error = interpreter.interpret(&parse_program("sleep with 5000\n").unwrap()).unwrap_err();
+/// assert!(matches!(error.root(), ValyrianError::Interrupted));
+/// assert!(started.elapsed() < Duration::from_secs(2));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationHandle(Arc<AtomicBool>);
+
+impl CancellationHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the program to stop before its next statement.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Withdraws a cancellation so the interpreter can run again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// How a program talks to the world outside the interpreter.
+pub(crate) struct HostIo {
+    pub(crate) output: Box<dyn Write + Send>,
+    pub(crate) input: Box<dyn BufRead + Send>,
+    pub(crate) sleeper: Option<Sleeper>,
+}
+
+/// Waits out a `sleep` for a host that set one with `set_sleeper`.
+pub(crate) type Sleeper = Box<dyn FnMut(Duration) -> io::Result<()> + Send>;
+
+/// Stack for threads that run programs, so deep recursion reaches the call
+/// depth limit before the host stack runs out. Hosts that run programs on
+/// threads of their own should give them this much too.
+pub const PROGRAM_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Stack left when evaluation moves to a new segment; see `with_stack`.
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// Size of each stack segment evaluation grows onto.
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Runs `f`, first moving to a fresh stack segment on the heap if the
+/// current stack is running low. Evaluation recurses once per level of
+/// nesting (a long chain of `+` is as deep as it is long), so without this
+/// a pathological program could crash the host instead of failing with an
+/// error.
+pub(crate) fn with_stack<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, f)
+    }
+    // WebAssembly stacks can't be switched; the engine traps on overflow
+    #[cfg(target_arch = "wasm32")]
+    {
+        f()
+    }
+}
+
+/// Adds the values that a tail call's parameters replaced to those its
+/// frame puts back on returning. A variable already saved by an earlier
+/// call in the frame keeps its first saved value, because that is the one
+/// the frame's caller had; later values belong to calls that are gone.
+fn keep_replaced(
+    old_vars: &mut Vec<(usize, Option<Value>)>,
+    replaced: Vec<(usize, Option<Value>)>
+) {
+    for (slot, old_value) in replaced {
+        if !old_vars.iter().any(|(saved, _)| *saved == slot) {
+            old_vars.push((slot, old_value));
+        }
+    }
+}
+
+/// How deeply Valyrian calls may nest unless configured otherwise.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// What `speaks for input` shows before reading.
+const DEFAULT_PROMPT: &str = "🗣️ Speak your words: ";
+
+/// An interpreter owns all of its state, so each one can be moved to its
+/// own thread (e.g. one per request in a web server).
+///
+/// Runs report what they do through the `tracing` crate, so hosts can send
+/// it to their own logging: an `info` span per run (closed by a "run
+/// finished" event with its statistics), a `debug` span per call, and a
+/// `trace` event per executed statement. Without a subscriber this costs
+/// next to nothing.
 pub struct Interpreter {
-    variables: HashMap<String, Value>,
-    functions: HashMap<String, (Vec<String>, Vec<Statement>)>,
+    variables: Variables,
+    /// The variable slot of each slot of the frame being walked.
+    frame: Arc<[usize]>,
+    functions: HashMap<Symbol, DeclaredFunction>,
+    /// Functions declared by bytecode, which has no AST to keep.
+    compiled_functions: HashMap<Symbol, ResolvedChunk>,
+    /// How programs are run.
+    backend: ExecutionBackend,
     debug: bool,
+    strict: bool,
+    /// Declared types of variables, checked on assignment in strict mode.
+    declared_types: HashMap<Symbol, DataType>,
+    /// Functions whose declaration has actually been reached, as opposed to
+    /// merely hoisted. Only tracked in strict mode.
+    declared_functions: HashSet<Symbol>,
+    /// Valyrian functions currently being executed, innermost last.
+    call_stack: Vec<StackFrame>,
+    /// Arguments passed to the script, read by the `arg` builtins.
+    script_args: Vec<String>,
+    /// Functions registered by the host with `register_native`.
+    natives: HashMap<String, NativeFunction>,
+    /// Variables seeded by the host with `set_global`, restored by `reset`.
+    globals: HashMap<String, Value>,
+    /// Where `speak` writes, stdout unless the host redirects it. Output is
+    /// buffered and flushed before the program waits on the outside world
+    /// (a prompt, a sleep, a debugger pause) and when a run ends.
+    output: BufWriter<Box<dyn Write + Send>>,
+    /// Where debug mode writes the AST, stderr unless the host redirects
+    /// it, so it never mixes with what the program speaks.
+    debug_output: Box<dyn Write + Send>,
+    /// Where `speaks for input` reads lines from, stdin unless the host
+    /// redirects it.
+    input: Box<dyn BufRead + Send>,
+    /// Statements executed and expressions evaluated in the current run.
+    steps: u64,
+    /// Bytes the current run has written to `output`.
+    output_written: usize,
+    /// What the current (or last) run has done so far.
+    stats: ExecutionStats,
+    /// Aborts the run once `steps` would exceed this.
+    max_steps: Option<u64>,
+    /// How long each run may take.
+    timeout: Option<Duration>,
+    /// When the current run must stop, if it has a timeout.
+    deadline: Option<Instant>,
+    /// For a raven, when the run that sent it must stop, which its own
+    /// run may not outlast.
+    sender_deadline: Option<Instant>,
+    limits: ResourceLimits,
+    /// How deeply calls may nest before the run fails.
+    max_call_depth: usize,
+    /// Refuses input and I/O builtins.
+    sandboxed: bool,
+    /// Lets the `command` builtin run programs.
+    commands_allowed: bool,
+    /// How many decimal places `speak` shows wine with.
+    float_precision: Option<usize>,
+    /// Carries out the `sleep` builtin; without one, the interpreter
+    /// sleeps on its own thread.
+    sleeper: Option<Sleeper>,
+    /// Told about every statement and call, in the order they were added.
+    observers: Vec<Box<dyn ExecutionObserver>>,
+    /// Stops the run when triggered by the host.
+    cancellation: CancellationHandle,
+    /// Pauses the program for a `Debugger`.
+    debug_hook: Option<DebugHook>,
+    /// Spells set by `after` and `every`, cast by `run_pending`.
+    timers: Timers,
+}
+
+/// How an interpreter runs programs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionBackend {
+    /// Walk the AST statement by statement. This is the reference
+    /// implementation, and the only one a `Debugger` can pause.
+    #[default]
+    TreeWalking,
+    /// Compile the program to bytecode (see `compiler`) and run it on a
+    /// stack machine, which is faster for loop-heavy programs.
+    Bytecode,
+}
+
+/// Caps on how much state a program may build up, so untrusted code can't
+/// exhaust the host's memory. Exceeding one aborts the run with a
+/// `ResourceLimit` error. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The most variables (including bound parameters) alive at once.
+    pub max_variables: Option<usize>,
+    /// The longest scroll, in bytes, the program may create.
+    pub max_string_length: Option<usize>,
+    /// The most bytes a run may write to its output, counting what it
+    /// speaks, its input prompts, and what its ravens speak.
+    pub max_output: Option<usize>,
+}
+
+/// Counters describing the work a run did; see `Interpreter::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutionStats {
+    pub statements: u64,
+    /// Calls to Valyrian functions, natives, and builtins alike.
+    pub calls: u64,
+    /// The most variables (including bound parameters) alive at once.
+    pub peak_variables: usize,
+    /// Wall-clock time from the start of the run to its end.
+    pub elapsed: Duration,
+}
+
+/// The program state of an interpreter: its variables and the functions
+/// the council has declared, without any host configuration. With the
+/// `serde` feature a snapshot can be saved to disk and restored in a later
+/// session; `Value::External` values cannot be serialized.
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// use mid_valyrian::{ parse_program, Interpreter, StateSnapshot, Value };
+///
+/// let source = "we declare double with x ->\ncouncil says:\n    return x * 2\n\ngold is a blade with 21\n";
+/// let mut interpreter = Interpreter::new(false);
+/// interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+/// let saved = serde_json::to_string(&interpreter.snapshot()).unwrap();
+///
+/// let mut resumed = Interpreter::new(false);
+/// resumed.restore(serde_json::from_str::<StateSnapshot>(&saved).unwrap());
+/// assert_eq!(resumed.eval_expression("double with gold").unwrap(), Value::Integer(42));
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateSnapshot {
+    pub variables: BTreeMap<String, Value>,
+    pub functions: BTreeMap<String, FunctionDefinition>,
+    /// Declared types of variables, enforced on assignment in strict mode.
+    pub declared_types: BTreeMap<String, DataType>,
+}
+
+/// A declared function, as the interpreter keeps it and a `StateSnapshot`
+/// stores it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionDefinition {
+    pub parameters: Vec<Symbol>,
+    pub body: Vec<Statement>,
+}
+
+/// A function the council declared, with its body resolved to a frame of
+/// its own.
+#[derive(Clone)]
+struct DeclaredFunction {
+    /// Shared, so calls don't copy the body.
+    definition: Arc<FunctionDefinition>,
+    /// The variable slot of each slot of the function's frame.
+    frame: Arc<[usize]>,
+    parameters: Arc<[usize]>,
 }
 
 impl Interpreter {
     pub fn new(debug: bool) -> Self {
         Self {
-            variables: HashMap::new(),
+            variables: Variables::default(),
+            frame: Arc::from([]),
             functions: HashMap::new(),
+            compiled_functions: HashMap::new(),
+            backend: ExecutionBackend::default(),
             debug,
+            strict: false,
+            declared_types: HashMap::new(),
+            declared_functions: HashSet::new(),
+            call_stack: Vec::new(),
+            script_args: Vec::new(),
+            natives: HashMap::new(),
+            globals: HashMap::new(),
+            output: BufWriter::new(Box::new(io::stdout())),
+            debug_output: Box::new(io::stderr()),
+            input: Box::new(io::BufReader::new(io::stdin())),
+            steps: 0,
+            output_written: 0,
+            stats: ExecutionStats::default(),
+            max_steps: None,
+            timeout: None,
+            deadline: None,
+            sender_deadline: None,
+            limits: ResourceLimits::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            sandboxed: false,
+            commands_allowed: false,
+            float_precision: None,
+            sleeper: None,
+            observers: Vec::new(),
+            cancellation: CancellationHandle::new(),
+            debug_hook: None,
+            timers: Timers::default(),
+        }
+    }
+
+    pub(crate) fn set_debug_hook(&mut self, hook: Option<DebugHook>) {
+        self.debug_hook = hook;
+    }
+
+    /// Lets an attached debugger pause before `statement`.
+    fn check_debugger(&mut self, statement: &Statement) -> Result<(), ValyrianError> {
+        let Some(mut hook) = self.debug_hook.take() else {
+            return Ok(());
+        };
+        let result = match hook.pause_reason(statement, self.call_stack.len()) {
+            Some(reason) => {
+                let _ = self.output.flush();
+                let pause = Pause {
+                    reason,
+                    statement: statement.clone(),
+                    span: statement.span,
+                    call_stack: self.call_stack.clone(),
+                    variables: self.variables
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect(),
+                };
+                hook.pause(pause, |source| {
+                    let expression = crate::parser::parse_expression_source(source)?;
+                    self.evaluate_expression(&expression)
+                })
+            }
+            None => Ok(()),
+        };
+        self.debug_hook = Some(hook);
+        result
+    }
+
+    /// Returns a handle that stops this interpreter's runs from any thread;
+    /// see `CancellationHandle`.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        self.cancellation.clone()
+    }
+
+    /// Makes the interpreter answer to `handle`, so one handle can stop
+    /// several interpreters.
+    pub fn with_cancellation(mut self, handle: CancellationHandle) -> Self {
+        self.cancellation = handle;
+        self
+    }
+
+    /// Adds a hook that is told about every statement and call from now
+    /// on; see `ExecutionObserver`. Observers survive `reset`.
+    pub fn add_observer(&mut self, observer: impl ExecutionObserver + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Chooses how programs are run; see `ExecutionBackend`. Both backends
+    /// behave the same, except that a `Debugger` always walks the AST.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, ExecutionBackend, Interpreter, Value };
+    ///
+    /// let source = "we declare square with x ->\ncouncil says:\n    return x * x\n\non the iron throne:\n    return square with 12\n";
+    /// let mut interpreter = Interpreter::new(false).with_backend(ExecutionBackend::Bytecode);
+    /// let value = interpreter.interpret_with_result(&parse_program(source).unwrap()).unwrap();
+    /// assert_eq!(value, Value::Integer(144));
+    /// ```
+    pub fn with_backend(mut self, backend: ExecutionBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn backend(&self) -> ExecutionBackend {
+        self.backend
+    }
+
+    /// Replaces how the `sleep` builtin waits, e.g. to hand the wait to an
+    /// async runtime or to skip it in tests.
+    pub fn set_sleeper(&mut self, sleeper: impl FnMut(Duration) -> io::Result<()> + Send + 'static) {
+        self.sleeper = Some(Box::new(sleeper));
+    }
+
+    /// Exchanges the interpreter's writer, reader, and sleeper for `io`,
+    /// returning the old ones.
+    pub(crate) fn swap_io(&mut self, mut io: HostIo) -> HostIo {
+        // Whatever is still buffered belongs to the old writer
+        let _ = self.output.flush();
+        std::mem::swap(self.output.get_mut(), &mut io.output);
+        std::mem::swap(&mut self.input, &mut io.input);
+        std::mem::swap(&mut self.sleeper, &mut io.sleeper);
+        io
+    }
+
+    fn flush_output(&mut self) -> Result<(), ValyrianError> {
+        self.output.flush().map_err(ValyrianError::from)
+    }
+
+    /// Flushes what a run spoke once it ends, reporting a failed flush
+    /// unless the run already failed.
+    fn finish_output<T>(&mut self, result: Result<T, ValyrianError>) -> Result<T, ValyrianError> {
+        let flushed = self.flush_output();
+        result.and_then(|value| flushed.map(|()| value))
+    }
+
+    /// Pauses the program, as the `sleep` builtin does. A sleep that would
+    /// outlast the run's deadline ends at it with a timeout, and one on
+    /// the interpreter's own thread ends early when the run is cancelled.
+    pub(crate) fn sleep(&mut self, duration: Duration) -> Result<(), ValyrianError> {
+        self.flush_output()?;
+        let duration = match self.deadline {
+            Some(deadline) => duration.min(deadline.saturating_duration_since(Instant::now())),
+            None => duration,
+        };
+        match &mut self.sleeper {
+            Some(sleeper) => {
+                sleeper(duration).map_err(|e| ValyrianError::io("The program could not sleep", e))?;
+            }
+            None => {
+                let until = Instant::now() + duration;
+                loop {
+                    self.check_cancelled()?;
+                    let left = until.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        break;
+                    }
+                    std::thread::sleep(left.min(raven::WAIT_POLL));
+                }
+            }
+        }
+        self.check_deadline()?;
+        self.check_cancelled()
+    }
+
+    /// Sandbox mode refuses everything that reaches outside the
+    /// interpreter: reading input and the file, process, and network
+    /// builtins fail with a `SandboxViolation` error. Native functions
+    /// registered by the host are still allowed.
+    pub fn with_sandbox(mut self, sandboxed: bool) -> Self {
+        self.sandboxed = sandboxed;
+        self
+    }
+
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandboxed
+    }
+
+    /// Lets the `command` builtin run other programs, which it refuses to
+    /// do by default. The sandbox refuses it all the same.
+    pub fn with_commands(mut self, allowed: bool) -> Self {
+        self.commands_allowed = allowed;
+        self
+    }
+
+    /// Has `speak` show wine rounded to `decimals` decimal places, rather
+    /// than with as many as it takes to tell the number apart (the
+    /// default). Only wine spoken by itself is rounded, not wine joined
+    /// into a scroll; `format_wine` rounds it there.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+    ///
+    /// let program = parse_program("on the iron throne:\n    speak 0.1 + 0.2\n    speak 3.0\n").unwrap();
+    /// let output = OutputBuffer::new();
+    /// let mut interpreter = Interpreter::new(false).with_output(output.clone());
+    /// interpreter.interpret(&program).unwrap();
+    /// assert_eq!(output.contents(), "0.30000000000000004\n3\n");
+    ///
+    /// let output = OutputBuffer::new();
+    /// let mut interpreter = Interpreter::new(false)
+    ///     .with_float_precision(Some(2))
+    ///     .with_output(output.clone());
+    /// interpreter.interpret(&program).unwrap();
+    /// assert_eq!(output.contents(), "0.30\n3.00\n");
+    /// ```
+    pub fn with_float_precision(mut self, decimals: Option<usize>) -> Self {
+        self.float_precision = decimals;
+        self
+    }
+
+    /// Sets how deeply Valyrian calls may nest before the run fails with a
+    /// `StackOverflow` error (`DEFAULT_MAX_CALL_DEPTH` by default).
+    ///
+    /// Each call uses host stack too, so a high limit needs a thread with a
+    /// big enough stack; the CLI runs programs on one. A call in tail
+    /// position (`return f with ...`) reuses the caller's frame, so loops
+    /// written as recursion don't count against the limit.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, Value };
+    ///
+    /// let source = "we declare down with n ->\ncouncil says:\n    if n == 0:\n        return 0\n    return down with n - 1\n";
+    /// let mut interpreter = Interpreter::new(false).with_max_call_depth(10);
+    /// interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+    /// assert_eq!(interpreter.eval_expression("down with 5000").unwrap(), Value::Integer(0));
+    /// ```
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Caps the state the program may build up; see `ResourceLimits`.
+    ///
+    /// ```
+    /// use mid_valyrian::{ Interpreter, ResourceLimits, ValyrianError };
+    ///
+    /// let limits = ResourceLimits { max_string_length: Some(8), ..ResourceLimits::default() };
+    /// let mut interpreter = Interpreter::new(false).with_limits(limits);
+    /// let result = interpreter.eval_expression("\"Valar \" + \"Morghulis\"");
+    /// assert!(matches!(result, Err(ValyrianError::ResourceLimit(_))));
+    /// ```
+    ///
+    /// A program that speaks forever stops once it has spoken too much:
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer, ResourceLimits, ValyrianError };
+    ///
+    /// let program = parse_program("on the iron throne:\n    while aye:\n        speak \"winter\"\n").unwrap();
+    /// let limits = ResourceLimits { max_output: Some(20), ..ResourceLimits::default() };
+    /// let output = OutputBuffer::new();
+    /// let mut interpreter = Interpreter::new(false).with_limits(limits).with_output(output.clone());
+    /// let error = interpreter.interpret(&program).unwrap_err();
+    /// assert!(matches!(error.root(), ValyrianError::ResourceLimit(_)));
+    /// assert_eq!(output.contents(), "winter\nwinter\n");
+    /// ```
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fails if binding the variable in `slot` would exceed the variable
+    /// limit.
+    fn check_new_variable(&self, slot: usize) -> Result<(), ValyrianError> {
+        match self.limits.max_variables {
+            Some(max) if !self.variables.is_bound(slot) && self.variables.len() >= max =>
+                Err(
+                    ValyrianError::ResourceLimit(
+                        format!(
+                            "declaring '{}' would exceed the limit of {} variables",
+                            self.variables.name(slot),
+                            max
+                        )
+                    )
+                ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if `value` is larger than the limits allow.
+    fn check_value_size(&self, value: &Value) -> Result<(), ValyrianError> {
+        match (value, self.limits.max_string_length) {
+            (Value::String(s), Some(max)) if s.len() > max =>
+                Err(
+                    ValyrianError::ResourceLimit(
+                        format!("a scroll of {} bytes exceeds the limit of {}", s.len(), max)
+                    )
+                ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Aborts each run with a `Timeout` error once it has taken longer than
+    /// `timeout`. The clock is checked before every statement, and a `sleep`
+    /// ends at the deadline, but a program blocked waiting for input is only
+    /// stopped once the input arrives.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limits each run to `max_steps` executed statements and evaluated
+    /// expressions, so a runaway loop ends in an
+    /// `ExecutionLimitExceeded` error instead of hanging the host. `None`
+    /// (the default) means no limit.
+    pub fn with_max_steps(mut self, max_steps: Option<u64>) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Marks the start of a run, which gets a fresh step budget and fresh
+    /// statistics.
+    fn begin_run(&mut self) {
+        self.steps = 0;
+        self.output_written = 0;
+        self.stats = ExecutionStats {
+            peak_variables: self.variables.len(),
+            ..ExecutionStats::default()
+        };
+        let own = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.deadline = match (own, self.sender_deadline) {
+            (Some(own), Some(sender)) => Some(own.min(sender)),
+            (own, sender) => own.or(sender),
+        };
+    }
+
+    fn check_deadline(&self) -> Result<(), ValyrianError> {
+        match (self.deadline, self.timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() >= deadline =>
+                Err(ValyrianError::Timeout(timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<(), ValyrianError> {
+        if self.cancellation.is_cancelled() {
+            Err(ValyrianError::Interrupted)
+        } else {
+            Ok(())
         }
     }
 
+    /// What the last run did: statements executed, calls made, the peak
+    /// number of variables, and how long it took. Counting is cheap, so it
+    /// is always on.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter };
+    ///
+    /// let program = parse_program("gold is a blade with 1\ngold = gold + 1\n").unwrap();
+    /// let mut interpreter = Interpreter::new(false);
+    /// interpreter.interpret(&program).unwrap();
+    /// assert_eq!(interpreter.stats().statements, 2);
+    /// assert_eq!(interpreter.stats().peak_variables, 1);
+    /// ```
+    pub fn stats(&self) -> ExecutionStats {
+        self.stats
+    }
+
+    fn note_variable_count(&mut self) {
+        self.stats.peak_variables = self.stats.peak_variables.max(self.variables.len());
+    }
+
+    /// Counts one unit of work against the step limit.
+    fn charge_step(&mut self) -> Result<(), ValyrianError> {
+        self.steps += 1;
+        match self.max_steps {
+            Some(max) if self.steps > max => Err(ValyrianError::ExecutionLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends everything the program speaks (and the input prompt) to
+    /// `output` instead of stdout. Use `io::sink()` to discard it, or an
+    /// `OutputBuffer` to capture it. Writes are buffered, so `output` sees
+    /// them at input prompts and when a run ends rather than line by line.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.set_output(output);
+        self
+    }
+
+    /// Replaces the writer `speak` writes to.
+    pub fn set_output(&mut self, output: impl Write + Send + 'static) {
+        let _ = self.output.flush();
+        self.output = BufWriter::new(Box::new(output));
+    }
+
+    /// Sends what debug mode shows to `output` instead of stderr, e.g. a
+    /// log file, leaving stdout to the program.
+    pub fn with_debug_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.set_debug_output(output);
+        self
+    }
+
+    /// Replaces the writer debug mode writes to.
+    pub fn set_debug_output(&mut self, output: impl Write + Send + 'static) {
+        self.debug_output = Box::new(output);
+    }
+
+    /// Reads the lines answered to `speaks for input` from `input` instead
+    /// of stdin, so interactive programs can be scripted.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use mid_valyrian::{ parse_program, Interpreter, Value };
+    ///
+    /// let program = parse_program("name is a scroll with arya speaks for input\n").unwrap();
+    /// let mut interpreter = Interpreter::new(false)
+    ///     .with_output(std::io::sink())
+    ///     .with_input(Cursor::new("No One\n"));
+    /// interpreter.interpret(&program).unwrap();
+    /// assert_eq!(interpreter.get_variable("name"), Some(&Value::String("No One".into())));
+    /// ```
+    pub fn with_input(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.set_input(input);
+        self
+    }
+
+    /// Replaces the reader `speaks for input` reads from.
+    pub fn set_input(&mut self, input: impl BufRead + Send + 'static) {
+        self.input = Box::new(input);
+    }
+
+    /// Starts configuring an interpreter option by option.
+    ///
+    /// ```
+    /// use mid_valyrian::{ Interpreter, OutputBuffer, Value };
+    ///
+    /// let output = OutputBuffer::new();
+    /// let mut interpreter = Interpreter::builder()
+    ///     .strict(true)
+    ///     .global("house", Value::String("Stark".into()))
+    ///     .stdout(output.clone())
+    ///     .build();
+    /// assert_eq!(interpreter.eval_expression("house").unwrap(), Value::String("Stark".into()));
+    /// ```
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder { interpreter: Interpreter::new(false) }
+    }
+
+    /// Makes a Rust function callable from Valyrian code as `name with ...`.
+    ///
+    /// Native functions take precedence over builtins of the same name, and
+    /// are themselves shadowed by functions the program declares. They
+    /// survive `reset`.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, Value };
+    ///
+    /// let mut interpreter = Interpreter::new(false);
+    /// interpreter.register_native("summon_weather", |args| {
+    ///     let season = if args.is_empty() { "winter" } else { "summer" };
+    ///     Ok(Value::String(season.into()))
+    /// });
+    ///
+    /// let program = parse_program("weather is a scroll with summon_weather with\n").unwrap();
+    /// interpreter.interpret(&program).unwrap();
+    /// ```
+    pub fn register_native<F>(&mut self, name: &str, function: F)
+        where F: Fn(&[Value]) -> Result<Value, ValyrianError> + Send + Sync + 'static
+    {
+        self.natives.insert(name.to_string(), Arc::new(function));
+    }
+
+    /// Enables strict mode, which turns soft issues into errors:
+    ///
+    /// * calling a function before its declaration has been reached,
+    /// * declaring or assigning a value that doesn't match the variable's type,
+    /// * discarding a non-void value returned from a function call.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
     pub fn interpret(&mut self, program: &Program) -> Result<(), ValyrianError> {
         if self.debug {
-            println!("🐉 AST: {:#?}", program);
+            let _ = writeln!(self.debug_output, "🐉 AST: {:#?}", program);
         }
 
         // println!("Number of statements in program: {}", program.statements.len());
         // println!("Program statement[0]: {:?}", program.statements.get(0));
 
-        for statement in &program.statements {
-            if let Statement::FunctionDeclaration { name, parameters, body } = statement {
-                self.functions.insert(name.clone(), (parameters.clone(), body.clone()));
+        self.run_statements(&program.statements)
+    }
+
+    /// Runs a program like `interpret`, returning the value the main block
+    /// hands back with `return` (or `Void` if it never returns one).
+    pub fn interpret_with_result(&mut self, program: &Program) -> Result<Value, ValyrianError> {
+        if self.debug {
+            let _ = writeln!(self.debug_output, "🐉 AST: {:#?}", program);
+        }
+        self.run_statements_with_result(&program.statements)
+    }
+
+    /// Runs top-level statements against the interpreter's current state.
+    ///
+    /// Unlike a fresh `interpret`, variables and functions from earlier runs
+    /// stay in scope, so the same interpreter can be fed a program piece by
+    /// piece (as the REPL does).
+    pub fn run_statements(&mut self, statements: &[Statement]) -> Result<(), ValyrianError> {
+        self.run_statements_with_result(statements).map(|_| ())
+    }
+
+    /// Runs top-level statements like `run_statements`, returning the value
+    /// of a top-level `return`. A `return` ends the program early.
+    pub fn run_statements_with_result(
+        &mut self,
+        statements: &[Statement]
+    ) -> Result<Value, ValyrianError> {
+        self.timed_run(|interpreter| interpreter.run_top_level(statements))
+    }
+
+    /// Runs a compiled program against the interpreter's current state, as
+    /// `run_statements_with_result` runs its source, whatever the configured
+    /// backend. Compiled code has no syntax tree to step through, so a
+    /// debug hook never sees it.
+    pub fn run_compiled(&mut self, chunk: impl Into<Arc<Chunk>>) -> Result<Value, ValyrianError> {
+        let chunk = chunk.into();
+        if self.debug {
+            let _ = writeln!(self.debug_output, "🐉 Bytecode: {:#?}", chunk);
+        }
+        self.timed_run(|interpreter| interpreter.run_chunk(chunk))
+    }
+
+    fn timed_run(
+        &mut self,
+        run: impl FnOnce(&mut Self) -> Result<Value, ValyrianError>
+    ) -> Result<Value, ValyrianError> {
+        let _run = info_span!("run").entered();
+        self.begin_run();
+        let started = Instant::now();
+        let result = run(self);
+        let result = self.finish_output(result);
+        self.stats.elapsed = started.elapsed();
+        info!(
+            statements = self.stats.statements,
+            calls = self.stats.calls,
+            elapsed = ?self.stats.elapsed,
+            ok = result.is_ok(),
+            "run finished"
+        );
+        result
+    }
+
+    fn run_top_level(&mut self, statements: &[Statement]) -> Result<Value, ValyrianError> {
+        if self.backend == ExecutionBackend::Bytecode && self.debug_hook.is_none() {
+            return self.run_chunk(Arc::new(compiler::compile_statements(statements)));
+        }
+        let mut statements = statements.to_vec();
+        let names = frame::resolve(&[], &mut statements);
+        let frame = self.frame_slots(&names);
+        let caller = std::mem::replace(&mut self.frame, frame);
+        let result = self.run_resolved(&statements);
+        self.frame = caller;
+        result
+    }
+
+    /// Runs top-level statements already resolved to the current frame.
+    fn run_resolved(&mut self, statements: &[Statement]) -> Result<Value, ValyrianError> {
+        for statement in statements {
+            if let StatementKind::FunctionDeclaration { name, parameters, body } = &statement.kind {
+                self.declare_function(name, parameters, body);
             }
         }
 
-        for statement in &program.statements {
-            match statement {
-                Statement::MainBlock(statements) => {
-                    for stmt in statements {
-                        self.execute_statement(stmt)?;
-                    }
-                }
-                Statement::FunctionDeclaration { .. } => {}
-                _ => {
-                    self.execute_statement(statement)?;
-                }
+        for statement in statements {
+            let flow = match &statement.kind {
+                // Trials only run under the test runner
+                StatementKind::Trial { .. } => None,
+                _ => self.execute_statement(statement)?,
+            };
+            if let Some(ControlFlow::Return(value)) = flow {
+                return Ok(value);
             }
         }
 
-        Ok(())
+        Ok(Value::Void)
+    }
+
+    /// The variable slot of each of a frame's `names`.
+    fn frame_slots(&mut self, names: &[Symbol]) -> Arc<[usize]> {
+        names
+            .iter()
+            .map(|name| self.variables.slot(name))
+            .collect()
+    }
+
+    /// The variable slot `name` stands for in the frame being walked.
+    /// Names that weren't resolved, such as those of an expression the
+    /// host evaluates, are looked up by their text.
+    fn variable_slot(&mut self, name: &Name) -> usize {
+        match name.slot {
+            Some(slot) => self.frame[slot as usize],
+            None => self.variables.slot(name),
+        }
+    }
+
+    /// Sets the arguments the script sees through `arg_count` and `arg`.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.script_args = args;
+        self
+    }
+
+    /// Defines a variable before the program runs, so the host can pass in
+    /// configuration the script reads by name. The program may reassign it;
+    /// `reset` puts it back.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value.clone());
+        self.variables.insert(name, value);
+    }
+
+    /// Builder form of `set_global`.
+    pub fn with_global(mut self, name: &str, value: Value) -> Self {
+        self.set_global(name, value);
+        self
+    }
+
+    /// Returns the arguments passed to the script.
+    pub fn script_args(&self) -> &[String] {
+        &self.script_args
+    }
+
+    /// Returns the current value of a variable, if it is bound.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, Value };
+    ///
+    /// let program = parse_program("ravens is a blade with 2 + 3\n").unwrap();
+    /// let mut interpreter = Interpreter::new(false);
+    /// interpreter.interpret(&program).unwrap();
+    /// assert_eq!(interpreter.get_variable("ravens"), Some(&Value::Integer(5)));
+    /// ```
+    pub fn get_variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    /// Iterates over the current variable bindings, in no particular order.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.variables.iter()
+    }
+
+    /// Iterates over the declared functions and their parameter names, in
+    /// no particular order.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, &[Symbol])> {
+        self.functions
+            .iter()
+            .map(|(name, function)| (&**name, function.definition.parameters.as_slice()))
+    }
+
+    /// Forgets every variable and function, keeping the configuration
+    /// (including globals set by the host).
+    pub fn reset(&mut self) {
+        self.variables.clear();
+        for (name, value) in &self.globals {
+            self.variables.insert(name, value.clone());
+        }
+        self.functions.clear();
+        self.compiled_functions.clear();
+        self.declared_types.clear();
+        self.declared_functions.clear();
+        self.call_stack.clear();
+        self.timers.clear();
+    }
+
+    /// Captures the variables and functions the program has built up; see
+    /// `StateSnapshot`. Functions declared by bytecode are left out, as
+    /// they have no syntax tree to save.
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            variables: self.variables
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            functions: self.functions
+                .iter()
+                .map(|(name, function)| {
+                    let mut definition = FunctionDefinition::clone(&function.definition);
+                    frame::forget(&mut definition.body);
+                    (name.to_string(), definition)
+                })
+                .collect(),
+            declared_types: self.declared_types
+                .iter()
+                .map(|(name, data_type)| (name.to_string(), data_type.clone()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the program state with `snapshot`, keeping the
+    /// configuration, natives, and observers. Restored functions count as
+    /// declared, even in strict mode.
+    pub fn restore(&mut self, snapshot: StateSnapshot) {
+        self.call_stack.clear();
+        self.compiled_functions.clear();
+        self.variables.clear();
+        for (name, value) in snapshot.variables {
+            self.variables.insert(&name, value);
+        }
+        self.declared_types = snapshot.declared_types
+            .into_iter()
+            .map(|(name, data_type)| (name.into(), data_type))
+            .collect();
+        self.declared_functions = snapshot.functions
+            .keys()
+            .map(|name| name.as_str().into())
+            .collect();
+        self.functions.clear();
+        for (name, definition) in snapshot.functions {
+            self.define_function(name.into(), definition);
+        }
+    }
+
+    /// Runs a new version of a program against the state the old version
+    /// left behind: its functions replace the old ones, and its top-level
+    /// declarations of variables that are already bound are skipped, so
+    /// those keep their current values. Everything else runs as usual.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, Value };
+    ///
+    /// let mut interpreter = Interpreter::new(false);
+    /// let v1 = "gold is a blade with 10\nwe declare tax with x ->\ncouncil says:\n    return x - 1\n\ngold = tax with gold\n";
+    /// interpreter.interpret(&parse_program(v1).unwrap()).unwrap();
+    ///
+    /// let v2 = "gold is a blade with 10\nwe declare tax with x ->\ncouncil says:\n    return x - 5\n\ngold = tax with gold\n";
+    /// interpreter.hot_reload(&parse_program(v2).unwrap()).unwrap();
+    /// assert_eq!(interpreter.get_variable("gold"), Some(&Value::Integer(4)));
+    /// ```
+    pub fn hot_reload(&mut self, program: &Program) -> Result<(), ValyrianError> {
+        self.call_stack.clear();
+        self.functions.clear();
+        self.compiled_functions.clear();
+        self.declared_functions.clear();
+        let statements: Vec<Statement> = program.statements
+            .iter()
+            .filter(|statement| {
+                !matches!(
+                    &statement.kind,
+                    StatementKind::VariableDeclaration { name, .. } if self.variables.contains(name)
+                )
+            })
+            .cloned()
+            .collect();
+        self.run_statements(&statements)
+    }
+
+    /// Evaluates a single expression against the interpreter's current state.
+    pub fn evaluate(&mut self, expression: &Expression) -> Result<Value, ValyrianError> {
+        self.begin_run();
+        let result = self.evaluate_expression(expression);
+        self.finish_output(result)
+    }
+
+    /// Parses and evaluates an expression given as source text, against the
+    /// variables and functions the interpreter currently knows.
+    ///
+    /// ```
+    /// use mid_valyrian::{ Interpreter, Value };
+    ///
+    /// let mut interpreter = Interpreter::new(false).with_global("dragons", Value::Integer(3));
+    /// let value = interpreter.eval_expression("dragons * 2 + 1").unwrap();
+    /// assert_eq!(value, Value::Integer(7));
+    /// ```
+    pub fn eval_expression(&mut self, source: &str) -> Result<Value, ValyrianError> {
+        let expression = crate::parser::parse_expression_source(source)?;
+        self.evaluate(&expression)
     }
 
     fn execute_statement(
         &mut self,
         statement: &Statement
     ) -> Result<Option<ControlFlow>, ValyrianError> {
-
-        
-        if self.debug {
-            println!("🏰 Executing: {:?}", statement);
+        trace!(
+            line = statement.span.line,
+            column = statement.span.col,
+            statement = %crate::printer::statement_head(statement),
+            "executing"
+        );
+        self.stats.statements += 1;
+        for observer in &mut self.observers {
+            observer.on_statement(statement, &statement.span);
         }
 
-        match statement {
-            Statement::Return(expr_opt) => {
-                let value = if let Some(expr) = expr_opt {
-                    self.evaluate_expression(expr)?
-                } else {
-                    Value::Void
-                };
-                return Ok(Some(ControlFlow::Return(value)));
+        with_stack(|| {
+            self.check_debugger(statement)
+                .and_then(|()| self.charge_step())
+                .and_then(|()| self.check_deadline())
+                .and_then(|()| self.check_cancelled())
+                .and_then(|()| self.execute_statement_kind(statement))
+                .map_err(|error| self.attach_trace(error, statement.span))
+        })
+    }
+
+    fn execute_statement_kind(
+        &mut self,
+        statement: &Statement
+    ) -> Result<Option<ControlFlow>, ValyrianError> {
+        match &statement.kind {
+            StatementKind::Return(Some(expr)) => {
+                if let Some(tail_call) = self.tail_call(expr)? {
+                    return Ok(Some(tail_call));
+                }
+                let value = self.evaluate_expression(expr)?;
+                Ok(Some(ControlFlow::Return(value)))
             }
-            Statement::VariableDeclaration { name, data_type: _, value } => {
-                let val = self.evaluate_expression(value)?;
-                self.variables.insert(name.clone(), val);
+            StatementKind::Return(None) => Ok(Some(ControlFlow::Return(Value::Void))),
+            StatementKind::VariableDeclaration { name, data_type, value } => {
+                let val = match value {
+                    Some(value) => self.evaluate_expression(value)?,
+                    None => data_type.default_value(),
+                };
+                let slot = self.variable_slot(name);
+                self.declare_variable(slot, data_type, val)?;
                 Ok(None)
             }
-            Statement::Assignment { name, value } => {
-                if !self.variables.contains_key(name) {
-                    return Err(ValyrianError::UndefinedVariable(name.clone()));
-                }
+            StatementKind::Assignment { name, value } => {
+                let slot = self.variable_slot(name);
+                self.check_defined(slot)?;
                 let val = self.evaluate_expression(value)?;
-                self.variables.insert(name.clone(), val);
+                self.assign_variable(slot, val)?;
                 Ok(None)
             }
-            Statement::FunctionCall { name, arguments } => {
-                let _ = self.call_function(name, arguments)?;
+            StatementKind::FunctionCall { name, arguments } => {
+                let result = self.call_function(name, arguments, statement.span)?;
+                self.check_discarded(name, &result)?;
                 Ok(None)
             }
-            Statement::Conditional { condition, then_branch, else_branch } => {
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
                 let condition_value = self.evaluate_expression(condition)?;
                 let should_execute = match condition_value {
                     Value::Boolean(b) => b,
@@ -108,7 +1223,9 @@ impl Interpreter {
 
                 Ok(None)
             }
-            Statement::ForLoop { count, body } => {
+            // Nothing to repeat, and nothing to stop a long wait on it
+            StatementKind::ForLoop { body, .. } if body.is_empty() => Ok(None),
+            StatementKind::ForLoop { count, body } => {
                 for _ in 0..*count {
                     for stmt in body {
                         if let Some(flow) = self.execute_statement(stmt)? {
@@ -118,7 +1235,7 @@ impl Interpreter {
                 }
                 Ok(None)
             }
-            Statement::WhileLoop { condition, body } => {
+            StatementKind::WhileLoop { condition, body } => {
                 loop {
                     let condition_value = self.evaluate_expression(condition)?;
                     let should_continue = match condition_value {
@@ -145,12 +1262,12 @@ impl Interpreter {
                 }
                 Ok(None)
             }
-            Statement::Speak(expression) => {
+            StatementKind::Speak(expression) => {
                 let value = self.evaluate_expression(expression)?;
-                println!("{}", value);
+                self.speak(&value)?;
                 Ok(None)
             }
-            Statement::MainBlock(statements) => {
+            StatementKind::MainBlock(statements) => {
                 for stmt in statements {
                     if let Some(flow) = self.execute_statement(stmt)? {
                         return Ok(Some(flow));
@@ -158,67 +1275,459 @@ impl Interpreter {
                 }
                 Ok(None)
             }
-            Statement::FunctionDeclaration { .. } => Ok(None),
+            StatementKind::FunctionDeclaration { name, parameters, body } => {
+                self.declare_function(name, parameters, body);
+                if self.strict {
+                    self.declared_functions.insert(name.clone());
+                }
+                Ok(None)
+            }
+            StatementKind::Trial { .. } => Ok(None),
         }
     }
 
-    fn call_function(
+    fn declare_variable(
         &mut self,
+        slot: usize,
+        data_type: &DataType,
+        value: Value
+    ) -> Result<(), ValyrianError> {
+        if self.strict {
+            self.check_declared_type(data_type, &value)?;
+            self.declared_types.insert(self.variables.name(slot).clone(), data_type.clone());
+        }
+        self.check_new_variable(slot)?;
+        self.variables.set(slot, Some(value));
+        self.note_variable_count();
+        Ok(())
+    }
+
+    /// Fails unless the variable in `slot` is bound; assignments check this
+    /// before computing the new value.
+    fn check_defined(&self, slot: usize) -> Result<(), ValyrianError> {
+        if self.variables.is_bound(slot) {
+            Ok(())
+        } else {
+            Err(ValyrianError::UndefinedVariable(self.variables.name(slot).to_string()))
+        }
+    }
+
+    fn assign_variable(&mut self, slot: usize, value: Value) -> Result<(), ValyrianError> {
+        if let Some(data_type) = self.declared_types.get(self.variables.name(slot)) {
+            self.check_declared_type(data_type, &value)?;
+        }
+        self.variables.set(slot, Some(value));
+        Ok(())
+    }
+
+    /// In strict mode, fails if a call statement threw away a value.
+    fn check_discarded(&self, name: &str, result: &Value) -> Result<(), ValyrianError> {
+        if self.strict && *result != Value::Void {
+            return Err(
+                ValyrianError::StrictViolation(
+                    format!("the value returned by '{}' is never used", name)
+                )
+            );
+        }
+        Ok(())
+    }
+
+    fn speak(&mut self, value: &Value) -> Result<(), ValyrianError> {
+        let spoken = match (value, self.float_precision) {
+            (Value::Float(wine), Some(decimals)) => format!("{:.*}\n", decimals, wine),
+            _ => format!("{}\n", value),
+        };
+        self.write_output(&spoken)
+    }
+
+    /// Writes `text` to the output, unless it would take the run past the
+    /// output limit.
+    fn write_output(&mut self, text: &str) -> Result<(), ValyrianError> {
+        self.output_written += text.len();
+        if let Some(max) = self.limits.max_output {
+            if self.output_written > max {
+                return Err(
+                    ValyrianError::ResourceLimit(format!("the program spoke more than the limit of {} bytes", max))
+                );
+            }
+        }
+        self.output.write_all(text.as_bytes()).map_err(|e|
+            ValyrianError::io("The raven carrying your words was lost", e)
+        )
+    }
+
+    /// Prompts for and reads a line of input, as `speaks for input` does.
+    fn read_input(&mut self, prompt: &str) -> Result<Value, ValyrianError> {
+        self.prompt(prompt)?;
+        let mut input = String::new();
+        self.input.read_line(&mut input).map_err(ValyrianError::from)?;
+        Ok(Value::String(input.trim().into()))
+    }
+
+    /// Asks with `prompt` until the answer is a blade from `min` to `max`,
+    /// telling the user what was wrong with each answer that isn't.
+    pub(crate) fn ask_until_blade(&mut self, prompt: &str, min: i64, max: i64) -> Result<i64, ValyrianError> {
+        if min > max {
+            return Err(
+                ValyrianError::RuntimeError(format!("no blade is at least {} and at most {}", min, max))
+            );
+        }
+        loop {
+            self.prompt(prompt)?;
+            let mut input = String::new();
+            if self.input.read_line(&mut input).map_err(ValyrianError::from)? == 0 {
+                return Err(
+                    ValyrianError::RuntimeError(
+                        format!("the input ended before a blade from {} to {} was given", min, max)
+                    )
+                );
+            }
+            let complaint = match input.trim().parse::<i64>() {
+                Ok(blade) if (min..=max).contains(&blade) => {
+                    return Ok(blade);
+                }
+                Ok(blade) => format!("⚔️ {} is not from {} to {}; try again.\n", blade, min, max),
+                Err(_) => format!("⚔️ '{}' is not a blade; try again.\n", input.trim()),
+            };
+            self.write_output(&complaint)?;
+        }
+    }
+
+    /// Asks for a line of input, without waiting for it.
+    fn prompt(&mut self, prompt: &str) -> Result<(), ValyrianError> {
+        if self.sandboxed {
+            return Err(
+                ValyrianError::SandboxViolation("reading input is not allowed in the sandbox".into())
+            );
+        }
+        self.write_output(prompt)?;
+        self.flush_output()
+    }
+
+    /// If a call to `name` is a call to the `sleep` builtin, gives how long
+    /// it asks to sleep for.
+    fn sleep_duration(
+        &self,
         name: &str,
-        arguments: &[Expression]
-    ) -> Result<Value, ValyrianError> {
-        let (params, body) = self.functions
-            .get(name)
-            .ok_or_else(|| ValyrianError::UndefinedFunction(name.to_string()))?
-            .clone();
+        values: &[Value]
+    ) -> Option<Result<Duration, ValyrianError>> {
+        if name != "sleep" || self.functions.contains_key(name) || self.natives.contains_key(name) {
+            return None;
+        }
+        if self.compiled_functions.contains_key(name) {
+            return None;
+        }
+        Some(builtins::sleep_duration(self, values))
+    }
 
-        if arguments.len() != params.len() {
-            return Err(ValyrianError::ArgumentMismatch);
+    fn check_declared_type(&self, data_type: &DataType, value: &Value) -> Result<(), ValyrianError> {
+        let matches = matches!(
+            (data_type, value),
+            (DataType::Scroll, Value::String(_)) |
+                (DataType::Blade, Value::Integer(_)) |
+                (DataType::Wine, Value::Float(_)) |
+                (DataType::Vow, Value::Boolean(_)) |
+                (DataType::Sigil, Value::Char(_)) |
+                (DataType::Raven, Value::Raven(_)) |
+                (DataType::Rookery, Value::Rookery(_)) |
+                (DataType::Vault, Value::Vault(_)) |
+                (DataType::Tidings, Value::Tidings(_)) |
+                (DataType::Missive, Value::Missive(_)) |
+                (DataType::Socket, Value::Socket(_)) |
+                (DataType::Void, Value::Void)
+        );
+        if matches {
+            Ok(())
+        } else {
+            Err(ValyrianError::type_error(&data_type.to_string(), &self.type_name(value)))
         }
+    }
+
+    fn declare_function(&mut self, name: &Symbol, parameters: &[Symbol], body: &[Statement]) {
+        let function = FunctionDefinition { parameters: parameters.to_vec(), body: body.to_vec() };
+        self.define_function(name.clone(), function);
+    }
 
-        let old_vars: Vec<_> = params
+    /// Keeps `definition` as the function `name`, resolved to its frame.
+    fn define_function(&mut self, name: Symbol, mut definition: FunctionDefinition) {
+        let names = frame::resolve(&definition.parameters, &mut definition.body);
+        let frame = self.frame_slots(&names);
+        let parameters = self.frame_slots(&definition.parameters);
+        let function = DeclaredFunction { definition: Arc::new(definition), frame, parameters };
+        self.functions.insert(name, function);
+    }
+
+    fn call_function(
+        &mut self,
+        name: &Symbol,
+        arguments: &[Expression],
+        call_site: Span
+    ) -> Result<Value, ValyrianError> {
+        let Some(function) = self.functions.get(name).cloned() else {
+            let values = self.evaluate_arguments(arguments)?;
+            return self.call_with_values(name, values, call_site);
+        };
+        self.check_call(name, function.parameters.len(), arguments.len())?;
+        let values = self.evaluate_arguments(arguments)?;
+        self.call_body(name, &function, values, call_site)
+    }
+
+    fn evaluate_arguments(&mut self, arguments: &[Expression]) -> Result<Vec<Value>, ValyrianError> {
+        arguments
             .iter()
-            .map(|p| (p.clone(), self.variables.get(p).cloned()))
-            .collect();
+            .map(|arg_expr| self.evaluate_expression(arg_expr))
+            .collect()
+    }
 
-        for (param, arg_expr) in params.iter().zip(arguments.iter()) {
-            let value = self.evaluate_expression(arg_expr)?;
-            self.variables.insert(param.clone(), value);
+    /// Calls a function whose arguments are already evaluated: one the
+    /// council declared (walked or compiled), a native, or a builtin.
+    fn call_with_values(
+        &mut self,
+        name: &Symbol,
+        values: Vec<Value>,
+        call_site: Span
+    ) -> Result<Value, ValyrianError> {
+        if let Some(function) = self.functions.get(name).cloned() {
+            self.check_call(name, function.parameters.len(), values.len())?;
+            return self.call_body(name, &function, values, call_site);
+        }
+        if let Some(function) = self.compiled_functions.get(name).cloned() {
+            return self.call_compiled(function, values, call_site);
         }
 
-        for stmt in &body {
-            if let Some(ControlFlow::Return(val)) = self.execute_statement(stmt)? {
-                for (param, old_val) in old_vars {
-                    match old_val {
-                        Some(v) => {
-                            self.variables.insert(param, v);
-                        }
-                        None => {
-                            self.variables.remove(&param);
-                        }
-                    }
+        self.notify_call(name, &values, &call_site);
+        let _call = debug_span!("call", function = &**name, line = call_site.line).entered();
+        let result = match self.natives.get(&**name) {
+            Some(native) => native(&values),
+            None =>
+                builtins::call(self, name, &values).unwrap_or_else(||
+                    Err(ValyrianError::UndefinedFunction(name.to_string()))
+                ),
+        };
+        self.notify_return(name, &result);
+        result
+    }
+
+    /// Fails if a declared function can't be called with `given` arguments
+    /// right now.
+    fn check_call(&self, name: &str, expected: usize, given: usize) -> Result<(), ValyrianError> {
+        self.check_arguments(name, expected, given)?;
+        if self.call_stack.len() >= self.max_call_depth {
+            return Err(ValyrianError::StackOverflow(self.max_call_depth));
+        }
+        Ok(())
+    }
+
+    /// Like `check_call`, for a call that takes over its caller's frame and
+    /// so can't nest too deeply.
+    fn check_arguments(&self, name: &str, expected: usize, given: usize) -> Result<(), ValyrianError> {
+        if self.strict && !self.declared_functions.contains(name) {
+            return Err(
+                ValyrianError::StrictViolation(
+                    format!("'{}' is called before the council has declared it", name)
+                )
+            );
+        }
+
+        if given != expected {
+            return Err(ValyrianError::ArgumentMismatch);
+        }
+        Ok(())
+    }
+
+    /// Runs the body of a declared function with its parameters bound.
+    ///
+    /// Tail calls the body makes (see `tail_call`) run in the same frame, one
+    /// after another, so recursion through them never nests. Each still
+    /// returns to the observers, innermost first, once the last one is done.
+    fn call_body(
+        &mut self,
+        name: &Symbol,
+        function: &DeclaredFunction,
+        values: Vec<Value>,
+        call_site: Span
+    ) -> Result<Value, ValyrianError> {
+        self.notify_call(name, &values, &call_site);
+        let mut old_vars = self.bind_parameters(name, &function.parameters, values)?;
+        let caller = std::mem::replace(&mut self.frame, function.frame.clone());
+
+        self.call_stack.push(StackFrame {
+            function: name.clone(),
+            caller: self.current_function(),
+            call_site,
+            elided: 0,
+        });
+        if let Some(hook) = &mut self.debug_hook {
+            hook.enter_function(name);
+        }
+        let mut flow = self.run_function_body(name, &function.definition.body, call_site);
+        let mut tail_called = Vec::new();
+        while let Ok(Some(ControlFlow::TailCall { function, arguments, call_site })) = flow {
+            flow = match self.enter_tail_call(&function, arguments, call_site, &mut old_vars) {
+                Ok(callee) => {
+                    tail_called.push(function.clone());
+                    self.frame = callee.frame;
+                    self.run_function_body(&function, &callee.definition.body, call_site)
                 }
-                return Ok(val);
+                Err(error) => Err(self.attach_trace(error, call_site)),
+            };
+        }
+        self.call_stack.pop();
+
+        self.frame = caller;
+        self.restore_variables(old_vars);
+        let result = flow.map(|flow| {
+            match flow {
+                Some(ControlFlow::Return(value)) => value,
+                Some(ControlFlow::TailCall { .. }) => unreachable!("tail calls run above"),
+                None => Value::Void,
             }
+        });
+        for function in tail_called.iter().rev() {
+            self.notify_return(function, &result);
         }
+        self.notify_return(name, &result);
 
-        for (param, old_val) in old_vars {
-            match old_val {
-                Some(v) => {
-                    self.variables.insert(param, v);
-                }
-                None => {
-                    self.variables.remove(&param);
-                }
+        result
+    }
+
+    /// Starts a `return` of a call to a declared function from within
+    /// another: checks the call and evaluates its arguments, leaving the
+    /// caller to run it in place of itself. Returns `None` for anything
+    /// else, including while a `Debugger` is attached, which needs every
+    /// frame.
+    fn tail_call(&mut self, expression: &Expression) -> Result<Option<ControlFlow>, ValyrianError> {
+        let ExpressionKind::FunctionCall { name, arguments } = &expression.kind else {
+            return Ok(None);
+        };
+        if self.call_stack.is_empty() || self.debug_hook.is_some() {
+            return Ok(None);
+        }
+        let Some(expected) = self.functions.get(name).map(|function| function.parameters.len()) else {
+            return Ok(None);
+        };
+        // The step evaluating the call would take
+        self.charge_step()?;
+        self.check_arguments(name, expected, arguments.len())?;
+        let arguments = self.evaluate_arguments(arguments)?;
+        Ok(Some(ControlFlow::TailCall { function: name.clone(), arguments, call_site: expression.span }))
+    }
+
+    /// Enters a tail call in the current frame, adding what its parameters
+    /// replace to the frame's `old_vars`.
+    fn enter_tail_call(
+        &mut self,
+        name: &Symbol,
+        values: Vec<Value>,
+        call_site: Span,
+        old_vars: &mut Vec<(usize, Option<Value>)>
+    ) -> Result<DeclaredFunction, ValyrianError> {
+        let function = self.functions[name].clone();
+        self.notify_call(name, &values, &call_site);
+        let replaced = self.bind_parameters(name, &function.parameters, values)?;
+        keep_replaced(old_vars, replaced);
+        // The frame still returns to whoever made the first call in it
+        let frame = self.call_stack.last_mut().expect("tail calls are made from a call");
+        frame.function = name.clone();
+        frame.elided += 1;
+        Ok(function)
+    }
+
+    /// Binds a call's parameters, returning the values they replace. If the
+    /// variable limit is hit, everything is put back and the failed call is
+    /// reported to the observers.
+    fn bind_parameters(
+        &mut self,
+        name: &str,
+        slots: &[usize],
+        values: Vec<Value>
+    ) -> Result<Vec<(usize, Option<Value>)>, ValyrianError> {
+        let mut old_vars = Vec::with_capacity(slots.len());
+        for (&slot, value) in slots.iter().zip(values) {
+            if let Err(error) = self.check_new_variable(slot) {
+                self.restore_variables(old_vars);
+                let result = Err(error);
+                self.notify_return(name, &result);
+                return result.map(|_| Vec::new());
             }
+            old_vars.push((slot, self.variables.set(slot, Some(value))));
         }
+        self.note_variable_count();
+        Ok(old_vars)
+    }
 
-        Ok(Value::Void)
+    /// Counts a call and tells the observers about it. Every kind of call
+    /// passes through here.
+    fn notify_call(&mut self, name: &str, arguments: &[Value], call_site: &Span) {
+        self.stats.calls += 1;
+        for observer in &mut self.observers {
+            observer.on_call(name, arguments, call_site);
+        }
+    }
+
+    fn notify_return(&mut self, name: &str, result: &Result<Value, ValyrianError>) {
+        for observer in &mut self.observers {
+            observer.on_return(name, result);
+        }
+    }
+
+    fn run_function_body(
+        &mut self,
+        name: &str,
+        body: &[Statement],
+        call_site: Span
+    ) -> Result<Option<ControlFlow>, ValyrianError> {
+        let _span = debug_span!("call", function = name, line = call_site.line).entered();
+        for stmt in body {
+            if let Some(flow) = self.execute_statement(stmt)? {
+                return Ok(Some(flow));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Name of the function currently executing, or `main` for the iron throne.
+    fn current_function(&self) -> Symbol {
+        static MAIN: OnceLock<Symbol> = OnceLock::new();
+        self.call_stack
+            .last()
+            .map(|frame| frame.function.clone())
+            .unwrap_or_else(|| MAIN.get_or_init(|| "main".into()).clone())
+    }
+
+    /// Wraps an error escaping a statement with that statement's span and the
+    /// Valyrian call stack as it was at the point of failure. Errors that
+    /// already carry a trace are passed through untouched.
+    fn attach_trace(&self, error: ValyrianError, span: Span) -> ValyrianError {
+        match error {
+            ValyrianError::Traced { .. } => error,
+            error =>
+                ValyrianError::Traced {
+                    error: Box::new(error),
+                    span,
+                    trace: self.call_stack.iter().rev().cloned().collect(),
+                },
+        }
+    }
+
+    /// Puts back variables saved while binding a call's parameters, last
+    /// first, so a parameter named twice gets its original value back.
+    fn restore_variables(&mut self, saved: Vec<(usize, Option<Value>)>) {
+        for (slot, old_val) in saved.into_iter().rev() {
+            self.variables.set(slot, old_val);
+        }
     }
 
     fn evaluate_expression(&mut self, expression: &Expression) -> Result<Value, ValyrianError> {
-        match expression {
-            Expression::Literal(literal) =>
+        self.charge_step()?;
+        let value = with_stack(|| self.evaluate_expression_kind(expression))?;
+        self.check_value_size(&value)?;
+        Ok(value)
+    }
+
+    fn evaluate_expression_kind(&mut self, expression: &Expression) -> Result<Value, ValyrianError> {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) =>
                 match literal {
                     Literal::String(s) => Ok(Value::String(s.clone())),
                     Literal::Integer(i) => Ok(Value::Integer(*i)),
@@ -226,33 +1735,45 @@ impl Interpreter {
                     Literal::Boolean(b) => Ok(Value::Boolean(*b)),
                     Literal::Char(c) => Ok(Value::Char(*c)),
                 }
-            Expression::Identifier(name) => {
+            ExpressionKind::Identifier(name) => {
+                let slot = self.variable_slot(name);
                 self.variables
-                    .get(name)
+                    .get_slot(slot)
                     .cloned()
-                    .ok_or_else(|| ValyrianError::UndefinedVariable(name.clone()))
+                    .ok_or_else(|| ValyrianError::UndefinedVariable(name.to_string()))
             }
-            Expression::Binary { left, operator, right } => {
+            ExpressionKind::Binary { left, operator, right } => {
                 let left_val = self.evaluate_expression(left)?;
                 let right_val = self.evaluate_expression(right)?;
                 self.apply_binary_operator(operator, &left_val, &right_val)
             }
-            Expression::Unary { operator, operand } => {
+            ExpressionKind::Unary { operator, operand } => {
                 let operand_val = self.evaluate_expression(operand)?;
                 self.apply_unary_operator(operator, &operand_val)
             }
-            Expression::Input(_) => {
-                print!("🗣️ Speak your words: ");
-                io::stdout().flush().map_err(ValyrianError::from)?;
-                let mut input = String::new();
-                io::stdin().read_line(&mut input).map_err(ValyrianError::from)?;
-                Ok(Value::String(input.trim().to_string()))
+            ExpressionKind::Input(_) => self.read_input(DEFAULT_PROMPT),
+            ExpressionKind::Ask(prompt) => {
+                let prompt = self.evaluate_expression(prompt)?.to_string();
+                self.read_input(&prompt)
+            }
+            ExpressionKind::FunctionCall { name, arguments } => {
+                self.call_function(name, arguments, expression.span)
+            }
+            ExpressionKind::SendRaven(call) => {
+                let ExpressionKind::FunctionCall { name, arguments } = &call.kind else {
+                    return Err(ValyrianError::RuntimeError("only a call can be sent by raven".into()));
+                };
+                let values = self.evaluate_arguments(arguments)?;
+                self.send_raven(name, values, call.span)
+            }
+            ExpressionKind::AwaitRaven(raven) => {
+                let raven = self.evaluate_expression(raven)?;
+                self.await_raven(raven)
             }
-            Expression::FunctionCall { name, arguments } => { self.call_function(name, arguments) }
         }
     }
 
-    fn apply_binary_operator(
+    pub(crate) fn apply_binary_operator(
         &self,
         op: &BinaryOperator,
         left: &Value,
@@ -261,42 +1782,42 @@ impl Interpreter {
         use BinaryOperator::*;
         match (op, left, right) {
             // Arithmetic operators
-            (Add, Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l + r)),
+            (Add, Value::Integer(l), Value::Integer(r)) => blade_result(l.checked_add(*r), || format!("{} + {}", l, r)),
             (Add, Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
-            (Add, Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+            (Add, Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r).into())),
             (Add, Value::Integer(l), Value::Float(r)) => Ok(Value::Float((*l as f64) + r)),
             (Add, Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l + (*r as f64))),
 
-            (Subtract, Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l - r)),
+            (Subtract, Value::Integer(l), Value::Integer(r)) => blade_result(l.checked_sub(*r), || format!("{} - {}", l, r)),
             (Subtract, Value::Float(l), Value::Float(r)) => Ok(Value::Float(l - r)),
             (Subtract, Value::Integer(l), Value::Float(r)) => Ok(Value::Float((*l as f64) - r)),
             (Subtract, Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l - (*r as f64))),
 
-            (Multiply, Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l * r)),
+            (Multiply, Value::Integer(l), Value::Integer(r)) => blade_result(l.checked_mul(*r), || format!("{} * {}", l, r)),
             (Multiply, Value::Float(l), Value::Float(r)) => Ok(Value::Float(l * r)),
             (Multiply, Value::Integer(l), Value::Float(r)) => Ok(Value::Float((*l as f64) * r)),
             (Multiply, Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l * (*r as f64))),
 
             (Divide, _, Value::Integer(r)) if *r == 0 => Err(ValyrianError::DivisionByZero),
             (Divide, _, Value::Float(r)) if *r == 0.0 => Err(ValyrianError::DivisionByZero),
-            (Divide, Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l / r)),
+            (Divide, Value::Integer(l), Value::Integer(r)) => blade_result(l.checked_div(*r), || format!("{} / {}", l, r)),
             (Divide, Value::Float(l), Value::Float(r)) => Ok(Value::Float(l / r)),
             (Divide, Value::Integer(l), Value::Float(r)) => Ok(Value::Float((*l as f64) / r)),
             (Divide, Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l / (*r as f64))),
 
-            // Boolean operators
-            (And, Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(*l && *r)),
-            (Or, Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(*l || *r)),
-
-            // Numeric comparisons - **put these before Equals/NotEquals**
+            // Numeric comparisons
             (Greater, Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l > r)),
             (Less, Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l < r)),
-            (GreaterEqual, Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l >= r)),
-            (LessEqual, Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l <= r)),
+            (Greater, Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l > r)),
+            (Less, Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l < r)),
+            (Greater, Value::Integer(l), Value::Float(r)) => Ok(Value::Boolean((*l as f64) > *r)),
+            (Less, Value::Integer(l), Value::Float(r)) => Ok(Value::Boolean((*l as f64) < *r)),
+            (Greater, Value::Float(l), Value::Integer(r)) => Ok(Value::Boolean(*l > (*r as f64))),
+            (Less, Value::Float(l), Value::Integer(r)) => Ok(Value::Boolean(*l < (*r as f64))),
 
             // General equality checks (catch all variants)
-            (Equals, l, r) => Ok(Value::Boolean(l == r)),
-            (NotEquals, l, r) => Ok(Value::Boolean(l != r)),
+            (Equal, l, r) => Ok(Value::Boolean(l == r)),
+            (NotEqual, l, r) => Ok(Value::Boolean(l != r)),
 
             // Catch-all fallback for unsupported operations
             _ =>
@@ -310,13 +1831,13 @@ impl Interpreter {
         }
     }
 
-    fn apply_unary_operator(
+    pub(crate) fn apply_unary_operator(
         &self,
         op: &UnaryOperator,
         operand: &Value
     ) -> Result<Value, ValyrianError> {
         match (op, operand) {
-            (UnaryOperator::Minus, Value::Integer(n)) => Ok(Value::Integer(-n)),
+            (UnaryOperator::Minus, Value::Integer(n)) => blade_result(n.checked_neg(), || format!("-({})", n)),
             (UnaryOperator::Minus, Value::Float(f)) => Ok(Value::Float(-f)),
             (UnaryOperator::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
             _ =>
@@ -328,7 +1849,7 @@ impl Interpreter {
         }
     }
 
-    fn type_name(&self, value: &Value) -> String {
+    pub(crate) fn type_name(&self, value: &Value) -> String {
         match value {
             Value::Integer(_) => "integer".to_string(),
             Value::Float(_) => "float".to_string(),
@@ -336,6 +1857,154 @@ impl Interpreter {
             Value::Boolean(_) => "boolean".to_string(),
             Value::Char(_) => "char".to_string(),
             Value::Void => "void".to_string(),
+            Value::External(external) => format!("external {}", external.type_name()),
+            Value::Raven(_) => "raven".to_string(),
+            Value::Rookery(_) => "rookery".to_string(),
+            Value::Vault(_) => "vault".to_string(),
+            Value::Tidings(_) => "tidings".to_string(),
+            Value::Missive(_) => "missive".to_string(),
+            Value::Socket(_) => "socket".to_string(),
         }
     }
 }
+
+/// The blade an arithmetic operation gave, or an error naming the
+/// operation if its result doesn't fit in one.
+fn blade_result(result: Option<i64>, operation: impl FnOnce() -> String) -> Result<Value, ValyrianError> {
+    result
+        .map(Value::Integer)
+        .ok_or_else(|| ValyrianError::RuntimeError(format!("{} is too big for a blade", operation())))
+}
+
+/// Configures an `Interpreter`; see `Interpreter::builder`.
+pub struct InterpreterBuilder {
+    interpreter: Interpreter,
+}
+
+impl InterpreterBuilder {
+    /// Shows the AST before running, on the `debug_output` writer.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.interpreter.debug = debug;
+        self
+    }
+
+    /// See `Interpreter::with_strict`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.interpreter = self.interpreter.with_strict(strict);
+        self
+    }
+
+    /// See `Interpreter::with_limits`.
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.interpreter.limits = limits;
+        self
+    }
+
+    /// See `Interpreter::with_sandbox`.
+    pub fn sandbox(mut self, sandboxed: bool) -> Self {
+        self.interpreter.sandboxed = sandboxed;
+        self
+    }
+
+    /// See `Interpreter::with_commands`.
+    pub fn commands(mut self, allowed: bool) -> Self {
+        self.interpreter.commands_allowed = allowed;
+        self
+    }
+
+    /// See `Interpreter::with_float_precision`.
+    pub fn float_precision(mut self, decimals: usize) -> Self {
+        self.interpreter.float_precision = Some(decimals);
+        self
+    }
+
+    /// See `Interpreter::with_max_call_depth`.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.interpreter.max_call_depth = depth;
+        self
+    }
+
+    /// See `Interpreter::with_timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.interpreter.timeout = Some(timeout);
+        self
+    }
+
+    /// See `Interpreter::with_backend`.
+    pub fn backend(mut self, backend: ExecutionBackend) -> Self {
+        self.interpreter.backend = backend;
+        self
+    }
+
+    /// See `Interpreter::with_max_steps`.
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.interpreter.max_steps = Some(max_steps);
+        self
+    }
+
+    /// The arguments the script sees through `arg_count` and `arg`.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.interpreter = self.interpreter.with_args(args);
+        self
+    }
+
+    /// See `Interpreter::set_global`.
+    pub fn global(mut self, name: &str, value: Value) -> Self {
+        self.interpreter.set_global(name, value);
+        self
+    }
+
+    /// See `Interpreter::register_native`.
+    pub fn native<F>(mut self, name: &str, function: F) -> Self
+        where F: Fn(&[Value]) -> Result<Value, ValyrianError> + Send + Sync + 'static
+    {
+        self.interpreter.register_native(name, function);
+        self
+    }
+
+    /// Where `speak` writes; see `Interpreter::with_output`.
+    pub fn stdout(mut self, output: impl Write + Send + 'static) -> Self {
+        self.interpreter.set_output(output);
+        self
+    }
+
+    /// Where debug mode writes; see `Interpreter::with_debug_output`.
+    pub fn debug_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.interpreter.set_debug_output(output);
+        self
+    }
+
+    /// Where `speaks for input` reads; see `Interpreter::with_input`.
+    pub fn stdin(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.interpreter.set_input(input);
+        self
+    }
+
+    /// See `Interpreter::with_cancellation`.
+    pub fn cancellation(mut self, handle: CancellationHandle) -> Self {
+        self.interpreter.cancellation = handle;
+        self
+    }
+
+    /// See `Interpreter::install_plugin`.
+    pub fn plugin(mut self, plugin: &dyn Plugin) -> Self {
+        self.interpreter.install_plugin(plugin);
+        self
+    }
+
+    /// See `Interpreter::add_observer`.
+    pub fn observer(mut self, observer: impl ExecutionObserver + 'static) -> Self {
+        self.interpreter.add_observer(observer);
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        self.interpreter
+    }
+}
+
+// Embedders rely on moving interpreters between threads
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Interpreter>();
+};
@@ -0,0 +1,97 @@
+//! 📜 Running other programs.
+//!
+//! `command with "ls", "-l -a"` runs a program and waits for it, giving
+//! back `Tidings` of how it went: `exit_code`, `stdout`, and `stderr` read
+//! them. Valyrian has no lists, so the arguments are a single scroll split
+//! at whitespace; no shell is involved, so there is no quoting, globbing,
+//! or piping. The program gets no input.
+//!
+//! Running programs is refused unless the host allows it with
+//! `Interpreter::with_commands` (`--allow-commands` on the command line),
+//! and always refused in the sandbox.
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+//!
+//! let source = "on the iron throne:\n    tidings is a tidings with command with \"echo\", \"Valar  morghulis\"\n    speak exit_code with tidings\n    speak stdout with tidings\n";
+//! let program = parse_program(source).unwrap();
+//! let output = OutputBuffer::new();
+//! let mut interpreter = Interpreter::new(false).with_output(output.clone());
+//! assert!(interpreter.interpret(&program).is_err());
+//!
+//! let mut interpreter = interpreter.with_commands(true);
+//! # if cfg!(unix) {
+//! interpreter.interpret(&program).unwrap();
+//! assert_eq!(output.contents(), "0\nValar morghulis\n\n");
+//! # }
+//! ```
+
+use std::process::{ Command, Stdio };
+use crate::ast::{ Nested, Symbol, Value };
+use crate::error::ValyrianError;
+use super::Interpreter;
+
+/// How a program run by the `command` builtin went. It shows as
+/// `tidings {exit_code: 0, stdout: "hello\n", stderr: ""}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tidings {
+    code: i64,
+    stdout: Symbol,
+    stderr: Symbol,
+}
+
+impl Tidings {
+    /// The program's exit code, or -1 if a signal ended it.
+    pub fn exit_code(&self) -> i64 {
+        self.code
+    }
+
+    /// What the program wrote to its standard output.
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
+
+    /// What the program wrote to its standard error.
+    pub fn stderr(&self) -> &str {
+        &self.stderr
+    }
+}
+
+impl std::fmt::Display for Tidings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tidings {{exit_code: {}, stdout: {}, stderr: {}}}",
+            self.code,
+            Nested(&Value::String(self.stdout.clone())),
+            Nested(&Value::String(self.stderr.clone()))
+        )
+    }
+}
+
+impl Interpreter {
+    /// Runs `program` with `arguments` split at whitespace, and waits for
+    /// it to finish.
+    pub(crate) fn command(&mut self, program: &str, arguments: &str) -> Result<Value, ValyrianError> {
+        if !self.commands_allowed {
+            return Err(
+                ValyrianError::SandboxViolation(
+                    format!("running '{}' needs commands to be allowed (--allow-commands)", program)
+                )
+            );
+        }
+        self.flush_output()?;
+        let output = Command::new(program)
+            .args(arguments.split_whitespace())
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| ValyrianError::io(format!("Failed to run '{}': {}", program, e), e))?;
+        Ok(
+            Value::Tidings(Tidings {
+                code: output.status.code().map_or(-1, i64::from),
+                stdout: String::from_utf8_lossy(&output.stdout).as_ref().into(),
+                stderr: String::from_utf8_lossy(&output.stderr).as_ref().into(),
+            })
+        )
+    }
+}
@@ -0,0 +1,110 @@
+//! 📇 Frames for the tree-walking interpreter.
+//!
+//! Before a function body or the statements of a run are walked, each
+//! `Name` in them is resolved to a slot of their frame: the parameters
+//! first, then the other names in the order they appear. The frame's slots
+//! are mapped to the interpreter's variable slots when the function is
+//! declared or the run starts, so reading a variable takes two indexes and
+//! no hashing, as it does in a compiled chunk.
+//!
+//! Function declarations and trials within the code are left alone; each
+//! gets a frame of its own when it is declared or run.
+//!
+//! Frames only change how names are found. Scoping stays dynamic (see
+//! `Variables`), so every frame naming a variable reaches the same one:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Interpreter, Value };
+//!
+//! let source = "gold is a blade with 1\nwe declare spend with gold ->\ncouncil says:\n    left is a blade with gold - 1\n    return left\n\nspent is a blade with spend with 10\n";
+//! let mut interpreter = Interpreter::new(false);
+//! interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+//! assert_eq!(interpreter.get_variable("spent"), Some(&Value::Integer(9)));
+//! assert_eq!(interpreter.get_variable("left"), Some(&Value::Integer(9)));
+//! assert_eq!(interpreter.get_variable("gold"), Some(&Value::Integer(1)));
+//! ```
+
+use std::collections::HashMap;
+use crate::ast::{ Expression, ExpressionKind, Name, Statement, StatementKind, Symbol };
+use crate::visit::{ walk_expression_mut, walk_statement_mut, VisitorMut };
+
+/// Resolves the names in `statements` to the slots of a frame that starts
+/// with `parameters`, returning the name in each slot.
+pub(super) fn resolve(parameters: &[Symbol], statements: &mut Vec<Statement>) -> Vec<Symbol> {
+    let mut frame = FrameNames::default();
+    for parameter in parameters {
+        frame.slot(parameter);
+    }
+    frame.visit_block_mut(statements);
+    frame.names
+}
+
+/// Takes back what `resolve` gave the names in `statements`, for code
+/// handed out of the interpreter.
+pub(super) fn forget(statements: &mut Vec<Statement>) {
+    Forget.visit_block_mut(statements);
+}
+
+#[derive(Default)]
+struct FrameNames {
+    names: Vec<Symbol>,
+    slots: HashMap<Symbol, u32>,
+}
+
+impl FrameNames {
+    fn slot(&mut self, name: &Symbol) -> u32 {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        let slot = self.names.len() as u32;
+        self.slots.insert(name.clone(), slot);
+        self.names.push(name.clone());
+        slot
+    }
+
+    fn resolve(&mut self, name: &mut Name) {
+        name.slot = Some(self.slot(&name.symbol));
+    }
+}
+
+impl VisitorMut for FrameNames {
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        match &mut statement.kind {
+            StatementKind::FunctionDeclaration { .. } | StatementKind::Trial { .. } => {
+                return;
+            }
+            StatementKind::VariableDeclaration { name, .. } |
+            StatementKind::Assignment { name, .. } => self.resolve(name),
+            _ => {}
+        }
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        if let ExpressionKind::Identifier(name) = &mut expression.kind {
+            self.resolve(name);
+        }
+        walk_expression_mut(self, expression);
+    }
+}
+
+struct Forget;
+
+impl VisitorMut for Forget {
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        if let
+            StatementKind::VariableDeclaration { name, .. } |
+            StatementKind::Assignment { name, .. } = &mut statement.kind
+        {
+            name.slot = None;
+        }
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        if let ExpressionKind::Identifier(name) = &mut expression.kind {
+            name.slot = None;
+        }
+        walk_expression_mut(self, expression);
+    }
+}
@@ -0,0 +1,103 @@
+//! 🌐 Asking the web for scrolls.
+//!
+//! `fetch with url` makes a GET request and `post with url, body` a POST
+//! with `body` as its text, each giving back a `Missive` that `status` and
+//! `body` read. A missive is given back whatever the status, so a program
+//! can tell a 404 from a 200; only failing to reach the server is an
+//! error.
+//!
+//! Both need the crate's `http` feature, and the sandbox refuses them.
+//!
+//! ```
+//! use std::io::{ BufRead, BufReader, Write };
+//! use std::net::TcpListener;
+//! use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+//!
+//! // A server that answers one request
+//! let server = TcpListener::bind("127.0.0.1:0").unwrap();
+//! let address = server.local_addr().unwrap();
+//! std::thread::spawn(move || {
+//!     let (stream, _) = server.accept().unwrap();
+//!     let mut reader = BufReader::new(stream);
+//!     let mut line = String::new();
+//!     while reader.read_line(&mut line).unwrap() > 2 {
+//!         line.clear();
+//!     }
+//!     let response = "HTTP/1.1 200 OK\r\nContent-Length: 14\r\nConnection: close\r\n\r\nValar dohaeris";
+//!     reader.get_mut().write_all(response.as_bytes()).unwrap();
+//! });
+//!
+//! let source = format!(
+//!     "on the iron throne:\n    missive is a missive with fetch with \"http://{}/\"\n    speak status with missive\n    speak body with missive\n",
+//!     address
+//! );
+//! let output = OutputBuffer::new();
+//! let mut interpreter = Interpreter::new(false).with_output(output.clone());
+//! match interpreter.interpret(&parse_program(&source).unwrap()) {
+//!     Ok(()) => assert_eq!(output.contents(), "200\nValar dohaeris\n"),
+//!     // Built without the `http` feature
+//!     Err(error) => assert!(error.to_string().contains("`http` feature")),
+//! }
+//! ```
+
+use crate::ast::{ Nested, Symbol, Value };
+use crate::error::ValyrianError;
+use super::Interpreter;
+
+/// A server's answer to `fetch` or `post`. It shows as
+/// `missive {status: 200, body: "..."}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Missive {
+    status: i64,
+    body: Symbol,
+}
+
+impl Missive {
+    /// The HTTP status code, e.g. 200.
+    pub fn status(&self) -> i64 {
+        self.status
+    }
+
+    /// The text of the response.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+impl std::fmt::Display for Missive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let body = Value::String(self.body.clone());
+        write!(f, "missive {{status: {}, body: {}}}", self.status, Nested(&body))
+    }
+}
+
+impl Interpreter {
+    /// Sends a GET request to `url`, or a POST with `body` if one is given,
+    /// and waits for the answer.
+    #[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+    pub(crate) fn request(&mut self, url: &str, body: Option<&str>) -> Result<Value, ValyrianError> {
+        self.flush_output()?;
+        let sent = match body {
+            Some(body) => ureq::post(url).send_string(body),
+            None => ureq::get(url).call(),
+        };
+        let response = match sent {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(ureq::Error::Transport(error)) => {
+                return Err(
+                    ValyrianError::RuntimeError(format!("The raven to '{}' was lost: {}", url, error))
+                );
+            }
+        };
+        let status = i64::from(response.status());
+        let body = response
+            .into_string()
+            .map_err(|e| ValyrianError::io(format!("Failed to read the answer from '{}'", url), e))?;
+        Ok(Value::Missive(Missive { status, body: body.into() }))
+    }
+
+    #[cfg(not(all(feature = "http", not(target_arch = "wasm32"))))]
+    pub(crate) fn request(&mut self, _url: &str, _body: Option<&str>) -> Result<Value, ValyrianError> {
+        Err(ValyrianError::RuntimeError("fetching from the web needs the `http` feature".into()))
+    }
+}
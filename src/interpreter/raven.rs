@@ -0,0 +1,418 @@
+//! 🐦‍⬛ Ravens: calls sent to run on their own thread.
+//!
+//! `send_raven` evaluates a call's arguments, then hands the call to a
+//! fresh interpreter on a new thread and carries on at once. The raven
+//! starts with a copy of the sender's functions, variables, and limits, so
+//! what it changes stays with it, and it times out no later than the run
+//! that sent it. Whatever it speaks is held back until `await_raven`,
+//! which waits for the call to finish and gives its result, so ravens never
+//! talk over each other.
+//!
+//! A raven can't read input, and a debugger or observers never see it.
+//! WebAssembly has no threads, so there the call runs when it is sent.
+//!
+//! Ravens pass values to each other through a `Rookery`, a channel made
+//! by the `rookery` builtin. `dispatch` leaves a value in it, and
+//! `receive` takes the oldest one out, waiting for one to arrive if it is
+//! empty.
+//!
+//! A value they all work on goes in a `Vault`, made by the `vault`
+//! builtin. `claim` takes the value out, waiting while another raven holds
+//! it, and `release` puts the new value back for the next. A raven that
+//! never releases a vault it claimed keeps the others waiting for good.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{ self, BufRead, Read };
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard, PoisonError };
+use std::thread::ThreadId;
+use std::time::Duration;
+use crate::ast::{ Nested, Span, Symbol, Value };
+use crate::builtins;
+use crate::error::ValyrianError;
+use super::{ Interpreter, OutputBuffer };
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::JoinHandle;
+#[cfg(not(target_arch = "wasm32"))]
+use super::PROGRAM_STACK_SIZE;
+
+/// A call sent off with `send_raven`, as a value a program can hold and
+/// await. Clones are the same raven, and two ravens are equal only when
+/// they are the same one.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer, Value };
+///
+/// let source = "we declare square with n ->\ncouncil says:\n    speak \"squaring\"\n    return n * n\n\non the iron throne:\n    raven is a raven with send_raven square with 7\n    speak \"sent\"\n    return await_raven raven\n";
+/// let output = OutputBuffer::new();
+/// let mut interpreter = Interpreter::new(false).with_output(output.clone());
+/// let result = interpreter.interpret_with_result(&parse_program(source).unwrap()).unwrap();
+/// assert_eq!(result, Value::Integer(49));
+/// // The raven's words arrive when it is awaited
+/// assert_eq!(output.contents(), "sent\nsquaring\n");
+/// ```
+#[derive(Clone)]
+pub struct Raven(Arc<Mutex<Flight>>);
+
+enum Flight {
+    #[cfg(not(target_arch = "wasm32"))]
+    Flying(JoinHandle<Landing>),
+    #[cfg(target_arch = "wasm32")]
+    Landed(Landing),
+    /// Already awaited: the result, or `None` if the call failed.
+    Awaited(Option<Value>),
+}
+
+/// What a raven brings back: the call's result and what it spoke.
+type Landing = (Result<Value, ValyrianError>, String);
+
+impl std::fmt::Debug for Raven {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Raven")
+    }
+}
+
+impl PartialEq for Raven {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Raven {
+    /// Returns true once the call has finished, so awaiting the raven won't
+    /// wait.
+    pub fn has_landed(&self) -> bool {
+        match &*self.0.lock().unwrap_or_else(PoisonError::into_inner) {
+            #[cfg(not(target_arch = "wasm32"))]
+            Flight::Flying(handle) => handle.is_finished(),
+            _ => true,
+        }
+    }
+}
+
+/// A channel between ravens, as a value a program can hold. Clones are the
+/// same rookery, and two rookeries are equal only when they are the same
+/// one.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+///
+/// let source = "we declare produce with nest, count ->
+/// council says:
+///     the realm marches 3 times:
+///         dispatch with nest, count
+///         count = count + 1
+///
+/// on the iron throne:
+///     letters is a rookery with rookery with
+///     producer is a raven with send_raven produce with letters, 1
+///     the realm marches 3 times:
+///         speak receive with letters
+///     return await_raven producer
+/// ";
+/// let output = OutputBuffer::new();
+/// let mut interpreter = Interpreter::new(false).with_output(output.clone());
+/// interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+/// assert_eq!(output.contents(), "1\n2\n3\n");
+/// ```
+#[derive(Clone, Default)]
+pub struct Rookery(Arc<(Mutex<VecDeque<Value>>, Condvar)>);
+
+/// How often a raven waiting on a rookery or vault checks whether the run
+/// has timed out or been cancelled.
+pub(super) const WAIT_POLL: Duration = Duration::from_millis(20);
+
+impl Rookery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leaves `value` for the next `receive`.
+    pub(crate) fn dispatch(&self, value: Value) {
+        let (letters, arrived) = &*self.0;
+        letters.lock().unwrap_or_else(PoisonError::into_inner).push_back(value);
+        arrived.notify_one();
+    }
+}
+
+impl std::fmt::Debug for Rookery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rookery")
+    }
+}
+
+impl PartialEq for Rookery {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A value ravens share, which one at a time may claim, change, and
+/// release. Clones are the same vault, and two vaults are equal only when
+/// they are the same one. A vault shows what it holds, e.g. `vault(200)`,
+/// and `vault(…)` where it holds itself.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter, Value };
+///
+/// let source = "we declare deposit with bank ->
+/// council says:
+///     the realm marches 100 times:
+///         gold is a blade with claim with bank
+///         release with bank, gold + 1
+///
+/// on the iron throne:
+///     bank is a vault with vault with 0
+///     first is a raven with send_raven deposit with bank
+///     second is a raven with send_raven deposit with bank
+///     landed is a void with await_raven first
+///     landed = await_raven second
+///     return claim with bank
+/// ";
+/// let mut interpreter = Interpreter::new(false);
+/// let gold = interpreter.interpret_with_result(&parse_program(source).unwrap()).unwrap();
+/// assert_eq!(gold, Value::Integer(200));
+/// ```
+#[derive(Clone)]
+pub struct Vault(Arc<(Mutex<VaultContents>, Condvar)>);
+
+struct VaultContents {
+    value: Value,
+    /// The thread of the raven that has claimed the vault, if any.
+    holder: Option<ThreadId>,
+}
+
+impl Vault {
+    pub fn new(value: Value) -> Self {
+        Self(Arc::new((Mutex::new(VaultContents { value, holder: None }), Condvar::new())))
+    }
+
+    /// Puts `value`, if given, in the vault and lets the next raven claim
+    /// it. Only the raven holding the vault may release it.
+    pub(crate) fn release(&self, value: Option<Value>) -> Result<(), ValyrianError> {
+        let mut contents = self.lock();
+        if contents.holder != Some(std::thread::current().id()) {
+            return Err(
+                ValyrianError::RuntimeError("this vault can't be released without claiming it first".into())
+            );
+        }
+        if let Some(value) = value {
+            contents.value = value;
+        }
+        contents.holder = None;
+        self.0.1.notify_one();
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VaultContents> {
+        self.0.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Vault")
+    }
+}
+
+impl PartialEq for Vault {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+thread_local! {
+    /// The vaults being shown on this thread, outermost first.
+    static SHOWING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+impl std::fmt::Display for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = Arc::as_ptr(&self.0) as usize;
+        if SHOWING.with(|showing| showing.borrow().contains(&id)) {
+            return write!(f, "vault(…)");
+        }
+        // Shown from a copy, so no lock is held while another vault is
+        let value = self.lock().value.clone();
+        SHOWING.with(|showing| showing.borrow_mut().push(id));
+        let shown = write!(f, "vault({})", Nested(&value));
+        SHOWING.with(|showing| showing.borrow_mut().pop());
+        shown
+    }
+}
+
+/// The input of a raven, which has no one to ask.
+struct NoInput;
+
+impl Read for NoInput {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "a raven cannot hear input"))
+    }
+}
+
+impl BufRead for NoInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "a raven cannot hear input"))
+    }
+
+    fn consume(&mut self, _: usize) {}
+}
+
+impl Interpreter {
+    /// Starts calling `name` with `values` on its own thread.
+    pub(super) fn send_raven(
+        &mut self,
+        name: &Symbol,
+        values: Vec<Value>,
+        call_site: Span
+    ) -> Result<Value, ValyrianError> {
+        if let Some(function) = self.functions.get(name) {
+            self.check_arguments(name, function.parameters.len(), values.len())?;
+        } else if
+            !self.compiled_functions.contains_key(name) &&
+            !self.natives.contains_key(&**name) &&
+            !builtins::is_builtin(name)
+        {
+            return Err(ValyrianError::UndefinedFunction(name.to_string()));
+        }
+
+        let output = OutputBuffer::new();
+        let mut raven = self.raven_interpreter(output.clone());
+        let name = name.clone();
+        let fly = move || {
+            let result = raven.timed_run(|raven| raven.call_with_values(&name, values, call_site));
+            (result, output.contents())
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let flight = std::thread::Builder
+            ::new()
+            .name("mid-valyrian-raven".into())
+            .stack_size(PROGRAM_STACK_SIZE)
+            .spawn(fly)
+            .map(Flight::Flying)
+            .map_err(|e| ValyrianError::io("The raven could not take flight", e))?;
+        #[cfg(target_arch = "wasm32")]
+        let flight = Flight::Landed(fly());
+
+        Ok(Value::Raven(Raven(Arc::new(Mutex::new(flight)))))
+    }
+
+    /// Waits for a raven and gives its result, speaking what it spoke. The
+    /// wait ends early when the run times out or is cancelled, leaving the
+    /// raven to be awaited again.
+    pub(super) fn await_raven(&mut self, value: Value) -> Result<Value, ValyrianError> {
+        let Value::Raven(raven) = value else {
+            return Err(ValyrianError::type_error("raven", &self.type_name(&value)));
+        };
+        if !raven.has_landed() {
+            self.flush_output()?;
+            while !raven.has_landed() {
+                self.check_deadline()?;
+                self.check_cancelled()?;
+                std::thread::sleep(WAIT_POLL);
+            }
+        }
+        let mut flight = raven.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let (result, spoken) = match std::mem::replace(&mut *flight, Flight::Awaited(None)) {
+            #[cfg(not(target_arch = "wasm32"))]
+            Flight::Flying(handle) =>
+                handle.join().unwrap_or_else(|_| {
+                    (Err(ValyrianError::RuntimeError("the raven was lost in a storm".into())), String::new())
+                }),
+            #[cfg(target_arch = "wasm32")]
+            Flight::Landed(landing) => landing,
+            Flight::Awaited(result) => {
+                *flight = Flight::Awaited(result.clone());
+                return result.ok_or_else(|| {
+                    ValyrianError::RuntimeError("this raven already fell, when it was first awaited".into())
+                });
+            }
+        };
+        *flight = Flight::Awaited(result.as_ref().ok().cloned());
+        drop(flight);
+        self.write_output(&spoken)?;
+        result
+    }
+
+    /// Takes the oldest value out of `rookery`, waiting until one is
+    /// dispatched, the run times out, or it is cancelled.
+    pub(crate) fn receive(&mut self, rookery: &Rookery) -> Result<Value, ValyrianError> {
+        let stuck = "the rookery is empty, and without threads no raven can fill it";
+        self.wait_for(&rookery.0, VecDeque::pop_front, stuck)
+    }
+
+    /// Takes the value out of `vault` for this thread, waiting until no
+    /// other raven holds it, the run times out, or it is cancelled.
+    pub(crate) fn claim(&mut self, vault: &Vault) -> Result<Value, ValyrianError> {
+        let me = std::thread::current().id();
+        if vault.lock().holder == Some(me) {
+            return Err(
+                ValyrianError::RuntimeError("this vault is already claimed here; release it first".into())
+            );
+        }
+        let stuck = "the vault is claimed, and without threads no raven can release it";
+        self.wait_for(
+            &vault.0,
+            |contents| {
+                contents.holder.is_none().then(|| {
+                    contents.holder = Some(me);
+                    contents.value.clone()
+                })
+            },
+            stuck
+        )
+    }
+
+    /// Waits until `take` gets something out of the state behind the
+    /// mutex, which is tried again whenever the condvar is notified,
+    /// failing with `stuck` where there are no threads to change it.
+    fn wait_for<T, R>(
+        &mut self,
+        (state, changed): &(Mutex<T>, Condvar),
+        mut take: impl FnMut(&mut T) -> Option<R>,
+        stuck: &str
+    ) -> Result<R, ValyrianError> {
+        self.flush_output()?;
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(taken) = take(&mut state) {
+                return Ok(taken);
+            }
+            if cfg!(target_arch = "wasm32") {
+                return Err(ValyrianError::RuntimeError(stuck.into()));
+            }
+            self.check_deadline()?;
+            self.check_cancelled()?;
+            state = changed.wait_timeout(state, WAIT_POLL).unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+
+    /// An interpreter for a raven, with a copy of this one's state and
+    /// limits, writing to `output`.
+    fn raven_interpreter(&self, output: OutputBuffer) -> Interpreter {
+        let mut raven = Interpreter::new(false)
+            .with_backend(self.backend)
+            .with_strict(self.strict)
+            .with_sandbox(self.sandboxed)
+            .with_commands(self.commands_allowed)
+            .with_float_precision(self.float_precision)
+            .with_limits(self.limits)
+            .with_max_call_depth(self.max_call_depth)
+            .with_timeout(self.timeout)
+            .with_max_steps(self.max_steps)
+            .with_args(self.script_args.clone())
+            .with_cancellation(self.cancellation.clone())
+            .with_output(output)
+            .with_input(NoInput);
+        raven.sender_deadline = self.deadline;
+        raven.variables = self.variables.clone();
+        raven.functions = self.functions.clone();
+        raven.compiled_functions = self.compiled_functions.clone();
+        raven.natives = self.natives.clone();
+        raven.declared_types = self.declared_types.clone();
+        raven.declared_functions = self.declared_functions.clone();
+        raven
+    }
+}
@@ -0,0 +1,160 @@
+//! 🔌 Talking to other machines over TCP.
+//!
+//! `connect with host, port` opens a connection and `listen with port`
+//! waits for them, `accept` taking the next one that arrives. Both give a
+//! `Socket`. Talk goes a line at a time, as `speak` and input do: `send`
+//! writes a scroll and a line break, and `recv` reads up to the next line
+//! break, giving void once the other side has hung up.
+//!
+//! Waiting in `accept` or `recv` stops when the run times out or is
+//! cancelled. The sandbox refuses every socket builtin.
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+//!
+//! let source = "we declare greet with port ->\ncouncil says:\n    socket is a socket with connect with \"127.0.0.1\", port\n    send with socket, \"Valar morghulis\"\n    return recv with socket\n\non the iron throne:\n    listener is a socket with listen with 0\n    raven is a raven with send_raven greet with port with listener\n    guest is a socket with accept with listener\n    speak recv with guest\n    send with guest, \"Valar dohaeris\"\n    speak await_raven raven\n";
+//! let output = OutputBuffer::new();
+//! let mut interpreter = Interpreter::new(false).with_output(output.clone());
+//! interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+//! assert_eq!(output.contents(), "Valar morghulis\nValar dohaeris\n");
+//! ```
+
+use std::io::{ self, BufRead, BufReader, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::sync::{ Arc, Mutex, PoisonError };
+use crate::ast::Value;
+use crate::error::ValyrianError;
+use super::raven::WAIT_POLL;
+use super::Interpreter;
+
+/// A TCP connection, or a listener waiting for them, as a value a program
+/// can hold. Clones are the same socket, and two sockets are equal only
+/// when they are the same one.
+#[derive(Clone)]
+pub struct Socket(Arc<Endpoint>);
+
+enum Endpoint {
+    Stream {
+        /// Locked separately, so a raven can send while another waits
+        /// in `recv`.
+        reader: Mutex<BufReader<TcpStream>>,
+        writer: Mutex<TcpStream>,
+    },
+    Listener(TcpListener),
+}
+
+impl std::fmt::Debug for Socket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Socket")
+    }
+}
+
+impl PartialEq for Socket {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Socket {
+    fn stream(stream: TcpStream) -> io::Result<Self> {
+        stream.set_read_timeout(Some(WAIT_POLL))?;
+        let writer = stream.try_clone()?;
+        let reader = Mutex::new(BufReader::new(stream));
+        Ok(Self(Arc::new(Endpoint::Stream { reader, writer: Mutex::new(writer) })))
+    }
+
+    /// The port this end of the socket is on, e.g. the one a listener
+    /// opened on port 0 was given.
+    pub fn port(&self) -> io::Result<u16> {
+        let address = match &*self.0 {
+            Endpoint::Stream { writer, .. } =>
+                writer.lock().unwrap_or_else(PoisonError::into_inner).local_addr(),
+            Endpoint::Listener(listener) => listener.local_addr(),
+        };
+        address.map(|address| address.port())
+    }
+}
+
+impl Interpreter {
+    /// Opens a connection to `host` on `port`.
+    pub(crate) fn connect(&mut self, host: &str, port: u16) -> Result<Value, ValyrianError> {
+        self.flush_output()?;
+        TcpStream::connect((host, port))
+            .and_then(Socket::stream)
+            .map(Value::Socket)
+            .map_err(|e| ValyrianError::io(format!("Failed to connect to {}:{}", host, port), e))
+    }
+
+    /// Listens for connections to `port` on `host`.
+    pub(crate) fn listen(&mut self, host: &str, port: u16) -> Result<Value, ValyrianError> {
+        let listener = TcpListener::bind((host, port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+            .map_err(|e| ValyrianError::io(format!("Failed to listen on {}:{}", host, port), e))?;
+        Ok(Value::Socket(Socket(Arc::new(Endpoint::Listener(listener)))))
+    }
+
+    /// Waits for the next connection to a listening socket.
+    pub(crate) fn accept(&mut self, socket: &Socket) -> Result<Value, ValyrianError> {
+        let Endpoint::Listener(listener) = &*socket.0 else {
+            return Err(ValyrianError::RuntimeError("only a listening socket can accept".into()));
+        };
+        self.flush_output()?;
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    return stream
+                        .set_nonblocking(false)
+                        .and_then(|()| Socket::stream(stream))
+                        .map(Value::Socket)
+                        .map_err(|e| ValyrianError::io("Failed to accept a connection", e));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.check_deadline()?;
+                    self.check_cancelled()?;
+                    std::thread::sleep(WAIT_POLL);
+                }
+                Err(e) => {
+                    return Err(ValyrianError::io("Failed to accept a connection", e));
+                }
+            }
+        }
+    }
+
+    /// Writes `text` and a line break to a connection.
+    pub(crate) fn send(&mut self, socket: &Socket, text: &str) -> Result<(), ValyrianError> {
+        let Endpoint::Stream { writer, .. } = &*socket.0 else {
+            return Err(ValyrianError::RuntimeError("a listening socket can't send".into()));
+        };
+        let mut writer = writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writeln!(writer, "{}", text).map_err(|e| ValyrianError::io("Failed to send", e))
+    }
+
+    /// Reads the next line from a connection, or void if it has closed.
+    pub(crate) fn recv(&mut self, socket: &Socket) -> Result<Value, ValyrianError> {
+        let Endpoint::Stream { reader, .. } = &*socket.0 else {
+            return Err(ValyrianError::RuntimeError("a listening socket can't recv; accept first".into()));
+        };
+        self.flush_output()?;
+        let mut reader = reader.lock().unwrap_or_else(PoisonError::into_inner);
+        // What arrives before a timeout stays in `line` for the next try
+        let mut line = String::new();
+        loop {
+            match reader.read_line(&mut line) {
+                Ok(0) if line.is_empty() => {
+                    return Ok(Value::Void);
+                }
+                Ok(_) => {
+                    let line = line.strip_suffix('\n').unwrap_or(&line);
+                    return Ok(Value::String(line.strip_suffix('\r').unwrap_or(line).into()));
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    self.check_deadline()?;
+                    self.check_cancelled()?;
+                }
+                Err(e) => {
+                    return Err(ValyrianError::io("Failed to recv", e));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! ⏸️ Programs that stop while they wait.
+//!
+//! `Interpreter::start` runs a program on the bytecode machine as a `Task`
+//! that the host steps with `resume`. Where the program would block — a
+//! `sleep`, a line of input, or `await_raven` on a raven still in flight —
+//! the task stops instead and hands back a `Suspension` saying what it
+//! waits for. The host waits however suits it (a timer, a text box, other
+//! work) and resumes the task, which carries on from the same instruction
+//! on the same thread, without a thread of its own.
+//!
+//! Ravens still fly on threads of their own; a task only keeps from
+//! blocking while one is out. Calls that block within a builtin still
+//! block the host's thread: `receive`, `claim`, and `ask_until_blade`, the
+//! network calls `fetch` and `post`, and the socket calls `connect`,
+//! `accept`, and `recv`. A program that mustn't hold up its host can send
+//! such a call off in a raven and await it, which suspends the task.
+
+use std::time::{ Duration, Instant };
+use crate::ast::{ Program, Value };
+use crate::compiler;
+use crate::error::ValyrianError;
+use super::raven::Raven;
+use super::vm::Machine;
+use super::Interpreter;
+
+/// What a suspended task waits for before it is resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suspension {
+    /// The program called `sleep`; resume it once the time has passed.
+    Sleep(Duration),
+    /// The program asked for input; give it with `Task::answer`.
+    Input,
+    /// The program awaits a raven that hasn't landed; resume it once
+    /// `Raven::has_landed` says so.
+    Raven(Raven),
+}
+
+/// How far a task got when resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    Suspended(Suspension),
+    /// The program ended, with the value of its top-level `return`.
+    Finished(Value),
+}
+
+/// A program started with `Interpreter::start`. Dropping a task that
+/// hasn't finished stops the program where it is.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer, Progress, Suspension, Value };
+/// use std::time::Duration;
+///
+/// let source = "on the iron throne:\n    sleep with 50\n    name is a scroll with guest speaks for input\n    return \"Hello, \" + name\n";
+/// let program = parse_program(source).unwrap();
+/// let mut interpreter = Interpreter::new(false).with_output(OutputBuffer::new());
+/// let mut task = interpreter.start(&program);
+/// assert_eq!(
+///     task.resume().unwrap(),
+///     Progress::Suspended(Suspension::Sleep(Duration::from_millis(50)))
+/// );
+/// assert_eq!(task.resume().unwrap(), Progress::Suspended(Suspension::Input));
+/// task.answer("Hodor");
+/// assert_eq!(task.resume().unwrap(), Progress::Finished(Value::String("Hello, Hodor".into())));
+/// ```
+pub struct Task<'i> {
+    interpreter: &'i mut Interpreter,
+    machine: Machine,
+    started: Instant,
+}
+
+impl Interpreter {
+    /// Starts `program` as a task, which runs nothing until it is resumed.
+    /// Tasks always run on the bytecode machine, whatever the configured
+    /// backend, and a debug hook never sees them.
+    pub fn start(&mut self, program: &Program) -> Task<'_> {
+        self.begin_run();
+        let chunk = compiler::compile(program);
+        let machine = self.load_chunk(chunk.into(), true);
+        Task { interpreter: self, machine, started: Instant::now() }
+    }
+}
+
+impl Task<'_> {
+    /// Runs the program until it has to wait or it ends.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError` if the program fails, or if the task has
+    /// already finished or failed.
+    ///
+    /// ```
+    /// use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+    ///
+    /// let program = parse_program("on the iron throne:\n    return 1 / 0\n").unwrap();
+    /// let mut interpreter = Interpreter::new(false).with_output(OutputBuffer::new());
+    /// let mut task = interpreter.start(&program);
+    /// assert!(task.resume().is_err());
+    /// assert!(task.resume().unwrap_err().to_string().contains("already ended"));
+    /// ```
+    pub fn resume(&mut self) -> Result<Progress, ValyrianError> {
+        if self.machine.is_finished() {
+            return Err(ValyrianError::RuntimeError("this task has already ended".into()));
+        }
+        let progress = self.interpreter.run_machine(&mut self.machine);
+        if matches!(progress, Ok(Progress::Suspended(_))) {
+            return progress;
+        }
+        let progress = self.interpreter.finish_output(progress);
+        self.interpreter.stats.elapsed = self.started.elapsed();
+        progress
+    }
+
+    /// Gives the line of input a task suspended on `Suspension::Input`
+    /// waits for. It is read when the task is next resumed.
+    pub fn answer(&mut self, line: impl Into<String>) {
+        self.machine.input = Some(line.into());
+    }
+}
+
+impl Drop for Task<'_> {
+    fn drop(&mut self) {
+        self.interpreter.abandon(&mut self.machine);
+    }
+}
@@ -0,0 +1,120 @@
+//! ⏰ Spells cast later.
+//!
+//! `after with ms, "spell"` asks for the function called `spell` to be
+//! called once, `ms` milliseconds from now, and `every with ms, "spell"`
+//! asks for it every `ms` milliseconds. Nothing interrupts the program to
+//! cast them: the program calls `run_pending` (usually once per turn of
+//! its main loop), which casts every spell that has come due, oldest
+//! first, and says how many it cast.
+//!
+//! A pump casts each spell at most once, so a repeating spell that fell
+//! behind skips the turns it missed rather than catching up on them.
+//! Spells are called with no arguments, and what they return is thrown
+//! away. Spells still waiting when the program ends are never cast.
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Interpreter, OutputBuffer };
+//!
+//! let source = "we declare tick with ->\ncouncil says:\n    speak \"tick\"\n\non the iron throne:\n    every with 0, \"tick\"\n    after with 0, \"tick\"\n    after with 60000, \"tick\"\n    speak run_pending with\n    speak run_pending with\n";
+//! let output = OutputBuffer::new();
+//! let mut interpreter = Interpreter::new(false).with_output(output.clone());
+//! interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+//! assert_eq!(output.contents(), "tick\ntick\n2\ntick\n1\n");
+//! ```
+
+use std::time::{ Duration, Instant };
+use crate::ast::{ Span, Symbol };
+use crate::builtins;
+use crate::error::ValyrianError;
+use super::Interpreter;
+
+/// The spells waiting to be cast by `run_pending`.
+#[derive(Default)]
+pub(super) struct Timers {
+    pending: Vec<Timer>,
+    /// Numbers timers in the order they were set, so ties are cast in
+    /// that order.
+    next_id: u64,
+}
+
+struct Timer {
+    id: u64,
+    due: Instant,
+    /// How long until the spell is cast again, if it repeats.
+    every: Option<Duration>,
+    spell: Symbol,
+}
+
+impl Timers {
+    /// Forgets every spell still waiting.
+    pub(super) fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+impl Interpreter {
+    /// Sets `spell` to be cast `delay` from now, and every `delay` after
+    /// that if it `repeats`.
+    pub(crate) fn schedule(
+        &mut self,
+        delay: Duration,
+        repeats: bool,
+        spell: &str
+    ) -> Result<(), ValyrianError> {
+        if let Some(function) = self.functions.get(spell) {
+            self.check_arguments(spell, function.parameters.len(), 0)?;
+        } else if
+            !self.compiled_functions.contains_key(spell) &&
+            !self.natives.contains_key(spell) &&
+            !builtins::is_builtin(spell)
+        {
+            return Err(ValyrianError::UndefinedFunction(spell.to_string()));
+        }
+
+        let timers = &mut self.timers;
+        timers.pending.push(Timer {
+            id: timers.next_id,
+            due: Instant::now() + delay,
+            every: repeats.then_some(delay),
+            spell: spell.into(),
+        });
+        timers.next_id += 1;
+        Ok(())
+    }
+
+    /// Casts every spell that has come due, oldest first, and gives how
+    /// many were cast. If one fails, the ones after it wait for the next
+    /// pump.
+    pub(crate) fn run_pending(&mut self) -> Result<usize, ValyrianError> {
+        let now = Instant::now();
+        let mut due: Vec<(Instant, u64)> = self.timers.pending
+            .iter()
+            .filter(|timer| timer.due <= now)
+            .map(|timer| (timer.due, timer.id))
+            .collect();
+        due.sort_unstable();
+
+        for &(_, id) in &due {
+            let index = self.timers.pending
+                .iter()
+                .position(|timer| timer.id == id)
+                .expect("due timers stay pending until cast");
+            let timer = &mut self.timers.pending[index];
+            let spell = timer.spell.clone();
+            match timer.every {
+                Some(every) => {
+                    let next = timer.due + every;
+                    timer.due = if next > now { next } else { now + every };
+                }
+                None => {
+                    self.timers.pending.remove(index);
+                }
+            }
+
+            let value = self.call_with_values(&spell, Vec::new(), Span::default());
+            value.and_then(|value| self.check_discarded(&spell, &value))?;
+        }
+        Ok(due.len())
+    }
+}
+
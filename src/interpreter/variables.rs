@@ -0,0 +1,92 @@
+//! 🗃️ Variables stored in slots.
+//!
+//! Scoping is dynamic: a call binds its parameters over the caller's
+//! variables and puts them back when it returns. Every name therefore has
+//! a single slot for the life of the interpreter, handed out the first
+//! time the name is seen. Code resolved ahead of time (compiled chunks,
+//! and walked code through its frame) reads and writes its slots by index,
+//! without hashing or cloning names.
+
+use std::collections::HashMap;
+use crate::ast::{ Symbol, Value };
+
+/// The variables of an interpreter, one slot per name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Variables {
+    slots: HashMap<Symbol, usize>,
+    names: Vec<Symbol>,
+    values: Vec<Option<Value>>,
+    /// How many slots hold a value.
+    bound: usize,
+}
+
+impl Variables {
+    /// The slot for `name`, made if the name is new. Slots are never taken
+    /// back, so resolved code stays valid.
+    pub(crate) fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        let slot = self.names.len();
+        let name = Symbol::from(name);
+        self.slots.insert(name.clone(), slot);
+        self.names.push(name);
+        self.values.push(None);
+        slot
+    }
+
+    pub(crate) fn name(&self, slot: usize) -> &Symbol {
+        &self.names[slot]
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        self.slots.get(name).and_then(|&slot| self.get_slot(slot))
+    }
+
+    pub(crate) fn get_slot(&self, slot: usize) -> Option<&Value> {
+        self.values[slot].as_ref()
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub(crate) fn is_bound(&self, slot: usize) -> bool {
+        self.values[slot].is_some()
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, value: Value) {
+        let slot = self.slot(name);
+        self.set(slot, Some(value));
+    }
+
+    /// Binds (or, with `None`, unbinds) a slot, returning what it held.
+    pub(crate) fn set(&mut self, slot: usize, value: Option<Value>) -> Option<Value> {
+        let old = std::mem::replace(&mut self.values[slot], value);
+        match (&old, &self.values[slot]) {
+            (None, Some(_)) => self.bound += 1,
+            (Some(_), None) => self.bound -= 1,
+            _ => {}
+        }
+        old
+    }
+
+    /// How many variables are bound.
+    pub(crate) fn len(&self) -> usize {
+        self.bound
+    }
+
+    /// The bound variables, in the order their names were first seen.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| Some((&**name, value.as_ref()?)))
+    }
+
+    /// Unbinds every variable, keeping the slots.
+    pub(crate) fn clear(&mut self) {
+        self.values.iter_mut().for_each(|value| *value = None);
+        self.bound = 0;
+    }
+}
@@ -0,0 +1,524 @@
+//! 🏎️ The stack machine that runs compiled chunks.
+//!
+//! Calls between compiled functions push frames on a heap-allocated stack
+//! instead of recursing, and everything a statement or expression does
+//! goes through the same helpers the tree-walking interpreter uses, so the
+//! two backends agree on behavior, errors, and limits.
+//!
+//! A chunk's names are resolved to variable slots once, when the program
+//! starts or a function is declared, so instructions reach variables by
+//! index.
+//!
+//! The frames and operand stack of a run live in a `Machine`, so a `Task`
+//! can stop one while the program waits and carry on with it later.
+
+use std::sync::Arc;
+use tracing::span::EnteredSpan;
+use tracing::{ debug_span, trace };
+use crate::ast::{ Span, Symbol, Value };
+use crate::compiler::{ Chunk, Instruction };
+use crate::error::{ StackFrame, ValyrianError };
+use super::task::{ Progress, Suspension };
+use super::{ keep_replaced, Interpreter, DEFAULT_PROMPT };
+
+/// A chunk with its names resolved to the interpreter's variable slots.
+#[derive(Clone)]
+pub(super) struct ResolvedChunk {
+    chunk: Arc<Chunk>,
+    /// The slot of each of the chunk's names.
+    slots: Arc<[usize]>,
+    parameters: Arc<[usize]>,
+}
+
+/// A chunk being run.
+struct Frame {
+    chunk: Arc<Chunk>,
+    slots: Arc<[usize]>,
+    /// The next instruction.
+    ip: usize,
+    /// Where this frame's values start on the operand stack.
+    base: usize,
+    /// The statement running, for error traces.
+    span: Span,
+    /// `None` for the program itself.
+    call: Option<Call>,
+}
+
+/// A run of the machine, from the program's first instruction to its
+/// last.
+pub(super) struct Machine {
+    frames: Vec<Frame>,
+    stack: Vec<Value>,
+    /// Whether sleeping, input, and flying ravens stop the machine rather
+    /// than block it.
+    suspendable: bool,
+    /// The line of input the program is waiting for, once it is given.
+    pub(super) input: Option<String>,
+}
+
+impl Machine {
+    pub(super) fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// What running an instruction came to.
+enum Step {
+    Next,
+    /// The bottom frame returned.
+    Returned(Value),
+    /// The instruction waits on the outside world, and runs again when
+    /// the machine is resumed.
+    Suspended(Suspension),
+}
+
+/// What a call to a compiled function must undo when it returns.
+struct Call {
+    /// The values the parameters replaced.
+    saved: Vec<(usize, Option<Value>)>,
+    /// Whether the caller throws the result away (a call statement).
+    discard: bool,
+    /// The functions that ran in this frame before tail-calling the one
+    /// running now, outermost first.
+    tail_callers: Vec<Symbol>,
+    _span: EnteredSpan,
+}
+
+impl Interpreter {
+    /// Runs a compiled program against the interpreter's state, returning
+    /// the value of a top-level `return`.
+    pub(super) fn run_chunk(&mut self, chunk: Arc<Chunk>) -> Result<Value, ValyrianError> {
+        let mut machine = self.load_chunk(chunk, false);
+        self.run_to_end(&mut machine)
+    }
+
+    /// A machine about to run a compiled program, with its functions
+    /// declared.
+    pub(super) fn load_chunk(&mut self, chunk: Arc<Chunk>, suspendable: bool) -> Machine {
+        for &index in &chunk.hoisted {
+            self.define_compiled(chunk.functions[index as usize].clone());
+        }
+        let ResolvedChunk { chunk, slots, .. } = self.resolve(chunk);
+        let program = Frame { chunk, slots, ip: 0, base: 0, span: Span::default(), call: None };
+        Machine { frames: vec![program], stack: Vec::new(), suspendable, input: None }
+    }
+
+    /// Calls a compiled function from the tree-walking interpreter.
+    pub(super) fn call_compiled(
+        &mut self,
+        function: ResolvedChunk,
+        values: Vec<Value>,
+        call_site: Span
+    ) -> Result<Value, ValyrianError> {
+        let frame = self.enter_compiled(function, values, call_site, false, 0)?;
+        let mut machine = Machine { frames: vec![frame], stack: Vec::new(), suspendable: false, input: None };
+        self.run_to_end(&mut machine)
+    }
+
+    fn resolve(&mut self, chunk: Arc<Chunk>) -> ResolvedChunk {
+        let slots = chunk.names
+            .iter()
+            .map(|name| self.variables.slot(name))
+            .collect();
+        let parameters = chunk.parameters
+            .iter()
+            .map(|name| self.variables.slot(name))
+            .collect();
+        ResolvedChunk { chunk, slots, parameters }
+    }
+
+    fn define_compiled(&mut self, function: Arc<Chunk>) {
+        self.functions.remove(&function.name);
+        let name = function.name.clone();
+        let resolved = self.resolve(function);
+        self.compiled_functions.insert(name, resolved);
+    }
+
+    /// Checks and starts a call, returning the frame that runs it.
+    fn enter_compiled(
+        &mut self,
+        function: ResolvedChunk,
+        values: Vec<Value>,
+        call_site: Span,
+        discard: bool,
+        base: usize
+    ) -> Result<Frame, ValyrianError> {
+        let ResolvedChunk { chunk, slots, parameters } = function;
+        let name = &chunk.name;
+        self.check_call(name, parameters.len(), values.len())?;
+        self.notify_call(name, &values, &call_site);
+        let saved = self.bind_parameters(name, &parameters, values)?;
+        self.call_stack.push(StackFrame {
+            function: name.clone(),
+            caller: self.current_function(),
+            call_site,
+            elided: 0,
+        });
+        let span = debug_span!("call", function = &**name, line = call_site.line).entered();
+        Ok(Frame {
+            chunk,
+            slots,
+            ip: 0,
+            base,
+            span: call_site,
+            call: Some(Call { saved, discard, tail_callers: Vec::new(), _span: span }),
+        })
+    }
+
+    /// Finishes a call, putting back what it changed.
+    fn leave_compiled(&mut self, name: &str, call: Call, result: &Result<Value, ValyrianError>) {
+        self.call_stack.pop();
+        self.restore_variables(call.saved);
+        self.notify_return(name, result);
+        for caller in call.tail_callers.iter().rev() {
+            self.notify_return(caller, result);
+        }
+    }
+
+    /// Runs a machine that never suspends until the bottom frame returns.
+    fn run_to_end(&mut self, machine: &mut Machine) -> Result<Value, ValyrianError> {
+        match self.run_machine(machine)? {
+            Progress::Finished(value) => Ok(value),
+            Progress::Suspended(_) => unreachable!("the machine can't suspend"),
+        }
+    }
+
+    /// Runs until the bottom frame returns or, if the machine is
+    /// suspendable, the program has to wait.
+    pub(super) fn run_machine(&mut self, machine: &mut Machine) -> Result<Progress, ValyrianError> {
+        loop {
+            match self.execute_instruction(machine) {
+                Ok(Step::Next) => {}
+                Ok(Step::Returned(value)) => {
+                    return Ok(Progress::Finished(value));
+                }
+                Ok(Step::Suspended(suspension)) => {
+                    self.flush_output()?;
+                    return Ok(Progress::Suspended(suspension));
+                }
+                Err(error) => {
+                    return Err(self.unwind(std::mem::take(&mut machine.frames), error));
+                }
+            }
+        }
+    }
+
+    /// Traces an error where it happened, then abandons every frame.
+    fn unwind(&mut self, mut frames: Vec<Frame>, error: ValyrianError) -> ValyrianError {
+        let span = frames.last().map_or_else(Span::default, |frame| frame.span);
+        let result = Err(self.attach_trace(error, span));
+        while let Some(frame) = frames.pop() {
+            if let Some(call) = frame.call {
+                self.leave_compiled(&frame.chunk.name, call, &result);
+            }
+        }
+        let Err(error) = result else {
+            unreachable!("unwinding without an error")
+        };
+        error
+    }
+
+    /// Stops a machine that hasn't finished, putting back what its calls
+    /// changed.
+    pub(super) fn abandon(&mut self, machine: &mut Machine) {
+        let frames = std::mem::take(&mut machine.frames);
+        if !frames.is_empty() {
+            self.unwind(frames, ValyrianError::Interrupted);
+        }
+    }
+
+    /// Runs the next instruction.
+    fn execute_instruction(&mut self, machine: &mut Machine) -> Result<Step, ValyrianError> {
+        let Machine { frames, stack, .. } = machine;
+        let frame = frames.last_mut().expect("the machine has a frame");
+        let instruction = frame.chunk.instructions[frame.ip].clone();
+        frame.ip += 1;
+
+        match instruction {
+            Instruction::Statement(index) => {
+                let statement = &frame.chunk.statements[index as usize];
+                trace!(
+                    line = statement.span.line,
+                    column = statement.span.col,
+                    statement = %crate::printer::statement_head(statement),
+                    "executing"
+                );
+                frame.span = statement.span;
+                self.stats.statements += 1;
+                for observer in &mut self.observers {
+                    observer.on_statement(statement, &statement.span);
+                }
+                self.charge_step()?;
+                self.check_deadline()?;
+                self.check_cancelled()?;
+            }
+            Instruction::Resume(index) => {
+                frame.span = frame.chunk.statements[index as usize].span;
+            }
+            Instruction::Constant(index) => {
+                let value = frame.chunk.constants[index as usize].clone();
+                self.push_value(stack, value)?;
+            }
+            Instruction::Load(name) => {
+                let value = self.variables
+                    .get_slot(frame.slots[name as usize])
+                    .cloned()
+                    .ok_or_else(|| {
+                        ValyrianError::UndefinedVariable(frame.chunk.names[name as usize].to_string())
+                    })?;
+                self.push_value(stack, value)?;
+            }
+            Instruction::Declare(name, data_type) => {
+                let value = pop(stack);
+                self.declare_variable(frame.slots[name as usize], &data_type, value)?;
+            }
+            Instruction::CheckDefined(name) => {
+                self.check_defined(frame.slots[name as usize])?;
+            }
+            Instruction::Assign(name) => {
+                let value = pop(stack);
+                self.assign_variable(frame.slots[name as usize], value)?;
+            }
+            Instruction::Binary(operator) => {
+                let right = pop(stack);
+                let left = pop(stack);
+                let value = self.apply_binary_operator(&operator, &left, &right)?;
+                self.push_value(stack, value)?;
+            }
+            Instruction::Unary(operator) => {
+                let operand = pop(stack);
+                let value = self.apply_unary_operator(&operator, &operand)?;
+                self.push_value(stack, value)?;
+            }
+            Instruction::Input if machine.suspendable => {
+                let Some(line) = machine.input.take() else {
+                    self.prompt(DEFAULT_PROMPT)?;
+                    frame.ip -= 1;
+                    return Ok(Step::Suspended(Suspension::Input));
+                };
+                self.push_value(stack, Value::String(line.trim().into()))?;
+            }
+            Instruction::Input => {
+                let value = self.read_input(DEFAULT_PROMPT)?;
+                self.push_value(stack, value)?;
+            }
+            // The prompt stays on the stack until the line arrives
+            Instruction::Ask if machine.suspendable => {
+                let Some(line) = machine.input.take() else {
+                    let prompt = stack.last().expect("a prompt to ask with").to_string();
+                    self.prompt(&prompt)?;
+                    frame.ip -= 1;
+                    return Ok(Step::Suspended(Suspension::Input));
+                };
+                pop(stack);
+                self.push_value(stack, Value::String(line.trim().into()))?;
+            }
+            Instruction::Ask => {
+                let prompt = pop(stack).to_string();
+                let value = self.read_input(&prompt)?;
+                self.push_value(stack, value)?;
+            }
+            Instruction::Call { function, arguments, site } => {
+                return self.call_instruction(machine, function, arguments, site, false);
+            }
+            Instruction::CallStatement { function, arguments, site } => {
+                return self.call_instruction(machine, function, arguments, site, true);
+            }
+            Instruction::TailCall { function, arguments, site } => {
+                return self.tail_call_instruction(machine, function, arguments, site);
+            }
+            Instruction::SendRaven { function, arguments, site } => {
+                let name = frame.chunk.names[function as usize].clone();
+                let call_site = frame.chunk.call_sites[site as usize];
+                let values = stack.split_off(stack.len() - (arguments as usize));
+                let raven = self.send_raven(&name, values, call_site)?;
+                stack.push(raven);
+            }
+            Instruction::AwaitRaven => {
+                if let Some(Value::Raven(raven)) = stack.last() {
+                    if machine.suspendable && !raven.has_landed() {
+                        let raven = raven.clone();
+                        frame.ip -= 1;
+                        return Ok(Step::Suspended(Suspension::Raven(raven)));
+                    }
+                }
+                let raven = pop(stack);
+                let value = self.await_raven(raven)?;
+                self.push_value(stack, value)?;
+            }
+            Instruction::Speak => {
+                let value = pop(stack);
+                self.speak(&value)?;
+            }
+            Instruction::Jump(target) => {
+                frame.ip = target as usize;
+            }
+            Instruction::JumpUnless(target) => {
+                match pop(stack) {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => {
+                        frame.ip = target as usize;
+                    }
+                    other => {
+                        return Err(ValyrianError::type_error("boolean", &self.type_name(&other)));
+                    }
+                }
+            }
+            Instruction::Counter(count) => {
+                stack.push(Value::Integer(count));
+            }
+            Instruction::CountDown(target) => {
+                match stack.last_mut() {
+                    Some(Value::Integer(remaining)) if *remaining > 0 => {
+                        *remaining -= 1;
+                    }
+                    _ => {
+                        stack.pop();
+                        frame.ip = target as usize;
+                    }
+                }
+            }
+            Instruction::DeclareFunction(index) => {
+                let function = frame.chunk.functions[index as usize].clone();
+                if self.strict {
+                    self.declared_functions.insert(function.name.clone());
+                }
+                self.define_compiled(function);
+            }
+            Instruction::Return => {
+                let value = pop(stack);
+                return self.return_from_frame(machine, value);
+            }
+            Instruction::ReturnVoid => {
+                return self.return_from_frame(machine, Value::Void);
+            }
+        }
+        Ok(Step::Next)
+    }
+
+    fn call_instruction(
+        &mut self,
+        machine: &mut Machine,
+        function: u32,
+        arguments: u32,
+        site: u32,
+        discard: bool
+    ) -> Result<Step, ValyrianError> {
+        let Machine { frames, stack, .. } = machine;
+        let chunk = &frames.last().expect("the machine has a frame").chunk;
+        let name = chunk.names[function as usize].clone();
+        let call_site = chunk.call_sites[site as usize];
+        let values = stack.split_off(stack.len() - (arguments as usize));
+
+        if let Some(function) = self.compiled_functions.get(&name).cloned() {
+            let frame = self.enter_compiled(function, values, call_site, discard, stack.len())?;
+            frames.push(frame);
+            return Ok(Step::Next);
+        }
+        let mut suspension = None;
+        let value = match self.sleep_duration(&name, &values) {
+            Some(duration) if machine.suspendable => {
+                // The host does the sleeping
+                let duration = duration?;
+                self.notify_call(&name, &values, &call_site);
+                let result = Ok(Value::Void);
+                self.notify_return(&name, &result);
+                suspension = Some(Suspension::Sleep(duration));
+                result?
+            }
+            _ => self.call_with_values(&name, values, call_site)?,
+        };
+        if discard {
+            self.check_discarded(&name, &value)?;
+        } else {
+            self.push_value(&mut machine.stack, value)?;
+        }
+        Ok(suspension.map_or(Step::Next, Step::Suspended))
+    }
+
+    /// Runs a compiled function returned by another in the returning
+    /// frame, as the tree-walking interpreter does; any other call is made
+    /// as a `Call`.
+    fn tail_call_instruction(
+        &mut self,
+        machine: &mut Machine,
+        function: u32,
+        arguments: u32,
+        site: u32
+    ) -> Result<Step, ValyrianError> {
+        let Machine { frames, stack, .. } = machine;
+        let frame = frames.last_mut().expect("the machine has a frame");
+        let name = frame.chunk.names[function as usize].clone();
+        let callee = match (&frame.call, self.compiled_functions.get(&name)) {
+            (Some(_), Some(callee)) => callee.clone(),
+            _ => {
+                return self.call_instruction(machine, function, arguments, site, false);
+            }
+        };
+        let call_site = frame.chunk.call_sites[site as usize];
+        let values = stack.split_off(stack.len() - (arguments as usize));
+        // The step pushing the result into the returning frame would take
+        self.charge_step()?;
+
+        let ResolvedChunk { chunk, slots, parameters } = callee;
+        self.check_arguments(&name, parameters.len(), values.len())?;
+        self.notify_call(&name, &values, &call_site);
+        let replaced = self.bind_parameters(&name, &parameters, values)?;
+        let Some(Call { mut saved, discard, mut tail_callers, _span: caller_span }) = frame.call.take() else {
+            unreachable!("checked above")
+        };
+        keep_replaced(&mut saved, replaced);
+        let top = self.call_stack.last_mut().expect("a call has a stack frame");
+        top.function = name.clone();
+        top.elided += 1;
+
+        tail_callers.push(std::mem::replace(&mut frame.chunk, chunk).name.clone());
+        frame.slots = slots;
+        frame.ip = 0;
+        frame.span = call_site;
+        stack.truncate(frame.base);
+        drop(caller_span);
+        let span = debug_span!("call", function = &*name, line = call_site.line).entered();
+        frame.call = Some(Call { saved, discard, tail_callers, _span: span });
+        Ok(Step::Next)
+    }
+
+    fn return_from_frame(&mut self, machine: &mut Machine, value: Value) -> Result<Step, ValyrianError> {
+        let Machine { frames, stack, .. } = machine;
+        let frame = frames.pop().expect("the machine has a frame");
+        stack.truncate(frame.base);
+        let Some(call) = frame.call else {
+            return Ok(Step::Returned(value));
+        };
+        let discard = call.discard;
+        let called = call.tail_callers.first().unwrap_or(&frame.chunk.name).clone();
+        let result = Ok(value);
+        self.leave_compiled(&frame.chunk.name, call, &result);
+        let Ok(value) = result else {
+            unreachable!("returning is never an error")
+        };
+
+        if frames.is_empty() {
+            return Ok(Step::Returned(value));
+        }
+        if discard {
+            self.check_discarded(&called, &value)?;
+        } else {
+            self.push_value(stack, value)?;
+        }
+        Ok(Step::Next)
+    }
+
+    /// Pushes the value of an expression, charging the step and enforcing
+    /// the size limits as evaluating it would.
+    fn push_value(&mut self, stack: &mut Vec<Value>, value: Value) -> Result<(), ValyrianError> {
+        self.charge_step()?;
+        self.check_value_size(&value)?;
+        stack.push(value);
+        Ok(())
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("the compiler balances the operand stack")
+}
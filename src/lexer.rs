@@ -0,0 +1,196 @@
+//! 🪶 Token streams for tools.
+//!
+//! `tokenize` splits source into the words, literals, and symbols of the
+//! language, each with its span, for syntax highlighters and editors that
+//! want to color code without walking the AST. It matches the literal and
+//! name rules of the grammar itself, and never fails: source that doesn't
+//! parse still gets tokens, with `Unknown` for anything that fits no rule.
+//!
+//! ```
+//! use mid_valyrian::{ tokenize, TokenKind };
+//!
+//! let source = "gold is a blade with 10 // coins";
+//! let kinds: Vec<TokenKind> = tokenize(source).iter().map(|token| token.kind).collect();
+//! assert_eq!(kinds, [
+//!     TokenKind::Identifier,
+//!     TokenKind::Keyword,
+//!     TokenKind::Type,
+//!     TokenKind::Keyword,
+//!     TokenKind::Integer,
+//!     TokenKind::Comment,
+//! ]);
+//! assert_eq!(tokenize(source)[1].text(source), "is a");
+//! ```
+//!
+//! Words are sorted by spelling alone, so a variable named like a keyword
+//! (the grammar allows `speak is a blade with 1`) comes out as one.
+
+use pest::Parser;
+use crate::ast::Span;
+use crate::parser::{ MidValyrianParser, Rule };
+
+/// What sort of text a token is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// A word or phrase of the language, such as `speak` or `we declare`.
+    Keyword,
+    Identifier,
+    /// The name of a data type, such as `blade`.
+    Type,
+    String,
+    Integer,
+    Float,
+    /// `aye` or `nay`.
+    Boolean,
+    Char,
+    /// An arithmetic, comparison, or logical operator.
+    Operator,
+    /// `(`, `)`, `,`, `:`, `=`, or `->`.
+    Punctuation,
+    /// A `//` comment, or the shebang line of a script.
+    Comment,
+    /// A character no rule of the grammar starts with.
+    Unknown,
+}
+
+/// A piece of source text and what sort it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    /// The text of the token in `source`, which it was read from.
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.span.start..self.span.end]
+    }
+}
+
+/// The keywords of the grammar, longest first so that `speaks for input`
+/// isn't read as `speak`.
+const KEYWORDS: &[&str] = &[
+    "on the iron throne",
+    "the realm marches",
+    "speaks for input",
+    "council says",
+    "await_raven",
+    "send_raven",
+    "we declare",
+    "return",
+    "times",
+    "canon",
+    "trial",
+    "speak",
+    "while",
+    "is a",
+    "else",
+    "with",
+    "ask",
+    "if",
+];
+
+const PUNCTUATION: &[&str] = &["(", ")", ",", ":", "="];
+
+/// Splits `source` into tokens, skipping whitespace.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = Position { offset: 0, line: 1, col: 1 };
+    if source.starts_with("#!") {
+        let end = source.find('\n').unwrap_or(source.len());
+        tokens.push(position.token(TokenKind::Comment, source, end));
+    }
+    while let Some(c) = source[position.offset..].chars().next() {
+        let rest = &source[position.offset..];
+        if c.is_whitespace() {
+            position.advance(source, c.len_utf8());
+            continue;
+        }
+        let (kind, length) = next_token(rest);
+        tokens.push(position.token(kind, source, length));
+    }
+    tokens
+}
+
+/// The kind and byte length of the token `rest` starts with.
+fn next_token(rest: &str) -> (TokenKind, usize) {
+    if rest.starts_with("//") {
+        let line = rest.split('\n').next().unwrap_or_default();
+        return (TokenKind::Comment, line.trim_end_matches('\r').len());
+    }
+    if rest.starts_with("->") {
+        return (TokenKind::Punctuation, 2);
+    }
+    // Before literals, since a literal's sign is read as an operator
+    if let Some(length) = matched(Rule::binary_op, rest).or_else(|| matched(Rule::unary_op, rest)) {
+        return (TokenKind::Operator, length);
+    }
+    if let Some(punctuation) = PUNCTUATION.iter().find(|punctuation| rest.starts_with(**punctuation)) {
+        return (TokenKind::Punctuation, punctuation.len());
+    }
+    if let Some(keyword) = KEYWORDS.iter().find(|keyword| starts_with_word(rest, keyword)) {
+        return (TokenKind::Keyword, keyword.len());
+    }
+    let literals = [
+        (Rule::string_literal, TokenKind::String),
+        (Rule::float_literal, TokenKind::Float),
+        (Rule::integer_literal, TokenKind::Integer),
+        (Rule::boolean_literal, TokenKind::Boolean),
+        (Rule::char_literal, TokenKind::Char),
+    ];
+    for (rule, kind) in literals {
+        if let Some(length) = matched(rule, rest) {
+            return (kind, length);
+        }
+    }
+    if let Some(length) = matched(Rule::identifier, rest) {
+        let kind = match matched(Rule::data_type, rest) {
+            Some(type_length) if type_length == length => TokenKind::Type,
+            _ => TokenKind::Identifier,
+        };
+        return (kind, length);
+    }
+    (TokenKind::Unknown, rest.chars().next().map_or(0, char::len_utf8))
+}
+
+/// The length of the text at the start of `rest` that `rule` matches.
+fn matched(rule: Rule, rest: &str) -> Option<usize> {
+    let pair = MidValyrianParser::parse(rule, rest).ok()?.next()?;
+    let length = pair.as_span().end();
+    (length > 0).then_some(length)
+}
+
+/// Whether `rest` starts with `word`, and not merely with a longer name
+/// that begins the same way.
+fn starts_with_word(rest: &str, word: &str) -> bool {
+    rest.starts_with(word) &&
+        !rest[word.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Where `tokenize` has got to in the source.
+struct Position {
+    offset: usize,
+    line: usize,
+    col: usize,
+}
+
+impl Position {
+    /// Makes a token of the next `length` bytes and moves past it.
+    fn token(&mut self, kind: TokenKind, source: &str, length: usize) -> Token {
+        let span = Span::new(self.offset, self.offset + length, self.line, self.col);
+        self.advance(source, length);
+        Token { kind, span }
+    }
+
+    fn advance(&mut self, source: &str, length: usize) {
+        for c in source[self.offset..self.offset + length].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        self.offset += length;
+    }
+}
@@ -3,48 +3,278 @@
 //! This crate provides parsing, interpreting, and error handling for the
 //! Mid Valyrian programming language, inspired by *Game of Thrones*.
 //!
-//! Use `run_file` to execute a `.valyrian` source file,
+//! Use `run_file` to execute a `.mv` (or `.valyrian`) source file,
 //! or `run_code` to interpret Valyrian code from a string.
 
 pub mod ast;
+pub mod edition;
+pub mod builder;
 pub mod parser;
+pub mod incremental;
+pub mod lexer;
+pub mod semantic;
+pub mod intern;
 pub mod interpreter;
+pub mod compiler;
+#[cfg(feature = "serde")]
+pub mod bytecode;
+pub mod disassembler;
+pub mod passes;
+pub mod visit;
 pub mod error;
+pub mod builtins;
+pub mod analysis;
+pub mod symbols;
+pub mod rename;
+pub mod diagnostic;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+pub mod printer;
+pub mod trivia;
+pub mod codegen;
+pub mod manifest;
+pub mod package;
+pub mod trial;
+pub mod link;
+pub mod testing;
+pub mod async_host;
+pub mod observer;
+pub mod plugin;
+pub mod debugger;
+pub mod profiler;
+pub mod coverage;
+pub mod replay;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use ast::*;
+pub use edition::Edition;
+pub use builder::ProgramBuilder;
 pub use parser::*;
+pub use incremental::{ reparse, TextEdit };
+pub use lexer::{ tokenize, Token, TokenKind };
+pub use semantic::{ semantic_tokens, SemanticKind, SemanticToken };
+pub use intern::Interner;
 pub use interpreter::*;
+pub use compiler::{ compile, Chunk, Instruction };
+pub use disassembler::disassemble;
+pub use passes::{ AstPass, ConstantFolding, DeadCodeElimination, OptimizationLevel, PassPipeline };
 pub use error::*;
+pub use analysis::{ analyze, typecheck, Warning, WarningKind };
+pub use symbols::{ Access, Definition, Reference, SymbolKind, SymbolTable };
+pub use rename::rename;
+pub use diagnostic::{ Diagnostic, Severity };
+pub use printer::{ format_source, to_source, to_source_with_trivia };
+pub use trivia::{ StatementTrivia, Trivia, Trivium };
+pub use codegen::{ transpile, Target };
+pub use link::{ link, LinkedProgram, SourceFile };
+pub use manifest::{ Manifest, MANIFEST_FILE };
+pub use async_host::{ AsyncHost, HostFuture };
+pub use observer::ExecutionObserver;
+pub use profiler::{ FunctionProfile, Profiler };
+pub use coverage::{ Coverage, CoverageReport };
+pub use replay::{ Recorder, RecordingInput, ReplayEvent, ReplayInput };
+pub use debugger::{ Breakpoint, DebugEvent, Debugger, Pause, PauseReason };
+#[cfg(not(target_arch = "wasm32"))]
+pub use plugin::load_plugin;
+pub use plugin::Plugin;
 
 use std::fs;
 use std::path::Path;
 
-/// Runs a Mid Valyrian source file.
+/// Options for running a whole program with `run_file_with` or
+/// `run_code_with`.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Show the AST on stderr before running.
+    pub debug: bool,
+    /// Turn soft issues into errors; see `Interpreter::with_strict`.
+    pub strict: bool,
+    /// Arguments the script sees through `arg_count` and `arg`.
+    pub args: Vec<String>,
+    /// Variables defined before the program runs.
+    pub globals: Vec<(String, Value)>,
+    /// Abort after this many executed statements and evaluated expressions.
+    pub max_steps: Option<u64>,
+    /// Abort once the run has taken longer than this.
+    pub timeout: Option<std::time::Duration>,
+    /// Caps on the state the program may build up.
+    pub limits: ResourceLimits,
+    /// How deeply calls may nest (`DEFAULT_MAX_CALL_DEPTH` when `None`).
+    pub max_call_depth: Option<usize>,
+    /// Refuse input and I/O builtins; see `Interpreter::with_sandbox`.
+    pub sandbox: bool,
+    /// Let the `command` builtin run programs; see
+    /// `Interpreter::with_commands`.
+    pub allow_commands: bool,
+    /// Decimal places `speak` shows wine with; see
+    /// `Interpreter::with_float_precision`.
+    pub float_precision: Option<usize>,
+    /// How the program is executed.
+    pub backend: ExecutionBackend,
+    /// Which of the standard passes rewrite the program before it runs;
+    /// see `PassPipeline::standard`.
+    pub optimization: OptimizationLevel,
+}
+
+impl RunOptions {
+    /// Returns a builder for an interpreter configured with these options,
+    /// for settings (such as writers) that `RunOptions` doesn't carry.
+    pub fn builder(&self) -> InterpreterBuilder {
+        let mut builder = Interpreter::builder()
+            .limits(self.limits)
+            .sandbox(self.sandbox)
+            .commands(self.allow_commands)
+            .backend(self.backend)
+            .debug(self.debug)
+            .strict(self.strict)
+            .args(self.args.clone());
+        if let Some(max_steps) = self.max_steps {
+            builder = builder.max_steps(max_steps);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(depth) = self.max_call_depth {
+            builder = builder.max_call_depth(depth);
+        }
+        if let Some(decimals) = self.float_precision {
+            builder = builder.float_precision(decimals);
+        }
+        self.globals
+            .iter()
+            .fold(builder, |builder, (name, value)| builder.global(name, value.clone()))
+    }
+}
+
+/// Runs a Mid Valyrian source file, or a program compiled into a `.mvc`
+/// file by `build` (see `bytecode`).
 ///
 /// # Arguments
 ///
-/// * `path` - Path to the `.valyrian` source file.
-/// * `debug` - Enables verbose AST and execution output if `true`.
+/// * `path` - Path to the source file, with one of the `SOURCE_EXTENSIONS`.
+/// * `debug` - Shows the AST on stderr before running if `true`.
 ///
 /// # Errors
 ///
 /// Returns `ValyrianError` if file reading, parsing, or interpretation fails.
 pub fn run_file<P: AsRef<Path>>(path: P, debug: bool) -> Result<(), ValyrianError> {
+    #[cfg(feature = "serde")]
+    if bytecode::is_bytecode_file(&path) {
+        let file = bytecode::BytecodeFile::load(path)?;
+        return Interpreter::new(debug).run_compiled(file.chunk).map(|_| ());
+    }
+    let contents = read_source(path)?;
+    run_code(&contents, debug)
+}
+
+/// File extensions accepted for Mid Valyrian source files.
+pub const SOURCE_EXTENSIONS: &[&str] = &["mv", "valyrian"];
+
+/// Returns true if `path` ends in one of the `SOURCE_EXTENSIONS`.
+pub fn has_source_extension<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext))
+}
+
+/// Reads the source of a Mid Valyrian file without running it.
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if the file has the wrong extension or cannot be read.
+pub fn read_source<P: AsRef<Path>>(path: P) -> Result<String, ValyrianError> {
     let path_ref = path.as_ref();
 
-    // ✅ Proper extension check
-    if path_ref.extension().and_then(|s| s.to_str()) != Some("mv") {
-        return Err(ValyrianError::ParseError("File must end with .mv".to_string()));
+    if !has_source_extension(path_ref) {
+        return Err(ValyrianError::WrongExtension(path_ref.display().to_string()));
     }
-    
-    let contents = fs::read_to_string(path_ref)
-        .map_err(|e| ValyrianError::IoError(format!(
+    read_source_unchecked(path_ref)
+}
+
+/// Reads the source of a file whatever its extension, for embedders that
+/// load programs from arbitrary paths.
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if the file cannot be read.
+pub fn read_source_unchecked<P: AsRef<Path>>(path: P) -> Result<String, ValyrianError> {
+    let path_ref = path.as_ref();
+    fs::read_to_string(path_ref)
+        .map_err(|e| ValyrianError::io(format!(
             "Failed to read file '{}': {}",
             path_ref.display(),
             e
-        )))?;
+        ), e))
+}
 
-    run_code(&contents, debug)
+/// A file read by `parse_files`, with the program parsed from it.
+#[derive(Debug)]
+pub struct ParsedSource {
+    /// The text of the file, for rendering diagnostics against.
+    pub source: String,
+    pub program: Result<Program, ValyrianError>,
+}
+
+/// Reads and parses several source files, spreading them over the cores of
+/// the machine. Results come back in the order of `paths`; an `Err` means
+/// the file couldn't be read.
+///
+/// ```
+/// let parsed = mid_valyrian::parse_files(&["examples/hello.mv", "examples/missing.mv"]);
+/// assert!(parsed[0].as_ref().is_ok_and(|file| file.program.is_ok()));
+/// assert!(parsed[1].is_err());
+/// ```
+pub fn parse_files<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<ParsedSource, ValyrianError>> {
+    parse_files_with(paths, |path| read_source(path))
+}
+
+/// Like `parse_files`, but reads each file whatever its extension (see
+/// `read_source_unchecked`).
+pub fn parse_files_unchecked<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<ParsedSource, ValyrianError>> {
+    parse_files_with(paths, |path| read_source_unchecked(path))
+}
+
+fn parse_files_with<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    read: impl Fn(&Path) -> Result<String, ValyrianError> + Sync
+) -> Vec<Result<ParsedSource, ValyrianError>> {
+    let parse_file = |path: &P| {
+        read(path.as_ref()).map(|source| ParsedSource { program: parse_program(&source), source })
+    };
+    let threads = if cfg!(target_arch = "wasm32") {
+        1
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get()).min(paths.len())
+    };
+    if threads <= 1 {
+        return paths.iter().map(parse_file).collect();
+    }
+
+    let per_thread = paths.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(per_thread)
+            .map(|chunk| {
+                let worker = std::thread::Builder
+                    ::new()
+                    .stack_size(parser::PARSER_STACK_SIZE)
+                    .spawn_scoped(scope, move || chunk.iter().map(parse_file).collect::<Vec<_>>());
+                (chunk, worker)
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|(chunk, worker)| match worker {
+                Ok(worker) => worker.join().expect("the parser does not panic"),
+                // No thread to spare, so parse these here
+                Err(_) => chunk.iter().map(parse_file).collect(),
+            })
+            .collect()
+    })
 }
 
 /// Runs Mid Valyrian code from a string.
@@ -52,7 +282,7 @@ pub fn run_file<P: AsRef<Path>>(path: P, debug: bool) -> Result<(), ValyrianErro
 /// # Arguments
 ///
 /// * `code` - The source code as a string.
-/// * `debug` - Enables verbose AST and execution output if `true`.
+/// * `debug` - Shows the AST on stderr before running if `true`.
 ///
 /// # Errors
 ///
@@ -62,3 +292,85 @@ pub fn run_code(code: &str, debug: bool) -> Result<(), ValyrianError> {
     let mut interpreter = Interpreter::new(debug);
     interpreter.interpret(&program)
 }
+
+/// Runs a Mid Valyrian source file (or compiled `.mvc` file) with the
+/// given options, returning the value the main block hands back with
+/// `return`.
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if file reading, parsing, or interpretation fails.
+pub fn run_file_with<P: AsRef<Path>>(path: P, options: &RunOptions) -> Result<Value, ValyrianError> {
+    #[cfg(feature = "serde")]
+    if bytecode::is_bytecode_file(&path) {
+        let file = bytecode::BytecodeFile::load(path)?;
+        return options.builder().build().run_compiled(file.chunk);
+    }
+    let contents = read_source(path)?;
+    run_code_with(&contents, options)
+}
+
+/// Runs Mid Valyrian code from a string with the given options, returning
+/// the value the main block hands back with `return`.
+///
+/// ```
+/// use mid_valyrian::{ run_code_with, RunOptions, Value };
+///
+/// let options = RunOptions {
+///     globals: vec![("dragons".into(), Value::Integer(3))],
+///     ..RunOptions::default()
+/// };
+/// let code = "on the iron throne:\n    return dragons + 1\n";
+/// assert_eq!(run_code_with(code, &options).unwrap(), Value::Integer(4));
+/// ```
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if parsing or interpretation fails.
+pub fn run_code_with(code: &str, options: &RunOptions) -> Result<Value, ValyrianError> {
+    run_code_with_passes(code, options, &mut PassPipeline::standard())
+}
+
+/// Runs Mid Valyrian code from a string like `run_code_with`, rewriting it
+/// first with the passes of `pipeline` enabled at `options.optimization`.
+///
+/// ```
+/// use mid_valyrian::{ run_code_with_passes, ConstantFolding, OptimizationLevel, PassPipeline, RunOptions, Value };
+///
+/// let options = RunOptions { optimization: OptimizationLevel::O1, ..RunOptions::default() };
+/// let mut pipeline = PassPipeline::new().with_pass(OptimizationLevel::O1, ConstantFolding);
+/// let code = "on the iron throne:\n    return 6 * 7\n";
+/// assert_eq!(run_code_with_passes(code, &options, &mut pipeline).unwrap(), Value::Integer(42));
+/// ```
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if parsing or interpretation fails.
+pub fn run_code_with_passes(
+    code: &str,
+    options: &RunOptions,
+    pipeline: &mut PassPipeline
+) -> Result<Value, ValyrianError> {
+    let mut program = parse_program(code)?;
+    pipeline.run(&mut program, options.optimization);
+    options.builder().build().interpret_with_result(&program)
+}
+
+/// Runs Mid Valyrian code from a string, returning the value the main
+/// block hands back with `return`, or `Value::Void` if it returns nothing.
+///
+/// ```
+/// use mid_valyrian::{ run_code_with_result, Value };
+///
+/// let code = "on the iron throne:\n    return 6 * 7\n";
+/// assert_eq!(run_code_with_result(code, false).unwrap(), Value::Integer(42));
+/// ```
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if parsing or interpretation fails.
+pub fn run_code_with_result(code: &str, debug: bool) -> Result<Value, ValyrianError> {
+    let program = parse_program(code)?;
+    let mut interpreter = Interpreter::new(debug);
+    interpreter.interpret_with_result(&program)
+}
@@ -0,0 +1,161 @@
+//! 🔗 Programs spread over several files.
+//!
+//! `link` puts the programs parsed from several files together into one,
+//! until the language has imports of its own. The functions the files
+//! declare share one namespace, so a function may be declared in only one
+//! of them, and exactly one file holds the main block. The top-level
+//! statements of the other files run first, in the order given, and then
+//! those of the file with the main block.
+//!
+//! ```
+//! use mid_valyrian::{ link, parse_program, Interpreter, OutputBuffer, SourceFile };
+//!
+//! let library = "we declare greet with name ->\ncouncil says:\n    speak \"Hello, \" + name\n";
+//! let main = "on the iron throne:\n    greet with \"Arya\"\n";
+//! let files = [("main.mv", main), ("greet.mv", library)]
+//!     .map(|(name, source)| (SourceFile::new(name, source), parse_program(source).unwrap()));
+//! let linked = link(files.into()).unwrap();
+//!
+//! let output = OutputBuffer::new();
+//! let mut interpreter = Interpreter::new(false).with_output(output.clone());
+//! interpreter.interpret(&linked.program).unwrap();
+//! assert_eq!(output.contents(), "Hello, Arya\n");
+//! ```
+//!
+//! Spans in the linked program are moved so that each file has bytes of
+//! its own; `LinkedProgram::locate` finds the file a span came from, and
+//! `LinkedProgram::render` shows a diagnostic against it.
+
+use std::collections::HashMap;
+use crate::ast::{ Program, Span, StatementKind, Symbol };
+use crate::diagnostic::Diagnostic;
+use crate::error::ValyrianError;
+use crate::incremental::Shift;
+use crate::visit::VisitorMut;
+
+/// The text of a source file, and the name diagnostics give it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self { name: name.into(), source: source.into() }
+    }
+}
+
+/// Several files linked into one program by `link`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkedProgram {
+    pub program: Program,
+    /// Each file, with the offset its spans were moved by.
+    files: Vec<(SourceFile, usize)>,
+    /// The index of the file with the main block.
+    main: usize,
+}
+
+impl LinkedProgram {
+    /// The files linked, in the order given to `link`.
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter().map(|(file, _)| file)
+    }
+
+    /// The file with the main block.
+    pub fn main_file(&self) -> &SourceFile {
+        &self.files[self.main].0
+    }
+
+    /// Finds the file `span` of the linked program came from, and where
+    /// in that file it is.
+    pub fn locate(&self, span: Span) -> Option<(&SourceFile, Span)> {
+        self.files
+            .iter()
+            .find(|(file, offset)| (*offset..=offset + file.source.len()).contains(&span.start))
+            .map(|(file, offset)| {
+                (file, Span::new(span.start - offset, span.end.saturating_sub(*offset), span.line, span.col))
+            })
+    }
+
+    /// Renders `diagnostic` against the file its span is in. One without
+    /// a span is given the name of the file with the main block.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        if let Some((file, span)) = diagnostic.span.and_then(|span| self.locate(span)) {
+            let diagnostic = Diagnostic { span: Some(span), ..diagnostic.clone() };
+            return diagnostic.render(&file.source, &file.name);
+        }
+        Diagnostic { span: None, ..diagnostic.clone() }.render("", &self.main_file().name)
+    }
+}
+
+/// Links the programs parsed from `files` into one. The linked program is
+/// in the edition of the file with the main block.
+///
+/// # Errors
+///
+/// Returns `ValyrianError::ParseError` if two files declare the same
+/// function, or if not exactly one file has a main block.
+pub fn link(files: Vec<(SourceFile, Program)>) -> Result<LinkedProgram, ValyrianError> {
+    let mut declared: HashMap<Symbol, &str> = HashMap::new();
+    let mut mains = Vec::new();
+    for (index, (file, program)) in files.iter().enumerate() {
+        for statement in &program.statements {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration { name, .. } => {
+                    match declared.insert(name.clone(), &file.name) {
+                        Some(other) if other != file.name => {
+                            return Err(
+                                ValyrianError::ParseError(
+                                    format!("'{}' is declared in both {} and {}", name, other, file.name)
+                                )
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                StatementKind::MainBlock(_) if mains.last() != Some(&index) => {
+                    mains.push(index);
+                }
+                _ => {}
+            }
+        }
+    }
+    let main = match mains[..] {
+        [main] => main,
+        [] => {
+            return Err(ValyrianError::ParseError("none of the files has a main block".into()));
+        }
+        _ => {
+            let names: Vec<_> = mains.iter().map(|&index| files[index].0.name.as_str()).collect();
+            return Err(
+                ValyrianError::ParseError(
+                    format!("only one file may have a main block, not {}", names.join(", "))
+                )
+            );
+        }
+    };
+
+    let mut linked = Program { statements: Vec::new(), edition: files[main].1.edition };
+    let mut main_statements = Vec::new();
+    let mut sources = Vec::with_capacity(files.len());
+    let mut offset = 0;
+    for (index, (file, mut program)) in files.into_iter().enumerate() {
+        let mut shift = Shift { offset: offset as isize, lines: 0 };
+        for statement in &mut program.statements {
+            shift.visit_statement_mut(statement);
+        }
+        if index == main {
+            main_statements = program.statements;
+        } else {
+            linked.statements.extend(program.statements);
+        }
+        // A byte between files, so a span at the end of one isn't at the
+        // start of the next
+        let next = offset + file.source.len() + 1;
+        sources.push((file, offset));
+        offset = next;
+    }
+    linked.statements.extend(main_statements);
+    Ok(LinkedProgram { program: linked, files: sources, main })
+}
@@ -1,59 +1,1940 @@
-use clap::{Arg, Command, ArgAction};
+use clap::{ Arg, ArgAction, ArgMatches, Command };
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
 use colored::*;
-use mid_valyrian::run_file;
+use std::io::{ self, Read };
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc;
+use std::time::Duration;
+use notify::{ RecursiveMode, Watcher };
+use mid_valyrian::{
+    analyze,
+    compile,
+    disassemble,
+    Breakpoint,
+    Coverage,
+    CoverageReport,
+    DebugEvent,
+    Debugger,
+    ExecutionBackend,
+    ExecutionStats,
+    FunctionProfile,
+    Pause,
+    PauseReason,
+    Profiler,
+    Program,
+    Recorder,
+    ReplayInput,
+    SourceFile,
+    ResourceLimits,
+    format_source,
+    link,
+    parse_files,
+    parse_files_unchecked,
+    parse_program,
+    ParsedSource,
+    read_source,
+    read_source_unchecked,
+    transpile,
+    Interpreter,
+    Manifest,
+    OptimizationLevel,
+    PassPipeline,
+    PROGRAM_STACK_SIZE,
+    Target,
+    ValyrianError,
+    MANIFEST_FILE,
+    WarningKind,
+};
+use mid_valyrian::{ package, trial };
+use mid_valyrian::repl::Repl;
+use mid_valyrian::diagnostic::{ strip_emoji, PlainWriter };
+#[cfg(feature = "serde")]
+use mid_valyrian::bytecode::{ self, BytecodeFile, BYTECODE_EXTENSION };
 
 fn main() {
-    print_banner();
+    let worker = std::thread::Builder
+        ::new()
+        .stack_size(PROGRAM_STACK_SIZE)
+        .spawn(cli)
+        .expect("failed to start the interpreter thread");
+    if worker.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn cli() {
+    let matches = command().get_matches();
+    let sub = matches.subcommand();
+    if let Some((name, sub)) = sub {
+        reject_misplaced_run_args(&matches, name, sub);
+    }
 
-    let matches = Command::new("mid_valyrian")
+    let plain = matches.get_flag("plain");
+    if plain {
+        colored::control::set_override(false);
+        PLAIN.store(true, Ordering::Relaxed);
+    }
+
+    let flags = RunFlags::from_matches(&matches, sub.map(|(_, sub)| sub));
+    let log_level = match matches.get_one::<String>("log") {
+        Some(level) => level.parse().ok(),
+        None if flags.debug => Some(tracing::Level::TRACE),
+        None => None,
+    };
+    if let Some(level) = log_level {
+        let json = matches.get_one::<String>("log-format").is_some_and(|format| format == "json");
+        init_logging(level, json);
+    }
+
+    // Keep stdout clean for machine-readable output
+    if !matches!(matches.subcommand_name(), Some("ast" | "disasm" | "transpile")) && !plain && !matches.get_flag("quiet") {
+        print_banner();
+    }
+
+    match sub {
+        Some(("run", sub)) => run_subcommand(sub, flags),
+        Some(("debug", sub)) => {
+            let file = sub.get_one::<String>("file").expect("required");
+            std::process::exit(debug_file(file, &flags));
+        }
+        Some(("test", sub)) => {
+            let paths: Vec<PathBuf> = match sub.get_many::<String>("paths") {
+                Some(paths) => paths.map(PathBuf::from).collect(),
+                None => match find_project() {
+                    Ok(_) => load_project().map_or_else(|_| vec![PathBuf::from(".")], |m| m.sources),
+                    Err(_) => vec![PathBuf::from(".")],
+                },
+            };
+            std::process::exit(run_tests(&paths, &flags));
+        }
+        Some(("verify", sub)) => {
+            let paths: Vec<PathBuf> = sub
+                .get_many::<String>("paths")
+                .unwrap_or_default()
+                .map(PathBuf::from)
+                .collect();
+            std::process::exit(verify(&paths, sub.get_flag("bless")));
+        }
+        Some(("add", sub)) => add_subcommand(sub),
+        Some(("vendor", _)) => vendor_subcommand(),
+        Some(("check", sub)) => {
+            let files: Vec<&String> = sub.get_many::<String>("files").unwrap_or_default().collect();
+            // Check every file, even after a failure, so all problems are reported
+            let results: Vec<bool> = files
+                .iter()
+                .zip(parse_files(&files))
+                .map(|(file, parsed)| check_file(file, parsed, sub.get_flag("typecheck"), &flags))
+                .collect();
+            let ok = results.iter().all(|ok| *ok);
+            std::process::exit(if ok { 0 } else { 2 });
+        }
+        Some(("fmt", sub)) => {
+            let check = sub.get_flag("check");
+            let results: Vec<bool> = sub
+                .get_many::<String>("files")
+                .unwrap_or_default()
+                .map(|file| format_file(file, check))
+                .collect();
+            std::process::exit(if results.iter().all(|ok| *ok) { 0 } else { 1 });
+        }
+        Some(("lint", sub)) => lint_subcommand(sub),
+        Some(("ast", sub)) => {
+            let file = sub.get_one::<String>("file").expect("required");
+            let format = sub.get_one::<String>("format").expect("defaulted");
+            dump_ast(file, format);
+        }
+        Some(("build", sub)) => {
+            let file = sub.get_one::<String>("file").expect("required");
+            build_file(file, sub.get_one::<PathBuf>("output"), &flags);
+        }
+        Some(("transpile", sub)) => {
+            let file = sub.get_one::<String>("file").expect("required");
+            let target = sub.get_one::<String>("target").and_then(|name| Target::from_name(name));
+            transpile_file(file, target.expect("defaulted"), sub.get_one::<PathBuf>("output"));
+        }
+        Some(("disasm", sub)) => {
+            disassemble_file(sub.get_one::<String>("file").expect("required"), &flags);
+        }
+        _ => run_without_subcommand(&matches, &flags),
+    }
+}
+
+/// The whole command line: a program to run, or a subcommand.
+fn command() -> Command {
+    Command::new("mid_valyrian")
         .version("0.1.0")
         .author("The Maesters of Oldtown and Oishik Biswas")
         .about("A Game of Thrones inspired interpreted programming language")
         .arg(
             Arg::new("file")
-                .help("The .mv file to execute")
-                .required(true)
+                .help("The .mv file to execute, or `-` to read the program from stdin (starts the REPL when omitted)")
                 .index(1),
         )
         .arg(
-            Arg::new("debug")
-                .short('d')
-                .long("debug")
-                .help("Enable debug mode (show AST and execution trace)")
+            Arg::new("eval")
+                .short('e')
+                .long("eval")
+                .value_name("CODE")
+                .help("Run CODE instead of a file")
+                .conflicts_with("file"),
+        )
+        .arg(
+            Arg::new("args")
+                .help("Arguments passed to the program, after `--`")
+                .index(2)
+                .num_args(0..)
+                .last(true),
+        )
+        .args(output_args())
+        .args(run_args())
+        .subcommand(repl_command())
+        .subcommand(run_command())
+        .subcommand(check_command())
+        .subcommand(debug_command())
+        .subcommand(ast_command())
+        .subcommand(build_command())
+        .subcommand(disasm_command())
+        .subcommand(transpile_command())
+        .subcommand(fmt_command())
+        .subcommand(lint_command())
+        .subcommand(test_command())
+        .subcommand(verify_command())
+        .subcommand(add_command())
+        .subcommand(vendor_command())
+}
+
+/// Options for what the CLI itself prints, which every subcommand takes.
+fn output_args() -> [Arg; 4] {
+    [
+        Arg::new("log")
+            .long("log")
+            .value_name("LEVEL")
+            .global(true)
+            .value_parser(["error", "warn", "info", "debug", "trace"])
+            .help("Log interpreter telemetry (runs, calls, statements) to stderr at LEVEL"),
+        Arg::new("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .global(true)
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .help("Write the log as human-readable text or one JSON object per line"),
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .visible_alias("no-banner")
+            .global(true)
+            .help("Don't print the banner")
+            .action(ArgAction::SetTrue),
+        Arg::new("plain")
+            .long("plain")
+            .global(true)
+            .help("Print without banner, colors, or emoji, for scripts and tests")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// Options for running a program. A program run directly takes them, as
+/// do the subcommands that run one; other subcommands take the few that
+/// apply to them (see `run_args_named`).
+fn run_args() -> Vec<Arg> {
+    vec![
+        Arg::new("debug")
+            .short('d')
+            .long("debug")
+            .help("Enable debug mode (show AST and execution trace; implies --log trace)")
+            .action(ArgAction::SetTrue),
+        Arg::new("strict")
+            .long("strict")
+            .help("Turn soft issues (type mismatches, ignored return values) into errors")
+            .action(ArgAction::SetTrue),
+        Arg::new("max-steps")
+            .long("max-steps")
+            .value_name("N")
+            .value_parser(clap::value_parser!(u64))
+            .help("Stop the program after N executed statements and evaluated expressions"),
+        Arg::new("timeout")
+            .long("timeout")
+            .value_name("DURATION")
+            .value_parser(parse_duration)
+            .help("Stop the program after DURATION, e.g. 5s, 500ms or 2m"),
+        Arg::new("max-output")
+            .long("max-output")
+            .value_name("BYTES")
+            .value_parser(clap::value_parser!(usize))
+            .help("Stop the program once it has written more than BYTES of output"),
+        Arg::new("precision")
+            .long("precision")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .help("Speak wine rounded to N decimal places"),
+        Arg::new("sandbox")
+            .long("sandbox")
+            .help("Refuse input and file, process, and network access")
+            .action(ArgAction::SetTrue),
+        Arg::new("allow-commands")
+            .long("allow-commands")
+            .help("Let the command builtin run other programs")
+            .action(ArgAction::SetTrue),
+        Arg::new("profile")
+            .long("profile")
+            .help("Time every function call and print a table after the run")
+            .action(ArgAction::SetTrue),
+        Arg::new("stats")
+            .long("stats")
+            .help("Print statements executed, calls, peak variables, and wall time after the run")
+            .action(ArgAction::SetTrue),
+        Arg::new("coverage")
+            .long("coverage")
+            .value_name("LCOV_FILE")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("")
+            .help("Show which lines ran after the run; --coverage=FILE also writes an lcov tracefile"),
+        Arg::new("record")
+            .long("record")
+            .value_name("FILE")
+            .value_parser(clap::value_parser!(PathBuf))
+            .conflicts_with("replay")
+            .help("Save every line the program reads to a replay file"),
+        Arg::new("replay")
+            .long("replay")
+            .value_name("FILE")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Answer the program's reads from a replay file saved with --record"),
+        Arg::new("backend")
+            .long("backend")
+            .value_name("BACKEND")
+            .value_parser(["tree", "bytecode"])
+            .default_value("tree")
+            .help("Run programs by walking their syntax tree or as compiled bytecode"),
+        Arg::new("opt-level")
+            .short('O')
+            .value_name("LEVEL")
+            .value_parser(["0", "1"])
+            .default_value("0")
+            .help("Optimize programs before running or building them: 0 runs them as written, 1 folds constants and drops dead code"),
+        Arg::new("plugin")
+            .long("plugin")
+            .value_name("LIBRARY")
+            .value_parser(clap::value_parser!(PathBuf))
+            .action(ArgAction::Append)
+            .help("Load a native plugin library, built by the same compiler and version of mid-valyrian, before running (repeatable)"),
+        Arg::new("any-extension")
+            .long("any-extension")
+            .help("Run files whatever their extension (e.g. extensionless scripts with a shebang)")
+            .action(ArgAction::SetTrue),
+        Arg::new("deny-warnings")
+            .long("deny-warnings")
+            .help("Refuse to run the program if the analysis raises any warnings")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// The `run_args` with the given ids.
+fn run_args_named(ids: &[&str]) -> Vec<Arg> {
+    run_args()
+        .into_iter()
+        .filter(|arg| ids.contains(&arg.get_id().as_str()))
+        .collect()
+}
+
+/// Exits with a usage error if a run option given before the subcommand
+/// `name` is one it doesn't take, rather than silently ignoring it.
+fn reject_misplaced_run_args(matches: &ArgMatches, name: &str, sub: &ArgMatches) {
+    for arg in run_args() {
+        let id = arg.get_id().as_str();
+        let given = matches.value_source(id) == Some(ValueSource::CommandLine);
+        if given && sub.try_contains_id(id).is_err() {
+            let flag = match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => format!("-{}", arg.get_short().expect("every option has a flag")),
+            };
+            command()
+                .bin_name(env!("CARGO_BIN_NAME"))
+                .error(ErrorKind::ArgumentConflict, format!("the argument '{}' cannot be used with '{}'", flag, name))
+                .exit();
+        }
+    }
+}
+
+fn repl_command() -> Command {
+    Command::new("repl")
+        .about("Start an interactive Mid Valyrian session")
+        .args(run_args_named(&["debug"]))
+}
+
+fn run_command() -> Command {
+    Command::new("run")
+        .about("Run a .mv file, or the current project when no file is given")
+        .arg(
+            Arg::new("files")
+                .num_args(0..)
+                .help(
+                    "The file to run (defaults to the entry point in valyrian.toml), or several files and directories to run as one program"
+                ),
+        )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .help("Re-run the file every time it is saved")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hot")
+                .long("hot")
+                .requires("watch")
+                .help(
+                    "With --watch, keep the program's variables between runs and only swap in the new code"
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("args")
+                .help("Arguments passed to the program, after `--`")
+                .num_args(0..)
+                .last(true),
+        )
+        .args(run_args())
+}
+
+fn check_command() -> Command {
+    Command::new("check")
+        .about("Parse and analyze .mv files without running them")
+        .arg(Arg::new("files").required(true).num_args(1..))
+        .arg(
+            Arg::new("typecheck")
+                .long("typecheck")
+                .help("Also check declared types against values known before running")
                 .action(ArgAction::SetTrue),
         )
-        .get_matches();
+        .args(run_args_named(&["deny-warnings"]))
+}
+
+fn debug_command() -> Command {
+    Command::new("debug")
+        .about("Step through a .mv file line by line")
+        .arg(Arg::new("file").required(true))
+        .arg(
+            Arg::new("args")
+                .help("Arguments passed to the program, after `--`")
+                .num_args(0..)
+                .last(true),
+        )
+        .args(run_args())
+}
+
+fn ast_command() -> Command {
+    Command::new("ast")
+        .about("Print the parse tree of a .mv file")
+        .arg(Arg::new("file").required(true))
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["json", "debug"])
+                .default_value(if cfg!(feature = "serde") { "json" } else { "debug" })
+                .help("Output format"),
+        )
+}
+
+fn build_command() -> Command {
+    Command::new("build")
+        .about("Compile a .mv file to bytecode, so running it skips parsing")
+        .arg(Arg::new("file").required(true))
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Where to write the compiled program (defaults to FILE with a .mvc extension)"),
+        )
+        .args(run_args_named(&["opt-level", "any-extension"]))
+}
+
+fn disasm_command() -> Command {
+    Command::new("disasm")
+        .about("Print the bytecode of a .mvc file, or of a .mv file as it compiles")
+        .arg(Arg::new("file").required(true))
+        .args(run_args_named(&["opt-level", "any-extension"]))
+}
+
+fn transpile_command() -> Command {
+    Command::new("transpile")
+        .about("Translate a .mv file into another language")
+        .arg(Arg::new("file").required(true))
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .value_parser(Target::ALL.map(|target| target.name()))
+                .default_value("python")
+                .help("Language to translate into"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Where to write the translation (defaults to stdout)"),
+        )
+}
+
+fn fmt_command() -> Command {
+    Command::new("fmt")
+        .about("Rewrite .mv files in the canonical style")
+        .arg(Arg::new("files").required(true).num_args(1..))
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .help("Don't write anything; exit nonzero if a file would change")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn lint_command() -> Command {
+    Command::new("lint")
+        .about("Report suspicious code in .mv files without running them")
+        .arg(Arg::new("files").required(true).num_args(1..))
+        .arg(
+            Arg::new("enable")
+                .long("enable")
+                .value_name("RULE")
+                .value_parser(WarningKind::ALL.map(|kind| kind.name()))
+                .action(ArgAction::Append)
+                .help("Only report the given rules (may be repeated)"),
+        )
+        .arg(
+            Arg::new("disable")
+                .long("disable")
+                .value_name("RULE")
+                .value_parser(WarningKind::ALL.map(|kind| kind.name()))
+                .action(ArgAction::Append)
+                .help("Don't report the given rule (may be repeated)"),
+        )
+}
+
+fn test_command() -> Command {
+    Command::new("test")
+        .about("Run the trials in .mv files")
+        .arg(
+            Arg::new("paths")
+                .num_args(0..)
+                .help(
+                    "Files or directories to search (defaults to the project's sources, or the current directory)"
+                )
+        )
+        .args(run_args())
+}
+
+fn verify_command() -> Command {
+    Command::new("verify")
+        .about("Run .mv files and compare their output with sibling .expected files")
+        .arg(Arg::new("paths").required(true).num_args(1..))
+        .arg(
+            Arg::new("bless")
+                .long("bless")
+                .help("Write the actual output to the .expected files instead of comparing")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn add_command() -> Command {
+    Command::new("add")
+        .about("Add a shared .mv library to the current project")
+        .arg(
+            Arg::new("source")
+                .required(true)
+                .help("A .mv file or a directory of them")
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .help("The name to list it under (defaults to the file or directory name)")
+        )
+}
+
+fn vendor_command() -> Command {
+    Command::new("vendor").about(
+        "Copy every dependency of the current project into its modules directory"
+    )
+}
+
+/// Runs the files given to `run`, or the current project, exiting the
+/// process on failure.
+fn run_subcommand(sub: &ArgMatches, mut flags: RunFlags) {
+    let paths: Vec<&String> = sub.get_many::<String>("files").unwrap_or_default().collect();
+    let run_several = |files: Vec<PathBuf>, flags: &RunFlags| -> ! {
+        if sub.get_flag("watch") || flags.coverage {
+            let message = "--watch and --coverage take a single file, not several";
+            stderr_line(message.bright_red());
+            std::process::exit(2);
+        }
+        std::process::exit(run_files(&files, flags));
+    };
+    let file = match paths[..] {
+        [file] if !Path::new(file).is_dir() => file.clone(),
+        [_, ..] => {
+            let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+            match collect_files(&paths) {
+                Ok(files) => run_several(files, &flags),
+                Err(error) => {
+                    stderr_line(format!("{}", error).bright_red());
+                    std::process::exit(error.exit_code());
+                }
+            }
+        }
+        [] => {
+            let project = load_project().and_then(|manifest| {
+                let files = project_files(&manifest)?;
+                Ok((manifest, files))
+            });
+            let (manifest, files) = match project {
+                Ok(project) => project,
+                Err(error) => {
+                    stderr_line(format!("{}", error).bright_red());
+                    std::process::exit(error.exit_code());
+                }
+            };
+            flags.apply_manifest(&manifest);
+            if files.len() > 1 {
+                run_several(files, &flags);
+            }
+            manifest.entry.display().to_string()
+        }
+    };
+    if sub.get_flag("watch") {
+        watch_file(&file, &flags, sub.get_flag("hot"));
+    } else {
+        run_file(&file, &flags);
+    }
+}
+
+fn add_subcommand(sub: &ArgMatches) {
+    let source = Path::new(sub.get_one::<String>("source").expect("required"));
+    let name = match sub.get_one::<String>("name") {
+        Some(name) => name.clone(),
+        None => source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let result = find_project().and_then(|path| package::add(&path, &name, source));
+    match result {
+        Ok(target) => {
+            stdout_line(format!("📦 added {} as {}", name, target.display()).bright_green());
+        }
+        Err(error) => {
+            stderr_line(format!("{}", error).bright_red());
+            std::process::exit(error.exit_code());
+        }
+    }
+}
+
+fn vendor_subcommand() {
+    let result = load_project().and_then(|manifest| package::vendor(&manifest));
+    match result {
+        Ok(targets) => {
+            for target in targets {
+                stdout_line(format!("📦 vendored {}", target.display()).bright_green());
+            }
+        }
+        Err(error) => {
+            stderr_line(format!("{}", error).bright_red());
+            std::process::exit(error.exit_code());
+        }
+    }
+}
+
+fn lint_subcommand(sub: &ArgMatches) {
+    let rules = |id: &str| -> Vec<WarningKind> {
+        sub.get_many::<String>(id)
+            .unwrap_or_default()
+            .filter_map(|name| WarningKind::from_name(name))
+            .collect()
+    };
+    let enabled = match rules("enable") {
+        enabled if enabled.is_empty() => WarningKind::ALL.to_vec(),
+        enabled => enabled,
+    };
+    let disabled = rules("disable");
+    let rules: Vec<WarningKind> = enabled
+        .into_iter()
+        .filter(|kind| !disabled.contains(kind))
+        .collect();
+
+    let files: Vec<&String> = sub.get_many::<String>("files").unwrap_or_default().collect();
+    let outcomes: Vec<LintOutcome> = files
+        .iter()
+        .zip(parse_files(&files))
+        .map(|(file, parsed)| lint_file(file, parsed, &rules))
+        .collect();
+    let code = if outcomes.contains(&LintOutcome::Failed) {
+        2
+    } else if outcomes.contains(&LintOutcome::Findings) {
+        1
+    } else {
+        0
+    };
+    std::process::exit(code);
+}
+
+/// Runs code given with `--eval`, a file, or stdin, or starts the REPL
+/// when there is none.
+fn run_without_subcommand(matches: &ArgMatches, flags: &RunFlags) {
+    if let Some(code) = matches.get_one::<String>("eval") {
+        run_source(code, "<eval>", flags);
+        return;
+    }
+
+    let file_path = match matches.get_one::<String>("file") {
+        Some(file) => file.trim(),
+        None => {
+            if let Err(error) = Repl::new(flags.debug).plain(PLAIN.load(Ordering::Relaxed)).run() {
+                stderr_line(format!("{}", error).bright_red());
+                std::process::exit(error.exit_code());
+            }
+            return;
+        }
+    };
+
+    if file_path == "-" {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            let error = ValyrianError::io("Failed to read the program from stdin", error);
+            stderr_line(format!("{}", error).bright_red());
+            std::process::exit(error.exit_code());
+        }
+        run_source(&source, "<stdin>", flags);
+        return;
+    }
+
+    run_file(file_path, flags);
+}
+
+/// Sends the interpreter's `tracing` telemetry at `level` and above to
+/// stderr.
+fn init_logging(level: tracing::Level, json: bool) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_ansi(!PLAIN.load(Ordering::Relaxed));
+    if json {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+}
+
+/// Options shared by every way of running a program.
+struct RunFlags {
+    debug: bool,
+    strict: bool,
+    deny_warnings: bool,
+    any_extension: bool,
+    sandbox: bool,
+    /// Let the `command` builtin run programs.
+    allow_commands: bool,
+    /// Print a table of call counts and timings after the run.
+    profile: bool,
+    /// Print execution statistics after the run.
+    stats: bool,
+    /// Print which lines ran after the run.
+    coverage: bool,
+    /// Also write the coverage as an lcov tracefile here.
+    lcov: Option<PathBuf>,
+    /// Save the program's input here as it is read.
+    record: Option<PathBuf>,
+    /// Feed the program the input saved in this replay file.
+    replay: Option<PathBuf>,
+    backend: ExecutionBackend,
+    optimization: OptimizationLevel,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    /// The most bytes of output the program may write.
+    max_output: Option<usize>,
+    /// Decimal places `speak` shows wine with.
+    precision: Option<usize>,
+    /// Plugin libraries to install in the interpreter.
+    plugins: Vec<PathBuf>,
+    args: Vec<String>,
+}
+
+impl RunFlags {
+    /// Reads the run options given before the subcommand, if any, and
+    /// after it. An option given in both places takes the later value.
+    fn from_matches(matches: &ArgMatches, sub: Option<&ArgMatches>) -> Self {
+        // The subcommand's matches, if it takes the option `id` and it
+        // was given there or nowhere
+        let source = |id: &str| -> &ArgMatches {
+            match sub {
+                Some(sub) if
+                    sub.try_contains_id(id).is_ok() &&
+                    (sub.value_source(id) == Some(ValueSource::CommandLine) ||
+                        matches.value_source(id) != Some(ValueSource::CommandLine))
+                => sub,
+                _ => matches,
+            }
+        };
+        let flag = |id: &str| source(id).get_flag(id);
+        let many = |id: &str| -> Vec<PathBuf> {
+            [Some(matches), sub]
+                .into_iter()
+                .flatten()
+                .filter_map(|matches| matches.try_get_many::<PathBuf>(id).ok().flatten())
+                .flatten()
+                .cloned()
+                .collect()
+        };
+        RunFlags {
+            debug: flag("debug"),
+            strict: flag("strict"),
+            deny_warnings: flag("deny-warnings"),
+            any_extension: flag("any-extension"),
+            sandbox: flag("sandbox"),
+            allow_commands: flag("allow-commands"),
+            profile: flag("profile"),
+            stats: flag("stats"),
+            coverage: source("coverage").contains_id("coverage"),
+            lcov: source("coverage")
+                .get_one::<String>("coverage")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            record: source("record").get_one::<PathBuf>("record").cloned(),
+            replay: source("replay").get_one::<PathBuf>("replay").cloned(),
+            backend: match source("backend").get_one::<String>("backend").map(String::as_str) {
+                Some("bytecode") => ExecutionBackend::Bytecode,
+                _ => ExecutionBackend::TreeWalking,
+            },
+            optimization: source("opt-level")
+                .get_one::<String>("opt-level")
+                .and_then(|level| OptimizationLevel::from_str(level))
+                .unwrap_or_default(),
+            max_steps: source("max-steps").get_one::<u64>("max-steps").copied(),
+            timeout: source("timeout").get_one::<Duration>("timeout").copied(),
+            max_output: source("max-output").get_one::<usize>("max-output").copied(),
+            precision: source("precision").get_one::<usize>("precision").copied(),
+            plugins: many("plugin"),
+            args: source("args")
+                .get_many::<String>("args")
+                .map(|args| args.cloned().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Turns on the options the project asks for. Arguments given on the
+    /// command line replace the manifest's.
+    fn apply_manifest(&mut self, manifest: &Manifest) {
+        self.debug |= manifest.debug;
+        self.strict |= manifest.strict;
+        self.deny_warnings |= manifest.deny_warnings;
+        if self.args.is_empty() {
+            self.args = manifest.args.clone();
+        }
+    }
+}
+
+/// Finds the manifest of the project around the current directory.
+fn find_project() -> Result<PathBuf, ValyrianError> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| ValyrianError::io("Failed to find the current directory", e))?;
+    Manifest::find(&cwd).ok_or_else(|| {
+        ValyrianError::ManifestError(
+            format!("no {} found in {} or its parents", MANIFEST_FILE, cwd.display())
+        )
+    })
+}
+
+/// Finds and loads the manifest of the project around the current directory.
+fn load_project() -> Result<Manifest, ValyrianError> {
+    Manifest::load(&find_project()?)
+}
+
+/// The files a project run links together: the libraries vendored into
+/// its modules directory, the files in its source directories, and its
+/// entry. A dependency's own files are left out where the sources reach
+/// them, since its copy in the modules directory stands for them.
+fn project_files(manifest: &Manifest) -> Result<Vec<PathBuf>, ValyrianError> {
+    let mut paths = Vec::new();
+    let modules = manifest.modules_dir();
+    if modules.is_dir() {
+        paths.push(modules);
+    }
+    paths.extend(manifest.sources.iter().cloned());
+    paths.push(manifest.entry.clone());
+    let mut files = collect_files(&paths)?;
+
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let modules = canonical(&manifest.modules_dir());
+    let origins: Vec<PathBuf> = manifest.dependencies
+        .iter()
+        .map(|(_, origin)| canonical(origin))
+        .collect();
+    files.retain(|file| {
+        let file = canonical(file);
+        file.starts_with(&modules) || !origins.iter().any(|origin| file.starts_with(origin))
+    });
+    Ok(files)
+}
+
+/// Reads and runs a source file (or a compiled one), exiting the process
+/// on failure.
+fn run_file(path: &str, flags: &RunFlags) {
+    #[cfg(feature = "serde")]
+    if bytecode::is_bytecode_file(path) {
+        run_bytecode(path, flags);
+        return;
+    }
+    let source = match load_source(path, flags) {
+        Ok(source) => source,
+        Err(error) => {
+            report_read_error(&error);
+            std::process::exit(error.exit_code());
+        }
+    };
+
+    run_source(&source, path, flags);
+}
+
+/// Runs `path`, then runs it again each time it changes on disk. Errors are
+/// reported but don't end the session; only Ctrl-C does.
+///
+/// With `hot`, each change is hot-reloaded into the interpreter of the
+/// first run (see `Interpreter::hot_reload`) instead of starting afresh.
+fn watch_file(path: &str, flags: &RunFlags, hot: bool) {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            stderr_line(format!("Failed to watch '{}': {}", path, error).bright_red());
+            std::process::exit(4);
+        }
+    };
+    // Editors often save by replacing the file, which would end a watch on
+    // the file itself, so watch its directory and filter by name
+    let file = Path::new(path);
+    let directory = match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Err(error) = watcher.watch(directory, RecursiveMode::NonRecursive) {
+        stderr_line(format!("Failed to watch '{}': {}", path, error).bright_red());
+        std::process::exit(4);
+    }
+    let file_name = file.file_name();
+    let mut session = None;
+
+    loop {
+        // Clear the screen and move the cursor home
+        print!("\x1b[2J\x1b[H");
+        stdout_line(format!("👁️ Watching {} — press Ctrl-C to stop", path).bright_cyan());
+        match load_source(path, flags) {
+            Ok(source) => {
+                if !hot {
+                    session = None;
+                }
+                let _ = execute_source(&source, path, flags, &mut session);
+            }
+            Err(error) => report_read_error(&error),
+        }
+
+        // Wait for a change to the file, then let a burst of events from
+        // one save settle before running again
+        loop {
+            match events.recv() {
+                Ok(Ok(event)) if event.paths.iter().any(|p| p.file_name() == file_name) => {
+                    break;
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    return;
+                }
+            }
+        }
+        while events.recv_timeout(Duration::from_millis(50)).is_ok() {}
+    }
+}
+
+/// Reads a program file, enforcing the source extensions unless the user
+/// opted out with `--any-extension`.
+fn load_source(path: &str, flags: &RunFlags) -> Result<String, ValyrianError> {
+    if flags.any_extension {
+        read_source_unchecked(path)
+    } else {
+        read_source(path)
+    }
+}
+
+/// Parses, checks, and runs `source`, exiting the process on failure.
+/// `name` identifies the source in diagnostics.
+fn run_source(source: &str, name: &str, flags: &RunFlags) {
+    if let Err(error) = execute_source(source, name, flags, &mut None) {
+        std::process::exit(error.exit_code());
+    }
+}
+
+/// Parses, checks, and runs `source`, printing any diagnostics.
+///
+/// With a `session` left by an earlier run, the program is hot-reloaded
+/// into it, keeping its variables; otherwise a fresh interpreter runs it
+/// and is left in `session`.
+fn execute_source(
+    source: &str,
+    name: &str,
+    flags: &RunFlags,
+    session: &mut Option<Interpreter>
+) -> Result<(), ValyrianError> {
+    report_run(flags, |error| error.to_diagnostic().render(source, name), || {
+        let mut program = parse_program(source)?;
+        let warnings = analyze(&program);
+        for warning in &warnings {
+            let rendered = warning.to_diagnostic().render(source, name);
+            stderr_line(rendered.bright_yellow());
+        }
+        if flags.deny_warnings && !warnings.is_empty() {
+            return Err(ValyrianError::WarningsDenied(warnings.len()));
+        }
+        PassPipeline::standard().run(&mut program, flags.optimization);
+        if let Some(interpreter) = session {
+            return interpreter.hot_reload(&program);
+        }
+        let interpreter = session.insert(new_interpreter(flags)?);
+        run_observed(interpreter, &program, source, name, flags, |interpreter| {
+            interpreter.interpret(&program)
+        })
+    })
+}
+
+/// Runs `files` linked into one program (see `mid_valyrian::link`).
+/// Every file is read and parsed before any failure is reported; the exit
+/// code is that of the first file to fail.
+fn run_files(files: &[PathBuf], flags: &RunFlags) -> i32 {
+    let sources = if flags.any_extension { parse_files_unchecked(files) } else { parse_files(files) };
+    let mut parsed = Vec::with_capacity(files.len());
+    let mut failed = None;
+    for (file, source) in files.iter().zip(sources) {
+        let name = file.display().to_string();
+        let error = match source {
+            Ok(ParsedSource { source, program: Ok(program) }) => {
+                parsed.push((SourceFile::new(name, source), program));
+                continue;
+            }
+            Ok(ParsedSource { source, program: Err(error) }) => {
+                stderr_line(error.to_diagnostic().render(&source, &name).bright_red());
+                error
+            }
+            Err(error) => {
+                report_read_error(&error);
+                error
+            }
+        };
+        failed.get_or_insert(error.exit_code());
+    }
+    if let Some(code) = failed {
+        return code;
+    }
+    let linked = match link(parsed) {
+        Ok(linked) => linked,
+        Err(error) => {
+            stderr_line(format!("{}", error).bright_red());
+            return error.exit_code();
+        }
+    };
 
-    let file_path = matches
-    .get_one::<String>("file")
-    .expect("required")
-    .trim();
+    let result = report_run(flags, |error| linked.render(&error.to_diagnostic()), || {
+        let mut program = linked.program.clone();
+        let warnings = analyze(&program);
+        for warning in &warnings {
+            stderr_line(linked.render(&warning.to_diagnostic()).bright_yellow());
+        }
+        if flags.deny_warnings && !warnings.is_empty() {
+            return Err(ValyrianError::WarningsDenied(warnings.len()));
+        }
+        PassPipeline::standard().run(&mut program, flags.optimization);
+        let mut interpreter = new_interpreter(flags)?;
+        let main = linked.main_file();
+        run_observed(&mut interpreter, &program, &main.source, &main.name, flags, |interpreter| {
+            interpreter.interpret(&program)
+        })
+    });
+    result.map_or_else(|error| error.exit_code(), |()| 0)
+}
 
-    let debug = matches.get_flag("debug");
+/// Loads and runs a file compiled by `build`, exiting the process on
+/// failure.
+#[cfg(feature = "serde")]
+fn run_bytecode(path: &str, flags: &RunFlags) {
+    let file = match BytecodeFile::load(path) {
+        Ok(file) => file,
+        Err(error) => {
+            stderr_line(format!("{}", error).bright_red());
+            std::process::exit(error.exit_code());
+        }
+    };
+    let BytecodeFile { chunk, source } = file;
+    let result = report_run(flags, |error| error.to_diagnostic().render(&source, path), || {
+        let mut interpreter = new_interpreter(flags)?;
+        // Only coverage needs the syntax tree, so only coverage parses
+        let program = if flags.coverage { parse_program(&source)? } else { Program::default() };
+        run_observed(&mut interpreter, &program, &source, path, flags, |interpreter| {
+            interpreter.run_compiled(chunk).map(|_| ())
+        })
+    });
+    if let Err(error) = result {
+        std::process::exit(error.exit_code());
+    }
+}
 
-    // Enforce .mv extension
-    if !file_path.ends_with(".mv") {
-        eprintln!("{}", "Error: Only files with the `.mv` extension are allowed.".bright_red());
+/// Runs `run`, bracketing it with the debug banners and showing any error
+/// it returns with `render`.
+fn report_run(
+    flags: &RunFlags,
+    render: impl FnOnce(&ValyrianError) -> String,
+    run: impl FnOnce() -> Result<(), ValyrianError>
+) -> Result<(), ValyrianError> {
+    if flags.debug {
+        stderr_line("🐉 Debug mode enabled - The Maesters will show their work".bright_yellow());
+    }
+
+    match run() {
+        Ok(()) => {
+            if flags.debug {
+                stderr_line("✅ The realm prospers! Program executed successfully.".bright_green());
+            }
+        }
+        Err(error) => {
+            stderr_line(render(&error).bright_red());
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Runs `program` with `run`, attaching the observers the flags ask for
+/// and printing their reports afterwards.
+fn run_observed(
+    interpreter: &mut Interpreter,
+    program: &Program,
+    source: &str,
+    name: &str,
+    flags: &RunFlags,
+    run: impl FnOnce(&mut Interpreter) -> Result<(), ValyrianError>
+) -> Result<(), ValyrianError> {
+    let profiler = Profiler::new();
+    if flags.profile {
+        interpreter.add_observer(profiler.clone());
+    }
+    let coverage = Coverage::new();
+    if flags.coverage {
+        interpreter.add_observer(coverage.clone());
+    }
+    let recorder = Recorder::new();
+    if flags.record.is_some() {
+        interpreter.set_input(recorder.wrap(io::BufReader::new(io::stdin())));
+    }
+    let mut result = run(interpreter);
+    if let Some(path) = &flags.record {
+        // Keep the recording even (especially) when the run failed
+        result = result.and(recorder.save(path));
+    }
+    if flags.profile {
+        print_profile(&profiler.report());
+    }
+    if flags.stats {
+        print_stats(&interpreter.stats());
+    }
+    if flags.coverage {
+        let report = coverage.report(program);
+        print_coverage(source, name, &report);
+        if let Some(path) = &flags.lcov {
+            write_lcov(path, &report.to_lcov(name))?;
+        }
+    }
+    result
+}
+
+/// Compiles `path` into a bytecode file, exiting the process on failure.
+fn build_file(path: &str, output: Option<&PathBuf>, flags: &RunFlags) {
+    #[cfg(feature = "serde")]
+    {
+        let source = match load_source(path, flags) {
+            Ok(source) => source,
+            Err(error) => {
+                report_read_error(&error);
+                std::process::exit(error.exit_code());
+            }
+        };
+        let output = output
+            .cloned()
+            .unwrap_or_else(|| Path::new(path).with_extension(BYTECODE_EXTENSION));
+        let built = parse_program(&source).and_then(|mut program| {
+            PassPipeline::standard().run(&mut program, flags.optimization);
+            BytecodeFile { chunk: compile(&program), source: source.clone() }.save(&output)
+        });
+        if let Err(error) = built {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            std::process::exit(error.exit_code());
+        }
+        stdout_line(format!("📦 built {}", output.display()).bright_green());
+    }
+    #[cfg(not(feature = "serde"))]
+    {
+        let _ = (path, output, flags);
+        stderr_line("Building bytecode needs the `serde` feature".bright_red());
         std::process::exit(1);
     }
+}
+
+/// Prints the profile of a run to stderr, keeping stdout for the program.
+fn print_profile(report: &[FunctionProfile]) {
+    stderr_line("📊 Profile (slowest first, by time in the function itself)".bright_cyan());
+    if report.is_empty() {
+        stderr_line("No functions were called.".normal());
+        return;
+    }
+    let width = report
+        .iter()
+        .map(|function| function.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("function".len());
+    let header = format!("{:<width$} {:>8} {:>12} {:>12}", "function", "calls", "total", "self");
+    stderr_line(header.bold());
+    for function in report {
+        let row = format!(
+            "{:<width$} {:>8} {:>12} {:>12}",
+            function.name,
+            function.calls,
+            format!("{:.3?}", function.total),
+            format!("{:.3?}", function.self_time)
+        );
+        stderr_line(row.normal());
+    }
+}
+
+/// Prints what a run cost to stderr.
+fn print_stats(stats: &ExecutionStats) {
+    stderr_line("📜 Statistics".bright_cyan());
+    let rows = [
+        ("statements executed", stats.statements.to_string()),
+        ("function calls", stats.calls.to_string()),
+        ("peak variables", stats.peak_variables.to_string()),
+        ("wall time", format!("{:.3?}", stats.elapsed)),
+    ];
+    for (label, value) in rows {
+        stderr_line(format!("{:<20} {:>12}", label, value).normal());
+    }
+}
+
+/// Prints a run's coverage to stderr: each line of the source with how
+/// often it ran, `-` for lines without a statement, and `#####` for lines
+/// that never ran.
+fn print_coverage(source: &str, name: &str, report: &CoverageReport) {
+    let summary = format!(
+        "🗺️ Coverage of {}: {}/{} lines ({:.1}%)",
+        name,
+        report.covered_count(),
+        report.lines.len(),
+        report.percent()
+    );
+    stderr_line(summary.bright_cyan());
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        match report.lines.get(&line) {
+            Some(0) => stderr_line(format!("{:>8} | {:>4} | {}", "#####", line, text).bright_red()),
+            Some(count) => stderr_line(format!("{:>8} | {:>4} | {}", count, line, text).normal()),
+            None => stderr_line(format!("{:>8} | {:>4} | {}", "-", line, text).dimmed()),
+        }
+    }
+}
+
+/// Writes lcov tracefile records to `path`.
+fn write_lcov(path: &Path, records: &str) -> Result<(), ValyrianError> {
+    std::fs::write(path, records).map_err(|e| {
+        ValyrianError::io(format!("Could not write coverage to '{}'", path.display()), e)
+    })
+}
+
+/// Builds an interpreter configured by the command-line flags.
+fn new_interpreter(flags: &RunFlags) -> Result<Interpreter, ValyrianError> {
+    let mut builder = Interpreter::builder()
+        .sandbox(flags.sandbox)
+        .commands(flags.allow_commands)
+        .limits(ResourceLimits { max_output: flags.max_output, ..ResourceLimits::default() })
+        .backend(flags.backend)
+        .debug(flags.debug)
+        .strict(flags.strict)
+        .args(flags.args.clone());
+    if let Some(max_steps) = flags.max_steps {
+        builder = builder.max_steps(max_steps);
+    }
+    if let Some(timeout) = flags.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(decimals) = flags.precision {
+        builder = builder.float_precision(decimals);
+    }
+    if let Some(replay) = &flags.replay {
+        builder = builder.stdin(ReplayInput::load(replay)?);
+    }
+    if PLAIN.load(Ordering::Relaxed) {
+        builder = builder.debug_output(PlainWriter::new(io::stderr()));
+    }
+    let mut interpreter = builder.build();
+    for plugin in &flags.plugins {
+        // SAFETY: --plugin asks for libraries built like this binary; the
+        // user who names one vouches for it
+        unsafe { interpreter.load_plugin(plugin)? };
+    }
+    Ok(interpreter)
+}
+
+const DEBUG_HELP: &str = "\
+Commands (an empty line repeats the last one):
+  step, s            run one statement, stepping into calls
+  next, n            run one statement, stepping over calls
+  continue, c        run until the next breakpoint
+  print, p <expr>    show the value of an expression
+  vars               show every variable
+  where, w           show the call stack
+  break, b <target>  stop at a line number or function
+  delete <target>    remove a breakpoint
+  quit, q            stop the program";
+
+/// Runs `path` under the step-through debugger, reading commands from
+/// stdin. Returns the exit code.
+fn debug_file(path: &str, flags: &RunFlags) -> i32 {
+    let source = match load_source(path, flags) {
+        Ok(source) => source,
+        Err(error) => {
+            report_read_error(&error);
+            return error.exit_code();
+        }
+    };
+    let program = match parse_program(&source) {
+        Ok(program) => program,
+        Err(error) => {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            return error.exit_code();
+        }
+    };
+    let interpreter = match new_interpreter(flags) {
+        Ok(interpreter) => interpreter,
+        Err(error) => {
+            stderr_line(format!("{}", error).bright_red());
+            return error.exit_code();
+        }
+    };
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut debugger = Debugger::new(interpreter, program);
+    stdout_line("🔍 Type `help` for the commands.".bright_cyan());
+    let mut event = debugger.step();
+    let mut last_command = String::from("step");
+
+    loop {
+        let pause = match event {
+            DebugEvent::Paused(pause) => pause,
+            DebugEvent::Finished(Ok(_)) => {
+                stdout_line("✅ The program has finished.".bright_green());
+                return 0;
+            }
+            DebugEvent::Finished(Err(error)) => {
+                stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+                return error.exit_code();
+            }
+        };
+        show_pause(&pause, &lines);
+
+        event = loop {
+            print!("{}", "(debug) ".bright_cyan());
+            let _ = io::Write::flush(&mut io::stdout());
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    return 0;
+                }
+                Ok(_) => {}
+            }
+            let line = line.trim();
+            let command = if line.is_empty() { last_command.clone() } else { line.to_string() };
+            last_command.clone_from(&command);
+            let (name, argument) = command
+                .split_once(char::is_whitespace)
+                .map_or((command.as_str(), ""), |(name, rest)| (name, rest.trim()));
+
+            match name {
+                "step" | "s" => break debugger.step(),
+                "next" | "n" => break debugger.step_over(),
+                "continue" | "c" => break debugger.resume(),
+                "print" | "p" => match debugger.evaluate(argument) {
+                    Ok(value) => stdout_line(format!("{} = {}", argument, value).normal()),
+                    Err(error) => stderr_line(error.plain_message().bright_red()),
+                },
+                "vars" => {
+                    if pause.variables.is_empty() {
+                        stdout_line("No variables are known in this realm yet.".normal());
+                    }
+                    for (name, value) in &pause.variables {
+                        stdout_line(format!("{} = {}", name.bright_green(), value).normal());
+                    }
+                }
+                "where" | "w" => {
+                    stdout_line(format!("at line {}", pause.span.line).normal());
+                    for frame in pause.call_stack.iter().rev() {
+                        let line = format!(
+                            "in {} (called from {} at line {})",
+                            frame.function,
+                            frame.caller,
+                            frame.call_site.line
+                        );
+                        stdout_line(line.normal());
+                    }
+                }
+                "break" | "b" | "delete" => {
+                    let breakpoint = match argument.parse::<usize>() {
+                        Ok(line) => Breakpoint::Line(line),
+                        Err(_) if !argument.is_empty() => Breakpoint::Function(argument.to_string()),
+                        Err(_) => {
+                            stderr_line("Give a line number or a function name".bright_red());
+                            continue;
+                        }
+                    };
+                    if name == "delete" {
+                        if !debugger.remove_breakpoint(&breakpoint) {
+                            stderr_line(format!("No breakpoint at {}", argument).bright_red());
+                        }
+                    } else {
+                        debugger.add_breakpoint(breakpoint);
+                    }
+                }
+                "help" | "h" => stdout_line(DEBUG_HELP.normal()),
+                "quit" | "q" => {
+                    return 0;
+                }
+                other => {
+                    stderr_line(format!("Unknown command '{}' — try help", other).bright_red());
+                }
+            }
+        };
+    }
+}
+
+/// Shows where the program stopped, with the line about to run.
+fn show_pause(pause: &Pause, lines: &[&str]) {
+    let why = match &pause.reason {
+        PauseReason::Breakpoint(Breakpoint::Line(line)) => format!(" (breakpoint at line {})", line),
+        PauseReason::Breakpoint(Breakpoint::Function(name)) => format!(" (breakpoint in {})", name),
+        PauseReason::Step => String::new(),
+    };
+    let function = pause.call_stack.last().map_or("main", |frame| &*frame.function);
+    stdout_line(format!("⏸️ {}, line {}{}", function, pause.span.line, why).bright_yellow());
+    let text = lines.get(pause.span.line.saturating_sub(1)).copied().unwrap_or_default();
+    stdout_line(format!("{:>4} | {}", pause.span.line, text).normal());
+}
+
+/// Parses and analyzes one file, printing its diagnostics. Returns false if
+/// the file has errors (or warnings, when they are denied).
+fn check_file(
+    path: &str,
+    parsed: Result<ParsedSource, ValyrianError>,
+    typecheck: bool,
+    flags: &RunFlags
+) -> bool {
+    let ParsedSource { source, program } = match parsed {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            report_read_error(&error);
+            return false;
+        }
+    };
+
+    let program = match program {
+        Ok(program) => program,
+        Err(error) => {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            return false;
+        }
+    };
+
+    let warnings = analyze(&program);
+    for warning in &warnings {
+        stderr_line(warning.to_diagnostic().render(&source, path).bright_yellow());
+    }
+    let errors = if typecheck { mid_valyrian::typecheck(&program) } else { Vec::new() };
+    for error in &errors {
+        stderr_line(error.render(&source, path).bright_red());
+    }
+
+    let ok = errors.is_empty() && (warnings.is_empty() || !flags.deny_warnings);
+    if ok {
+        stdout_line(format!("✅ {}: the scroll is sound", path).bright_green());
+    }
+    ok
+}
+
+#[derive(PartialEq)]
+enum LintOutcome {
+    Clean,
+    Findings,
+    Failed,
+}
+
+/// Runs the analysis passes over one file, printing warnings for the
+/// selected rules.
+fn lint_file(
+    path: &str,
+    parsed: Result<ParsedSource, ValyrianError>,
+    rules: &[WarningKind]
+) -> LintOutcome {
+    let ParsedSource { source, program } = match parsed {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            report_read_error(&error);
+            return LintOutcome::Failed;
+        }
+    };
+    let program = match program {
+        Ok(program) => program,
+        Err(error) => {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            return LintOutcome::Failed;
+        }
+    };
+
+    let warnings: Vec<_> = analyze(&program)
+        .into_iter()
+        .filter(|warning| rules.contains(&warning.kind))
+        .collect();
+    for warning in &warnings {
+        stderr_line(warning.to_diagnostic().render(&source, path).bright_yellow());
+    }
+    if warnings.is_empty() {
+        stdout_line(format!("✅ {}: the maesters found nothing amiss", path).bright_green());
+        LintOutcome::Clean
+    } else {
+        LintOutcome::Findings
+    }
+}
+
+/// Runs the trials in every source file under `paths`, printing a line per
+/// trial and a summary. Returns the exit code: 0 if every trial passed, 1 if
+/// any failed, 2 if a file couldn't be read or parsed. With `--coverage`,
+/// also reports which lines of each file the trials ran.
+fn run_tests(paths: &[PathBuf], flags: &RunFlags) -> i32 {
+    let mut files = Vec::new();
+    for path in paths {
+        if let Err(error) = collect_sources(path, &mut files) {
+            stderr_line(format!("{}", error).bright_red());
+            return error.exit_code();
+        }
+    }
+
+    // Each trial gets an interpreter of its own, so make sure the flags
+    // (plugins, a replay file) can build one before running any
+    if let Err(error) = new_interpreter(flags) {
+        stderr_line(format!("{}", error).bright_red());
+        return error.exit_code();
+    }
+
+    let (mut passed, mut failed, mut broken) = (0, 0, 0);
+    let mut lcov = String::new();
+    for (file, parsed) in files.iter().zip(parse_files(&files)) {
+        let name = file.display().to_string();
+        let ParsedSource { source, program } = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                stderr_line(format!("{}", error).bright_red());
+                broken += 1;
+                continue;
+            }
+        };
+        let program = match program {
+            Ok(program) => program,
+            Err(error) => {
+                stderr_line(error.to_diagnostic().render(&source, &name).bright_red());
+                broken += 1;
+                continue;
+            }
+        };
+        let coverage = Coverage::new();
+        let results = trial::run_trials_with(&program, || {
+            let mut interpreter = new_interpreter(flags).expect("the flags built an interpreter above");
+            if flags.coverage {
+                interpreter.add_observer(coverage.clone());
+            }
+            interpreter
+        });
+        for result in results {
+            match &result.outcome {
+                Ok(()) => {
+                    passed += 1;
+                    stdout_line(format!("trial {} :: {} ... ok", name, result.name).normal());
+                }
+                Err(error) => {
+                    failed += 1;
+                    stdout_line(
+                        format!("trial {} :: {} ... FAILED", name, result.name).bright_red()
+                    );
+                    stderr_line(error.to_diagnostic().render(&source, &name).bright_red());
+                }
+            }
+        }
+        if flags.coverage {
+            let report = coverage.report(&program);
+            print_coverage(&source, &name, &report);
+            lcov.push_str(&report.to_lcov(&name));
+        }
+    }
+    if let Some(path) = &flags.lcov {
+        if let Err(error) = write_lcov(path, &lcov) {
+            stderr_line(format!("{}", error).bright_red());
+            broken += 1;
+        }
+    }
 
-    if debug {
-        println!("{}", "🐉 Debug mode enabled - The Maesters will show their work".bright_yellow());
+    let summary = format!("⚖️ {} passed; {} failed", passed, failed);
+    if failed == 0 && broken == 0 {
+        stdout_line(summary.bright_green());
+        0
+    } else {
+        stdout_line(summary.bright_red());
+        if broken > 0 { 2 } else { 1 }
     }
+}
+
+/// Runs every source file under `paths` and compares what it prints with
+/// the sibling `.expected` file, or rewrites that file when `bless` is set.
+/// Returns the exit code: 0 if everything matched, 1 otherwise.
+fn verify(paths: &[PathBuf], bless: bool) -> i32 {
+    let mut files = Vec::new();
+    for path in paths {
+        if let Err(error) = collect_sources(path, &mut files) {
+            stderr_line(format!("{}", error).bright_red());
+            return error.exit_code();
+        }
+    }
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(error) => {
+            stderr_line(format!("Failed to find the interpreter: {}", error).bright_red());
+            return 4;
+        }
+    };
+
+    let mut mismatched = 0;
+    for file in &files {
+        let expected_path = file.with_extension("expected");
+        // Each program runs in its own process so its output can be captured
+        // and a crash in one can't take down the rest
+        let output = std::process::Command
+            ::new(&exe)
+            .args(["--quiet", "--plain", "--any-extension", "run"])
+            .arg(file)
+            .stdin(std::process::Stdio::null())
+            .output();
+        let actual = match output {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+            Err(error) => {
+                stderr_line(format!("Failed to run '{}': {}", file.display(), error).bright_red());
+                mismatched += 1;
+                continue;
+            }
+        };
+
+        if bless {
+            if let Err(error) = std::fs::write(&expected_path, &actual) {
+                let message = format!("Failed to write '{}': {}", expected_path.display(), error);
+                stderr_line(message.bright_red());
+                mismatched += 1;
+            } else {
+                stdout_line(format!("✒️ blessed {}", expected_path.display()).bright_green());
+            }
+            continue;
+        }
+
+        match std::fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {
+                stdout_line(format!("verify {} ... ok", file.display()).normal());
+            }
+            Ok(expected) => {
+                mismatched += 1;
+                stdout_line(format!("verify {} ... MISMATCH", file.display()).bright_red());
+                for line in diff_lines(&expected, &actual) {
+                    stderr_line(line.normal());
+                }
+            }
+            Err(_) => {
+                mismatched += 1;
+                stdout_line(
+                    format!(
+                        "verify {} ... no {} (run with --bless to create it)",
+                        file.display(),
+                        expected_path.display()
+                    ).bright_yellow()
+                );
+            }
+        }
+    }
+
+    if mismatched == 0 { 0 } else { 1 }
+}
+
+/// Describes where `actual` first departs from `expected`, line by line.
+fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let first = expected
+        .iter()
+        .zip(&actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(actual.len()));
+    let mut out = vec![format!("  first difference at line {}:", first + 1)];
+    if let Some(line) = expected.get(first) {
+        out.push(format!("  - {}", line));
+    }
+    if let Some(line) = actual.get(first) {
+        out.push(format!("  + {}", line));
+    }
+    if expected.len() != actual.len() {
+        out.push(format!("  expected {} line(s), found {}", expected.len(), actual.len()));
+    }
+    out
+}
+
+/// The files in `paths` and the source files below the directories among
+/// them, each once, in a stable order.
+fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, ValyrianError> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_sources(path, &mut files)?;
+    }
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|file| seen.insert(file.canonicalize().unwrap_or_else(|_| file.clone())));
+    Ok(files)
+}
+
+/// Adds `path`, or every source file below it if it is a directory, to
+/// `files` in a stable order.
+fn collect_sources(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), ValyrianError> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let read_error = |e| ValyrianError::io(format!("Failed to read '{}': {}", path.display(), e), e);
+    let mut entries = std::fs::read_dir(path)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error)?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_sources(&entry, files)?;
+        } else if mid_valyrian::has_source_extension(&entry) {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Formats one file in place (or just compares, with `check`). Returns
+/// false if the file couldn't be formatted or, when checking, would change.
+fn format_file(path: &str, check: bool) -> bool {
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(error) => {
+            report_read_error(&error);
+            return false;
+        }
+    };
+    let formatted = match format_source(&source) {
+        Ok(formatted) => formatted,
+        Err(error) => {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            return false;
+        }
+    };
 
-    match run_file(file_path, debug) {
+    if formatted == source {
+        return true;
+    }
+    if check {
+        stdout_line(format!("✗ {} is not formatted", path).bright_yellow());
+        return false;
+    }
+    match std::fs::write(path, formatted) {
         Ok(()) => {
-            if debug {
-                println!("{}", "✅ The realm prospers! Program executed successfully.".bright_green());
+            stdout_line(format!("✒️ formatted {}", path).bright_green());
+            true
+        }
+        Err(error) => {
+            stderr_line(format!("Failed to write '{}': {}", path, error).bright_red());
+            false
+        }
+    }
+}
+
+/// Prints the bytecode of a compiled file, or of a source file compiled
+/// on the spot, exiting on failure.
+fn disassemble_file(path: &str, flags: &RunFlags) {
+    #[cfg(feature = "serde")]
+    if bytecode::is_bytecode_file(path) {
+        match BytecodeFile::load(path) {
+            Ok(file) => print!("{}", disassemble(&file.chunk)),
+            Err(error) => {
+                stderr_line(format!("{}", error).bright_red());
+                std::process::exit(error.exit_code());
             }
         }
+        return;
+    }
+    let source = match load_source(path, flags) {
+        Ok(source) => source,
+        Err(error) => {
+            report_read_error(&error);
+            std::process::exit(error.exit_code());
+        }
+    };
+    match parse_program(&source) {
+        Ok(mut program) => {
+            PassPipeline::standard().run(&mut program, flags.optimization);
+            print!("{}", disassemble(&compile(&program)));
+        }
         Err(error) => {
-            eprintln!("{}", format!("{}", error).bright_red());
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            std::process::exit(error.exit_code());
+        }
+    }
+}
+
+/// Prints the AST of a file in the requested format, exiting on failure.
+fn dump_ast(path: &str, format: &str) {
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(error) => {
+            report_read_error(&error);
+            std::process::exit(error.exit_code());
+        }
+    };
+    let program = match parse_program(&source) {
+        Ok(program) => program,
+        Err(error) => {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            std::process::exit(error.exit_code());
+        }
+    };
+
+    match format {
+        #[cfg(feature = "serde")]
+        "json" => {
+            match serde_json::to_string_pretty(&program) {
+                Ok(json) => write_stdout(&format!("{}\n", json)),
+                Err(error) => {
+                    stderr_line(format!("Failed to encode the AST: {}", error).bright_red());
+                    std::process::exit(1);
+                }
+            }
+        }
+        "debug" => write_stdout(&format!("{:#?}\n", program)),
+        other => {
+            stderr_line(format!("The `{}` format needs the `serde` feature", other).bright_red());
             std::process::exit(1);
         }
     }
 }
 
+/// Translates a source file into `target`, printing the result unless
+/// `output` names a file for it, exiting on failure.
+fn transpile_file(path: &str, target: Target, output: Option<&PathBuf>) {
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(error) => {
+            report_read_error(&error);
+            std::process::exit(error.exit_code());
+        }
+    };
+    let translated = match parse_program(&source).and_then(|program| transpile(&program, target)) {
+        Ok(translated) => translated,
+        Err(error) => {
+            stderr_line(error.to_diagnostic().render(&source, path).bright_red());
+            std::process::exit(error.exit_code());
+        }
+    };
+
+    let Some(output) = output else {
+        write_stdout(&translated);
+        return;
+    };
+    if let Err(error) = std::fs::write(output, translated) {
+        stderr_line(format!("Failed to write '{}': {}", output.display(), error).bright_red());
+        std::process::exit(4);
+    }
+}
+
+/// Parses a duration such as `5s`, `500ms` or `2m`. A bare number is in
+/// seconds.
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a duration like 5s or 500ms", text))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        other => {
+            return Err(format!("unknown unit '{}' (use ms, s or m)", other));
+        }
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Set by `--plain`: output carries no emoji (colors are switched off
+/// through `colored` itself).
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Writes `text` to stdout as it is, for output other tools read. A reader
+/// that stops early (as `head` does) ends the process quietly; any other
+/// failure to write exits with an error.
+fn write_stdout(text: &str) {
+    let mut stdout = io::stdout().lock();
+    match io::Write::write_all(&mut stdout, text.as_bytes()).and_then(|()| io::Write::flush(&mut stdout)) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(error) => {
+            stderr_line(format!("Failed to write to stdout: {}", error).bright_red());
+            std::process::exit(4);
+        }
+    }
+}
+
+/// Reports a file that couldn't be read as a diagnostic, with no source
+/// to point into.
+fn report_read_error(error: &ValyrianError) {
+    stderr_line(error.to_diagnostic().render("", "").bright_red());
+}
+
+fn stdout_line(text: ColoredString) {
+    if PLAIN.load(Ordering::Relaxed) {
+        println!("{}", strip_emoji(&text));
+    } else {
+        println!("{}", text);
+    }
+}
+
+fn stderr_line(text: ColoredString) {
+    if PLAIN.load(Ordering::Relaxed) {
+        eprintln!("{}", strip_emoji(&text));
+    } else {
+        eprintln!("{}", text);
+    }
+}
+
 fn print_banner() {
     println!(
         "{}",
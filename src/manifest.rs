@@ -0,0 +1,174 @@
+//! 📜 Project manifests.
+//!
+//! A project is a directory with a `valyrian.toml` at its root:
+//!
+//! ```toml
+//! [project]
+//! name = "kings-landing"
+//! entry = "src/main.mv"      # defaults to main.mv
+//! sources = ["src", "lib"]   # defaults to the project root
+//!
+//! [run]
+//! strict = true
+//! deny-warnings = true
+//! debug = false
+//! args = ["dragons"]
+//!
+//! [dependencies]
+//! westeros = "../shared/westeros"   # a .mv file or a directory of them
+//! ```
+//!
+//! Paths are relative to the directory holding the manifest.
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use toml::{ Table, Value as TomlValue };
+use crate::error::ValyrianError;
+
+/// The name of the manifest file at the root of a project.
+pub const MANIFEST_FILE: &str = "valyrian.toml";
+
+/// The directory, under the project root, that dependencies are vendored into.
+pub const MODULES_DIR: &str = "modules";
+
+/// A parsed `valyrian.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// The directory holding the manifest.
+    pub root: PathBuf,
+    pub name: Option<String>,
+    /// The file run by `mid_valyrian run`, resolved against `root`.
+    pub entry: PathBuf,
+    /// Directories holding the project's modules, resolved against `root`.
+    /// `mid_valyrian run` links their files with the entry, and
+    /// `mid_valyrian test` looks for trials in them.
+    pub sources: Vec<PathBuf>,
+    pub strict: bool,
+    pub deny_warnings: bool,
+    pub debug: bool,
+    /// Arguments passed to the program.
+    pub args: Vec<String>,
+    /// Shared libraries by name, with the path they are copied from,
+    /// resolved against `root`.
+    pub dependencies: Vec<(String, PathBuf)>,
+}
+
+impl Manifest {
+    /// Looks for a manifest in `start` and each of its ancestors, returning
+    /// the path of the first one found.
+    pub fn find(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(MANIFEST_FILE))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// The directory dependencies are vendored into.
+    pub fn modules_dir(&self) -> PathBuf {
+        self.root.join(MODULES_DIR)
+    }
+
+    /// Reads and parses the manifest at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError` if the file cannot be read or is not a valid manifest.
+    pub fn load(path: &Path) -> Result<Self, ValyrianError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            ValyrianError::io(format!("Failed to read manifest '{}': {}", path.display(), e), e)
+        })?;
+        let root = path.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        Self::parse(&text, root)
+    }
+
+    /// Parses manifest text, resolving paths against `root`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::ManifestError` if the text is not a valid manifest.
+    pub fn parse(text: &str, root: PathBuf) -> Result<Self, ValyrianError> {
+        let table: Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| ValyrianError::ManifestError(e.message().to_string()))?;
+
+        let empty = Table::new();
+        let project = section(&table, "project")?.unwrap_or(&empty);
+        let run = section(&table, "run")?.unwrap_or(&empty);
+        let dependencies = section(&table, "dependencies")?
+            .unwrap_or(&empty)
+            .iter()
+            .map(|(name, path)| match path {
+                TomlValue::String(path) => Ok((name.clone(), root.join(path))),
+                _ =>
+                    Err(
+                        ValyrianError::ManifestError(
+                            format!("`dependencies.{}` must be a path", name)
+                        )
+                    ),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let name = string(project, "project.name")?;
+        let entry = string(project, "project.entry")?.unwrap_or_else(|| "main.mv".to_string());
+        let sources = match strings(project, "project.sources")? {
+            Some(sources) => sources.iter().map(|dir| root.join(dir)).collect(),
+            None => vec![root.clone()],
+        };
+
+        Ok(Manifest {
+            entry: root.join(entry),
+            sources,
+            name,
+            strict: boolean(run, "run.strict")?,
+            deny_warnings: boolean(run, "run.deny-warnings")?,
+            debug: boolean(run, "run.debug")?,
+            args: strings(run, "run.args")?.unwrap_or_default(),
+            dependencies,
+            root,
+        })
+    }
+}
+
+fn section<'a>(table: &'a Table, name: &str) -> Result<Option<&'a Table>, ValyrianError> {
+    match table.get(name) {
+        None => Ok(None),
+        Some(TomlValue::Table(section)) => Ok(Some(section)),
+        Some(_) => Err(ValyrianError::ManifestError(format!("`{}` must be a table", name))),
+    }
+}
+
+/// Looks up `key` (written as `section.field` for messages) in a section.
+fn field<'a>(table: &'a Table, key: &str) -> Option<&'a TomlValue> {
+    let field = key.rsplit('.').next().unwrap_or(key);
+    table.get(field)
+}
+
+fn string(table: &Table, key: &str) -> Result<Option<String>, ValyrianError> {
+    match field(table, key) {
+        None => Ok(None),
+        Some(TomlValue::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(ValyrianError::ManifestError(format!("`{}` must be a string", key))),
+    }
+}
+
+fn boolean(table: &Table, key: &str) -> Result<bool, ValyrianError> {
+    match field(table, key) {
+        None => Ok(false),
+        Some(TomlValue::Boolean(b)) => Ok(*b),
+        Some(_) => Err(ValyrianError::ManifestError(format!("`{}` must be true or false", key))),
+    }
+}
+
+fn strings(table: &Table, key: &str) -> Result<Option<Vec<String>>, ValyrianError> {
+    let not_strings = || ValyrianError::ManifestError(format!("`{}` must be a list of strings", key));
+    match field(table, key) {
+        None => Ok(None),
+        Some(TomlValue::Array(items)) =>
+            items
+                .iter()
+                .map(|item| item.as_str().map(String::from).ok_or_else(not_strings))
+                .collect::<Result<_, _>>()
+                .map(Some),
+        Some(_) => Err(not_strings()),
+    }
+}
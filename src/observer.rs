@@ -0,0 +1,55 @@
+//! 🐦‍⬛ Watching a program run.
+//!
+//! An `ExecutionObserver` is told about every statement the interpreter
+//! executes and every call it makes, which is enough to build profilers,
+//! coverage tools, visualizers, and step debuggers on top of the
+//! interpreter. Observers are added with `Interpreter::add_observer`; to
+//! read what one collected, share its state with the host (an
+//! `Arc<Mutex<_>>`, a channel) before handing it over.
+//!
+//! ```
+//! use std::sync::{ Arc, Mutex };
+//! use mid_valyrian::{ parse_program, ExecutionObserver, Interpreter, Value };
+//!
+//! #[derive(Default)]
+//! struct CallCounter(Arc<Mutex<Vec<String>>>);
+//!
+//! impl ExecutionObserver for CallCounter {
+//!     fn on_call(&mut self, name: &str, _arguments: &[Value], _call_site: &mid_valyrian::Span) {
+//!         self.0.lock().unwrap().push(name.to_string());
+//!     }
+//! }
+//!
+//! let calls = Arc::new(Mutex::new(Vec::new()));
+//! let mut interpreter = Interpreter::new(false);
+//! interpreter.add_observer(CallCounter(calls.clone()));
+//!
+//! let source = "we declare double with x ->\ncouncil says:\n    return x * 2\n\nfour is a blade with double with 2\n";
+//! interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+//! assert_eq!(*calls.lock().unwrap(), ["double"]);
+//! ```
+
+use crate::ast::{ Span, Statement, Value };
+use crate::error::ValyrianError;
+
+/// Hooks the interpreter invokes as a program runs. Every method does
+/// nothing by default, so observers only implement what they need.
+pub trait ExecutionObserver: Send {
+    /// Called before each statement runs, including statements inside
+    /// function bodies and blocks.
+    fn on_statement(&mut self, statement: &Statement, span: &Span) {
+        let _ = (statement, span);
+    }
+
+    /// Called when a function is entered, after its arguments have been
+    /// evaluated. Covers declared functions, natives, and builtins.
+    fn on_call(&mut self, name: &str, arguments: &[Value], call_site: &Span) {
+        let _ = (name, arguments, call_site);
+    }
+
+    /// Called when the function last entered with `on_call` finishes,
+    /// whether it returned a value or failed.
+    fn on_return(&mut self, name: &str, result: &Result<Value, ValyrianError>) {
+        let _ = (name, result);
+    }
+}
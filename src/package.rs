@@ -0,0 +1,166 @@
+//! 📦 Sharing .mv libraries between projects.
+//!
+//! A dependency is a `.mv` file or a directory of them, listed under
+//! `[dependencies]` in `valyrian.toml`. Vendoring copies every dependency
+//! into the project's `modules/` directory, so a project carries its
+//! libraries with it: a file becomes `modules/<name>.mv`, a directory
+//! becomes `modules/<name>/`.
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use toml::Value as TomlValue;
+use crate::error::ValyrianError;
+use crate::manifest::{ Manifest, MANIFEST_FILE };
+
+/// Records `source` as a dependency called `name` in the manifest at
+/// `manifest_path`, then vendors it. Returns where it was copied to.
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if `source` is not a library, the name is
+/// already taken, or a file cannot be read or written.
+pub fn add(manifest_path: &Path, name: &str, source: &Path) -> Result<PathBuf, ValyrianError> {
+    if !is_valid_name(name) {
+        return Err(
+            ValyrianError::ManifestError(
+                format!("'{}' is not a valid dependency name (use letters, digits, - and _)", name)
+            )
+        );
+    }
+    let manifest = Manifest::load(manifest_path)?;
+    if manifest.dependencies.iter().any(|(existing, _)| existing == name) {
+        return Err(
+            ValyrianError::ManifestError(format!("the project already depends on '{}'", name))
+        );
+    }
+    let source = if source.is_absolute() {
+        source.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(source)
+    };
+    if !is_library(&source) {
+        return Err(
+            ValyrianError::ManifestError(
+                format!("'{}' is neither a .mv file nor a directory", source.display())
+            )
+        );
+    }
+
+    let text = fs::read_to_string(manifest_path).map_err(|e| {
+        ValyrianError::io(format!("Failed to read manifest '{}': {}", manifest_path.display(), e), e)
+    })?;
+    let path = relative_to(&source, &manifest.root).display().to_string();
+    let entry = format!("{} = {}", name, TomlValue::String(path));
+    fs::write(manifest_path, add_dependency_line(&text, &entry)).map_err(|e| {
+        ValyrianError::io(format!("Failed to write {}: {}", MANIFEST_FILE, e), e)
+    })?;
+
+    vendor_one(&manifest.modules_dir(), name, &source)
+}
+
+/// Copies every dependency of the project into its modules directory,
+/// replacing any earlier copies. Returns the vendored paths.
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if a dependency is missing or cannot be copied.
+pub fn vendor(manifest: &Manifest) -> Result<Vec<PathBuf>, ValyrianError> {
+    let modules = manifest.modules_dir();
+    manifest.dependencies
+        .iter()
+        .map(|(name, source)| vendor_one(&modules, name, source))
+        .collect()
+}
+
+fn vendor_one(modules: &Path, name: &str, source: &Path) -> Result<PathBuf, ValyrianError> {
+    let copy_error = |e: std::io::Error| {
+        let message = format!("Failed to vendor '{}' from '{}': {}", name, source.display(), e);
+        ValyrianError::io(message, e)
+    };
+    fs::create_dir_all(modules).map_err(copy_error)?;
+
+    if source.is_dir() {
+        let target = modules.join(name);
+        if target.exists() {
+            fs::remove_dir_all(&target).map_err(copy_error)?;
+        }
+        copy_modules(source, &target).map_err(copy_error)?;
+        Ok(target)
+    } else if is_library(source) {
+        let target = modules.join(format!("{}.mv", name));
+        fs::copy(source, &target).map_err(copy_error)?;
+        Ok(target)
+    } else {
+        Err(
+            ValyrianError::ManifestError(
+                format!(
+                    "dependency '{}' points at '{}', which is not a library",
+                    name,
+                    source.display()
+                )
+            )
+        )
+    }
+}
+
+/// Recursively copies the `.mv` files under `source` into `target`.
+fn copy_modules(source: &Path, target: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let path = entry?.path();
+        let destination = target.join(path.file_name().unwrap_or_default());
+        if path.is_dir() {
+            copy_modules(&path, &destination)?;
+        } else if path.extension().is_some_and(|ext| ext == "mv") {
+            fs::copy(&path, &destination)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_library(path: &Path) -> bool {
+    path.is_dir() || (path.is_file() && path.extension().is_some_and(|ext| ext == "mv"))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Expresses `path` relative to `base` when it lies inside it, so manifests
+/// stay portable; other paths are kept as they are.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let base = base.canonicalize().unwrap_or_else(|_| base.to_path_buf());
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.strip_prefix(&base).map_or(path.clone(), Path::to_path_buf)
+}
+
+/// Inserts `entry` into the `[dependencies]` table of manifest text,
+/// creating the table if needed. Editing the text rather than re-encoding
+/// the parsed table keeps the user's comments and layout.
+fn add_dependency_line(text: &str, entry: &str) -> String {
+    let mut lines: Vec<&str> = text.lines().collect();
+    match lines.iter().position(|line| line.trim() == "[dependencies]") {
+        Some(header) => {
+            // Insert after the last non-blank line of the table
+            let end = lines[header + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map_or(lines.len(), |offset| header + 1 + offset);
+            let mut at = end;
+            while at > header + 1 && lines[at - 1].trim().is_empty() {
+                at -= 1;
+            }
+            lines.insert(at, entry);
+        }
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push("");
+            }
+            lines.push("[dependencies]");
+            lines.push(entry);
+        }
+    }
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
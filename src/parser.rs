@@ -1,75 +1,364 @@
 use pest::Parser;
 use pest_derive::Parser;
 use crate::ast::*;
+use crate::edition::Edition;
 use crate::error::ValyrianError;
+use crate::intern::Interner;
+use crate::trivia::Trivia;
 
 #[derive(Parser)]
 #[grammar = "mid_valyrian.pest"]
 pub struct MidValyrianParser;
 
+/// How deeply blocks and parentheses may nest, counted together. The
+/// grammar recurses once per level, so deeper programs are refused up
+/// front instead of running the parser out of stack.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, MAX_NESTING_DEPTH };
+///
+/// let nested = |depth| format!("speak {}1{}\n", "(".repeat(depth), ")".repeat(depth));
+/// assert!(parse_program(&nested(MAX_NESTING_DEPTH)).is_ok());
+/// assert!(parse_program(&nested(MAX_NESTING_DEPTH + 1)).is_err());
+/// ```
+pub const MAX_NESTING_DEPTH: usize = 100;
+
+/// Stack for threads that parse, enough to reach `MAX_NESTING_DEPTH` in a
+/// debug build.
+pub(crate) const PARSER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Parses a whole program. A statement ends with its line, and a block is
+/// the lines after its opening line that are indented deeper, all at the
+/// indentation of the first.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, Interpreter };
+///
+/// // Dividing by zero would fail, so only the right branches may run
+/// let source = "on the iron throne:\n    if 2.5 > 2:\n        speak \"deeper\"\n        if 1 < 0.5:\n            speak 1 / 0\n    else:\n        speak 1 / 0\n    speak \"done\"\n";
+/// let program = parse_program(source).unwrap();
+/// Interpreter::new(false).interpret(&program).unwrap();
+///
+/// // A line indented deeper than its block belongs to no block
+/// assert!(parse_program("on the iron throne:\n    speak 1\n      speak 2\n").is_err());
+/// ```
 pub fn parse_program(input: &str) -> Result<Program, ValyrianError> {
+    parse_source(input, None)
+}
+
+/// Parses part of a program written in `edition`, such as the statements
+/// an edit touched, which can't have a `canon` line of its own.
+pub(crate) fn parse_program_in(input: &str, edition: Edition) -> Result<Program, ValyrianError> {
+    parse_source(input, Some(edition))
+}
+
+fn parse_source(input: &str, edition: Option<Edition>) -> Result<Program, ValyrianError> {
+    check_nesting(input)?;
     let pairs = MidValyrianParser::parse(Rule::program, input).map_err(|e|
-        ValyrianError::ParseError(format!("The Maester failed to decipher your scroll: {}", e))
+        ValyrianError::from(e.renamed_rules(describe_rule))
     )?;
 
+    let mut builder = TreeBuilder::new(input);
+    builder.edition = edition.unwrap_or_default();
     let mut statements = Vec::new();
     for pair in pairs {
         if pair.as_rule() == Rule::program {
-            for inner in pair.into_inner().filter(|p| p.as_rule() == Rule::statement) {
-                statements.push(parse_statement(inner)?);
+            for inner in pair.into_inner() {
+                match inner.as_rule() {
+                    Rule::canon => {
+                        builder.edition = canon_edition(inner, edition.is_none())?;
+                    }
+                    Rule::statement => statements.push(parse_statement(inner, &mut builder)?),
+                    _ => {}
+                }
             }
         }
     }
 
-    Ok(Program { statements })
+    Ok(Program { statements, edition: builder.edition })
 }
 
-fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Statement, ValyrianError> {
-    let inner = pair
-        .into_inner()
-        .next()
-        .ok_or_else(|| ValyrianError::ParseError("Empty statement found in the scroll".into()))?;
+/// The edition a `canon` line names, if it is `allowed` there.
+fn canon_edition(pair: pest::iterators::Pair<Rule>, allowed: bool) -> Result<Edition, ValyrianError> {
+    let year = pair.into_inner().next().expect("a canon line has a year");
+    let position = year.as_span().start_pos();
+    let year = year.as_str();
+    let message = match year.parse().ok().and_then(Edition::from_year) {
+        Some(edition) if allowed => {
+            return Ok(edition);
+        }
+        Some(_) => "a canon line only goes at the top of a file".to_string(),
+        None => {
+            let years: Vec<String> = Edition::ALL.iter().map(|edition| edition.year().to_string()).collect();
+            format!("there is no canon {}; the editions are {}", year, years.join(", "))
+        }
+    };
+    Err(
+        ValyrianError::from(
+            pest::error::Error::new_from_pos(pest::error::ErrorVariant::CustomError { message }, position)
+        )
+    )
+}
 
-    match inner.as_rule() {
-        Rule::main_block => {
-            // inner contains NEWLINEs, WHITESPACE, and one block pair
-            let mut body = Vec::new();
+/// Parses a program like `parse_program`, also gathering the comments and
+/// blank lines around its statements; see `trivia`.
+pub fn parse_program_with_trivia(input: &str) -> Result<(Program, Trivia), ValyrianError> {
+    let program = parse_program(input)?;
+    let trivia = Trivia::collect(input, &program);
+    Ok((program, trivia))
+}
 
-            for p in inner.into_inner() {
-                match p.as_rule() {
-                    Rule::block => {
-                        // The block contains statements, comments, and newlines
-                        for inner_p in p.into_inner() {
-                            if inner_p.as_rule() == Rule::statement {
-                                body.push(parse_statement(inner_p)?);
-                            }
-                            // ignore comments and newlines here
-                        }
+/// A chunk of input typed at the REPL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplInput {
+    /// A bare expression whose value should be shown.
+    Expression(Expression),
+    /// One or more statements to execute.
+    Statements(Vec<Statement>),
+}
+
+/// Parses REPL input, which is either a single expression or a fragment of
+/// a program made of top-level statements.
+pub fn parse_repl_input(input: &str) -> Result<ReplInput, ValyrianError> {
+    if let Ok(expression) = parse_expression_source(input.trim()) {
+        return Ok(ReplInput::Expression(expression));
+    }
+
+    let program = parse_program(input)?;
+    Ok(ReplInput::Statements(program.statements))
+}
+
+/// Parses source text consisting of a single expression, such as
+/// `dragons * 2 + 1`. Trailing whitespace and newlines are ignored.
+pub fn parse_expression_source(input: &str) -> Result<Expression, ValyrianError> {
+    check_nesting(input)?;
+    let mut pairs = MidValyrianParser::parse(Rule::expression_input, input.trim_end()).map_err(|e|
+        ValyrianError::from(e.renamed_rules(describe_rule))
+    )?;
+    let expr = pairs.next().and_then(|p| p.into_inner().next()).ok_or_else(||
+        ValyrianError::ParseError("Empty expression".into())
+    )?;
+    parse_expression(expr, &mut TreeBuilder::new(input))
+}
+
+/// Fails with a syntax error where `input` first nests deeper than
+/// `MAX_NESTING_DEPTH`, counting indented blocks the way the grammar does
+/// and skipping text inside literals and comments.
+fn check_nesting(input: &str) -> Result<(), ValyrianError> {
+    let mut indents = vec![0];
+    let mut in_string = false;
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let mut depth = indents.len() - 1;
+        let mut chars = line.char_indices();
+        if !in_string {
+            let code = line.trim_start_matches([' ', '\t']);
+            if code.trim().is_empty() || code.starts_with("//") {
+                continue;
+            }
+            let indent = line.len() - code.len();
+            while indent < *indents.last().unwrap() {
+                indents.pop();
+            }
+            if indent > *indents.last().unwrap() {
+                indents.push(indent);
+            }
+            depth = indents.len() - 1;
+            if depth > MAX_NESTING_DEPTH {
+                return Err(nested_too_deeply(input, start + indent));
+            }
+        }
+        while let Some((i, c)) = chars.next() {
+            if in_string {
+                match c {
+                    '\\' => {
+                        chars.next();
                     }
-                    _ => {
-                        // ignore other tokens (e.g. NEWLINE, WHITESPACE)
+                    '"' => {
+                        in_string = false;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '(' => {
+                    depth += 1;
+                    if depth > MAX_NESTING_DEPTH {
+                        return Err(nested_too_deeply(input, start + i));
                     }
                 }
+                ')' => {
+                    depth = depth.saturating_sub(1);
+                }
+                '"' => {
+                    in_string = true;
+                }
+                '\'' => {
+                    chars.nth(1);
+                }
+                '/' if line[i..].starts_with("//") => {
+                    break;
+                }
+                _ => {}
             }
+        }
+    }
+    Ok(())
+}
+
+fn nested_too_deeply(input: &str, offset: usize) -> ValyrianError {
+    let message = format!(
+        "blocks and parentheses nest more than {} deep here",
+        MAX_NESTING_DEPTH
+    );
+    let position = pest::Position::new(input, offset).expect("offset is on a char boundary");
+    ValyrianError::from(
+        pest::error::Error::new_from_pos(pest::error::ErrorVariant::CustomError { message }, position)
+    )
+}
+
+/// Gives grammar rules the names a student would recognize in error messages.
+fn describe_rule(rule: &Rule) -> String {
+    match rule {
+        Rule::statement => "a statement".into(),
+        Rule::block => "an indented block".into(),
+        Rule::expression | Rule::binary_expr | Rule::unary_expr | Rule::primary =>
+            "an expression".into(),
+        Rule::binary_op => "an operator".into(),
+        Rule::identifier => "a name".into(),
+        Rule::data_type => "a type (scroll, blade, wine, vow, sigil, raven, rookery, vault, tidings, missive, socket or void)".into(),
+        Rule::integer_literal => "a whole number".into(),
+        Rule::string_literal => "a scroll literal".into(),
+        Rule::parameter_list => "parameters".into(),
+        Rule::argument_list => "arguments".into(),
+        Rule::canon_year => "an edition year".into(),
+        Rule::EOI => "the end of the scroll".into(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Parses the statements of a `block` or `flat_block` pair.
+fn parse_block(
+    pair: pest::iterators::Pair<Rule>,
+    builder: &mut TreeBuilder<'_>
+) -> Result<Vec<Statement>, ValyrianError> {
+    pair.into_inner()
+        .filter(|p| p.as_rule() == Rule::statement)
+        .map(|statement| parse_statement(statement, builder))
+        .collect()
+}
+
+/// Finds the block among the children of a compound statement and parses it.
+fn parse_body(
+    pairs: pest::iterators::Pairs<Rule>,
+    context: &str,
+    builder: &mut TreeBuilder<'_>
+) -> Result<Vec<Statement>, ValyrianError> {
+    for p in pairs {
+        if matches!(p.as_rule(), Rule::block | Rule::flat_block) {
+            return parse_block(p, builder);
+        }
+    }
+    Err(ValyrianError::ParseError(format!("Missing body for {}", context)))
+}
+
+fn parse_arguments(
+    pairs: pest::iterators::Pairs<Rule>,
+    builder: &mut TreeBuilder<'_>
+) -> Result<Vec<Expression>, ValyrianError> {
+    let mut arguments = Vec::new();
+    for p in pairs {
+        if p.as_rule() == Rule::argument_list {
+            for arg in p.into_inner().filter(|a| a.as_rule() == Rule::expression) {
+                arguments.push(parse_expression(arg, builder)?);
+            }
+        }
+    }
+    Ok(arguments)
+}
 
-            Ok(Statement::MainBlock(body))
+/// What the parse functions share while turning one input into a tree.
+struct TreeBuilder<'i> {
+    input: &'i str,
+    interner: Interner,
+    /// Byte offset at which each line starts. pest works out a position's
+    /// line by rescanning the input from the top, which made parsing
+    /// quadratic in the length of the program.
+    line_starts: Vec<usize>,
+    /// How operators group.
+    edition: Edition,
+}
+
+impl<'i> TreeBuilder<'i> {
+    fn new(input: &'i str) -> Self {
+        let line_starts = std::iter
+            ::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { input, interner: Interner::new(), line_starts, edition: Edition::default() }
+    }
+
+    fn intern(&mut self, text: &str) -> Symbol {
+        self.interner.intern(text)
+    }
+
+    /// Converts a pest span into an AST span.
+    fn span(&self, pair: &pest::iterators::Pair<Rule>) -> Span {
+        let span = pair.as_span();
+        let line = self.line_starts.partition_point(|&start| start <= span.start());
+        let line_start = self.line_starts[line - 1];
+        let col = self.input[line_start..span.start()].chars().count() + 1;
+        Span::new(span.start(), span.end(), line, col)
+    }
+}
+
+fn parse_statement(
+    pair: pest::iterators::Pair<Rule>,
+    builder: &mut TreeBuilder<'_>
+) -> Result<Statement, ValyrianError> {
+    let span = builder.span(&pair);
+    let kind = parse_statement_kind(pair, builder)?;
+    Ok(Statement::new(kind, span))
+}
+
+fn parse_statement_kind(
+    pair: pest::iterators::Pair<Rule>,
+    builder: &mut TreeBuilder<'_>
+) -> Result<StatementKind, ValyrianError> {
+    let inner = pair
+        .into_inner()
+        .next()
+        .ok_or_else(|| ValyrianError::ParseError("Empty statement found in the scroll".into()))?;
+
+    match inner.as_rule() {
+        Rule::main_block => {
+            let body = parse_body(inner.into_inner(), "the iron throne", builder)?;
+            Ok(StatementKind::MainBlock(body))
         }
 
         Rule::variable_declaration => {
             let mut inner_rules = inner.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
+            let name = builder.intern(inner_rules.next().unwrap().as_str());
             let data_type_str = inner_rules.next().unwrap().as_str();
-            let value_expr = inner_rules
+            let value = inner_rules
                 .next()
-                .ok_or_else(|| {
-                    ValyrianError::ParseError("Missing expression in variable declaration".into())
-                })?;
-            let value = parse_expression(value_expr)?;
+                .map(|value| parse_expression(value, builder))
+                .transpose()?;
             let data_type = DataType::from_str(data_type_str).ok_or_else(|| {
                 ValyrianError::ParseError(format!("Unknown type: {}", data_type_str))
             })?;
-            Ok(Statement::VariableDeclaration {
-                name,
+            if value.is_none() && !data_type.has_default_value() {
+                return Err(
+                    ValyrianError::ParseError(
+                        format!("'{}' needs a value: a {} has no default to start at", name, data_type)
+                    )
+                );
+            }
+            Ok(StatementKind::VariableDeclaration {
+                name: name.into(),
                 data_type,
                 value,
             })
@@ -77,79 +366,63 @@ fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Valyr
 
         Rule::function_declaration => {
             let mut inner_rules = inner.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
+            let name = builder.intern(inner_rules.next().unwrap().as_str());
 
             // Collect parameters from the appropriate pair (should be first after name)
             let params_pair = inner_rules.next().unwrap();
             let parameters = params_pair
                 .into_inner()
                 .filter(|p| p.as_rule() == Rule::identifier)
-                .map(|p| p.as_str().to_string())
+                .map(|p| builder.intern(p.as_str()))
                 .collect::<Vec<_>>();
 
-            // The rest are statements (body)
-            let body = inner_rules
-                .filter(|p| p.as_rule() == Rule::statement)
-                .map(parse_statement)
-                .collect::<Result<Vec<_>, _>>()?;
+            let body = parse_body(inner_rules, &format!("function '{}'", name), builder)?;
 
-            Ok(Statement::FunctionDeclaration {
+            Ok(StatementKind::FunctionDeclaration {
                 name,
                 parameters,
                 body,
             })
         }
 
-        Rule::function_call => {
-            let mut inner_rules = inner.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
-            let arguments = inner_rules
-                .filter(|p| p.as_rule() == Rule::expression)
-                .map(parse_expression)
-                .collect::<Result<Vec<_>, _>>()?;
-            Ok(Statement::FunctionCall { name, arguments })
+        Rule::function_call_stmt => {
+            let call = inner.into_inner().next().unwrap();
+            let mut inner_rules = call.into_inner();
+            let name = builder.intern(inner_rules.next().unwrap().as_str());
+            let arguments = parse_arguments(inner_rules, builder)?;
+            Ok(StatementKind::FunctionCall { name, arguments })
         }
 
         Rule::assignment => {
             let mut inner_rules = inner.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
-            let value = parse_expression(inner_rules.next().unwrap())?;
-            Ok(Statement::Assignment { name, value })
+            let name = builder.intern(inner_rules.next().unwrap().as_str());
+            let value = parse_expression(inner_rules.next().unwrap(), builder)?;
+            Ok(StatementKind::Assignment { name: name.into(), value })
         }
 
         Rule::conditional => {
             let mut inner_rules = inner.into_inner();
-            let condition = parse_expression(inner_rules.next().unwrap())?;
+            let condition = parse_expression(inner_rules.next().unwrap(), builder)?;
 
             let mut then_branch = Vec::new();
-            let mut else_branch = Vec::new();
-            let mut in_else = false;
+            let mut else_branch = None;
 
-            for stmt in inner_rules {
-                match stmt.as_rule() {
-                    Rule::ELSE => {
-                        in_else = true;
+            for p in inner_rules {
+                match p.as_rule() {
+                    Rule::block => {
+                        then_branch = parse_block(p, builder)?;
                     }
-                    Rule::statement => {
-                        let parsed = parse_statement(stmt)?;
-                        if in_else {
-                            else_branch.push(parsed);
-                        } else {
-                            then_branch.push(parsed);
-                        }
+                    Rule::else_clause => {
+                        else_branch = Some(parse_body(p.into_inner(), "else", builder)?);
                     }
                     _ => {}
                 }
             }
 
-            Ok(Statement::Conditional {
+            Ok(StatementKind::Conditional {
                 condition,
                 then_branch,
-                else_branch: if else_branch.is_empty() {
-                    None
-                } else {
-                    Some(else_branch)
-                },
+                else_branch,
             })
         }
 
@@ -161,21 +434,30 @@ fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Valyr
                 .as_str()
                 .parse::<i64>()
                 .map_err(|_| ValyrianError::ParseError("Invalid loop count".into()))?;
-            let body = inner_rules
-                .filter(|p| p.as_rule() == Rule::statement)
-                .map(parse_statement)
-                .collect::<Result<Vec<_>, _>>()?;
-            Ok(Statement::ForLoop { count, body })
+            let body = parse_body(inner_rules, "the realm's march", builder)?;
+            Ok(StatementKind::ForLoop { count, body })
         }
 
         Rule::while_loop => {
             let mut inner_rules = inner.into_inner();
-            let condition = parse_expression(inner_rules.next().unwrap())?;
-            let body = inner_rules
-                .filter(|p| p.as_rule() == Rule::statement)
-                .map(parse_statement)
-                .collect::<Result<Vec<_>, _>>()?;
-            Ok(Statement::WhileLoop { condition, body })
+            let condition = parse_expression(inner_rules.next().unwrap(), builder)?;
+            let body = parse_body(inner_rules, "while", builder)?;
+            Ok(StatementKind::WhileLoop { condition, body })
+        }
+
+        Rule::trial => {
+            let mut inner_rules = inner.into_inner();
+            let name = inner_rules.next().unwrap().into_inner().next().unwrap().as_str();
+            let body = parse_body(inner_rules, "trial", builder)?;
+            Ok(StatementKind::Trial { name: unescape(name), body })
+        }
+
+        Rule::return_statement => {
+            let value = match inner.into_inner().next() {
+                Some(expr) => Some(parse_expression(expr, builder)?),
+                None => None,
+            };
+            Ok(StatementKind::Return(value))
         }
 
         Rule::speak_statement => {
@@ -183,7 +465,7 @@ fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Valyr
                 .into_inner()
                 .next()
                 .ok_or_else(|| ValyrianError::ParseError("speak() is empty".into()))?;
-            Ok(Statement::Speak(parse_expression(expr)?))
+            Ok(StatementKind::Speak(parse_expression(expr, builder)?))
         }
 
         _ =>
@@ -193,27 +475,27 @@ fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Valyr
     }
 }
 
-fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, ValyrianError> {
+fn parse_expression(
+    pair: pest::iterators::Pair<Rule>,
+    builder: &mut TreeBuilder<'_>
+) -> Result<Expression, ValyrianError> {
+    let span = builder.span(&pair);
     match pair.as_rule() {
-        Rule::expression => parse_expression(pair.into_inner().next().unwrap()),
+        Rule::expression => parse_expression(pair.into_inner().next().unwrap(), builder),
 
         Rule::binary_expr => {
             let mut inner = pair.into_inner();
-            let mut left = parse_expression(inner.next().unwrap())?;
+            let left = parse_expression(inner.next().unwrap(), builder)?;
 
+            let mut rest = Vec::new();
             while let Some(op) = inner.next() {
                 let operator = BinaryOperator::from_str(op.as_str()).ok_or_else(||
                     ValyrianError::ParseError(format!("Unknown binary operator: {}", op.as_str()))
                 )?;
-                let right = parse_expression(inner.next().unwrap())?;
-                left = Expression::Binary {
-                    left: Box::new(left),
-                    operator,
-                    right: Box::new(right),
-                };
+                rest.push((operator, parse_expression(inner.next().unwrap(), builder)?));
             }
 
-            Ok(left)
+            Ok(group_operators(left, &mut rest.into_iter().peekable(), 0, builder.edition))
         }
 
         // Rule::unary_expr => {
@@ -228,7 +510,7 @@ fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, Val
         //             );
         //         }
         //     };
-        //     let operand = parse_expression(inner.next().unwrap())?;
+        //     let operand = parse_expression(inner.next().unwrap(), builder)?;
         //     Ok(Expression::Unary {
         //         operator,
         //         operand: Box::new(operand),
@@ -236,42 +518,70 @@ fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, Val
         // }
 
         Rule::unary_expr => {
-            let mut inner = pair.into_inner();
+            let mut operators = Vec::new();
+            let mut operand = None;
 
-            // Peek at first token
-            let first = inner.next().unwrap();
-            let op = match first.as_rule() {
-                Rule::unary_op => {
-                    let op_str = first.as_str();
-                    let operator = match op_str {
-                        "-" => UnaryOperator::Minus,
-                        "!" => UnaryOperator::Not,
-                        _ => {
-                            return Err(
-                                ValyrianError::ParseError(
-                                    format!("Unknown unary operator: {}", op_str)
-                                )
-                            );
-                        }
-                    };
-                    let operand = parse_expression(inner.next().unwrap())?;
-                    return Ok(Expression::Unary {
-                        operator,
-                        operand: Box::new(operand),
-                    });
-                }
-                _ => {
-                    // no unary operator, so the whole unary_expr is just a primary
-                    // parse_expression on the pair directly
-                    return parse_expression(first);
+            for p in pair.into_inner() {
+                match p.as_rule() {
+                    Rule::unary_op => {
+                        let op_span = builder.span(&p);
+                        let operator = match p.as_str() {
+                            "-" => UnaryOperator::Minus,
+                            "!" => UnaryOperator::Not,
+                            op_str => {
+                                return Err(
+                                    ValyrianError::ParseError(
+                                        format!("Unknown unary operator: {}", op_str)
+                                    )
+                                );
+                            }
+                        };
+                        operators.push((operator, op_span));
+                    }
+                    _ => {
+                        operand = Some(parse_expression(p, builder)?);
+                    }
                 }
-            };
+            }
+
+            // Operators bind right-to-left: `-!x` is `-(!x)`
+            let mut expr = operand.ok_or_else(||
+                ValyrianError::ParseError("Unary operator without an operand".into())
+            )?;
+            for (operator, op_span) in operators.into_iter().rev() {
+                let span = op_span.to(expr.span);
+                expr = Expression::new(
+                    ExpressionKind::Unary {
+                        operator,
+                        operand: Box::new(expr),
+                    },
+                    span
+                );
+            }
+            Ok(expr)
         }
 
-        Rule::primary => parse_expression(pair.into_inner().next().unwrap()),
+        Rule::primary => parse_expression(pair.into_inner().next().unwrap(), builder),
 
-        Rule::string_literal =>
-            Ok(Expression::Literal(Literal::String(pair.as_str().trim_matches('"').to_string()))),
+        Rule::function_call => {
+            let mut inner = pair.into_inner();
+            let name = builder.intern(inner.next().unwrap().as_str());
+            let arguments = parse_arguments(inner, builder)?;
+            Ok(Expression::new(ExpressionKind::FunctionCall { name, arguments }, span))
+        }
+        Rule::raven_send => {
+            let call = parse_expression(pair.into_inner().nth(1).unwrap(), builder)?;
+            Ok(Expression::new(ExpressionKind::SendRaven(Box::new(call)), span))
+        }
+        Rule::raven_await => {
+            let raven = parse_expression(pair.into_inner().nth(1).unwrap(), builder)?;
+            Ok(Expression::new(ExpressionKind::AwaitRaven(Box::new(raven)), span))
+        }
+
+        Rule::string_literal => {
+            let raw = pair.into_inner().next().map(|p| p.as_str()).unwrap_or("");
+            Ok(Expression::new(ExpressionKind::Literal(Literal::String(builder.intern(&unescape(raw)))), span))
+        }
         Rule::integer_literal => {
             let value = pair
                 .as_str()
@@ -280,7 +590,7 @@ fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, Val
                 .map_err(|_|
                     ValyrianError::ParseError(format!("Invalid integer: {}", pair.as_str()))
                 )?;
-            Ok(Expression::Literal(Literal::Integer(value)))
+            Ok(Expression::new(ExpressionKind::Literal(Literal::Integer(value)), span))
         }
         Rule::float_literal => {
             let value = pair
@@ -290,7 +600,7 @@ fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, Val
                 .map_err(|_|
                     ValyrianError::ParseError(format!("Invalid float: {}", pair.as_str()))
                 )?;
-            Ok(Expression::Literal(Literal::Float(value)))
+            Ok(Expression::new(ExpressionKind::Literal(Literal::Float(value)), span))
         }
         Rule::boolean_literal => {
             let value = match pair.as_str() {
@@ -302,20 +612,27 @@ fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, Val
                     );
                 }
             };
-            Ok(Expression::Literal(Literal::Boolean(value)))
+            Ok(Expression::new(ExpressionKind::Literal(Literal::Boolean(value)), span))
         }
         Rule::char_literal => {
             let chars: Vec<char> = pair.as_str().chars().collect();
             if chars.len() < 3 {
                 return Err(ValyrianError::ParseError("Invalid character literal".into()));
             }
-            Ok(Expression::Literal(Literal::Char(chars[1])))
+            Ok(Expression::new(ExpressionKind::Literal(Literal::Char(chars[1])), span))
+        }
+        Rule::identifier => {
+            let name = builder.intern(pair.as_str());
+            Ok(Expression::new(ExpressionKind::Identifier(name.into()), span))
         }
-        Rule::identifier => Ok(Expression::Identifier(pair.as_str().to_string())),
 
         Rule::input_statement => {
             let name = pair.into_inner().next().unwrap().as_str().to_string();
-            Ok(Expression::Input(name))
+            Ok(Expression::new(ExpressionKind::Input(name), span))
+        }
+        Rule::ask_input => {
+            let prompt = parse_expression(pair.into_inner().nth(1).unwrap(), builder)?;
+            Ok(Expression::new(ExpressionKind::Ask(Box::new(prompt)), span))
         }
 
         _ =>
@@ -324,3 +641,52 @@ fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, Val
             ),
     }
 }
+
+/// Joins `left` with the operators and operands after it that bind at
+/// least as tightly as `min`, grouping them as `edition` does.
+fn group_operators(
+    mut left: Expression,
+    rest: &mut std::iter::Peekable<std::vec::IntoIter<(BinaryOperator, Expression)>>,
+    min: u8,
+    edition: Edition
+) -> Expression {
+    while let Some((operator, mut right)) = rest.next_if(|(operator, _)| edition.precedence(operator) >= min) {
+        let precedence = edition.precedence(&operator);
+        while rest.peek().is_some_and(|(next, _)| edition.precedence(next) > precedence) {
+            right = group_operators(right, rest, precedence + 1, edition);
+        }
+        let span = left.span.to(right.span);
+        left = Expression::new(
+            ExpressionKind::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+            span
+        );
+    }
+    left
+}
+
+/// Resolves the escape sequences allowed inside a string literal.
+fn unescape(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
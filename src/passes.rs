@@ -0,0 +1,281 @@
+//! 🔧 Rewriting programs before they run.
+//!
+//! An `AstPass` rewrites a parsed program in place without changing what
+//! it prints, returns, or fails with. A `PassPipeline` runs passes in the
+//! order they were added, each only from the `OptimizationLevel` it was
+//! registered at, so `-O0` runs the program as written and `-O1` runs the
+//! standard passes:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, to_source, OptimizationLevel, PassPipeline };
+//!
+//! let mut program = parse_program("if 2 > 1:\n    speak 6 * 7\n").unwrap();
+//! PassPipeline::standard().run(&mut program, OptimizationLevel::O1);
+//! assert_eq!(to_source(&program), "speak 42\n");
+//! ```
+//!
+//! Passes see the program after `analyze`, so warnings still describe the
+//! source as written. Fewer statements run, so step counts, statistics,
+//! and coverage reflect the rewritten program.
+
+use crate::ast::*;
+use crate::interpreter::Interpreter;
+use crate::visit::{ walk_expression_mut, VisitorMut };
+
+/// How hard to optimize a program before running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum OptimizationLevel {
+    /// Run the program as written.
+    #[default]
+    O0,
+    /// Fold constants and drop code that can never run.
+    O1,
+}
+
+impl OptimizationLevel {
+    /// Reads a level as given to `-O`: `0` or `1`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "0" => Some(OptimizationLevel::O0),
+            "1" => Some(OptimizationLevel::O1),
+            _ => None,
+        }
+    }
+}
+
+/// A rewrite of a whole program; see the module docs.
+pub trait AstPass: Send {
+    /// A short name for the pass, as listed by `PassPipeline::names`.
+    fn name(&self) -> &'static str;
+
+    /// Rewrites `program`, which must behave as it did before.
+    fn run(&mut self, program: &mut Program);
+}
+
+/// Passes to run in order, each from some optimization level up.
+#[derive(Default)]
+pub struct PassPipeline {
+    passes: Vec<(OptimizationLevel, Box<dyn AstPass>)>,
+}
+
+impl PassPipeline {
+    /// A pipeline without passes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The passes `-O1` runs: `ConstantFolding`, then
+    /// `DeadCodeElimination`, which can drop branches folding decided.
+    pub fn standard() -> Self {
+        Self::new()
+            .with_pass(OptimizationLevel::O1, ConstantFolding)
+            .with_pass(OptimizationLevel::O1, DeadCodeElimination)
+    }
+
+    /// Adds `pass` after the others, to run at `level` and above.
+    pub fn with_pass(mut self, level: OptimizationLevel, pass: impl AstPass + 'static) -> Self {
+        self.add_pass(level, pass);
+        self
+    }
+
+    /// Adds `pass` after the others, to run at `level` and above.
+    pub fn add_pass(&mut self, level: OptimizationLevel, pass: impl AstPass + 'static) {
+        self.passes.push((level, Box::new(pass)));
+    }
+
+    /// The names of the passes that would run at `level`, in order.
+    pub fn names(&self, level: OptimizationLevel) -> Vec<&'static str> {
+        self.passes
+            .iter()
+            .filter(|(from, _)| *from <= level)
+            .map(|(_, pass)| pass.name())
+            .collect()
+    }
+
+    /// Runs every pass enabled at `level` over `program`, in order.
+    pub fn run(&mut self, program: &mut Program, level: OptimizationLevel) {
+        for (from, pass) in &mut self.passes {
+            if *from <= level {
+                let _span = tracing::debug_span!("pass", name = pass.name()).entered();
+                pass.run(program);
+            }
+        }
+    }
+}
+
+/// Replaces operators applied to literals with their result, as the
+/// interpreter would compute it. Operations that would fail (or overflow)
+/// are left for the run to report.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, to_source, AstPass, ConstantFolding };
+///
+/// let mut program = parse_program("a is a blade with 2 * 3 + 1\nb is a blade with 1 / 0\n").unwrap();
+/// ConstantFolding.run(&mut program);
+/// assert_eq!(to_source(&program), "a is a blade with 7\nb is a blade with 1 / 0\n");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstantFolding;
+
+impl AstPass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant-folding"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        Folder { interpreter: Interpreter::new(false) }.visit_program_mut(program);
+    }
+}
+
+struct Folder {
+    /// Computes operators exactly as runs do.
+    interpreter: Interpreter,
+}
+
+impl VisitorMut for Folder {
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+        let folded = match &expression.kind {
+            ExpressionKind::Binary { left, operator, right } => {
+                match (constant(left), constant(right)) {
+                    (Some(left), Some(right)) =>
+                        self.interpreter.apply_binary_operator(operator, &left, &right).ok(),
+                    _ => None,
+                }
+            }
+            ExpressionKind::Unary { operator, operand } => {
+                constant(operand).and_then(|value| self.interpreter.apply_unary_operator(operator, &value).ok())
+            }
+            _ => None,
+        };
+        if let Some(literal) = folded.and_then(literal) {
+            expression.kind = ExpressionKind::Literal(literal);
+        }
+    }
+}
+
+/// The value of an expression that is a literal.
+fn constant(expression: &Expression) -> Option<Value> {
+    match &expression.kind {
+        ExpressionKind::Literal(Literal::String(s)) => Some(Value::String(s.clone())),
+        ExpressionKind::Literal(Literal::Integer(i)) => Some(Value::Integer(*i)),
+        ExpressionKind::Literal(Literal::Float(f)) => Some(Value::Float(*f)),
+        ExpressionKind::Literal(Literal::Boolean(b)) => Some(Value::Boolean(*b)),
+        ExpressionKind::Literal(Literal::Char(c)) => Some(Value::Char(*c)),
+        _ => None,
+    }
+}
+
+/// The literal for a computed value, if it has one.
+fn literal(value: Value) -> Option<Literal> {
+    match value {
+        Value::String(s) => Some(Literal::String(s)),
+        Value::Integer(i) => Some(Literal::Integer(i)),
+        Value::Float(f) => Some(Literal::Float(f)),
+        Value::Boolean(b) => Some(Literal::Boolean(b)),
+        Value::Char(c) => Some(Literal::Char(c)),
+        Value::Void | Value::External(_) | Value::Raven(_) | Value::Rookery(_) | Value::Vault(_) | Value::Tidings(_) | Value::Missive(_) | Value::Socket(_) => None,
+    }
+}
+
+/// Drops statements that can never run: branches of `if`s with a literal
+/// condition, loops that never iterate, and statements after a `return`.
+/// Function declarations and trials are always kept.
+///
+/// ```
+/// use mid_valyrian::{ parse_program, to_source, AstPass, DeadCodeElimination };
+///
+/// let source = "if nay:\n    speak 1\nelse:\n    speak 2\nwhile nay:\n    speak 3\n";
+/// let mut program = parse_program(source).unwrap();
+/// DeadCodeElimination.run(&mut program);
+/// assert_eq!(to_source(&program), "speak 2\n");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeadCodeElimination;
+
+impl AstPass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dead-code-elimination"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        eliminate(&mut program.statements);
+    }
+}
+
+fn eliminate(statements: &mut Vec<Statement>) {
+    let mut kept = Vec::with_capacity(statements.len());
+    let mut returned = false;
+    for mut statement in std::mem::take(statements) {
+        let keep_anyway = matches!(
+            statement.kind,
+            StatementKind::FunctionDeclaration { .. } | StatementKind::Trial { .. }
+        );
+        if returned && !keep_anyway {
+            continue;
+        }
+        match &mut statement.kind {
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
+                eliminate(then_branch);
+                if let Some(else_branch) = else_branch {
+                    eliminate(else_branch);
+                }
+                if let ExpressionKind::Literal(Literal::Boolean(taken)) = condition.kind {
+                    let branch = if taken {
+                        std::mem::take(then_branch)
+                    } else {
+                        else_branch.take().unwrap_or_default()
+                    };
+                    // Declarations run where they appear, so the branch is
+                    // only spliced in when that can't hoist one
+                    if !branch.iter().any(declares_function) {
+                        returned |= branch.iter().any(returns);
+                        kept.extend(branch);
+                        continue;
+                    }
+                    let (then_branch, else_branch) = if taken {
+                        (branch, None)
+                    } else {
+                        (Vec::new(), Some(branch))
+                    };
+                    statement.kind = StatementKind::Conditional {
+                        condition: condition.clone(),
+                        then_branch,
+                        else_branch,
+                    };
+                }
+            }
+            StatementKind::WhileLoop { condition, .. }
+                if matches!(condition.kind, ExpressionKind::Literal(Literal::Boolean(false))) => {
+                continue;
+            }
+            StatementKind::ForLoop { count, .. } if *count <= 0 => {
+                continue;
+            }
+            StatementKind::FunctionDeclaration { body, .. }
+            | StatementKind::ForLoop { body, .. }
+            | StatementKind::WhileLoop { body, .. }
+            | StatementKind::MainBlock(body)
+            | StatementKind::Trial { body, .. } => eliminate(body),
+            _ => {}
+        }
+        returned |= returns(&statement);
+        kept.push(statement);
+    }
+    *statements = kept;
+}
+
+fn declares_function(statement: &Statement) -> bool {
+    matches!(statement.kind, StatementKind::FunctionDeclaration { .. })
+}
+
+/// Whether `statement` always returns: it is a `return`, or a main block
+/// that ends in one.
+fn returns(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(_) => true,
+        StatementKind::MainBlock(body) => body.iter().any(returns),
+        _ => false,
+    }
+}
@@ -0,0 +1,155 @@
+//! 🧪 Native extensions loaded at startup.
+//!
+//! A plugin is a Rust library, built as a `cdylib`, that registers native
+//! functions (and the `Value::External` types they trade in) with an
+//! interpreter. The library implements `Plugin` and exports it with
+//! `declare_plugin!`:
+//!
+//! ```
+//! use mid_valyrian::{ declare_plugin, Interpreter, Plugin, Value };
+//!
+//! #[derive(Default)]
+//! struct Ravens;
+//!
+//! impl Plugin for Ravens {
+//!     fn name(&self) -> &str {
+//!         "ravens"
+//!     }
+//!
+//!     fn register(&self, interpreter: &mut Interpreter) {
+//!         interpreter.register_native("raven_count", |_| Ok(Value::Integer(3)));
+//!     }
+//! }
+//!
+//! declare_plugin!(Ravens::default());
+//! ```
+//!
+//! Hosts then call `load_plugin` (or pass `--plugin` to the CLI). Plugins
+//! talk to the interpreter through Rust types, so they must be built with
+//! the same compiler and the same version of this crate as the host.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::error::ValyrianError;
+use crate::interpreter::Interpreter;
+
+/// Bumped whenever the `Plugin` trait changes, so stale plugins are refused
+/// instead of crashing the host.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// The symbol `declare_plugin!` exports to report `PLUGIN_API_VERSION`.
+#[cfg(not(target_arch = "wasm32"))]
+const VERSION_SYMBOL: &[u8] = b"mid_valyrian_plugin_api_version";
+/// The symbol `declare_plugin!` exports to construct the plugin.
+#[cfg(not(target_arch = "wasm32"))]
+const CONSTRUCTOR_SYMBOL: &[u8] = b"mid_valyrian_plugin";
+
+/// An extension that adds functions to an interpreter.
+pub trait Plugin {
+    /// A short name for messages.
+    fn name(&self) -> &str;
+
+    /// Adds the plugin's natives (and globals) to `interpreter`.
+    fn register(&self, interpreter: &mut Interpreter);
+}
+
+/// Exports a plugin from a `cdylib` so `load_plugin` can find it. Takes an
+/// expression that builds the plugin.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($constructor:expr) => {
+        #[no_mangle]
+        pub extern "C" fn mid_valyrian_plugin_api_version() -> u32 {
+            $crate::plugin::PLUGIN_API_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "Rust" fn mid_valyrian_plugin() -> ::std::boxed::Box<dyn $crate::Plugin> {
+            ::std::boxed::Box::new($constructor)
+        }
+    };
+}
+
+/// Loads the plugin library at `path`. Not available on WebAssembly.
+///
+/// The library stays loaded until the process exits, since the functions
+/// it registers live in it.
+///
+/// # Safety
+///
+/// Opening the library runs its initialisers, and the plugin it hands back
+/// is a `Box<dyn Plugin>` made on the other side of a Rust ABI, which is
+/// not stable. The caller must make sure that `path` is a library built
+/// with `declare_plugin!` by the same compiler, against the same version
+/// of this crate, as the host. Only the plugin API version is checked;
+/// anything else is undefined behaviour.
+///
+/// # Errors
+///
+/// Returns `ValyrianError::PluginError` if the library cannot be opened,
+/// was not built with `declare_plugin!`, or targets another plugin API
+/// version.
+#[cfg(not(target_arch = "wasm32"))]
+pub unsafe fn load_plugin<P: AsRef<Path>>(path: P) -> Result<Box<dyn Plugin>, ValyrianError> {
+    let path = path.as_ref();
+    let plugin_error = |message: String| {
+        ValyrianError::PluginError(format!("'{}': {}", path.display(), message))
+    };
+
+    // SAFETY: the caller vouches for the library's initialisers
+    let library = unsafe { libloading::Library::new(path) }.map_err(|e| plugin_error(e.to_string()))?;
+
+    // SAFETY: the caller vouches that the library was built with
+    // `declare_plugin!`, which gives the symbols these signatures
+    let plugin = unsafe {
+        let version = library
+            .get::<extern "C" fn() -> u32>(VERSION_SYMBOL)
+            .map_err(|_| plugin_error("not a plugin (was it built with declare_plugin!?)".into()))?;
+        if version() != PLUGIN_API_VERSION {
+            return Err(
+                plugin_error(
+                    format!(
+                        "built for plugin API {}, but this interpreter speaks {}",
+                        version(),
+                        PLUGIN_API_VERSION
+                    )
+                )
+            );
+        }
+        let constructor = library
+            .get::<fn() -> Box<dyn Plugin>>(CONSTRUCTOR_SYMBOL)
+            .map_err(|e| plugin_error(e.to_string()))?;
+        constructor()
+    };
+
+    // Natives and external values registered by the plugin point into the
+    // library, so it must never be unloaded
+    std::mem::forget(library);
+    Ok(plugin)
+}
+
+impl Interpreter {
+    /// Lets `plugin` register its functions with this interpreter.
+    pub fn install_plugin(&mut self, plugin: &dyn Plugin) {
+        plugin.register(self);
+    }
+
+    /// Loads the plugin library at `path` and installs it; see `load_plugin`.
+    ///
+    /// # Safety
+    ///
+    /// As for `load_plugin`: the library must be a plugin built by the
+    /// same compiler, against the same version of this crate, as the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::PluginError` if the plugin cannot be loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn load_plugin<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ValyrianError> {
+        // SAFETY: passed on to our caller
+        let plugin = unsafe { load_plugin(path)? };
+        self.install_plugin(plugin.as_ref());
+        Ok(())
+    }
+}
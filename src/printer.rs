@@ -0,0 +1,345 @@
+//! Canonical source printing.
+//!
+//! Renders an AST back into Mid Valyrian source in the house style: four
+//! spaces of indentation, single spaces around operators and after commas,
+//! and a blank line between top-level declarations.
+
+use crate::ast::*;
+use crate::edition::Edition;
+use crate::error::ValyrianError;
+use crate::parser::parse_program_with_trivia;
+use crate::trivia::{ Trivia, Trivium };
+
+const INDENT: &str = "    ";
+
+/// Renders a program as canonically formatted source, which parses back
+/// into the same program (spans aside).
+///
+/// ```
+/// use mid_valyrian::{ parse_program, to_source };
+///
+/// let program = parse_program("on the iron throne:\n  speak 1+2*-3\n").unwrap();
+/// let source = to_source(&program);
+/// assert_eq!(source, "on the iron throne:\n    speak 1 + 2 * -3\n");
+/// assert_eq!(to_source(&parse_program(&source).unwrap()), source);
+/// ```
+///
+/// Trees the parser can't produce, built by hand or rewritten by passes,
+/// come back equivalent rather than identical: a negative literal parses
+/// as a negation, and numbers with no literal of their own (the smallest
+/// blade, infinite or NaN wines) are written as arithmetic giving them.
+/// Empty blocks and the sigil `'` have no source form at all.
+///
+/// A program in an edition after the first starts with its `canon` line.
+pub fn to_source(program: &Program) -> String {
+    render(program, None)
+}
+
+/// Renders a program as canonically formatted source like `to_source`,
+/// putting back the comments and blank lines in `trivia`.
+pub fn to_source_with_trivia(program: &Program, trivia: &Trivia) -> String {
+    render(program, Some(trivia))
+}
+
+fn render(program: &Program, trivia: Option<&Trivia>) -> String {
+    let mut printer = Printer { trivia, edition: program.edition, ..Printer::default() };
+    if program.edition != Edition::default() {
+        printer.line(&program.edition.to_string());
+        printer.blank_line();
+    }
+    let mut previous: Option<&Statement> = None;
+    for statement in &program.statements {
+        if let Some(previous) = previous {
+            if is_declaration(previous) || is_declaration(statement) {
+                printer.blank_line();
+            }
+        }
+        printer.statement(statement);
+        previous = Some(statement);
+    }
+    if let Some(trivia) = trivia {
+        printer.leading(&trivia.end);
+    }
+    printer.out
+}
+
+/// Renders the first line of a statement, without any block it opens, for
+/// logs and traces.
+pub(crate) fn statement_head(statement: &Statement) -> String {
+    let mut printer = Printer { skip_blocks: true, ..Printer::default() };
+    printer.statement(statement);
+    printer.out.lines().next().unwrap_or_default().to_string()
+}
+
+/// Declarations and the main block are set apart by blank lines.
+fn is_declaration(statement: &Statement) -> bool {
+    matches!(
+        statement.kind,
+        StatementKind::FunctionDeclaration { .. } |
+            StatementKind::MainBlock(_) |
+            StatementKind::Trial { .. }
+    )
+}
+
+/// Reformats source text into the canonical style, keeping its comments.
+/// A leading shebang line is kept as is.
+///
+/// # Errors
+///
+/// Returns `ValyrianError` if the source doesn't parse.
+pub fn format_source(source: &str) -> Result<String, ValyrianError> {
+    let (program, trivia) = parse_program_with_trivia(source)?;
+    let (shebang, _) = split_shebang(source);
+    Ok(format!("{}{}", shebang, to_source_with_trivia(&program, &trivia)))
+}
+
+/// Splits a leading `#!` line (with its newline) from the rest of the source.
+fn split_shebang(source: &str) -> (&str, &str) {
+    if !source.starts_with("#!") {
+        return ("", source);
+    }
+    match source.find('\n') {
+        Some(end) => source.split_at(end + 1),
+        None => (source, ""),
+    }
+}
+
+#[derive(Default)]
+struct Printer<'t> {
+    out: String,
+    depth: usize,
+    /// Print only the line that opens each block.
+    skip_blocks: bool,
+    /// Comments and blank lines to put back.
+    trivia: Option<&'t Trivia>,
+    /// Nothing has been printed since a block was opened.
+    block_start: bool,
+    /// How the program groups operators.
+    edition: Edition,
+}
+
+impl Printer<'_> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.block_start = false;
+    }
+
+    /// Ends the current run of lines, unless it hasn't started.
+    fn blank_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") && !self.block_start {
+            self.out.push('\n');
+        }
+    }
+
+    fn leading(&mut self, trivia: &[Trivium]) {
+        for trivium in trivia {
+            match trivium {
+                Trivium::Comment(comment) => self.line(comment),
+                Trivium::BlankLine => self.blank_line(),
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        if self.skip_blocks {
+            return;
+        }
+        self.depth += 1;
+        self.block_start = true;
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.depth -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let trivia = self.trivia.and_then(|trivia| trivia.get(statement));
+        if let Some(trivia) = trivia {
+            self.leading(&trivia.leading);
+        }
+        let start = self.out.len();
+        self.code(statement);
+        if let Some(comment) = trivia.and_then(|trivia| trivia.trailing.as_ref()) {
+            let end = self.out[start..].find('\n').map_or(self.out.len(), |end| start + end);
+            self.out.insert_str(end, &format!(" {}", comment));
+        }
+    }
+
+    fn code(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { name, data_type, value: Some(value) } => {
+                self.line(&format!("{} is a {} with {}", name, data_type, self.expression(value)));
+            }
+            StatementKind::VariableDeclaration { name, data_type, value: None } => {
+                self.line(&format!("{} is a {}", name, data_type));
+            }
+            StatementKind::FunctionDeclaration { name, parameters, body } => {
+                if parameters.is_empty() {
+                    self.line(&format!("we declare {} with ->", name));
+                } else {
+                    self.line(&format!("we declare {} with {} ->", name, parameters.join(", ")));
+                }
+                self.line("council says:");
+                self.block(body);
+            }
+            StatementKind::FunctionCall { name, arguments } => {
+                self.line(&self.call(name, arguments));
+            }
+            StatementKind::Assignment { name, value } => {
+                self.line(&format!("{} = {}", name, self.expression(value)));
+            }
+            StatementKind::Conditional { condition, then_branch, else_branch } => {
+                self.line(&format!("if {}:", self.expression(condition)));
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.line("else:");
+                    self.block(else_branch);
+                }
+            }
+            StatementKind::ForLoop { count, body } => {
+                self.line(&format!("the realm marches {} times:", count));
+                self.block(body);
+            }
+            StatementKind::WhileLoop { condition, body } => {
+                self.line(&format!("while {}:", self.expression(condition)));
+                self.block(body);
+            }
+            StatementKind::Return(value) => {
+                match value {
+                    Some(value) => self.line(&format!("return {}", self.expression(value))),
+                    None => self.line("return"),
+                }
+            }
+            StatementKind::Speak(value) => {
+                self.line(&format!("speak {}", self.expression(value)));
+            }
+            StatementKind::MainBlock(body) => {
+                self.line("on the iron throne:");
+                self.block(body);
+            }
+            StatementKind::Trial { name, body } => {
+                self.line(&format!("trial {}:", literal(&Literal::String(name.as_str().into()))));
+                self.block(body);
+            }
+        }
+    }
+}
+
+impl Printer<'_> {
+    fn call(&self, name: &str, arguments: &[Expression]) -> String {
+        let arguments: Vec<String> = arguments.iter().map(|argument| self.operand(argument)).collect();
+        if arguments.is_empty() {
+            format!("{} with", name)
+        } else {
+            format!("{} with {}", name, arguments.join(", "))
+        }
+    }
+
+    /// Renders an expression in a position where it stands alone.
+    fn expression(&self, expr: &Expression) -> String {
+        match &expr.kind {
+            ExpressionKind::FunctionCall { name, arguments } => self.call(name, arguments),
+            ExpressionKind::SendRaven(call) => format!("send_raven {}", self.expression(call)),
+            ExpressionKind::Ask(prompt) => format!("ask with {}", self.operand(prompt)),
+            _ => self.operand(expr),
+        }
+    }
+
+    /// Renders an expression that is part of a larger one. An operand
+    /// needs parentheses where the program's edition would otherwise
+    /// group it differently: on the right, unless it binds tighter, and on
+    /// the left, if it binds looser. Calls are wrapped because their
+    /// arguments would otherwise swallow the rest of the expression.
+    fn operand(&self, expr: &Expression) -> String {
+        let binds = |operand: &Expression| match &operand.kind {
+            ExpressionKind::Binary { operator, .. } => Some(self.edition.precedence(operator)),
+            _ => None,
+        };
+        match &expr.kind {
+            ExpressionKind::Literal(literal) => self::literal(literal),
+            ExpressionKind::Identifier(name) => name.to_string(),
+            ExpressionKind::Binary { left, operator, right } => {
+                let precedence = self.edition.precedence(operator);
+                let left = match binds(left) {
+                    Some(binds) if binds < precedence => format!("({})", self.operand(left)),
+                    _ => self.operand(left),
+                };
+                let right = match binds(right) {
+                    Some(binds) if binds <= precedence => format!("({})", self.operand(right)),
+                    _ => self.operand(right),
+                };
+                format!("{} {} {}", left, binary_operator(operator), right)
+            }
+            ExpressionKind::Unary { operator, operand: inner } => {
+                let op = unary_operator(operator);
+                match inner.kind {
+                    ExpressionKind::Binary { .. } => format!("{}({})", op, self.operand(inner)),
+                    _ => format!("{}{}", op, self.operand(inner)),
+                }
+            }
+            ExpressionKind::Input(name) => format!("{} speaks for input", name),
+            ExpressionKind::FunctionCall { name, arguments } => format!("({})", self.call(name, arguments)),
+            ExpressionKind::SendRaven(_) | ExpressionKind::Ask(_) => format!("({})", self.expression(expr)),
+            // `await_raven` takes a single operand, without operators
+            ExpressionKind::AwaitRaven(raven) =>
+                match raven.kind {
+                    ExpressionKind::Binary { .. } | ExpressionKind::Unary { .. } =>
+                        format!("await_raven ({})", self.operand(raven)),
+                    _ => format!("await_raven {}", self.operand(raven)),
+                }
+        }
+    }
+}
+
+pub(crate) fn binary_operator(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::Less => "<",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+    }
+}
+
+pub(crate) fn unary_operator(operator: &UnaryOperator) -> &'static str {
+    match operator {
+        UnaryOperator::Minus => "-",
+        UnaryOperator::Not => "!",
+    }
+}
+
+pub(crate) fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::String(s) => {
+            let escaped = s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("\"{}\"", escaped)
+        }
+        // Negative literals parse as a negation, and this one's operand
+        // would overflow
+        Literal::Integer(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+        Literal::Integer(i) => i.to_string(),
+        Literal::Float(f) if !f.is_finite() => {
+            let infinity = format!("({} * 10.0)", self::literal(&Literal::Float(f64::MAX)));
+            if f.is_nan() {
+                format!("({} * 0.0)", infinity)
+            } else if *f < 0.0 {
+                format!("-{}", infinity)
+            } else {
+                infinity
+            }
+        }
+        Literal::Float(f) => {
+            let text = f.to_string();
+            if text.contains('.') { text } else { format!("{}.0", text) }
+        }
+        Literal::Boolean(b) => (if *b { "aye" } else { "nay" }).to_string(),
+        Literal::Char(c) => format!("'{}'", c),
+    }
+}
@@ -0,0 +1,109 @@
+//! 📊 Finding where a program spends its time.
+//!
+//! `Profiler` is an `ExecutionObserver` that times every call. Clones share
+//! their measurements, so keep one and hand a clone to the interpreter:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Interpreter, Profiler };
+//!
+//! let profiler = Profiler::new();
+//! let mut interpreter = Interpreter::new(false);
+//! interpreter.add_observer(profiler.clone());
+//!
+//! let source = "we declare double with x ->\ncouncil says:\n    return x * 2\n\na is a blade with double with 1\nb is a blade with double with a\n";
+//! interpreter.interpret(&parse_program(source).unwrap()).unwrap();
+//!
+//! let report = profiler.report();
+//! assert_eq!(report[0].name, "double");
+//! assert_eq!(report[0].calls, 2);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::time::{ Duration, Instant };
+use crate::ast::{ Span, Value };
+use crate::error::ValyrianError;
+use crate::observer::ExecutionObserver;
+
+/// What the profiler measured for one function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Time from entering the function to leaving it, including the
+    /// functions it called. Recursive calls are only counted once.
+    pub total: Duration,
+    /// Time spent in the function's own statements.
+    pub self_time: Duration,
+}
+
+/// A call in progress.
+struct Frame {
+    name: String,
+    started: Instant,
+    /// Time spent in calls made from this one.
+    in_callees: Duration,
+}
+
+#[derive(Default)]
+struct Measurements {
+    stack: Vec<Frame>,
+    functions: HashMap<String, FunctionProfile>,
+}
+
+/// Times the calls a program makes; see the module docs.
+#[derive(Clone, Default)]
+pub struct Profiler(Arc<Mutex<Measurements>>);
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every function called so far, those with the most self time first.
+    pub fn report(&self) -> Vec<FunctionProfile> {
+        let mut report: Vec<FunctionProfile> = self.measurements().functions.values().cloned().collect();
+        report.sort_by(|a, b| b.self_time.cmp(&a.self_time).then_with(|| a.name.cmp(&b.name)));
+        report
+    }
+
+    fn measurements(&self) -> std::sync::MutexGuard<'_, Measurements> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ExecutionObserver for Profiler {
+    fn on_call(&mut self, name: &str, _arguments: &[Value], _call_site: &Span) {
+        self.measurements().stack.push(Frame {
+            name: name.to_string(),
+            started: Instant::now(),
+            in_callees: Duration::ZERO,
+        });
+    }
+
+    fn on_return(&mut self, name: &str, _result: &Result<Value, ValyrianError>) {
+        let mut measurements = self.measurements();
+        let Some(frame) = measurements.stack.pop() else {
+            return;
+        };
+        let elapsed = frame.started.elapsed();
+        let recursive = measurements.stack.iter().any(|outer| outer.name == frame.name);
+        if let Some(caller) = measurements.stack.last_mut() {
+            caller.in_callees += elapsed;
+        }
+
+        let profile = measurements.functions
+            .entry(name.to_string())
+            .or_insert_with(|| FunctionProfile {
+                name: name.to_string(),
+                calls: 0,
+                total: Duration::ZERO,
+                self_time: Duration::ZERO,
+            });
+        profile.calls += 1;
+        profile.self_time += elapsed.saturating_sub(frame.in_callees);
+        if !recursive {
+            profile.total += elapsed;
+        }
+    }
+}
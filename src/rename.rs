@@ -0,0 +1,290 @@
+//! ✒️ Renaming a variable, parameter, or function.
+//!
+//! `rename` finds the declaration behind a name with the `SymbolTable` and
+//! returns the edits that give it and every use of it a new name:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, rename, Span };
+//!
+//! let source = "we declare greet with name ->\ncouncil says:\n    speak name\non the iron throne:\n    greet with \"Arya\"\n";
+//! let program = parse_program(source).unwrap();
+//!
+//! let at = source.find("name").unwrap();
+//! let edits = rename(&program, source, Span::new(at, at + 4, 1, 23), "guest").unwrap();
+//! let mut renamed = source.to_string();
+//! for edit in edits.iter().rev() {
+//!     renamed = edit.apply(&renamed);
+//! }
+//! assert!(renamed.starts_with("we declare greet with guest ->\ncouncil says:\n    speak guest\n"));
+//!
+//! // `greet` can't become `assert` without hiding the builtin
+//! let at = source.rfind("greet").unwrap();
+//! assert!(rename(&program, source, Span::new(at, at + 5, 5, 5), "assert").is_err());
+//! ```
+//!
+//! A rename is refused if any name in the program would then refer to a
+//! different declaration than before: renaming a variable to the name of
+//! another, say, or a global to the name of a parameter of a function
+//! that reads it.
+
+use std::collections::HashSet;
+use crate::ast::*;
+use crate::builtins;
+use crate::error::ValyrianError;
+use crate::incremental::TextEdit;
+use crate::lexer::{ tokenize, TokenKind };
+use crate::symbols::{ Definition, SymbolKind, SymbolTable };
+use crate::visit::{ walk_expression_mut, walk_statement, walk_statement_mut, Visitor, VisitorMut };
+
+/// The edits that rename the variable, parameter, or function named at
+/// `span` to `new_name`, in source order. Every range is in `source`, the
+/// text `program` was parsed from, so apply them from the last.
+///
+/// # Errors
+///
+/// Returns `ValyrianError::RenameError` if nothing that can be renamed is
+/// named at `span`, if `new_name` isn't a name the grammar allows, or if
+/// the rename would change what some name refers to.
+pub fn rename(
+    program: &Program,
+    source: &str,
+    span: Span,
+    new_name: &str
+) -> Result<Vec<TextEdit>, ValyrianError> {
+    let refuse = |message: String, span: Span| ValyrianError::RenameError { message, span };
+    let symbols = SymbolTable::new(program);
+    let target = find_target(&symbols, program, source, span.start).ok_or_else(||
+        refuse("there is no variable, parameter, or function here to rename".into(), span)
+    )?;
+    let definition = target.definition;
+    if !is_name(new_name) {
+        return Err(refuse(format!("'{}' can't be used as a name", new_name), span));
+    }
+    if *definition.name == *new_name {
+        return Ok(Vec::new());
+    }
+    if definition.kind == SymbolKind::Function && builtins::is_builtin(new_name) {
+        return Err(
+            refuse(format!("'{}' would hide the builtin function of that name", new_name), definition.span)
+        );
+    }
+
+    let declaration = match target.header {
+        Some(position) => {
+            let names = header_names(source, definition.span);
+            names.get(position).copied().ok_or_else(||
+                refuse("the program was not parsed from this source".into(), definition.span)
+            )?
+        }
+        None => Span { end: definition.span.start + definition.name.len(), ..definition.span },
+    };
+    let mut spans: Vec<Span> = definition.references.iter().map(|reference| reference.span).collect();
+    spans.push(declaration);
+    spans.sort_by_key(|span| span.start);
+
+    let mut renamer = Renamer {
+        starts: spans.iter().map(|span| span.start).collect(),
+        declaration: definition.span.start,
+        header: target.header,
+        name: new_name.into(),
+    };
+    let mut renamed = program.clone();
+    renamer.visit_program_mut(&mut renamed);
+    let index = symbols.definitions().iter().position(|other| std::ptr::eq(other, definition)).unwrap_or_default();
+    if let Some(span) = first_difference(&symbols, &SymbolTable::new(&renamed), index, new_name) {
+        let message = format!(
+            "renaming '{}' to '{}' would change what the name here refers to",
+            definition.name,
+            new_name
+        );
+        return Err(refuse(message, span));
+    }
+
+    Ok(spans.iter().map(|span| TextEdit::new(span.start..span.end, new_name)).collect())
+}
+
+/// A definition to rename.
+struct Target<'s> {
+    definition: &'s Definition,
+    /// Where a function or parameter is named in its function's header: 0
+    /// for the function, 1 for its first parameter, and so on.
+    header: Option<usize>,
+}
+
+/// The definition named at `offset`, whether at a use or in a
+/// declaration.
+fn find_target<'s>(
+    symbols: &'s SymbolTable,
+    program: &Program,
+    source: &str,
+    offset: usize
+) -> Option<Target<'s>> {
+    if let Some(definition) = symbols.definition_at(offset) {
+        let header = match definition.kind {
+            SymbolKind::Variable => None,
+            _ => {
+                let declaration = find_declaration(program, |span| span.start == definition.span.start)?;
+                let parameters = symbols
+                    .declared_by(declaration)
+                    .filter(|other| other.kind == SymbolKind::Parameter);
+                match definition.kind {
+                    SymbolKind::Function => Some(0),
+                    _ => Some(parameters.take_while(|other| !std::ptr::eq(*other, definition)).count() + 1),
+                }
+            }
+        };
+        return Some(Target { definition, header });
+    }
+
+    // A name in the header of a function declaration
+    let declaration = find_declaration(program, |span| span.start <= offset && offset < span.end)?;
+    let names = header_names(source, declaration.span);
+    let position = names.iter().position(|name| name.start <= offset && offset <= name.end)?;
+    let definition = match (position, &declaration.kind) {
+        (0, StatementKind::FunctionDeclaration { name, .. }) => {
+            symbols
+                .definitions()
+                .iter()
+                .find(|definition| definition.kind == SymbolKind::Function && definition.name == *name)?
+        }
+        _ => {
+            symbols
+                .declared_by(declaration)
+                .filter(|definition| definition.kind == SymbolKind::Parameter)
+                .nth(position - 1)?
+        }
+    };
+    Some(Target { definition, header: Some(position) })
+}
+
+/// The innermost function declaration whose span passes `test`.
+fn find_declaration(program: &Program, test: impl Fn(Span) -> bool) -> Option<&Statement> {
+    struct Finder<'ast, F> {
+        test: F,
+        found: Option<&'ast Statement>,
+    }
+
+    impl<'ast, F: Fn(Span) -> bool> Visitor<'ast> for Finder<'ast, F> {
+        fn visit_statement(&mut self, statement: &'ast Statement) {
+            if matches!(statement.kind, StatementKind::FunctionDeclaration { .. }) && (self.test)(statement.span) {
+                self.found = Some(statement);
+            }
+            walk_statement(self, statement);
+        }
+    }
+
+    let mut finder = Finder { test, found: None };
+    finder.visit_program(program);
+    finder.found
+}
+
+/// The spans of the function name and parameters in the header of the
+/// function declared at `declaration`.
+fn header_names(source: &str, declaration: Span) -> Vec<Span> {
+    let start = declaration.start;
+    let header = source[start..].split('\n').next().unwrap_or_default();
+    tokenize(header)
+        .into_iter()
+        .take_while(|token| token.text(header) != "->")
+        .filter(|token| token.kind != TokenKind::Punctuation)
+        // Skipping `we declare`, and `with` after the function's name
+        .enumerate()
+        .filter(|(i, _)| *i == 1 || *i >= 3)
+        .map(|(_, token)| {
+            Span::new(
+                start + token.span.start,
+                start + token.span.end,
+                declaration.line,
+                declaration.col + token.span.col - 1
+            )
+        })
+        .collect()
+}
+
+/// Whether `name` is a single word the grammar reads as a name.
+fn is_name(name: &str) -> bool {
+    matches!(
+        tokenize(name).as_slice(),
+        [token] if token.kind == TokenKind::Identifier && token.span.end == name.len()
+    )
+}
+
+/// The first place the renamed program's names resolve differently from
+/// the original's, other than the definition at `index` having its new
+/// name.
+fn first_difference(before: &SymbolTable, after: &SymbolTable, index: usize, new_name: &str) -> Option<Span> {
+    let resolved = |definition: &Definition| (
+        definition.kind,
+        definition.span.start,
+        definition.references.iter().map(|reference| (reference.span.start, reference.access)).collect::<Vec<_>>(),
+    );
+    let mut after = after.definitions().iter();
+    for (i, old) in before.definitions().iter().enumerate() {
+        let Some(new) = after.next() else {
+            return Some(old.span);
+        };
+        let name = if i == index { new_name } else { &old.name };
+        if *new.name != *name || resolved(old) != resolved(new) {
+            let references = old.references.iter().zip(&new.references);
+            let moved = references.into_iter().find(|(old, new)| old.span.start != new.span.start);
+            return Some(moved.map_or(new.span, |(old, _)| old.span));
+        }
+    }
+    after.next().map(|new| new.span)
+}
+
+/// Gives the new name to the declaration and uses of one definition.
+struct Renamer {
+    /// Where each use starts, and the variable's declaration.
+    starts: HashSet<usize>,
+    /// Where the declaring statement starts.
+    declaration: usize,
+    /// Which name of the function's header is renamed, if any.
+    header: Option<usize>,
+    name: Symbol,
+}
+
+impl VisitorMut for Renamer {
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        let renamed = self.starts.contains(&statement.span.start);
+        match &mut statement.kind {
+            StatementKind::VariableDeclaration { name, .. } |
+            StatementKind::Assignment { name, .. } if renamed => {
+                *name = self.name.clone().into();
+            }
+            StatementKind::FunctionCall { name, .. } if renamed => {
+                *name = self.name.clone();
+            }
+            StatementKind::FunctionDeclaration { name, parameters, .. } if
+                statement.span.start == self.declaration
+            => {
+                match self.header {
+                    Some(0) => {
+                        *name = self.name.clone();
+                    }
+                    Some(position) => {
+                        parameters[position - 1] = self.name.clone();
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        if self.starts.contains(&expression.span.start) {
+            match &mut expression.kind {
+                ExpressionKind::Identifier(name) => {
+                    *name = self.name.clone().into();
+                }
+                ExpressionKind::FunctionCall { name, .. } => {
+                    *name = self.name.clone();
+                }
+                _ => {}
+            }
+        }
+        walk_expression_mut(self, expression);
+    }
+}
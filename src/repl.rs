@@ -0,0 +1,430 @@
+//! 🐉 Interactive Mid Valyrian session.
+//!
+//! The REPL keeps a single `Interpreter` alive between inputs, so variables
+//! and functions declared on one line can be used on the next. Bare
+//! expressions have their value printed; lines ending in `:` or `->` open a
+//! multi-line block that is closed by an empty line.
+//!
+//! Lines starting with `:` are meta-commands; see `HELP` for the list.
+
+use std::env;
+use std::fmt::Display;
+#[cfg(feature = "serde")]
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use colored::*;
+use rustyline::completion::{ Completer, Pair };
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{ Context, Editor, Helper };
+use crate::builtins;
+use crate::interpreter::Interpreter;
+use crate::parser::{ parse_program, parse_repl_input, ReplInput };
+use crate::ast::Value;
+use crate::error::ValyrianError;
+use crate::diagnostic::{ strip_emoji, PlainWriter };
+
+const PROMPT: &str = "🐉> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+const HELP: &str = "\
+Meta-commands:
+  :vars        show the current variable bindings
+  :funcs       list the functions declared by the council
+  :ast <code>  show how <code> is parsed, without running it
+  :doc <name>  describe a builtin function
+  :reset       forget all variables and functions
+  :save <file> save the variables and functions to <file>
+  :load <file> restore a session saved with :save
+  :help        show this message
+  :quit        leave the session";
+
+const META_COMMANDS: &[&str] = &[
+    ":vars",
+    ":funcs",
+    ":ast",
+    ":doc",
+    ":reset",
+    ":save",
+    ":load",
+    ":help",
+    ":quit",
+];
+
+/// What the REPL should do after a meta-command.
+enum Flow {
+    Continue,
+    Quit,
+}
+
+pub struct Repl {
+    interpreter: Interpreter,
+    plain: bool,
+}
+
+impl Repl {
+    pub fn new(debug: bool) -> Self {
+        Self {
+            interpreter: Interpreter::new(debug),
+            plain: false,
+        }
+    }
+
+    /// Leaves emoji out of the prompt, messages, and diagnostics, as
+    /// `--plain` does for the rest of the CLI.
+    pub fn plain(mut self, plain: bool) -> Self {
+        if plain {
+            self.interpreter.set_debug_output(PlainWriter::new(io::stderr()));
+        }
+        self.plain = plain;
+        self
+    }
+
+    /// Runs one complete input against the session, returning the value of
+    /// a bare expression (or `Void` for statements).
+    pub fn eval(&mut self, input: &str) -> Result<Value, ValyrianError> {
+        match parse_repl_input(input)? {
+            ReplInput::Expression(expression) => self.interpreter.evaluate(&expression),
+            ReplInput::Statements(statements) => {
+                self.interpreter.run_statements(&statements)?;
+                Ok(Value::Void)
+            }
+        }
+    }
+
+    /// Reads inputs from the terminal until end of input, printing results
+    /// and errors as it goes. History is kept in `~/.mid_valyrian_history`.
+    pub fn run(&mut self) -> Result<(), ValyrianError> {
+        let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new().map_err(readline_error)?;
+        editor.set_helper(Some(ReplHelper::default()));
+        let history = history_path();
+        if let Some(path) = &history {
+            // A missing history file just means this is the first session
+            let _ = editor.load_history(path);
+        }
+
+        let prompt = if self.plain { strip_emoji(PROMPT) } else { PROMPT.to_string() };
+        loop {
+            if let Some(helper) = editor.helper_mut() {
+                helper.variables = self.interpreter
+                    .variables()
+                    .map(|(name, _)| name.to_string())
+                    .chain(self.interpreter.functions().map(|(name, _)| name.to_string()))
+                    .collect();
+            }
+
+            let input = match read_input(&mut editor, &prompt)? {
+                Some(input) => input,
+                None => {
+                    println!();
+                    break;
+                }
+            };
+            if input.trim().is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(input.as_str());
+            if let Some(command) = input.trim().strip_prefix(':') {
+                match self.meta_command(command) {
+                    Flow::Continue => continue,
+                    Flow::Quit => break,
+                }
+            }
+
+            match self.eval(&input) {
+                Ok(Value::Void) => {}
+                Ok(value) => println!("{}", value),
+                Err(error) => {
+                    self.complain(error.to_diagnostic().render(&input, "<repl>").bright_red());
+                }
+            }
+        }
+
+        if let Some(path) = &history {
+            editor.save_history(path).map_err(readline_error)?;
+        }
+        Ok(())
+    }
+
+    fn meta_command(&mut self, command: &str) -> Flow {
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, rest)| (name, rest.trim()));
+
+        match name {
+            "vars" => {
+                let mut vars: Vec<_> = self.interpreter.variables().collect();
+                vars.sort_by_key(|(name, _)| *name);
+                if vars.is_empty() {
+                    println!("No variables are known in this realm yet.");
+                }
+                for (name, value) in vars {
+                    println!("{} = {}", name.bright_green(), value);
+                }
+            }
+            "funcs" => {
+                let mut funcs: Vec<_> = self.interpreter.functions().collect();
+                funcs.sort_by_key(|(name, _)| *name);
+                if funcs.is_empty() {
+                    println!("The council has declared no functions yet.");
+                }
+                for (name, params) in funcs {
+                    println!("{} with {}", name.bright_green(), params.join(", "));
+                }
+            }
+            "ast" => {
+                match parse_program(argument) {
+                    Ok(program) => self.say(format!("{:#?}", program)),
+                    Err(error) => {
+                        self.complain(
+                            error.to_diagnostic().render(argument, "<repl>").bright_red()
+                        );
+                    }
+                }
+            }
+            "doc" => {
+                match builtins::lookup(argument) {
+                    Some(builtin) => {
+                        println!("{}", builtin.signature().bright_green());
+                        println!("    {}", builtin.doc);
+                    }
+                    None => {
+                        self.complain(
+                            format!("'{}' is not a builtin function", argument).bright_red()
+                        );
+                    }
+                }
+            }
+            "reset" => {
+                self.interpreter.reset();
+                println!("The realm has been reset.");
+            }
+            "save" => {
+                match self.save(argument) {
+                    Ok(()) => println!("The realm has been recorded in {}.", argument),
+                    Err(error) => self.complain(format!("{}", error).bright_red()),
+                }
+            }
+            "load" => {
+                match self.load(argument) {
+                    Ok(()) => println!("The realm has been restored from {}.", argument),
+                    Err(error) => self.complain(format!("{}", error).bright_red()),
+                }
+            }
+            "help" => println!("{}", HELP),
+            "quit" | "q" | "exit" => {
+                return Flow::Quit;
+            }
+            other => {
+                self.complain(format!("Unknown command ':{}' — try :help", other).bright_red());
+            }
+        }
+        Flow::Continue
+    }
+}
+
+impl Repl {
+    /// Prints one of the REPL's own messages to stdout.
+    fn say(&self, text: impl Display) {
+        if self.plain {
+            println!("{}", strip_emoji(&text.to_string()));
+        } else {
+            println!("{}", text);
+        }
+    }
+
+    /// Prints an error to stderr.
+    fn complain(&self, text: impl Display) {
+        if self.plain {
+            eprintln!("{}", strip_emoji(&text.to_string()));
+        } else {
+            eprintln!("{}", text);
+        }
+    }
+
+    /// Writes the session's variables and functions to `path` as JSON.
+    #[cfg(feature = "serde")]
+    fn save(&self, path: &str) -> Result<(), ValyrianError> {
+        let json = serde_json
+            ::to_string_pretty(&self.interpreter.snapshot())
+            .map_err(|e| ValyrianError::RuntimeError(format!("Failed to save the session: {}", e)))?;
+        fs::write(path, json).map_err(|e| {
+            ValyrianError::io(format!("Failed to write '{}': {}", path, e), e)
+        })
+    }
+
+    /// Replaces the session's variables and functions with those saved in
+    /// `path`.
+    #[cfg(feature = "serde")]
+    fn load(&mut self, path: &str) -> Result<(), ValyrianError> {
+        let json = fs::read_to_string(path).map_err(|e| {
+            ValyrianError::io(format!("Failed to read '{}': {}", path, e), e)
+        })?;
+        let snapshot = serde_json
+            ::from_str(&json)
+            .map_err(|e| ValyrianError::RuntimeError(format!("'{}' is not a saved session: {}", path, e)))?;
+        self.interpreter.restore(snapshot);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn save(&self, _path: &str) -> Result<(), ValyrianError> {
+        Err(ValyrianError::RuntimeError("saving sessions needs the `serde` feature".into()))
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn load(&mut self, _path: &str) -> Result<(), ValyrianError> {
+        Err(ValyrianError::RuntimeError("loading sessions needs the `serde` feature".into()))
+    }
+}
+
+/// Returns true if `line` opens an indented block that continues on the
+/// following lines.
+fn opens_block(line: &str) -> bool {
+    let line = line.trim_end();
+    line.ends_with(':') || line.ends_with("->")
+}
+
+/// Reads one complete input: a single line, or a block-opening line plus
+/// every following line up to the first empty one. Returns `None` at end
+/// of input. Ctrl-C abandons the input being typed.
+fn read_input(
+    editor: &mut Editor<ReplHelper, DefaultHistory>,
+    prompt: &str
+) -> Result<Option<String>, ValyrianError> {
+    loop {
+        let first = match editor.readline(&prompt.bright_cyan().to_string()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => {
+                return Ok(None);
+            }
+            Err(error) => {
+                return Err(readline_error(error));
+            }
+        };
+        if !opens_block(&first) {
+            return Ok(Some(first));
+        }
+
+        let mut input = first;
+        loop {
+            match editor.readline(&CONTINUATION_PROMPT.bright_cyan().to_string()) {
+                Ok(line) => {
+                    if line.trim().is_empty() {
+                        return Ok(Some(input));
+                    }
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Err(ReadlineError::Interrupted) => break,
+                Err(ReadlineError::Eof) => {
+                    return Ok(Some(input));
+                }
+                Err(error) => {
+                    return Err(readline_error(error));
+                }
+            }
+        }
+    }
+}
+
+fn readline_error(error: ReadlineError) -> ValyrianError {
+    match error {
+        ReadlineError::Io(error) => ValyrianError::io("The REPL could not read input", error),
+        other => ValyrianError::RuntimeError(format!("The REPL could not read input: {}", other)),
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".mid_valyrian_history"))
+}
+
+/// Words offered by tab completion regardless of program state.
+const KEYWORDS: &[&str] = &[
+    "on the iron throne:",
+    "we declare",
+    "council says:",
+    "the realm marches",
+    "times:",
+    "speak",
+    "return",
+    "if",
+    "else:",
+    "while",
+    "with",
+    "is a",
+    "speaks for input",
+    "ask with",
+    "aye",
+    "nay",
+    "scroll",
+    "blade",
+    "wine",
+    "vow",
+    "sigil",
+    "raven",
+    "rookery",
+    "vault",
+    "tidings",
+    "missive",
+    "socket",
+    "void",
+    "send_raven",
+    "await_raven",
+];
+
+/// Tab completion over keywords, builtins, and the names currently defined
+/// in the session.
+#[derive(Default)]
+struct ReplHelper {
+    variables: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((start, Vec::new()));
+        }
+
+        let mut candidates: Vec<Pair> = KEYWORDS.iter()
+            .copied()
+            .chain(META_COMMANDS.iter().copied())
+            .chain(builtins::BUILTINS.iter().map(|builtin| builtin.name))
+            .chain(self.variables.iter().map(String::as_str))
+            .filter(|word| word.starts_with(prefix))
+            .map(|word| Pair {
+                display: word.to_string(),
+                replacement: word.to_string(),
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates.dedup_by(|a, b| a.display == b.display);
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
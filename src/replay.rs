@@ -0,0 +1,233 @@
+//! 🎞️ Recording what a run reads, so it can be replayed exactly.
+//!
+//! The lines a program reads with `speaks for input` are the only thing it
+//! takes from outside, so a run fed the same lines behaves the same way.
+//! `Recorder` wraps an interpreter's input and notes every read; its
+//! `to_replay` text can be saved and later handed to `ReplayInput`, which
+//! answers the same reads in the same order:
+//!
+//! ```
+//! use std::io::Cursor;
+//! use mid_valyrian::{ parse_program, Interpreter, Recorder, ReplayInput, Value };
+//!
+//! let program = parse_program("name is a scroll with arya speaks for input\n").unwrap();
+//!
+//! let recorder = Recorder::new();
+//! let mut interpreter = Interpreter::new(false)
+//!     .with_output(std::io::sink())
+//!     .with_input(recorder.wrap(Cursor::new("No One\n")));
+//! interpreter.interpret(&program).unwrap();
+//! let replay = recorder.to_replay();
+//!
+//! let mut interpreter = Interpreter::new(false)
+//!     .with_output(std::io::sink())
+//!     .with_input(ReplayInput::parse(&replay).unwrap());
+//! interpreter.interpret(&program).unwrap();
+//! assert_eq!(interpreter.get_variable("name"), Some(&Value::String("No One".into())));
+//! ```
+//!
+//! Replay files are text, one read per line: `line <text>` for a line the
+//! program read (without its line ending) and `eof` when it found no more
+//! input.
+
+use std::collections::VecDeque;
+use std::io::{ self, BufRead, Read };
+use std::path::Path;
+use std::sync::{ Arc, Mutex, PoisonError };
+use crate::error::ValyrianError;
+
+/// The first line of every replay file.
+const REPLAY_HEADER: &str = "# mid-valyrian replay 1";
+
+/// One read from a program's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayEvent {
+    /// A line, without its line ending.
+    Line(String),
+    /// The input had run out.
+    Eof,
+}
+
+/// Notes every line read through the inputs it wraps. Clones share the
+/// recording; see the module docs.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder(Arc<Mutex<Vec<ReplayEvent>>>);
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `input` so reads from it are recorded.
+    pub fn wrap<R: BufRead>(&self, input: R) -> RecordingInput<R> {
+        RecordingInput { inner: input, recorder: self.clone() }
+    }
+
+    /// Every read recorded so far, in order.
+    pub fn events(&self) -> Vec<ReplayEvent> {
+        self.lock().clone()
+    }
+
+    /// The recording in the replay file format.
+    pub fn to_replay(&self) -> String {
+        let mut replay = format!("{}\n", REPLAY_HEADER);
+        for event in self.lock().iter() {
+            match event {
+                ReplayEvent::Line(line) => {
+                    replay.push_str("line ");
+                    replay.push_str(line);
+                    replay.push('\n');
+                }
+                ReplayEvent::Eof => replay.push_str("eof\n"),
+            }
+        }
+        replay
+    }
+
+    /// Writes the recording to a replay file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::IoError` if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ValyrianError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_replay()).map_err(|e| {
+            ValyrianError::io(format!("Could not write the replay file '{}': {}", path.display(), e), e)
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ReplayEvent>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// An input whose line reads are recorded by a `Recorder`.
+pub struct RecordingInput<R> {
+    inner: R,
+    recorder: Recorder,
+}
+
+impl<R: BufRead> Read for RecordingInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for RecordingInput<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        let start = buf.len();
+        let read = self.inner.read_line(buf)?;
+        let event = if read == 0 {
+            ReplayEvent::Eof
+        } else {
+            let line = &buf[start..];
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            ReplayEvent::Line(line.strip_suffix('\r').unwrap_or(line).to_string())
+        };
+        self.recorder.lock().push(event);
+        Ok(read)
+    }
+}
+
+/// An input that answers reads from a recording. Reading more than was
+/// recorded fails, since the run has gone somewhere the original didn't.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayInput {
+    events: VecDeque<ReplayEvent>,
+    /// The current line, with its line ending, and how much of it was read.
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl ReplayInput {
+    pub fn new(events: impl IntoIterator<Item = ReplayEvent>) -> Self {
+        Self { events: events.into_iter().collect(), ..Self::default() }
+    }
+
+    /// Reads a recording in the replay file format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError::IoError` if the text isn't a replay.
+    pub fn parse(text: &str) -> Result<Self, ValyrianError> {
+        let invalid = |message: String| {
+            ValyrianError::io(message.clone(), io::Error::new(io::ErrorKind::InvalidData, message))
+        };
+        let mut lines = text.split('\n');
+        if lines.next().map(|header| header.trim_end_matches('\r')) != Some(REPLAY_HEADER) {
+            return Err(invalid("This is not a replay file".to_string()));
+        }
+        let mut events = Vec::new();
+        for (index, line) in lines.enumerate() {
+            if let Some(text) = line.strip_prefix("line ") {
+                events.push(ReplayEvent::Line(text.to_string()));
+            } else if line == "eof" {
+                events.push(ReplayEvent::Eof);
+            } else if !line.is_empty() {
+                return Err(invalid(format!("Line {} of the replay is not a recorded read", index + 2)));
+            }
+        }
+        Ok(Self::new(events))
+    }
+
+    /// Loads a replay file saved by `Recorder::save`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ValyrianError` if the file cannot be read or isn't a replay.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ValyrianError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            ValyrianError::io(format!("Could not read the replay file '{}': {}", path.display(), e), e)
+        })?;
+        Self::parse(&text)
+    }
+
+    /// The reads not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl Read for ReplayInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for ReplayInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position == self.pending.len() {
+            self.pending = match self.events.pop_front() {
+                Some(ReplayEvent::Line(line)) => format!("{}\n", line).into_bytes(),
+                Some(ReplayEvent::Eof) => Vec::new(),
+                None => {
+                    return Err(
+                        io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "the program read more input than the recorded run did"
+                        )
+                    );
+                }
+            };
+            self.position = 0;
+        }
+        Ok(&self.pending[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.pending.len());
+    }
+}
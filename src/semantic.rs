@@ -0,0 +1,146 @@
+//! 🎨 Semantic tokens for editors.
+//!
+//! `semantic_tokens` sorts source into the token types of the Language
+//! Server Protocol, telling apart what `tokenize` can't: the names of
+//! functions, where they are declared and wherever they are called, and
+//! the parameters of a function, in its header and in its body.
+//!
+//! ```
+//! use mid_valyrian::{ semantic_tokens, SemanticKind };
+//!
+//! let source = "we declare greet with name ->\ncouncil says:\n    speak name\non the iron throne:\n    greet with \"Arya\"\n";
+//! let names: Vec<(&str, SemanticKind)> = semantic_tokens(source)
+//!     .iter()
+//!     .filter(|token| matches!(token.kind, SemanticKind::Function | SemanticKind::Parameter))
+//!     .map(|token| (&source[token.span.start..token.span.end], token.kind))
+//!     .collect();
+//! assert_eq!(names, [
+//!     ("greet", SemanticKind::Function),
+//!     ("name", SemanticKind::Parameter),
+//!     ("name", SemanticKind::Parameter),
+//!     ("greet", SemanticKind::Function),
+//! ]);
+//! ```
+//!
+//! Source that doesn't parse still gets tokens. Only the bodies of its
+//! functions are lost, so a parameter used in one comes out as a variable.
+
+use crate::ast::Span;
+use crate::lexer::{ tokenize, Token, TokenKind };
+use crate::parser::parse_program;
+use crate::symbols::{ SymbolKind, SymbolTable };
+
+/// What a piece of source is, as an editor would color it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticKind {
+    /// A word of the language, including `aye` and `nay`.
+    Keyword,
+    /// The name of a declared or built-in function.
+    Function,
+    Parameter,
+    Variable,
+    Type,
+    /// A scroll or sigil literal.
+    String,
+    Number,
+    Operator,
+    Comment,
+}
+
+impl SemanticKind {
+    /// Every kind, in the order of the legend a language server sends.
+    pub const ALL: [SemanticKind; 9] = [
+        SemanticKind::Keyword,
+        SemanticKind::Function,
+        SemanticKind::Parameter,
+        SemanticKind::Variable,
+        SemanticKind::Type,
+        SemanticKind::String,
+        SemanticKind::Number,
+        SemanticKind::Operator,
+        SemanticKind::Comment,
+    ];
+
+    /// The Language Server Protocol's name for this token type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SemanticKind::Keyword => "keyword",
+            SemanticKind::Function => "function",
+            SemanticKind::Parameter => "parameter",
+            SemanticKind::Variable => "variable",
+            SemanticKind::Type => "type",
+            SemanticKind::String => "string",
+            SemanticKind::Number => "number",
+            SemanticKind::Operator => "operator",
+            SemanticKind::Comment => "comment",
+        }
+    }
+}
+
+/// A piece of source text and how to color it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub kind: SemanticKind,
+    pub span: Span,
+}
+
+/// Classifies the tokens of `source`, in order. Punctuation and text no
+/// rule of the grammar matches are left out.
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    let tokens = tokenize(source);
+    let symbols = parse_program(source).map(|program| SymbolTable::new(&program)).unwrap_or_default();
+
+    let mut semantic = Vec::new();
+    // The line of the `we declare` whose parameters are being read
+    let mut header = None;
+    let mut in_parameters = false;
+    for (i, token) in tokens.iter().enumerate() {
+        let text = token.text(source);
+        if header != Some(token.span.line) || text == "->" {
+            header = None;
+            in_parameters = false;
+        }
+        let kind = match token.kind {
+            TokenKind::Keyword => {
+                match text {
+                    "we declare" => {
+                        header = Some(token.span.line);
+                    }
+                    "with" if header.is_some() => {
+                        in_parameters = true;
+                    }
+                    _ => {}
+                }
+                SemanticKind::Keyword
+            }
+            TokenKind::Identifier => {
+                let next = tokens.get(i + 1).map(|next: &Token| next.text(source));
+                if next == Some("with") || (header.is_some() && !in_parameters) {
+                    SemanticKind::Function
+                } else if in_parameters || is_parameter(&symbols, token) {
+                    SemanticKind::Parameter
+                } else {
+                    SemanticKind::Variable
+                }
+            }
+            TokenKind::Type => SemanticKind::Type,
+            TokenKind::String | TokenKind::Char => SemanticKind::String,
+            TokenKind::Integer | TokenKind::Float => SemanticKind::Number,
+            TokenKind::Boolean => SemanticKind::Keyword,
+            TokenKind::Operator => SemanticKind::Operator,
+            TokenKind::Comment => SemanticKind::Comment,
+            TokenKind::Punctuation | TokenKind::Unknown => {
+                continue;
+            }
+        };
+        semantic.push(SemanticToken { kind, span: token.span });
+    }
+    semantic
+}
+
+/// Whether `token` is the use of a parameter in a function body.
+fn is_parameter(symbols: &SymbolTable, token: &Token) -> bool {
+    symbols
+        .definition_at(token.span.start)
+        .is_some_and(|definition| definition.kind == SymbolKind::Parameter)
+}
@@ -0,0 +1,262 @@
+//! 🗺️ Where names are declared and used.
+//!
+//! A `SymbolTable` links each use of a name in a program to the
+//! declaration it refers to, for go-to-definition and find-references in
+//! editors, and for spotting declarations nothing reads:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Access, SymbolKind, SymbolTable };
+//!
+//! let source = "gold is a blade with 10\nwe declare spend with gold ->\ncouncil says:\n    speak gold\non the iron throne:\n    spend with gold - 1\n";
+//! let table = SymbolTable::new(&parse_program(source).unwrap());
+//!
+//! // The `gold` inside `spend` is its parameter...
+//! let inside = table.definition_at(source.find("speak gold").unwrap() + 6).unwrap();
+//! assert_eq!(inside.kind, SymbolKind::Parameter);
+//! // ...and the one in the main block is the variable declared on line 1
+//! let outside = table.definition_at(source.rfind("gold").unwrap()).unwrap();
+//! assert_eq!((outside.kind, outside.span.line), (SymbolKind::Variable, 1));
+//! assert_eq!(outside.references.len(), 1);
+//! assert_eq!(outside.references[0].access, Access::Read);
+//! ```
+//!
+//! Variables are dynamically scoped, so the table can only follow a name
+//! the way it reads on the page. Inside a function body it is the
+//! parameter of that name of the function, or of one the function is
+//! declared in, and otherwise the first declaration of the variable
+//! anywhere in the program. A function that sees its caller's parameter
+//! has its use linked to the variable.
+
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::visit::{ walk_expression, walk_statement, Visitor };
+
+/// What sort of name a definition declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+/// Whether a use of a name reads it or gives it a value. Calls read the
+/// function they call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// One use of a name, spanning just the name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    pub access: Access,
+}
+
+/// A declared name and everywhere it is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: Symbol,
+    pub kind: SymbolKind,
+    /// The statement that declares it: the function declaration for a
+    /// function or parameter, the first declaration for a variable.
+    pub span: Span,
+    /// Every use but the declaration itself, in source order. Later
+    /// declarations of a variable count as writes to it.
+    pub references: Vec<Reference>,
+}
+
+impl Definition {
+    /// Whether anything reads the name.
+    pub fn is_read(&self) -> bool {
+        self.references.iter().any(|reference| reference.access == Access::Read)
+    }
+}
+
+/// The definitions of a program, with each use linked to one; see the
+/// module docs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    definitions: Vec<Definition>,
+    /// The span of each name that uses or declares a definition, in
+    /// source order, with the definition's index.
+    uses: Vec<(Span, usize)>,
+    /// The definitions each declaration statement makes, by the offset at
+    /// which it starts.
+    declared_by: HashMap<usize, Vec<usize>>,
+}
+
+impl SymbolTable {
+    /// Builds the table for a program from the parser, since declarations
+    /// are told apart by where they start.
+    pub fn new(program: &Program) -> Self {
+        let mut declarations = Declarations::default();
+        declarations.visit_program(program);
+        let mut resolver = Resolver { declarations, scopes: Vec::new(), uses: Vec::new() };
+        resolver.visit_program(program);
+        let Declarations { table, .. } = resolver.declarations;
+        SymbolTable { uses: resolver.uses, ..table }
+    }
+
+    /// Every definition, in the order they are declared.
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    /// The definition the name at `offset` refers to, for a use or for a
+    /// variable's declaration. Function and parameter names in the header
+    /// of a declaration have no entry, since the tree doesn't keep where
+    /// they are.
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        let after = self.uses.partition_point(|(span, _)| span.start <= offset);
+        let (span, index) = self.uses[..after].last()?;
+        (offset < span.end).then(|| &self.definitions[*index])
+    }
+
+    /// The definitions `statement` declares: a function and its
+    /// parameters, or a variable if this is its first declaration.
+    pub fn declared_by(&self, statement: &Statement) -> impl Iterator<Item = &Definition> {
+        self.declared_by
+            .get(&statement.span.start)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.definitions[index])
+    }
+}
+
+/// The span of `name` at the start of a statement or expression.
+fn name_span(span: Span, name: &str) -> Span {
+    Span { end: span.start + name.len(), ..span }
+}
+
+/// Makes the definitions of a program, before any use is resolved, since
+/// functions can be called and variables read above their declarations.
+#[derive(Default)]
+struct Declarations {
+    table: SymbolTable,
+    functions: HashMap<Symbol, usize>,
+    variables: HashMap<Symbol, usize>,
+    /// The parameters of the functions being walked, whose names a
+    /// declaration in their bodies gives a new value instead.
+    parameters: Vec<Symbol>,
+}
+
+impl Declarations {
+    fn define(&mut self, statement: &Statement, name: &Symbol, kind: SymbolKind) -> usize {
+        let index = self.table.definitions.len();
+        self.table.definitions.push(Definition {
+            name: name.clone(),
+            kind,
+            span: statement.span,
+            references: Vec::new(),
+        });
+        self.table.declared_by.entry(statement.span.start).or_default().push(index);
+        index
+    }
+}
+
+impl Visitor<'_> for Declarations {
+    fn visit_statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::FunctionDeclaration { name, parameters, .. } => {
+                if !self.functions.contains_key(name) {
+                    let index = self.define(statement, name, SymbolKind::Function);
+                    self.functions.insert(name.clone(), index);
+                }
+                for parameter in parameters {
+                    self.define(statement, parameter, SymbolKind::Parameter);
+                }
+                let outer = self.parameters.len();
+                self.parameters.extend(parameters.iter().cloned());
+                walk_statement(self, statement);
+                self.parameters.truncate(outer);
+                return;
+            }
+            StatementKind::VariableDeclaration { name, .. } if
+                !self.variables.contains_key(&**name) && !self.parameters.contains(&name.symbol)
+            => {
+                let index = self.define(statement, &name.symbol, SymbolKind::Variable);
+                self.variables.insert(name.symbol.clone(), index);
+            }
+            _ => {}
+        }
+        walk_statement(self, statement);
+    }
+}
+
+/// Links each use to its definition.
+struct Resolver {
+    declarations: Declarations,
+    /// The parameters of the functions whose bodies are being walked, the
+    /// innermost last.
+    scopes: Vec<HashMap<Symbol, usize>>,
+    uses: Vec<(Span, usize)>,
+}
+
+impl Resolver {
+    fn variable(&self, name: &Symbol) -> Option<usize> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(&index) => Some(index),
+            None => self.declarations.variables.get(name).copied(),
+        }
+    }
+
+    fn refer(&mut self, index: Option<usize>, span: Span, access: Access) {
+        if let Some(index) = index {
+            self.uses.push((span, index));
+            self.declarations.table.definitions[index].references.push(Reference { span, access });
+        }
+    }
+}
+
+impl Visitor<'_> for Resolver {
+    fn visit_statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::FunctionDeclaration { parameters, .. } => {
+                let declared_by = &self.declarations.table.declared_by;
+                let definitions = declared_by.get(&statement.span.start).map_or(&[][..], Vec::as_slice);
+                // Skip the function itself, if this declaration made it
+                let first = definitions.len().saturating_sub(parameters.len());
+                let scope = parameters.iter().cloned().zip(definitions[first..].iter().copied());
+                self.scopes.push(scope.collect());
+                walk_statement(self, statement);
+                self.scopes.pop();
+                return;
+            }
+            StatementKind::VariableDeclaration { name, .. } => {
+                let index = self.variable(&name.symbol);
+                let span = name_span(statement.span, name);
+                match index {
+                    Some(index) if self.declarations.table.definitions[index].span == statement.span => {
+                        self.uses.push((span, index));
+                    }
+                    _ => self.refer(index, span, Access::Write),
+                }
+            }
+            StatementKind::Assignment { name, .. } => {
+                self.refer(self.variable(&name.symbol), name_span(statement.span, name), Access::Write);
+            }
+            StatementKind::FunctionCall { name, .. } => {
+                let index = self.declarations.functions.get(name).copied();
+                self.refer(index, name_span(statement.span, name), Access::Read);
+            }
+            _ => {}
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Identifier(name) => {
+                self.refer(self.variable(&name.symbol), expression.span, Access::Read);
+            }
+            ExpressionKind::FunctionCall { name, .. } => {
+                let index = self.declarations.functions.get(name).copied();
+                self.refer(index, name_span(expression.span, name), Access::Read);
+            }
+            _ => {}
+        }
+        walk_expression(self, expression);
+    }
+}
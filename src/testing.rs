@@ -0,0 +1,176 @@
+//! 🧪 Testing programs from Rust.
+//!
+//! A `Harness` runs a program with its input scripted a line at a time,
+//! and hands back a `RunResult` with everything it did: what it wrote to
+//! stdout and stderr, the variables it left behind, and the value it
+//! returned or the error it stopped with. Interactive programs can be
+//! tested this way without spawning the `mid_valyrian` binary.
+//!
+//! ```
+//! use mid_valyrian::testing::Harness;
+//! use mid_valyrian::Value;
+//!
+//! let source = "on the iron throne:\n    name is a scroll with ask with \"Name? \"\n    speak \"Hello, \" + name\n    return 7\n";
+//! let run = Harness::new().input("Hodor").run(source);
+//! assert_eq!(run.stdout, "Name? Hello, Hodor\n");
+//! assert_eq!(run.variable("name"), Some(&Value::String("Hodor".into())));
+//! assert_eq!(run.result.unwrap(), Value::Integer(7));
+//! ```
+//!
+//! A program that fails shows its diagnostic on stderr, as the command
+//! line would:
+//!
+//! ```
+//! use mid_valyrian::testing::Harness;
+//!
+//! let run = Harness::new().run("on the iron throne:\n    speak 1 / 0\n");
+//! assert!(run.result.is_err());
+//! assert!(run.stderr.starts_with("error[MV0104]: division by zero"));
+//! ```
+//!
+//! Asking for more input than was scripted is an error too, rather than
+//! an empty answer:
+//!
+//! ```
+//! use mid_valyrian::testing::Harness;
+//! use mid_valyrian::ValyrianError;
+//!
+//! let source = "on the iron throne:\n    first is a scroll with ask with \"\"\n    second is a scroll with ask with \"\"\n";
+//! let run = Harness::new().input("Hodor").run(source);
+//! assert!(matches!(run.result.unwrap_err().root(), ValyrianError::IoError { .. }));
+//! assert!(run.stderr.contains("the scripted input ran out"));
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{ self, BufRead, Cursor, Read };
+use crate::ast::Value;
+use crate::error::ValyrianError;
+use crate::interpreter::OutputBuffer;
+use crate::parser::parse_program;
+use crate::passes::PassPipeline;
+use crate::RunOptions;
+
+/// Runs programs with scripted input, capturing what they do.
+#[derive(Debug, Clone)]
+pub struct Harness {
+    inputs: Vec<String>,
+    options: RunOptions,
+    name: String,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self { inputs: Vec::new(), options: RunOptions::default(), name: "main.mv".into() }
+    }
+}
+
+/// What a program run by a `Harness` did.
+#[derive(Debug)]
+pub struct RunResult {
+    /// Everything the program spoke, prompts included.
+    pub stdout: String,
+    /// What debug mode showed, then the diagnostic of the error the
+    /// program stopped with, if any.
+    pub stderr: String,
+    /// The variables bound when the program ended, even if it failed.
+    pub variables: BTreeMap<String, Value>,
+    /// The value the main block returned, or why the program failed.
+    pub result: Result<Value, ValyrianError>,
+}
+
+impl RunResult {
+    /// The value `name` was left with, if it was bound.
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a line the program reads when it next asks for input. Once the
+    /// scripted lines run out, asking for input fails.
+    pub fn input(mut self, line: impl Into<String>) -> Self {
+        self.inputs.push(line.into());
+        self
+    }
+
+    /// Adds several lines of input, in order; see `input`.
+    pub fn inputs<S: Into<String>>(mut self, lines: impl IntoIterator<Item = S>) -> Self {
+        self.inputs.extend(lines.into_iter().map(Into::into));
+        self
+    }
+
+    /// Runs programs with `options`, such as limits or arguments.
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Names the source in diagnostics (`main.mv` unless set).
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Parses and runs `source` in a fresh interpreter. The harness can
+    /// run any number of programs, each with all of its input.
+    pub fn run(&self, source: &str) -> RunResult {
+        let stdout = OutputBuffer::new();
+        let stderr = OutputBuffer::new();
+        let input = self.inputs.iter().map(|line| format!("{}\n", line)).collect::<String>();
+        let mut interpreter = self.options
+            .builder()
+            .stdout(stdout.clone())
+            .debug_output(stderr.clone())
+            .stdin(ScriptedInput(Cursor::new(input)))
+            .build();
+
+        let result = parse_program(source).and_then(|mut program| {
+            PassPipeline::standard().run(&mut program, self.options.optimization);
+            interpreter.interpret_with_result(&program)
+        });
+        let mut stderr = stderr.contents();
+        if let Err(error) = &result {
+            stderr.push_str(&error.to_diagnostic().render(source, &self.name));
+        }
+        RunResult {
+            stdout: stdout.contents(),
+            stderr,
+            variables: interpreter
+                .variables()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            result,
+        }
+    }
+}
+
+/// The scripted lines of a `Harness`, which fail to read once they have
+/// all been read.
+struct ScriptedInput(Cursor<String>);
+
+impl Read for ScriptedInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl BufRead for ScriptedInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.0.position() as usize >= self.0.get_ref().len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the scripted input ran out"));
+        }
+        self.0.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.0.consume(amount);
+    }
+}
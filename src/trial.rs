@@ -0,0 +1,68 @@
+//! ⚖️ Running `trial` blocks.
+//!
+//! A trial is a named block of statements, usually ending in calls to the
+//! `assert` and `assert_equal` builtins:
+//!
+//! ```text
+//! trial "doubling works":
+//!     assert_equal with (double with 2), 4
+//! ```
+//!
+//! Ordinary runs skip trials. Each trial runs in its own interpreter, after
+//! the program's top-level declarations (but not its main block), so trials
+//! cannot affect one another.
+
+use crate::ast::{ Program, Statement, StatementKind };
+use crate::error::ValyrianError;
+use crate::interpreter::Interpreter;
+
+/// The outcome of one trial.
+#[derive(Debug)]
+pub struct TrialResult {
+    pub name: String,
+    pub outcome: Result<(), ValyrianError>,
+}
+
+impl TrialResult {
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// Iterates over the top-level trials of a program, as `(name, body)`.
+pub fn trials(program: &Program) -> impl Iterator<Item = (&str, &[Statement])> {
+    program.statements.iter().filter_map(|statement| match &statement.kind {
+        StatementKind::Trial { name, body } => Some((name.as_str(), body.as_slice())),
+        _ => None,
+    })
+}
+
+/// Runs every trial in the program, each in a fresh interpreter.
+pub fn run_trials(program: &Program) -> Vec<TrialResult> {
+    run_trials_with(program, || Interpreter::new(false))
+}
+
+/// Runs every trial in the program, each in a fresh interpreter from
+/// `new_interpreter` (to install observers such as `Coverage`, say).
+pub fn run_trials_with(
+    program: &Program,
+    mut new_interpreter: impl FnMut() -> Interpreter
+) -> Vec<TrialResult> {
+    let setup: Vec<Statement> = program.statements
+        .iter()
+        .filter(|statement| {
+            !matches!(statement.kind, StatementKind::MainBlock(_) | StatementKind::Trial { .. })
+        })
+        .cloned()
+        .collect();
+
+    trials(program)
+        .map(|(name, body)| {
+            let mut interpreter = new_interpreter();
+            let outcome = interpreter
+                .run_statements(&setup)
+                .and_then(|()| interpreter.run_statements(body));
+            TrialResult { name: name.to_string(), outcome }
+        })
+        .collect()
+}
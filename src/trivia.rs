@@ -0,0 +1,164 @@
+//! Comments and blank lines, kept beside the tree.
+//!
+//! The parser drops everything that doesn't change what a program does.
+//! `parse_program_with_trivia` also gathers the comments and blank lines
+//! around each statement, so `to_source_with_trivia` can reformat a file
+//! without losing them:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program_with_trivia, to_source_with_trivia };
+//!
+//! let source = "// The realm's greeting\non the iron throne:\n  speak   \"hi\"  // loudly\n";
+//! let (program, trivia) = parse_program_with_trivia(source).unwrap();
+//! assert_eq!(
+//!     to_source_with_trivia(&program, &trivia),
+//!     "// The realm's greeting\non the iron throne:\n    speak \"hi\" // loudly\n"
+//! );
+//! ```
+//!
+//! A comment stays on its statement's first line, or on lines of its own
+//! just above it. One on a line no statement starts on (such as `else:`),
+//! or at the end of a block, moves above the next statement instead, and
+//! runs of blank lines shrink to one.
+
+use std::collections::HashMap;
+use crate::ast::{ Program, Statement };
+use crate::visit::{ walk_statement, Visitor };
+
+/// A piece of a line the parser skips.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivium {
+    /// A `//` comment, from the slashes to the end of the line.
+    Comment(String),
+    /// One or more empty lines.
+    BlankLine,
+}
+
+/// The trivia attached to one statement.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatementTrivia {
+    /// Comments and blank lines on the lines before the statement.
+    pub leading: Vec<Trivium>,
+    /// A comment after the code on the statement's first line.
+    pub trailing: Option<String>,
+}
+
+/// The comments and blank lines of a source file, by the statement they
+/// belong to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trivia {
+    /// Keyed by the offset at which the statement starts.
+    statements: HashMap<usize, StatementTrivia>,
+    /// Comments after the last statement.
+    pub end: Vec<Trivium>,
+}
+
+impl Trivia {
+    /// The trivia around `statement`, if it has any.
+    pub fn get(&self, statement: &Statement) -> Option<&StatementTrivia> {
+        self.statements.get(&statement.span.start)
+    }
+
+    /// Gathers the trivia of `source`, which `program` was parsed from.
+    pub(crate) fn collect(source: &str, program: &Program) -> Self {
+        let mut starts = StatementStarts::default();
+        starts.visit_program(program);
+
+        let mut trivia = Trivia::default();
+        let mut pending = Vec::new();
+        for (number, line) in lines(source).into_iter().enumerate() {
+            match line {
+                Line::Blank => {
+                    if pending.last() != Some(&Trivium::BlankLine) {
+                        pending.push(Trivium::BlankLine);
+                    }
+                }
+                Line::Comment(comment) => pending.push(Trivium::Comment(comment)),
+                Line::Code(comment) => {
+                    match starts.0.get(&(number + 1)) {
+                        Some(&start) => {
+                            let leading = std::mem::take(&mut pending);
+                            let statement = StatementTrivia { leading, trailing: comment };
+                            trivia.statements.insert(start, statement);
+                        }
+                        // A line such as `else:`, so the comment goes with
+                        // the statement after it
+                        None => pending.extend(comment.map(Trivium::Comment)),
+                    }
+                }
+            }
+        }
+        while pending.last() == Some(&Trivium::BlankLine) {
+            pending.pop();
+        }
+        trivia.end = pending;
+        trivia
+    }
+}
+
+/// Maps the line each statement starts on to its offset.
+#[derive(Default)]
+struct StatementStarts(HashMap<usize, usize>);
+
+impl Visitor<'_> for StatementStarts {
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.0.insert(statement.span.line, statement.span.start);
+        walk_statement(self, statement);
+    }
+}
+
+/// What a line of source holds, as far as trivia goes.
+enum Line {
+    Blank,
+    /// Nothing but a comment.
+    Comment(String),
+    /// Code, with the comment that ends the line if there is one.
+    Code(Option<String>),
+}
+
+/// Sorts the lines of `source`, skipping over the text of literals. A
+/// scroll that runs over several lines counts as code on each of them.
+fn lines(source: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut in_string = false;
+    for (number, line) in source.split('\n').enumerate() {
+        let shebang = number == 0 && line.starts_with("#!");
+        let mut has_code = in_string || shebang;
+        let mut comment = None;
+        let mut chars = line.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if in_string {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => {
+                        in_string = false;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '/' if line[i..].starts_with("//") && !shebang => {
+                    comment = Some(line[i..].trim_end().to_string());
+                    break;
+                }
+                '"' => {
+                    in_string = true;
+                }
+                '\'' => {
+                    chars.nth(1);
+                }
+                _ => {}
+            }
+            has_code |= !c.is_whitespace();
+        }
+        lines.push(match (has_code, comment) {
+            (false, None) => Line::Blank,
+            (false, Some(comment)) => Line::Comment(comment),
+            (true, comment) => Line::Code(comment),
+        });
+    }
+    lines
+}
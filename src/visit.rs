@@ -0,0 +1,199 @@
+//! 🧭 Walking the AST.
+//!
+//! A `Visitor` is called for every block, statement, and expression of a
+//! program. Each method only visits the children of its node unless
+//! overridden, so a pass handles the nodes it cares about and calls the
+//! matching `walk_` function to carry on below them:
+//!
+//! ```
+//! use mid_valyrian::{ parse_program, Expression, ExpressionKind };
+//! use mid_valyrian::visit::{ walk_expression, Visitor };
+//!
+//! /// Counts the variables read.
+//! struct Reads(usize);
+//!
+//! impl Visitor<'_> for Reads {
+//!     fn visit_expression(&mut self, expression: &Expression) {
+//!         if let ExpressionKind::Identifier(_) = expression.kind {
+//!             self.0 += 1;
+//!         }
+//!         walk_expression(self, expression);
+//!     }
+//! }
+//!
+//! let program = parse_program("on the iron throne:\n    if a > b:\n        speak a + 1\n").unwrap();
+//! let mut reads = Reads(0);
+//! reads.visit_program(&program);
+//! assert_eq!(reads.0, 3);
+//! ```
+//!
+//! `VisitorMut` is the same for passes that rewrite the tree in place.
+//! Blocks come to it as the `Vec` that holds them, so statements can be
+//! dropped or spliced in.
+
+use crate::ast::*;
+
+/// Called on each node of a program; see the module docs. Nodes are
+/// borrowed for `'ast`, so a visitor can keep references into the tree.
+pub trait Visitor<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        self.visit_block(&program.statements);
+    }
+
+    fn visit_block(&mut self, statements: &'ast [Statement]) {
+        walk_block(self, statements);
+    }
+
+    fn visit_statement(&mut self, statement: &'ast Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &'ast Expression) {
+        walk_expression(self, expression);
+    }
+}
+
+/// Visits each statement of a block.
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    statements: &'ast [Statement]
+) {
+    for statement in statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+/// Visits the expressions and blocks of a statement, in source order.
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    statement: &'ast Statement
+) {
+    match &statement.kind {
+        StatementKind::VariableDeclaration { value: Some(value), .. } |
+        StatementKind::Assignment { value, .. } |
+        StatementKind::Speak(value) |
+        StatementKind::Return(Some(value)) => visitor.visit_expression(value),
+        StatementKind::FunctionCall { arguments, .. } => {
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        StatementKind::Conditional { condition, then_branch, else_branch } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_block(else_branch);
+            }
+        }
+        StatementKind::WhileLoop { condition, body } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+        }
+        StatementKind::FunctionDeclaration { body, .. } |
+        StatementKind::ForLoop { body, .. } |
+        StatementKind::MainBlock(body) |
+        StatementKind::Trial { body, .. } => visitor.visit_block(body),
+        StatementKind::VariableDeclaration { value: None, .. } | StatementKind::Return(None) => {}
+    }
+}
+
+/// Visits the operands and arguments of an expression, left to right.
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    expression: &'ast Expression
+) {
+    match &expression.kind {
+        ExpressionKind::Binary { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        ExpressionKind::Unary { operand, .. } => visitor.visit_expression(operand),
+        ExpressionKind::SendRaven(call) => visitor.visit_expression(call),
+        ExpressionKind::AwaitRaven(raven) => visitor.visit_expression(raven),
+        ExpressionKind::Ask(prompt) => visitor.visit_expression(prompt),
+        ExpressionKind::FunctionCall { arguments, .. } => {
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        ExpressionKind::Literal(_) | ExpressionKind::Identifier(_) | ExpressionKind::Input(_) => {}
+    }
+}
+
+/// Called on each node of a program, with leave to change it; see the
+/// module docs.
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        self.visit_block_mut(&mut program.statements);
+    }
+
+    fn visit_block_mut(&mut self, statements: &mut Vec<Statement>) {
+        walk_block_mut(self, statements);
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+    }
+}
+
+/// Visits each statement of a block.
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statements: &mut Vec<Statement>) {
+    for statement in statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+/// Visits the expressions and blocks of a statement, in source order.
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::VariableDeclaration { value: Some(value), .. } |
+        StatementKind::Assignment { value, .. } |
+        StatementKind::Speak(value) |
+        StatementKind::Return(Some(value)) => visitor.visit_expression_mut(value),
+        StatementKind::FunctionCall { arguments, .. } => {
+            for argument in arguments {
+                visitor.visit_expression_mut(argument);
+            }
+        }
+        StatementKind::Conditional { condition, then_branch, else_branch } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_block_mut(else_branch);
+            }
+        }
+        StatementKind::WhileLoop { condition, body } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(body);
+        }
+        StatementKind::FunctionDeclaration { body, .. } |
+        StatementKind::ForLoop { body, .. } |
+        StatementKind::MainBlock(body) |
+        StatementKind::Trial { body, .. } => visitor.visit_block_mut(body),
+        StatementKind::VariableDeclaration { value: None, .. } | StatementKind::Return(None) => {}
+    }
+}
+
+/// Visits the operands and arguments of an expression, left to right.
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match &mut expression.kind {
+        ExpressionKind::Binary { left, right, .. } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(right);
+        }
+        ExpressionKind::Unary { operand, .. } => visitor.visit_expression_mut(operand),
+        ExpressionKind::SendRaven(call) => visitor.visit_expression_mut(call),
+        ExpressionKind::AwaitRaven(raven) => visitor.visit_expression_mut(raven),
+        ExpressionKind::Ask(prompt) => visitor.visit_expression_mut(prompt),
+        ExpressionKind::FunctionCall { arguments, .. } => {
+            for argument in arguments {
+                visitor.visit_expression_mut(argument);
+            }
+        }
+        ExpressionKind::Literal(_) | ExpressionKind::Identifier(_) | ExpressionKind::Input(_) => {}
+    }
+}
@@ -0,0 +1,94 @@
+//! 🌐 Running programs in the browser.
+//!
+//! With the `wasm` feature, `wasm-pack build --features wasm` produces a
+//! package an online playground can call entirely client-side:
+//!
+//! ```text
+//! import init, { run_code_wasm } from "./pkg/mid_valyrian.js";
+//!
+//! await init();
+//! const run = run_code_wasm('on the iron throne:\n    speak "Valar Morghulis"\n');
+//! console.log(run.output, run.diagnostics, run.success);
+//! ```
+//!
+//! Programs never touch the real stdin or stdout: what they speak is
+//! captured, input comes from a string, and `sleep` returns immediately.
+
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+use crate::analysis::analyze;
+use crate::interpreter::{ Interpreter, OutputBuffer };
+use crate::parser::parse_program;
+
+/// The name programs are given in diagnostics.
+const SOURCE_NAME: &str = "<playground>";
+
+/// What a playground run produced.
+#[wasm_bindgen]
+pub struct PlaygroundRun {
+    output: String,
+    diagnostics: Vec<String>,
+    success: bool,
+}
+
+#[wasm_bindgen]
+impl PlaygroundRun {
+    /// Everything the program spoke, including input prompts.
+    #[wasm_bindgen(getter)]
+    pub fn output(&self) -> String {
+        self.output.clone()
+    }
+
+    /// Rendered warnings, then the error that stopped the run, if any.
+    #[wasm_bindgen(getter)]
+    pub fn diagnostics(&self) -> Vec<String> {
+        self.diagnostics.clone()
+    }
+
+    /// Whether the program parsed and ran to the end.
+    #[wasm_bindgen(getter)]
+    pub fn success(&self) -> bool {
+        self.success
+    }
+}
+
+/// Runs a program with no input; see `run_code_wasm_with_input`.
+#[wasm_bindgen]
+pub fn run_code_wasm(code: &str) -> JsValue {
+    run_playground(code, "").into()
+}
+
+/// Runs a program, answering `speaks for input` with the lines of `input`.
+#[wasm_bindgen]
+pub fn run_code_wasm_with_input(code: &str, input: &str) -> JsValue {
+    run_playground(code, input).into()
+}
+
+fn run_playground(code: &str, input: &str) -> PlaygroundRun {
+    let output = OutputBuffer::new();
+    let mut diagnostics = Vec::new();
+
+    let result = parse_program(code).and_then(|program| {
+        diagnostics.extend(
+            analyze(&program)
+                .iter()
+                .map(|warning| warning.to_diagnostic().render(code, SOURCE_NAME))
+        );
+        let mut interpreter = Interpreter::builder()
+            .stdout(output.clone())
+            .stdin(Cursor::new(input.to_string()))
+            .build();
+        // Blocking would freeze the page
+        interpreter.set_sleeper(|_| Ok(()));
+        interpreter.interpret(&program)
+    });
+    if let Err(error) = &result {
+        diagnostics.push(error.to_diagnostic().render(code, SOURCE_NAME));
+    }
+
+    PlaygroundRun {
+        output: output.contents(),
+        diagnostics,
+        success: result.is_ok(),
+    }
+}
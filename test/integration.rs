@@ -1,12 +1,867 @@
 #[cfg(test)]
 mod tests {
-    use assert_cmd::Command;
+    use std::io::{ Read, Write };
+    use std::path::{ Path, PathBuf };
+    use std::process::{ Command, Output, Stdio };
+
+    fn mid_valyrian() -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_mid-valyrian"));
+        cmd.arg("--quiet");
+        cmd
+    }
+
+    /// An empty directory of its own for one test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mid-valyrian-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, file: &str, text: &str) {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    fn stderr(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    /// Runs each statement in a program of its own under `flags`, which
+    /// must fail with `code` and say `message`.
+    fn assert_refused(name: &str, flags: &[&str], statements: &[&str], code: &str, message: &str) {
+        let dir = scratch_dir(name);
+        for (i, statement) in statements.iter().enumerate() {
+            let file = dir.join(format!("{}.mv", i));
+            std::fs::write(&file, format!("on the iron throne:\n    {}\n", statement)).unwrap();
+            let output = mid_valyrian().arg("--plain").args(flags).arg("run").arg(&file).output().unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(3), "{}: {}", statement, stderr);
+            assert!(stderr.contains(code), "{}", stderr);
+            assert!(stderr.contains(message), "{}", stderr);
+            assert_eq!(stdout(&output), "");
+        }
+    }
 
     #[test]
     fn test_hello() {
-        let mut cmd = Command::cargo_bin("mid_valyrian").unwrap();
-        cmd.arg("examples/hello.mv");
-        cmd.assert().success()
-           .stdout(predicates::str::contains("Valar morghulis!"));
+        let output = mid_valyrian().arg("examples/hello.mv").output().unwrap();
+        assert!(output.status.success());
+        assert!(stdout(&output).contains("Valar Morghulis"));
+    }
+
+    #[test]
+    fn test_strict_mode_turns_soft_issues_into_errors() {
+        let dir = scratch_dir("strict");
+        let programs = [
+            (
+                "discarded.mv",
+                "we declare one with ->\ncouncil says:\n    return 1\n\non the iron throne:\n    one with\n    speak \"done\"\n",
+                "the value returned by 'one' is never used",
+            ),
+            (
+                "mismatch.mv",
+                "on the iron throne:\n    gold is a blade with \"coins\"\n    speak gold\n",
+                "expected blade, found string",
+            ),
+        ];
+        for (file, source, message) in programs {
+            write(&dir, file, source);
+            let lenient = mid_valyrian().arg(dir.join(file)).output().unwrap();
+            assert!(lenient.status.success(), "{}: {}", file, stderr(&lenient));
+
+            let strict = mid_valyrian().arg("--strict").arg(dir.join(file)).output().unwrap();
+            assert!(!strict.status.success(), "{}", file);
+            assert!(stderr(&strict).to_lowercase().contains(message), "{}", stderr(&strict));
+        }
+    }
+
+    #[test]
+    fn test_unused_names_warn_and_deny_warnings_refuses_to_run() {
+        let dir = scratch_dir("unused");
+        write(
+            &dir,
+            "unused.mv",
+            "we declare greet with name ->\ncouncil says:\n    speak \"Hello\"\n\non the iron throne:\n    gold is a blade with 1\n    greet with \"Arya\"\n"
+        );
+        let warned = mid_valyrian().arg(dir.join("unused.mv")).output().unwrap();
+        assert!(warned.status.success(), "{}", stderr(&warned));
+        assert!(stderr(&warned).contains("variable 'gold' is declared but never read"), "{}", stderr(&warned));
+        assert!(stderr(&warned).contains("parameter 'name' of 'greet' is never used"), "{}", stderr(&warned));
+        assert!(stdout(&warned).ends_with("Hello\n"));
+
+        let denied = mid_valyrian().arg("--deny-warnings").arg(dir.join("unused.mv")).output().unwrap();
+        assert!(!denied.status.success());
+        assert!(!stdout(&denied).contains("Hello"));
+    }
+
+    #[test]
+    fn test_exit_codes_tell_failures_apart() {
+        let dir = scratch_dir("exit-codes");
+        write(&dir, "parse.mv", "on the iron throne:\n    speak (1 +\n");
+        write(&dir, "runtime.mv", "on the iron throne:\n    speak missing\n");
+        write(&dir, "warned.mv", "on the iron throne:\n    gold is a blade with 1\n");
+        let runs: [(&[&str], &str, i32); 4] = [
+            (&[], "parse.mv", 2),
+            (&["--deny-warnings"], "warned.mv", 2),
+            (&[], "runtime.mv", 3),
+            (&[], "missing.mv", 4),
+        ];
+        for (flags, file, code) in runs {
+            let output = mid_valyrian().args(flags).arg(dir.join(file)).output().unwrap();
+            assert_eq!(output.status.code(), Some(code), "{}: {}", file, stderr(&output));
+        }
+    }
+
+    #[test]
+    fn test_eval_runs_code_from_the_command_line() {
+        let output = mid_valyrian().args(["-e", "speak 6 * 7"]).output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).ends_with("42\n"), "{}", stdout(&output));
+
+        let failed = mid_valyrian().args(["--eval", "speak missing"]).output().unwrap();
+        assert_eq!(failed.status.code(), Some(3));
+        assert!(stderr(&failed).contains("<eval>"), "{}", stderr(&failed));
+
+        let both = mid_valyrian().args(["-e", "speak 1", "examples/hello.mv"]).output().unwrap();
+        assert!(!both.status.success());
+        assert!(!stdout(&both).contains("Valar Morghulis"));
+    }
+
+    #[test]
+    fn test_dash_reads_the_program_from_stdin() {
+        let mut child = mid_valyrian()
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"on the iron throne:\n    speak \"from the pipe\"\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).ends_with("from the pipe\n"), "{}", stdout(&output));
+    }
+
+    #[test]
+    fn test_arguments_after_dashes_reach_the_script() {
+        let dir = scratch_dir("script-args");
+        write(&dir, "args.mv", "on the iron throne:\n    speak arg_count with\n    speak arg with 1\n");
+        let output = mid_valyrian().arg(dir.join("args.mv")).args(["--", "Jon", "Snow"]).output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).ends_with("2\nSnow\n"), "{}", stdout(&output));
+
+        let short = mid_valyrian().arg(dir.join("args.mv")).args(["--", "Jon"]).output().unwrap();
+        assert_eq!(short.status.code(), Some(3));
+        assert!(stderr(&short).contains("there is no script argument 1"), "{}", stderr(&short));
+    }
+
+    #[test]
+    fn test_check_parses_without_running() {
+        let dir = scratch_dir("check");
+        write(&dir, "sound.mv", "on the iron throne:\n    speak \"ran\"\n");
+        write(&dir, "broken.mv", "on the iron throne:\n    speak (1 +\n");
+        write(&dir, "mistyped.mv", "on the iron throne:\n    gold is a blade with \"coins\"\n    speak gold\n");
+
+        let sound = mid_valyrian().arg("check").arg(dir.join("sound.mv")).output().unwrap();
+        assert!(sound.status.success(), "{}", stderr(&sound));
+        assert!(stdout(&sound).contains("the scroll is sound"));
+        assert!(!stdout(&sound).contains("ran"));
+
+        // Every file is checked, even after one fails
+        let both = mid_valyrian()
+            .arg("check")
+            .arg(dir.join("broken.mv"))
+            .arg(dir.join("sound.mv"))
+            .output()
+            .unwrap();
+        assert_eq!(both.status.code(), Some(2), "{}", stderr(&both));
+        assert!(stdout(&both).contains("sound.mv: the scroll is sound"), "{}", stdout(&both));
+
+        let typed = mid_valyrian().args(["check", "--typecheck"]).arg(dir.join("mistyped.mv")).output().unwrap();
+        assert_eq!(typed.status.code(), Some(2), "{}", stderr(&typed));
+        assert!(stderr(&typed).contains("expected blade, found scroll"), "{}", stderr(&typed));
+    }
+
+    #[test]
+    fn test_ast_prints_the_parse_tree() {
+        let json = mid_valyrian().args(["ast", "examples/simple.mv"]).output().unwrap();
+        assert!(json.status.success(), "{}", stderr(&json));
+        // Nothing but the tree goes to stdout, so it can be piped on
+        let tree = stdout(&json);
+        assert!(tree.starts_with('{') && tree.trim_end().ends_with('}'), "{}", tree);
+        assert!(tree.contains("\"MainBlock\"") && tree.contains("\"Speak\""), "{}", tree);
+
+        let debug = mid_valyrian().args(["ast", "--format", "debug", "examples/simple.mv"]).output().unwrap();
+        assert!(debug.status.success(), "{}", stderr(&debug));
+        assert!(stdout(&debug).starts_with("Program {"), "{}", stdout(&debug));
+
+        let dir = scratch_dir("ast");
+        write(&dir, "broken.mv", "on the iron throne:\n    speak (1 +\n");
+        let broken = mid_valyrian().arg("ast").arg(dir.join("broken.mv")).output().unwrap();
+        assert_eq!(broken.status.code(), Some(2));
+        assert_eq!(stdout(&broken), "");
+    }
+
+    #[test]
+    fn test_fmt_rewrites_files_in_the_canonical_style() {
+        let dir = scratch_dir("fmt");
+        let messy = "on the iron throne:\n  gold   is a blade with 1+2\n  speak gold\n";
+        write(&dir, "messy.mv", messy);
+        let file = dir.join("messy.mv");
+
+        let checked = mid_valyrian().args(["fmt", "--check"]).arg(&file).output().unwrap();
+        assert!(!checked.status.success());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), messy);
+
+        let formatted = mid_valyrian().arg("fmt").arg(&file).output().unwrap();
+        assert!(formatted.status.success(), "{}", stderr(&formatted));
+        let tidy = std::fs::read_to_string(&file).unwrap();
+        assert_ne!(tidy, messy);
+        assert!(tidy.contains("gold is a blade with 1 + 2"), "{}", tidy);
+
+        let rechecked = mid_valyrian().args(["fmt", "--check"]).arg(&file).output().unwrap();
+        assert!(rechecked.status.success(), "{}", stdout(&rechecked));
+        let ran = mid_valyrian().arg(&file).output().unwrap();
+        assert!(stdout(&ran).ends_with("3\n"), "{}", stdout(&ran));
+    }
+
+    #[test]
+    fn test_lint_reports_the_selected_rules() {
+        let dir = scratch_dir("lint");
+        write(
+            &dir,
+            "suspicious.mv",
+            "on the iron throne:\n    gold is a blade with 1\n    if 1 < 2:\n        speak \"always\"\n"
+        );
+        let file = dir.join("suspicious.mv");
+        let lint = |rules: &[&str]| mid_valyrian().arg("lint").args(rules).arg(&file).output().unwrap();
+        let unused = "variable 'gold' is declared but never read";
+        let constant = "this condition is always aye";
+
+        let all = lint(&[]);
+        assert_eq!(all.status.code(), Some(1));
+        assert!(stderr(&all).contains(unused) && stderr(&all).contains(constant), "{}", stderr(&all));
+
+        let disabled = lint(&["--disable", "unused-variable"]);
+        assert_eq!(disabled.status.code(), Some(1));
+        assert!(!stderr(&disabled).contains(unused) && stderr(&disabled).contains(constant));
+
+        let enabled = lint(&["--enable", "unused-variable"]);
+        assert_eq!(enabled.status.code(), Some(1));
+        assert!(stderr(&enabled).contains(unused) && !stderr(&enabled).contains(constant));
+
+        let quiet = lint(&["--disable", "unused-variable", "--disable", "constant-condition"]);
+        assert_eq!(quiet.status.code(), Some(0), "{}", stderr(&quiet));
+        assert!(stdout(&quiet).contains("the maesters found nothing amiss"));
+
+        let unknown = lint(&["--disable", "dragons"]);
+        assert!(!unknown.status.success());
+        assert!(stderr(&unknown).contains("dragons"));
+    }
+
+    #[test]
+    fn test_verify_compares_output_with_expected_files() {
+        let dir = scratch_dir("verify");
+        write(&dir, "count.mv", "on the iron throne:\n    speak 1\n    speak 2\n");
+        let verify = |flags: &[&str]| mid_valyrian().arg("verify").args(flags).arg(&dir).output().unwrap();
+
+        let missing = verify(&[]);
+        assert_eq!(missing.status.code(), Some(1));
+        assert!(stdout(&missing).contains("run with --bless to create it"), "{}", stdout(&missing));
+
+        write(&dir, "count.expected", "1\n3\n");
+        let mismatched = verify(&[]);
+        assert_eq!(mismatched.status.code(), Some(1));
+        assert!(stdout(&mismatched).contains("MISMATCH"), "{}", stdout(&mismatched));
+        assert!(stderr(&mismatched).contains("first difference at line 2"), "{}", stderr(&mismatched));
+
+        let blessed = verify(&["--bless"]);
+        assert!(blessed.status.success(), "{}", stderr(&blessed));
+        assert_eq!(std::fs::read_to_string(dir.join("count.expected")).unwrap(), "1\n2\n");
+        let matched = verify(&[]);
+        assert!(matched.status.success(), "{}", stderr(&matched));
+        assert!(stdout(&matched).contains("... ok"), "{}", stdout(&matched));
+    }
+
+    #[test]
+    fn test_transpile_writes_python_that_prints_the_same() {
+        let dir = scratch_dir("transpile");
+        write(
+            &dir,
+            "double.mv",
+            "we declare double with n ->\ncouncil says:\n    return n * 2\n\non the iron throne:\n    gold is a blade with 7 / 2\n    speak double with gold\n    speak \"done\"\n"
+        );
+        let output = mid_valyrian()
+            .args(["transpile", "-o"])
+            .arg(dir.join("double.py"))
+            .arg(dir.join("double.mv"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        let python = std::fs::read_to_string(dir.join("double.py")).unwrap();
+        assert!(python.contains("def double(n):"), "{}", python);
+
+        // Python may not be installed where the tests run
+        if let Ok(run) = Command::new("python3").arg(dir.join("double.py")).output() {
+            assert!(run.status.success(), "{}", stderr(&run));
+            assert_eq!(stdout(&run), "6\ndone\n");
+        }
+
+        write(
+            &dir,
+            "dynamic.mv",
+            "we declare inner with ->\ncouncil says:\n    return n\n\nwe declare outer with n ->\ncouncil says:\n    return inner with\n\non the iron throne:\n    speak outer with 1\n"
+        );
+        let refused = mid_valyrian().args(["transpile"]).arg(dir.join("dynamic.mv")).output().unwrap();
+        assert_eq!(refused.status.code(), Some(2), "{}", stderr(&refused));
+        assert!(stderr(&refused).contains("MV0301"), "{}", stderr(&refused));
+        assert_eq!(stdout(&refused), "");
+    }
+
+    #[test]
+    fn test_project_runs_added_library() {
+        let project = scratch_dir("add-run");
+        let library = scratch_dir("add-run-library");
+        write(&project, "valyrian.toml", "[project]\nname = \"winterfell\"\n");
+        write(&project, "main.mv", "on the iron throne:\n    greet with \"Arya\"\n");
+        write(
+            &library,
+            "greetings.mv",
+            "we declare greet with name ->\ncouncil says:\n    speak \"Hello, \" + name\n"
+        );
+
+        let added = mid_valyrian()
+            .current_dir(&project)
+            .arg("add")
+            .arg(library.join("greetings.mv"))
+            .output()
+            .unwrap();
+        assert!(added.status.success(), "{:?}", added);
+        let run = mid_valyrian().current_dir(&project).arg("run").output().unwrap();
+        assert!(run.status.success(), "{:?}", run);
+        assert_eq!(stdout(&run), "Hello, Arya\n");
+    }
+
+    #[test]
+    fn test_project_runs_sources() {
+        let project = scratch_dir("sources");
+        write(
+            &project,
+            "valyrian.toml",
+            "[project]\nentry = \"src/main.mv\"\nsources = [\"src\", \"lib\"]\n"
+        );
+        write(&project, "src/main.mv", "on the iron throne:\n    speak double with 21\n");
+        write(&project, "lib/math.mv", "we declare double with n ->\ncouncil says:\n    return n * 2\n");
+
+        let run = mid_valyrian().current_dir(&project).arg("run").output().unwrap();
+        assert!(run.status.success(), "{:?}", run);
+        assert_eq!(stdout(&run), "42\n");
+    }
+
+    #[test]
+    fn test_project_links_added_library_once() {
+        // The library sits in the project root, which is also its sources
+        let project = scratch_dir("add-in-project");
+        write(&project, "valyrian.toml", "[project]\nname = \"riverrun\"\n");
+        write(&project, "main.mv", "on the iron throne:\n    speak double with 4\n");
+        write(&project, "math.mv", "we declare double with n ->\ncouncil says:\n    return n * 2\n");
+
+        let added = mid_valyrian().current_dir(&project).args(["add", "math.mv"]).output().unwrap();
+        assert!(added.status.success(), "{:?}", added);
+        let run = mid_valyrian().current_dir(&project).arg("run").output().unwrap();
+        assert!(run.status.success(), "{:?}", run);
+        assert_eq!(stdout(&run), "8\n");
+    }
+
+    #[test]
+    fn test_blade_overflow_is_an_error_on_both_backends() {
+        let dir = scratch_dir("overflow");
+        let programs = [
+            ("add.mv", "speak 9223372036854775807 + 1", "9223372036854775807 + 1"),
+            ("subtract.mv", "speak (0 - 9223372036854775807) - 2", "-9223372036854775807 - 2"),
+            ("multiply.mv", "speak 4611686018427387904 * 2", "4611686018427387904 * 2"),
+            ("divide.mv", "speak (0 - 9223372036854775807 - 1) / (0 - 1)", "-9223372036854775808 / -1"),
+            ("negate.mv", "low is a blade with 0 - 9223372036854775807 - 1\n    speak -low", "-(-9223372036854775808)"),
+        ];
+        for (file, statement, operation) in programs {
+            write(&dir, file, &format!("on the iron throne:\n    {}\n", statement));
+            for backend in ["tree", "bytecode"] {
+                let output = mid_valyrian()
+                    .args(["--plain", "run", "--backend", backend])
+                    .arg(dir.join(file))
+                    .output()
+                    .unwrap();
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert_eq!(output.status.code(), Some(3), "{} on {}: {}", file, backend, stderr);
+                assert!(stderr.contains(&format!("{} is too big for a blade", operation)), "{}", stderr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sleep_ends_at_the_timeout() {
+        let dir = scratch_dir("sleep-timeout");
+        write(&dir, "sleep.mv", "on the iron throne:\n    sleep with 5000\n    speak \"awake\"\n");
+        let started = std::time::Instant::now();
+        let output = mid_valyrian()
+            .args(["--plain", "--timeout", "300ms", "run"])
+            .arg(dir.join("sleep.mv"))
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(3), "{}", stderr);
+        assert!(stderr.contains("MV0110"), "{}", stderr);
+        assert_eq!(stdout(&output), "");
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_trials_obey_the_run_flags() {
+        let dir = scratch_dir("trial-flags");
+        write(
+            &dir,
+            "trials.mv",
+            "trial \"forever\":\n    while aye:\n        x is a blade with 1\n\ntrial \"fine\":\n    assert with aye\n"
+        );
+        let output = mid_valyrian()
+            .args(["--plain", "--timeout", "300ms", "test"])
+            .arg(&dir)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{}", stderr);
+        assert!(stderr.contains("MV0110"), "{}", stderr);
+        assert!(stdout(&output).contains("1 passed; 1 failed"), "{}", stdout(&output));
+    }
+
+    #[test]
+    fn test_failed_assertion_exits_with_5() {
+        let dir = scratch_dir("assertion");
+        write(&dir, "assert.mv", "on the iron throne:\n    assert with nay\n");
+        let output = mid_valyrian().arg("run").arg(dir.join("assert.mv")).output().unwrap();
+        assert_eq!(output.status.code(), Some(5), "{:?}", output);
+    }
+
+    #[test]
+    fn test_plain_output_is_ascii() {
+        let dir = scratch_dir("plain-ascii");
+        write(&dir, "forever.mv", "on the iron throne:\n    while aye:\n        x is a blade with 1\n");
+        for limit in [["--timeout", "300ms"], ["--max-steps", "1000"]] {
+            let output = mid_valyrian()
+                .args(["--plain", "--debug", "--log", "warn"])
+                .args(limit)
+                .arg("run")
+                .arg(dir.join("forever.mv"))
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(3), "{}", stderr);
+            assert!(stderr.contains("AST: Program"), "{}", stderr);
+            assert!(stderr.contains("= hint: Check that every loop"), "{}", stderr);
+            assert!(stderr.is_ascii(), "{}", stderr);
+            assert!(stdout(&output).is_ascii());
+        }
+    }
+
+    #[test]
+    fn test_declarations_without_a_value_start_at_defaults() {
+        let dir = scratch_dir("defaults");
+        write(
+            &dir,
+            "defaults.mv",
+            "on the iron throne:\n    s is a scroll\n    b is a blade\n    w is a wine\n    v is a vow\n    c is a sigil\n    n is a void\n    speak s\n    speak b\n    speak w\n    speak v\n    speak c\n    speak n\n"
+        );
+        for strict in [&[][..], &["--strict"][..]] {
+            for backend in ["tree", "bytecode"] {
+                let output = mid_valyrian()
+                    .arg("--plain")
+                    .args(strict)
+                    .args(["run", "--backend", backend])
+                    .arg(dir.join("defaults.mv"))
+                    .output()
+                    .unwrap();
+                assert!(output.status.success(), "{:?} on {}: {:?}", strict, backend, output);
+                assert_eq!(stdout(&output), "\n0\n0\nnay\n\0\nvoid\n");
+            }
+        }
+    }
+
+    #[test]
+    fn test_declarations_of_handles_need_a_value() {
+        let dir = scratch_dir("handle-defaults");
+        for data_type in ["raven", "rookery", "vault", "tidings", "missive", "socket"] {
+            let file = format!("{}.mv", data_type);
+            write(&dir, &file, &format!("on the iron throne:\n    x is a {}\n", data_type));
+            for strict in [&[][..], &["--strict"][..]] {
+                let output = mid_valyrian()
+                    .arg("--plain")
+                    .args(strict)
+                    .arg("run")
+                    .arg(dir.join(&file))
+                    .output()
+                    .unwrap();
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert_eq!(output.status.code(), Some(2), "{}: {}", data_type, stderr);
+                assert!(stderr.contains(&format!("a {} has no default", data_type)), "{}", stderr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plugins_that_cannot_load_are_refused() {
+        let dir = scratch_dir("plugins");
+        write(&dir, "main.mv", "on the iron throne:\n    speak \"unreachable\"\n");
+        // The crate's own C library opens fine but exports no plugin
+        let library = format!("{}mid_valyrian{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX);
+        let target = Path::new(env!("CARGO_BIN_EXE_mid-valyrian")).parent().unwrap();
+        // `cargo build` puts it beside the binary; `cargo test` alone leaves it in deps
+        let not_a_plugin = [target.join(&library), target.join("deps").join(&library)]
+            .into_iter()
+            .find(|path| path.exists())
+            .expect("cargo builds the crate's C library");
+        let libraries = [
+            (dir.join("missing.so"), "missing.so"),
+            (not_a_plugin, "not a plugin"),
+        ];
+        for (library, message) in libraries {
+            let output = mid_valyrian()
+                .arg("--plain")
+                .arg("--plugin")
+                .arg(&library)
+                .arg("run")
+                .arg(dir.join("main.mv"))
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(2), "{}: {}", library.display(), stderr);
+            assert!(stderr.contains("MV0202"), "{}", stderr);
+            assert!(stderr.contains(message), "{}", stderr);
+            assert_eq!(stdout(&output), "");
+        }
+    }
+
+    #[test]
+    fn test_ravens_end_at_the_senders_deadline() {
+        let dir = scratch_dir("raven-deadline");
+        write(
+            &dir,
+            "late.mv",
+            "we declare circle with ->\ncouncil says:\n    while aye:\n        x is a blade with 1\n\non the iron throne:\n    sleep with 700\n    raven is a raven with send_raven circle with\n    speak await_raven raven\n"
+        );
+        for backend in ["tree", "bytecode"] {
+            let started = std::time::Instant::now();
+            let output = mid_valyrian()
+                .args(["--plain", "--timeout", "1s", "run", "--backend", backend])
+                .arg(dir.join("late.mv"))
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(3), "{}: {}", backend, stderr);
+            assert!(stderr.contains("MV0110"), "{}", stderr);
+            // A raven with a clock of its own would run until 1.7s
+            assert!(started.elapsed() < std::time::Duration::from_millis(1500), "{:?}", started.elapsed());
+        }
+    }
+
+    #[test]
+    fn test_awaiting_a_raven_twice_gives_its_result_again() {
+        let dir = scratch_dir("raven-twice");
+        write(
+            &dir,
+            "twice.mv",
+            "we declare square with n ->\ncouncil says:\n    speak \"squaring\"\n    return n * n\n\non the iron throne:\n    raven is a raven with send_raven square with 7\n    speak await_raven raven\n    speak await_raven raven\n"
+        );
+        for backend in ["tree", "bytecode"] {
+            let output = mid_valyrian()
+                .args(["--plain", "run", "--backend", backend])
+                .arg(dir.join("twice.mv"))
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}: {:?}", backend, output);
+            assert_eq!(stdout(&output), "squaring\n49\n49\n");
+        }
+    }
+
+    #[test]
+    fn test_type_mismatch_hint_names_the_types() {
+        let dir = scratch_dir("mismatch-hint");
+        write(&dir, "vow.mv", "on the iron throne:\n    oath is a vow with aye\n    name is a scroll with oath\n    speak name\n");
+        let output = mid_valyrian()
+            .args(["--plain", "--strict", "run"])
+            .arg(dir.join("vow.mv"))
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(3), "{}", stderr);
+        assert!(stderr.contains("MV0103"), "{}", stderr);
+        assert!(stderr.contains("= hint: A scroll cannot hold vow"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_vaults_refuse_a_second_claim_and_a_stray_release() {
+        let dir = scratch_dir("vault-claims");
+        let programs = [
+            (
+                "twice.mv",
+                "gold is a blade with claim with bank\n    more is a blade with claim with bank\n    speak gold + more",
+                "this vault is already claimed here",
+            ),
+            ("stray.mv", "release with bank, 1", "can't be released without claiming it first"),
+        ];
+        for (file, statements, message) in programs {
+            write(
+                &dir,
+                file,
+                &format!("on the iron throne:\n    bank is a vault with vault with 0\n    {}\n", statements)
+            );
+            for backend in ["tree", "bytecode"] {
+                let output = mid_valyrian()
+                    .args(["--plain", "run", "--backend", backend])
+                    .arg(dir.join(file))
+                    .output()
+                    .unwrap();
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert_eq!(output.status.code(), Some(3), "{} on {}: {}", file, backend, stderr);
+                assert!(stderr.contains(message), "{}", stderr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_commands_need_permission_outside_the_sandbox() {
+        let run_echo = ["speak command with \"echo\", \"Valar dohaeris\""];
+        assert_refused("command-denied", &[], &run_echo, "MV0113", "needs commands to be allowed");
+        assert_refused(
+            "command-sandboxed",
+            &["--sandbox", "--allow-commands"],
+            &run_echo,
+            "MV0113",
+            "'command' is not allowed in the sandbox"
+        );
+    }
+
+    #[test]
+    fn test_sandbox_refuses_http() {
+        let requests = [
+            "speak fetch with \"http://127.0.0.1:1/\"",
+            "speak post with \"http://127.0.0.1:1/\", \"raven\"",
+        ];
+        assert_refused("http-sandboxed", &["--sandbox"], &requests, "MV0113", "is not allowed in the sandbox");
+    }
+
+    #[test]
+    fn test_sandbox_refuses_sockets() {
+        let sockets = ["speak connect with \"127.0.0.1\", 1", "speak listen with 0"];
+        assert_refused("sockets-sandboxed", &["--sandbox"], &sockets, "MV0113", "is not allowed in the sandbox");
+    }
+
+    #[test]
+    fn test_receive_on_an_empty_rookery_ends_at_the_timeout() {
+        let dir = scratch_dir("rookery-timeout");
+        write(
+            &dir,
+            "empty.mv",
+            "on the iron throne:\n    letters is a rookery with rookery with\n    speak receive with letters\n"
+        );
+        for backend in ["tree", "bytecode"] {
+            let started = std::time::Instant::now();
+            let output = mid_valyrian()
+                .args(["--plain", "--timeout", "300ms", "run", "--backend", backend])
+                .arg(dir.join("empty.mv"))
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(3), "{}: {}", backend, stderr);
+            assert!(stderr.contains("MV0110"), "{}", stderr);
+            assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        }
+    }
+
+    #[test]
+    fn test_timers_refuse_undeclared_spells() {
+        let dir = scratch_dir("timer-spells");
+        for timer in ["after", "every"] {
+            let file = format!("{}.mv", timer);
+            write(
+                &dir,
+                &file,
+                &format!("on the iron throne:\n    {} with 0, \"nobody\"\n    speak run_pending with\n", timer)
+            );
+            for backend in ["tree", "bytecode"] {
+                let output = mid_valyrian()
+                    .args(["--plain", "run", "--backend", backend])
+                    .arg(dir.join(&file))
+                    .output()
+                    .unwrap();
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert_eq!(output.status.code(), Some(3), "{} on {}: {}", timer, backend, stderr);
+                assert!(stderr.contains("undefined function 'nobody'"), "{}", stderr);
+                assert_eq!(stdout(&output), "");
+            }
+        }
+    }
+
+    #[test]
+    fn test_traces_keep_the_caller_of_a_tail_call() {
+        let dir = scratch_dir("tail-trace");
+        write(
+            &dir,
+            "tail.mv",
+            "we declare count_dragons with n ->\ncouncil says:\n    return n / 0\n\nwe declare outer with n ->\ncouncil says:\n    return count_dragons with n\n\non the iron throne:\n    speak outer with 3\n"
+        );
+        for backend in ["tree", "bytecode"] {
+            let output = mid_valyrian()
+                .args(["--plain", "--backend", backend])
+                .arg(dir.join("tail.mv"))
+                .output()
+                .unwrap();
+            assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+            assert!(
+                stderr(&output).contains(
+                    "in `count_dragons` called from `main` at line 10, column 11 (1 tail call(s) elided)"
+                ),
+                "{}: {}",
+                backend,
+                stderr(&output)
+            );
+        }
+    }
+
+    #[test]
+    fn test_running_several_files_reports_every_one_that_fails() {
+        let dir = scratch_dir("several");
+        write(&dir, "main.mv", "on the iron throne:\n    greet with \"Arya\"\n");
+        write(&dir, "greetings.mv", "we declare greet with name ->\ncouncil says:\n    speak \"Hello, \" + name\n");
+        write(&dir, "broken.mv", "on the iron throne:\n    speak (\n");
+        // Not UTF-8, so it can't be read as source
+        std::fs::write(dir.join("garbled.mv"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let linked = mid_valyrian()
+            .current_dir(&dir)
+            .args(["run", "main.mv", "greetings.mv"])
+            .output()
+            .unwrap();
+        assert!(linked.status.success(), "{}", stderr(&linked));
+        assert_eq!(stdout(&linked), "Hello, Arya\n");
+
+        let failed = mid_valyrian()
+            .current_dir(&dir)
+            .args(["--plain", "run", "main.mv", "broken.mv", "garbled.mv", "greetings.mv"])
+            .output()
+            .unwrap();
+        assert_eq!(failed.status.code(), Some(2), "{}", stderr(&failed));
+        assert!(stderr(&failed).contains("--> broken.mv:2:12"), "{}", stderr(&failed));
+        assert!(stderr(&failed).contains("Failed to read file 'garbled.mv'"), "{}", stderr(&failed));
+        assert_eq!(stdout(&failed), "");
+    }
+
+    #[test]
+    fn test_ast_stops_quietly_when_the_reader_goes_away() {
+        let dir = scratch_dir("ast-pipe");
+        let speeches: String = (0..5000).map(|i| format!("    speak {}\n", i)).collect();
+        write(&dir, "long.mv", &format!("on the iron throne:\n{}", speeches));
+        let mut child = mid_valyrian()
+            .arg("ast")
+            .arg(dir.join("long.mv"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Far more than a pipe holds is left unread, as `| head` would
+        let mut start = [0; 16];
+        child.stdout.as_mut().unwrap().read_exact(&mut start).unwrap();
+        drop(child.stdout.take());
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[test]
+    fn test_transpiled_self_tail_calls_loop_instead_of_recursing() {
+        let dir = scratch_dir("transpile-tail");
+        write(
+            &dir,
+            "countdown.mv",
+            "we declare countdown with n, total ->\ncouncil says:\n    if n == 0:\n        return total\n    return countdown with n - 1, total + n\n\non the iron throne:\n    speak countdown with 100000, 0\n"
+        );
+        let output = mid_valyrian()
+            .args(["transpile", "-o"])
+            .arg(dir.join("countdown.py"))
+            .arg(dir.join("countdown.mv"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        let python = std::fs::read_to_string(dir.join("countdown.py")).unwrap();
+        assert!(python.contains("n, total = n - 1, total + n\n        continue\n"), "{}", python);
+
+        // Python may not be installed where the tests run
+        if let Ok(run) = Command::new("python3").arg(dir.join("countdown.py")).output() {
+            assert!(run.status.success(), "{}", stderr(&run));
+            assert_eq!(stdout(&run), "5000050000\n");
+        }
+    }
+
+    #[test]
+    fn test_add_writes_any_path_as_valid_toml() {
+        let project = scratch_dir("add-toml");
+        // Rust would escape the accent as \u{301}, which TOML can't read
+        let library = scratch_dir("add-toml-cafe\u{301} \"quoted\"");
+        write(&project, "valyrian.toml", "[project]\nname = \"winterfell\"\n");
+        write(&project, "main.mv", "on the iron throne:\n    greet with \"Arya\"\n");
+        write(&library, "greetings.mv", "we declare greet with name ->\ncouncil says:\n    speak \"Hello, \" + name\n");
+
+        let added = mid_valyrian()
+            .current_dir(&project)
+            .args(["add", "--name", "greetings"])
+            .arg(library.join("greetings.mv"))
+            .output()
+            .unwrap();
+        assert!(added.status.success(), "{:?}", added);
+        let vendored = mid_valyrian().current_dir(&project).arg("vendor").output().unwrap();
+        assert!(vendored.status.success(), "{}", stderr(&vendored));
+        let run = mid_valyrian().current_dir(&project).arg("run").output().unwrap();
+        assert_eq!(stdout(&run), "Hello, Arya\n", "{}", stderr(&run));
+    }
+
+    #[test]
+    fn test_run_options_only_go_to_subcommands_that_run() {
+        for args in [&["fmt", "--sandbox", "examples/hello.mv"][..], &["--max-steps", "5", "ast", "examples/hello.mv"]] {
+            let output = mid_valyrian().args(args).output().unwrap();
+            assert_eq!(output.status.code(), Some(2), "{:?}", args);
+            assert!(stderr(&output).contains("cannot be used with") || stderr(&output).contains("unexpected argument"));
+            assert_eq!(stdout(&output), "");
+        }
+
+        for args in [["--max-steps", "5", "run", "examples/hello.mv"], ["run", "--max-steps", "5", "examples/hello.mv"]] {
+            let output = mid_valyrian().args(args).output().unwrap();
+            assert_eq!(output.status.code(), Some(3), "{:?}", args);
+            assert!(stderr(&output).contains("execution limit of 5 steps exceeded"), "{}", stderr(&output));
+        }
+    }
+
+    #[test]
+    fn test_files_without_a_source_extension_are_refused() {
+        let dir = scratch_dir("extension");
+        write(&dir, "notes.txt", "on the iron throne:\n    speak 1\n");
+        let run = mid_valyrian().arg("--plain").arg(dir.join("notes.txt")).output().unwrap();
+        assert_eq!(run.status.code(), Some(4), "{}", stderr(&run));
+        // The batch subcommands exit as they do for any failure
+        for args in [&["--plain"][..], &["--plain", "run"], &["--plain", "check"], &["--plain", "lint"], &["--plain", "fmt"]] {
+            let output = mid_valyrian().args(args).arg(dir.join("notes.txt")).output().unwrap();
+            assert!(!output.status.success(), "{:?}", args);
+            assert!(stderr(&output).starts_with("error[MV0203]: "), "{}", stderr(&output));
+            assert!(stderr(&output).contains("must end with .mv or .valyrian"), "{}", stderr(&output));
+        }
+
+        let run = mid_valyrian().arg("--any-extension").arg(dir.join("notes.txt")).output().unwrap();
+        assert!(run.status.success(), "{}", stderr(&run));
+        assert_eq!(stdout(&run), "1\n");
     }
 }